@@ -0,0 +1,74 @@
+//! Times `scratch-compiler lint` on a program that is mostly calls to nested
+//! function macros, which spends its time expanding them. Run with
+//! `cargo bench --bench macros`.
+
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    process::{Command, Stdio},
+    time::Instant,
+};
+
+const MACROS: &str = r#"
+(macro (clamp! var low high)
+  (if (< ,var ,low)
+    (:= ,var ,low)
+    (if (> ,var ,high) (:= ,var ,high) (do))))
+
+(macro (step! var speed)
+  (do
+    (:= ,var (+ ,var ,speed))
+    (clamp! ,var -240 240)))
+
+(macro (move-all! speed)
+  (do
+    (step! x ,speed)
+    (step! y (* 2 ,speed))
+    (step! z (- 0 ,speed))))
+"#;
+
+const CALLS: usize = 2000;
+const RUNS: usize = 10;
+
+fn program() -> String {
+    let mut program = MACROS.to_owned();
+    program.push_str("(sprite \"Stage\"\n  (variables x y z)\n");
+    program.push_str("  (proc when-flag-clicked\n");
+    for i in 0..CALLS {
+        writeln!(program, "    (move-all! {i})").unwrap();
+    }
+    program.push_str("))\n");
+    program
+}
+
+fn main() {
+    let dir = env::temp_dir()
+        .join(format!("scratch-compiler-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.scratch");
+    fs::write(&source, program()).unwrap();
+
+    let mut times = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let status = Command::new(env!("CARGO_BIN_EXE_scratch-compiler"))
+                .arg("lint")
+                .arg(&source)
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            let elapsed = start.elapsed();
+            assert!(status.success(), "expanding the benchmark failed");
+            elapsed
+        })
+        .collect::<Vec<_>>();
+    fs::remove_dir_all(&dir).unwrap();
+
+    times.sort_unstable();
+    println!(
+        "{CALLS} macro calls: median {:?}, fastest {:?} over {RUNS} runs",
+        times[RUNS / 2],
+        times[0],
+    );
+}
@@ -0,0 +1,66 @@
+//! Times `scratch-compiler lint` on a program that is mostly a macro which is
+//! never called, so that most of the time is spent reading its body, which
+//! looks like the large sources that macros generate. Run with
+//! `cargo bench --bench parser`.
+
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    process::{Command, Stdio},
+    time::Instant,
+};
+
+const STATEMENTS: usize = 50_000;
+const RUNS: usize = 10;
+
+fn program() -> String {
+    let mut program = "(macro (unused!)\n  '(do\n".to_owned();
+    for i in 0..STATEMENTS {
+        writeln!(
+            program,
+            "    (if (< (item {i} \"list\") 0.5) ; comment\n      \
+             (:= x (+ x #ff8000 -0x1F {i}e-3)) (say \"line\\n{i}\"))",
+        )
+        .unwrap();
+    }
+    program.push_str("))\n\n");
+    program.push_str("(sprite \"Stage\"\n  (variables x)\n");
+    program.push_str("  (proc when-flag-clicked (:= x 0)))\n");
+    program
+}
+
+fn main() {
+    let dir = env::temp_dir()
+        .join(format!("scratch-compiler-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.scratch");
+    let program = program();
+    fs::write(&source, &program).unwrap();
+
+    let mut times = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let status = Command::new(env!("CARGO_BIN_EXE_scratch-compiler"))
+                .arg("lint")
+                .arg(&source)
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            let elapsed = start.elapsed();
+            assert!(status.success(), "parsing the benchmark failed");
+            elapsed
+        })
+        .collect::<Vec<_>>();
+    fs::remove_dir_all(&dir).unwrap();
+
+    times.sort_unstable();
+    let megabytes = program.len() as f64 / 1e6;
+    println!(
+        "{megabytes:.1} MB of source: median {:?} ({:.1} MB/s), fastest {:?} \
+         over {RUNS} runs",
+        times[RUNS / 2],
+        megabytes / times[RUNS / 2].as_secs_f64(),
+        times[0],
+    );
+}
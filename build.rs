@@ -0,0 +1,26 @@
+//! Builds the runtime crate in `runtime/` as a static library, which gets
+//! embedded in the compiler and linked into every executable.
+
+use std::{env, path::PathBuf, process::Command};
+
+fn main() {
+    println!("cargo:rerun-if-changed=runtime");
+    let target_dir =
+        PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("runtime");
+    let status = Command::new(env::var_os("CARGO").unwrap())
+        .args([
+            "build",
+            "--release",
+            "--manifest-path",
+            "runtime/Cargo.toml",
+        ])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("could not run cargo to build the runtime");
+    assert!(status.success(), "building the runtime failed");
+    println!(
+        "cargo:rustc-env=RUNTIME_LIB={}",
+        target_dir.join("release/libruntime.a").display()
+    );
+}
@@ -0,0 +1,55 @@
+//! Feeds arbitrary source code through parsing, macro expansion and lowering
+//! to IR, which have to report malformed input as diagnostics instead of
+//! panicking. Run with `cargo fuzz run frontend`.
+
+#![no_main]
+#![allow(dead_code)]
+
+/// The modules of the compiler, which has to be kept in sync with the module
+/// declarations in `src/main.rs`.
+#[path = "../../src"]
+mod compiler {
+    pub mod allow;
+    pub mod asset;
+    pub mod assigned;
+    pub mod ast;
+    pub mod codegen;
+    pub mod diagnostic;
+    pub mod ir;
+    pub mod lexer;
+    pub mod lint;
+    pub mod locale;
+    pub mod macros;
+    pub mod optimize;
+    pub mod opts;
+    pub mod parser;
+    pub mod pattern;
+    pub mod remarks;
+    pub mod source;
+    pub mod span;
+    pub mod stats;
+    pub mod typecheck;
+    pub mod uid;
+}
+
+use crate::{allow::Allowed, ir::Program, macros::expand, opts::Opts};
+use codemap::CodeMap;
+use compiler::*;
+use gumdrop::Options;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let opts = Opts::parse_args_default(&["main.scratch"]).unwrap();
+    let mut code_map = CodeMap::new();
+    let file = code_map.add_file("main.scratch".to_owned(), source.to_owned());
+    // Errors are fine, only panics are bugs.
+    let _ = parser::program(&file, opts.max_nesting).and_then(|mut asts| {
+        let mut allowed = Allowed::default();
+        allowed.collect(file.span, &mut asts)?;
+        let expanded = expand(asts, &opts, &mut code_map, &mut allowed)?;
+        Program::from_asts(expanded)
+    });
+});
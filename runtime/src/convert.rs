@@ -0,0 +1,376 @@
+//! Conversions between values, following the casts in the Scratch VM.
+
+use crate::{alloc_str, Any, Cow, StaticStr};
+
+static TRUE: StaticStr<5> = StaticStr(*b"\0true");
+static FALSE: StaticStr<6> = StaticStr(*b"\0false");
+
+/// Truncates a number toward zero, saturating to the range of a `usize`. NaN
+/// and negative numbers become zero. List indices are converted like this,
+/// which matches Scratch flooring them, since every index below one is
+/// invalid.
+#[no_mangle]
+pub extern "C" fn double_to_usize(n: f64) -> usize {
+    n as usize
+}
+
+/// Converts a letter index to a one-based index like Scratch, which subtracts
+/// one and gives no letter if that is negative, so anything below one is out
+/// of range. Returns zero for NaN and numbers below one.
+#[no_mangle]
+pub extern "C" fn double_to_char_index(n: f64) -> usize {
+    double_to_usize(n)
+}
+
+#[no_mangle]
+pub extern "C" fn bool_to_str(b: bool) -> Cow {
+    if b {
+        TRUE.cow()
+    } else {
+        FALSE.cow()
+    }
+}
+
+/// Formats a number like JavaScript.
+#[no_mangle]
+pub extern "C" fn double_to_cow(n: f64) -> Cow {
+    // SAFETY: The string is only read.
+    unsafe { alloc_str(format_number(n).as_bytes()) }
+}
+
+/// Converts a value to a number. Strings that aren't numbers become zero. The
+/// value is consumed.
+///
+/// # Safety
+///
+/// `value` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn any_to_double(value: Any) -> f64 {
+    match value.low {
+        0 => 0.0,
+        1 => 1.0,
+        2 => f64::from_bits(value.high),
+        _ => {
+            let s = value.as_cow();
+            let n = parse_number(s.as_bytes()).unwrap_or(0.0);
+            s.drop();
+            n
+        }
+    }
+}
+
+/// Converts a value to a boolean. The empty string, `"0"` and `"false"` in any
+/// case are false. The value is consumed.
+///
+/// # Safety
+///
+/// `value` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn any_to_bool(value: Any) -> bool {
+    match value.low {
+        0 => false,
+        1 => true,
+        2 => {
+            let n = f64::from_bits(value.high);
+            n != 0.0 && !n.is_nan()
+        }
+        _ => {
+            let s = value.as_cow();
+            let bytes = s.as_bytes();
+            let b = !(bytes.is_empty()
+                || bytes == b"0"
+                || bytes.eq_ignore_ascii_case(b"false"));
+            s.drop();
+            b
+        }
+    }
+}
+
+/// Parses a string into `n`, returning whether it is a number at all. `n` is
+/// set to zero if it isn't. The string is not consumed.
+///
+/// # Safety
+///
+/// `s` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn str_to_number(s: Cow, n: &mut f64) -> bool {
+    let parsed = parse_number(s.as_bytes());
+    *n = parsed.unwrap_or(0.0);
+    parsed.is_some()
+}
+
+/// Parses a string like JavaScript's `Number`, returning `None` where it
+/// would return NaN.
+pub fn parse_number(bytes: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(bytes)
+        .ok()?
+        .trim_matches(is_js_whitespace);
+    match s {
+        "" => return Some(0.0),
+        "Infinity" | "+Infinity" => return Some(f64::INFINITY),
+        "-Infinity" => return Some(f64::NEG_INFINITY),
+        _ => {}
+    }
+    // Radix prefixes can't have a sign.
+    let radix = match s.get(..2).map(str::to_ascii_lowercase).as_deref() {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = &s[2..];
+        if !digits.chars().all(|c| c.is_digit(radix)) {
+            return None;
+        }
+        return u128::from_str_radix(digits, radix).ok().map(|n| n as f64);
+    }
+    // Rust also accepts spellings like `inf` and `NaN`, which JavaScript
+    // doesn't.
+    if !s.bytes().all(|b| {
+        b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E')
+    }) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// JavaScript trims the same whitespace as Rust, except that it includes the
+/// byte order mark but not U+0085.
+fn is_js_whitespace(c: char) -> bool {
+    (c.is_whitespace() && c != '\u{85}') || c == '\u{feff}'
+}
+
+/// Formats a number like JavaScript's `Number.prototype.toString`.
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_owned();
+    }
+    if n == 0.0 {
+        return "0".to_owned();
+    }
+    let sign = if n < 0.0 { "-" } else { "" };
+    if n.is_infinite() {
+        return format!("{sign}Infinity");
+    }
+    // Rust and JavaScript both pick the shortest digits that round-trip.
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    // The position of the decimal point relative to the start of the digits.
+    let point = exponent.parse::<i32>().unwrap() + 1;
+    let len = digits.len() as i32;
+    let body = if len <= point && point <= 21 {
+        format!("{digits}{}", "0".repeat((point - len) as usize))
+    } else if 0 < point && point <= 21 {
+        let (integer, fraction) = digits.split_at(point as usize);
+        format!("{integer}.{fraction}")
+    } else if -6 < point && point <= 0 {
+        format!("0.{}{digits}", "0".repeat(-point as usize))
+    } else {
+        let exponent = point - 1;
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        let (first, rest) = digits.split_at(1);
+        let point = if rest.is_empty() { "" } else { "." };
+        format!("{first}{point}{rest}e{exponent_sign}{}", exponent.abs())
+    };
+    format!("{sign}{body}")
+}
+
+/// Formats a number like JavaScript's `Number.prototype.toFixed`, with
+/// `digits` digits after the decimal point.
+pub fn format_fixed(n: f64, digits: usize) -> String {
+    if !n.is_finite() || n.abs() >= 1e21 {
+        return format_number(n);
+    }
+    // The digits of a double never go further than 1074 places after the
+    // decimal point, so this is exact. Rust rounds halfway cases to even, but
+    // JavaScript rounds them away from zero, so the rounding is done here.
+    let exact = format!("{:.1074}", n.abs());
+    let point = exact.find('.').unwrap();
+    let (kept, rest) = exact.split_at(point + 1 + digits);
+    let mut number = kept.bytes().filter(|&b| b != b'.').collect::<Vec<_>>();
+    if rest.as_bytes()[0] >= b'5' {
+        let carried = number.iter_mut().rev().all(|digit| {
+            if *digit == b'9' {
+                *digit = b'0';
+                true
+            } else {
+                *digit += 1;
+                false
+            }
+        });
+        if carried {
+            number.insert(0, b'1');
+        }
+    }
+    let (integer, fraction) = number.split_at(number.len() - digits);
+    let sign = if n < 0.0 { "-" } else { "" };
+    let point = if digits == 0 { "" } else { "." };
+    format!(
+        "{sign}{}{point}{}",
+        String::from_utf8_lossy(integer),
+        String::from_utf8_lossy(fraction),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sb3_stuff::Value;
+
+    /// Strings that are easy to convert wrong.
+    const STRINGS: &[&str] = &[
+        "",
+        " ",
+        "0",
+        "-0",
+        "12",
+        "  12  ",
+        "\t12\n",
+        "1.5",
+        ".5",
+        "5.",
+        "1e3",
+        "1e",
+        "+7",
+        "-7",
+        "0x10",
+        "0X1f",
+        "0b101",
+        "0o17",
+        "-0x10",
+        "0x",
+        "Infinity",
+        "-Infinity",
+        "infinity",
+        "inf",
+        "NaN",
+        "true",
+        "false",
+        "FALSE",
+        "abc",
+        "1 2",
+        "\u{feff}3",
+    ];
+
+    const NUMBERS: &[f64] = &[
+        0.0,
+        -0.0,
+        1.0,
+        -1.5,
+        0.1 + 0.2,
+        100.0,
+        1e20,
+        1e21,
+        1e-6,
+        1e-7,
+        1.25e-5,
+        9_007_199_254_740_992.0,
+        f64::MAX,
+        f64::MIN_POSITIVE,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NAN,
+    ];
+
+    fn owned_str(s: &str) -> Any {
+        // SAFETY: The string is only read.
+        unsafe { alloc_str(s.as_bytes()) }.into()
+    }
+
+    fn number(n: f64) -> Any {
+        Any {
+            low: 2,
+            high: n.to_bits(),
+        }
+    }
+
+    #[test]
+    fn str_to_double_matches_sb3_stuff() {
+        for s in STRINGS {
+            let expected = Value::String((*s).into()).to_num();
+            // SAFETY: The string was just allocated.
+            let got = unsafe { any_to_double(owned_str(s)) };
+            assert!(
+                got == expected || (got.is_nan() && expected.is_nan()),
+                "{s:?} became {got} instead of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn str_to_bool_matches_sb3_stuff() {
+        for s in STRINGS {
+            let expected = Value::String((*s).into()).to_bool();
+            // SAFETY: The string was just allocated.
+            let got = unsafe { any_to_bool(owned_str(s)) };
+            assert_eq!(got, expected, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn double_to_str_matches_sb3_stuff() {
+        for &n in NUMBERS {
+            let expected = Value::Num(n).to_cow_str().into_owned();
+            let s = double_to_cow(n);
+            // SAFETY: The string was just allocated and is dropped after
+            // being read.
+            let got = unsafe {
+                let got = String::from_utf8_lossy(s.as_bytes()).into_owned();
+                s.drop();
+                got
+            };
+            assert_eq!(got, expected, "{n:e}");
+        }
+    }
+
+    #[test]
+    fn format_fixed_matches_javascript() {
+        for (n, digits, expected) in [
+            (42.0, 0, "42"),
+            (42.0, 3, "42.000"),
+            (1234.5678, 2, "1234.57"),
+            (1.005, 2, "1.00"),
+            (0.5, 0, "1"),
+            (2.5, 0, "3"),
+            (-2.5, 0, "-3"),
+            (99.5, 0, "100"),
+            (9.995, 2, "9.99"),
+            (0.1 + 0.2, 20, "0.30000000000000004441"),
+            (-0.0, 2, "0.00"),
+            (-0.001, 2, "-0.00"),
+            (1e21, 2, "1e+21"),
+            (f64::INFINITY, 2, "Infinity"),
+            (f64::NAN, 2, "NaN"),
+        ] {
+            assert_eq!(format_fixed(n, digits), expected, "{n:e} {digits}");
+        }
+    }
+
+    #[test]
+    fn double_to_char_index_matches_scratch() {
+        for (n, expected) in [
+            (f64::NAN, 0),
+            (-1.0, 0),
+            (0.0, 0),
+            (0.5, 0),
+            (0.999, 0),
+            (1.0, 1),
+            (1.9, 1),
+            (2.5, 2),
+            (f64::INFINITY, usize::MAX),
+        ] {
+            assert_eq!(double_to_char_index(n), expected, "{n}");
+        }
+    }
+
+    #[test]
+    fn double_to_bool_matches_sb3_stuff() {
+        for &n in NUMBERS {
+            // SAFETY: Numbers are always valid.
+            let got = unsafe { any_to_bool(number(n)) };
+            assert_eq!(got, Value::Num(n).to_bool(), "{n:e}");
+        }
+    }
+}
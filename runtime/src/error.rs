@@ -0,0 +1,40 @@
+use crate::{rt_flush, Cow};
+use std::{
+    io::{self, Write},
+    ptr,
+};
+
+/// Reports a failed runtime check and exits without running `atexit`
+/// handlers, so a debug build doesn't also report leaks.
+///
+/// # Safety
+///
+/// `message` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn runtime_error(message: Cow) -> ! {
+    rt_flush();
+    fflush(ptr::null_mut());
+    let mut stderr = io::stderr().lock();
+    let _ = stderr.write_all(b"runtime error: ");
+    let _ = stderr.write_all(message.as_bytes());
+    let _ = stderr.write_all(b"\n");
+    _exit(101)
+}
+
+/// Exits right away with `status`, flushing output first. Like
+/// [`runtime_error`], `atexit` handlers don't run. The prelude exits like
+/// this from paths that aren't implemented yet.
+#[no_mangle]
+pub extern "C" fn rt_exit(status: i32) -> ! {
+    rt_flush();
+    // SAFETY: Flushing every stream can be done at any time.
+    unsafe {
+        fflush(ptr::null_mut());
+        _exit(status)
+    }
+}
+
+extern "C" {
+    fn fflush(stream: *mut u8) -> i32;
+    fn _exit(status: i32) -> !;
+}
@@ -0,0 +1,142 @@
+//! Output of the program. Standard output is buffered and flushed when the
+//! buffer fills up, before the program waits or reads input, and at exit.
+//! Standard error is written right away.
+
+use crate::{double_to_usize, format_fixed, with_any_bytes, Any, Cow};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+const STDOUT_FILENO: i32 = 1;
+const STDERR_FILENO: i32 = 2;
+const BUFFER_SIZE: usize = 8192;
+
+/// Output that hasn't been written to standard output yet. It isn't heap
+/// allocated so that debug builds don't report it as a leak.
+static STDOUT: Mutex<Buffer> = Mutex::new(Buffer {
+    bytes: [0; BUFFER_SIZE],
+    len: 0,
+});
+
+/// Whether standard output is written right away, set with
+/// `--unbuffered`.
+static UNBUFFERED: AtomicBool = AtomicBool::new(false);
+
+struct Buffer {
+    bytes: [u8; BUFFER_SIZE],
+    len: usize,
+}
+
+impl Buffer {
+    fn write(&mut self, bytes: &[u8]) {
+        if self.len + bytes.len() > self.bytes.len() {
+            self.flush();
+        }
+        if bytes.len() >= self.bytes.len() || UNBUFFERED.load(Ordering::Relaxed)
+        {
+            write_all(STDOUT_FILENO, bytes);
+        } else {
+            self.bytes[self.len..][..bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+        }
+    }
+
+    fn flush(&mut self) {
+        write_all(STDOUT_FILENO, &self.bytes[..self.len]);
+        self.len = 0;
+    }
+}
+
+fn print(bytes: &[u8]) {
+    STDOUT
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .write(bytes);
+}
+
+/// Writes everything that was printed to standard output so far.
+#[no_mangle]
+pub extern "C" fn rt_flush() {
+    STDOUT.lock().unwrap_or_else(|err| err.into_inner()).flush();
+}
+
+/// Makes standard output unbuffered.
+#[no_mangle]
+pub extern "C" fn rt_unbuffered_stdout() {
+    UNBUFFERED.store(true, Ordering::Relaxed);
+}
+
+/// Writes a string to standard output.
+///
+/// # Safety
+///
+/// `s` must be a valid string. It is only borrowed.
+#[no_mangle]
+pub unsafe extern "C" fn rt_print_str(s: Cow) {
+    print(s.as_bytes());
+}
+
+/// Writes any value to standard output, formatted like it would be when
+/// converted to a string.
+///
+/// # Safety
+///
+/// `value` must be valid. It is only borrowed.
+#[no_mangle]
+pub unsafe extern "C" fn rt_print_any(value: Any) {
+    with_any_bytes(value, print);
+}
+
+/// Writes a number to standard output with `digits` digits after the decimal
+/// point, for `print-fixed`. Like in JavaScript, there are at most 100.
+#[no_mangle]
+pub extern "C" fn rt_print_fixed(n: f64, digits: f64) {
+    print(format_fixed(n, double_to_usize(digits).min(100)).as_bytes());
+}
+
+/// Ends the current line of standard output.
+#[no_mangle]
+pub extern "C" fn rt_print_newline() {
+    print(b"\n");
+}
+
+/// Like [`rt_print_str`], but for standard error. Standard output is flushed
+/// first to keep the order of the output in a terminal.
+///
+/// # Safety
+///
+/// `s` must be a valid string. It is only borrowed.
+#[no_mangle]
+pub unsafe extern "C" fn rt_eprint_str(s: Cow) {
+    rt_flush();
+    write_all(STDERR_FILENO, s.as_bytes());
+}
+
+/// Like [`rt_print_any`], but for standard error.
+///
+/// # Safety
+///
+/// `value` must be valid. It is only borrowed.
+#[no_mangle]
+pub unsafe extern "C" fn rt_eprint_any(value: Any) {
+    rt_flush();
+    with_any_bytes(value, |bytes| write_all(STDERR_FILENO, bytes));
+}
+
+/// Writes all of `bytes`, retrying after partial writes. Errors are ignored
+/// since printing has no way to report them.
+fn write_all(fd: i32, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        // SAFETY: The pointer and length come from a slice.
+        let written = unsafe { write(fd, bytes.as_ptr(), bytes.len()) };
+        let Ok(written @ 1..) = usize::try_from(written) else {
+            return;
+        };
+        bytes = &bytes[written..];
+    }
+}
+
+extern "C" {
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+}
@@ -0,0 +1,92 @@
+//! The parts of the runtime of native executables that are written in Rust.
+//! They are linked together with `prelude.s`, and both sides call each other
+//! through the C ABI, so the types here mirror the representations used by
+//! the generated code.
+
+mod convert;
+mod error;
+mod io;
+mod list;
+mod math;
+mod speech;
+mod stack;
+mod state;
+mod string;
+
+pub use convert::*;
+pub use error::*;
+pub use io::*;
+pub use list::*;
+pub use math::*;
+pub use speech::*;
+pub use stack::*;
+pub use state::*;
+pub use string::*;
+
+/// A value of unknown type. `low` is 0 for false, 1 for true and 2 for a
+/// number whose bits are in `high`. Any other value is a pointer to a string
+/// whose length is in `high`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Any {
+    pub low: u64,
+    pub high: u64,
+}
+
+impl Any {
+    const fn is_str(self) -> bool {
+        self.low > 2
+    }
+
+    const fn as_cow(self) -> Cow {
+        Cow {
+            ptr: self.low as *const u8,
+            len: self.high as usize,
+        }
+    }
+}
+
+impl From<Cow> for Any {
+    fn from(s: Cow) -> Self {
+        Self {
+            low: s.ptr as u64,
+            high: s.len as u64,
+        }
+    }
+}
+
+/// A string that is only owned if its address is even. Static strings are
+/// always placed at odd addresses.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Cow {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Cow {
+    fn is_owned(self) -> bool {
+        self.ptr as usize & 1 == 0
+    }
+
+    /// # Safety
+    ///
+    /// The string must not be used afterwards.
+    unsafe fn drop(self) {
+        if self.is_owned() {
+            free(self.ptr.cast_mut());
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The string must be valid for as long as the returned slice is used.
+    unsafe fn as_bytes<'a>(self) -> &'a [u8] {
+        std::slice::from_raw_parts(self.ptr, self.len)
+    }
+}
+
+extern "C" {
+    fn malloc(size: usize) -> *mut u8;
+    fn free(ptr: *mut u8);
+}
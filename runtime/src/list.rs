@@ -0,0 +1,55 @@
+use crate::{any_to_double, clone_any, double_to_usize, Any, EMPTY};
+
+/// A growable array of values.
+#[repr(C)]
+pub struct List {
+    pub data: *mut Any,
+    pub len: usize,
+    pub cap: usize,
+}
+
+/// Returns the item at `index`, which is either a one-based number or the
+/// string `last`, or an empty string if there is no such item. The index is
+/// consumed.
+///
+/// # Safety
+///
+/// `index` and `list` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn list_get(index: Any, list: &List) -> Any {
+    list_get_index(list_index(index, list.len), list)
+}
+
+/// Converts an index like [`list_get`] takes, for a list with `len` items, to
+/// a one-based number that may be out of range. The index is consumed.
+///
+/// # Safety
+///
+/// `index` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn list_index(index: Any, len: usize) -> usize {
+    if is_last(index) {
+        index.as_cow().drop();
+        len
+    } else {
+        double_to_usize(any_to_double(index))
+    }
+}
+
+/// Like [`list_get`], but with an index that the compiler knows is a whole
+/// number, so it doesn't have to be converted.
+///
+/// # Safety
+///
+/// `list` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn list_get_index(index: usize, list: &List) -> Any {
+    match index.checked_sub(1).filter(|&index| index < list.len) {
+        Some(index) => clone_any(*list.data.add(index)),
+        None => EMPTY.cow().into(),
+    }
+}
+
+unsafe fn is_last(index: Any) -> bool {
+    index.is_str() && index.as_cow().as_bytes().eq_ignore_ascii_case(b"last")
+}
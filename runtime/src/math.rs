@@ -0,0 +1,41 @@
+//! Arithmetic that differs from the instructions and libc functions the code
+//! generator would otherwise use.
+
+/// The remainder of floored division like Scratch, so the result has the sign
+/// of the modulus instead of the dividend like `fmod`.
+#[no_mangle]
+pub extern "C" fn scratch_mod(n: f64, modulus: f64) -> f64 {
+    let remainder = n % modulus;
+    // Written as a select so it compiles without a branch.
+    let adjustment = if remainder / modulus < 0.0 {
+        modulus
+    } else {
+        0.0
+    };
+    remainder + adjustment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_has_sign_of_modulus() {
+        assert_eq!(scratch_mod(7.0, 3.0), 1.0);
+        assert_eq!(scratch_mod(-7.0, 3.0), 2.0);
+        assert_eq!(scratch_mod(7.0, -3.0), -2.0);
+        assert_eq!(scratch_mod(-7.0, -3.0), -1.0);
+        assert_eq!(scratch_mod(5.5, 2.0), 1.5);
+        assert_eq!(scratch_mod(-0.5, 1.0), 0.5);
+    }
+
+    #[test]
+    fn edge_cases_match_javascript() {
+        assert_eq!(scratch_mod(6.0, 3.0), 0.0);
+        assert_eq!(scratch_mod(5.0, f64::INFINITY), 5.0);
+        assert_eq!(scratch_mod(-5.0, f64::INFINITY), -5.0);
+        assert!(scratch_mod(5.0, 0.0).is_nan());
+        assert!(scratch_mod(f64::INFINITY, 3.0).is_nan());
+        assert!(scratch_mod(f64::NAN, 3.0).is_nan());
+    }
+}
@@ -0,0 +1,51 @@
+//! Text to speech for `speak`, which runs the first speech synthesizer that
+//! is installed. Like the Scratch block, it waits until the text has been
+//! spoken.
+
+use crate::{runtime_error, Cow};
+use std::{
+    io::{ErrorKind, Write},
+    process::{Command, Stdio},
+};
+
+/// The programs that are tried, in order. `say` comes with macOS. All of
+/// them read the text from stdin when they aren't given any, so text that
+/// starts with `-` isn't taken as an option.
+const SYNTHESIZERS: [&str; 3] = ["espeak-ng", "espeak", "say"];
+
+/// # Safety
+///
+/// `text` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn rt_speak(text: Cow) {
+    for synthesizer in SYNTHESIZERS {
+        match Command::new(synthesizer)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    let _ = stdin.write_all(text.as_bytes());
+                }
+                let _ = child.wait();
+                return;
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => {
+                let message =
+                    format!("`speak` could not run {synthesizer}: {err}");
+                runtime_error(Cow {
+                    ptr: message.as_ptr(),
+                    len: message.len(),
+                })
+            }
+        }
+    }
+    let message = "`speak` needs espeak-ng, espeak or say to be installed";
+    runtime_error(Cow {
+        ptr: message.as_ptr(),
+        len: message.len(),
+    })
+}
@@ -0,0 +1,54 @@
+use crate::rt_flush;
+use std::{
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// The lowest stack address a procedure may be entered with. Procedures
+/// check the stack pointer against it so that deep recursion ends with an
+/// error instead of overflowing the stack.
+#[export_name = "stack_limit"]
+pub static STACK_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// Room left below the limit for the frame of the procedure that checked it
+/// and the runtime functions that it calls.
+const RESERVED: usize = 64 * 1024;
+
+/// Calls `entry` on a new thread with a stack of `stack_size` bytes and
+/// returns its exit code.
+#[no_mangle]
+pub extern "C" fn run_with_stack(
+    entry: extern "C" fn() -> i32,
+    stack_size: usize,
+) -> i32 {
+    // Printed output is buffered, so it has to be flushed however the
+    // program exits.
+    // SAFETY: `rt_flush` can be called at any time.
+    unsafe { atexit(rt_flush) };
+    let stack_size = stack_size.max(2 * RESERVED);
+    let builder = thread::Builder::new().stack_size(stack_size);
+    let thread = builder.spawn(move || {
+        // The address of a local is close enough to the top of the stack.
+        let local = 0_u8;
+        let top = ptr::addr_of!(local) as usize;
+        STACK_LIMIT.store(
+            top.saturating_sub(stack_size) + RESERVED,
+            Ordering::Relaxed,
+        );
+        entry()
+    });
+    match thread {
+        Ok(thread) => thread.join().unwrap_or(101),
+        Err(err) => {
+            eprintln!(
+                "error: could not create a stack of {stack_size} bytes: {err}"
+            );
+            101
+        }
+    }
+}
+
+extern "C" {
+    fn atexit(function: extern "C" fn()) -> i32;
+}
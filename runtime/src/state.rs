@@ -0,0 +1,150 @@
+//! Loading save files written by `save-state`. Their values are read by the
+//! `state_read_*` functions of the prelude, while this walks the objects that
+//! hold them, so entries are matched by key and files from an older version
+//! of the program can still be loaded.
+
+use crate::{any_to_double, Any, Cow, List};
+
+/// A variable or list that the program saves, laid out like the tables that
+/// the compiler generates.
+#[repr(C)]
+pub struct SavedEntry {
+    key: Cow,
+    /// The address of an `Any`, or of a `List` for lists.
+    address: *mut u8,
+    /// Whether a variable holds an unboxed number.
+    is_num: bool,
+}
+
+/// Loads a save file into the `var_count` variables of `vars` and the
+/// `list_count` lists of `lists`. Entries that the program doesn't have are
+/// skipped and those that the file doesn't have are left alone.
+///
+/// # Safety
+///
+/// `file` must be open for reading, and the entries must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn state_load(
+    file: *mut u8,
+    vars: *const SavedEntry,
+    var_count: usize,
+    lists: *const SavedEntry,
+    list_count: usize,
+) {
+    let vars = std::slice::from_raw_parts(vars, var_count);
+    let lists = std::slice::from_raw_parts(lists, list_count);
+    read_object(file, |key| match key {
+        b"variables" => read_object(file, |key| match find(vars, key) {
+            Some(var) => load_var(file, var),
+            None => skip_value(file),
+        }),
+        b"lists" => read_object(file, |key| match find(lists, key) {
+            Some(list) => {
+                let list = &mut *list.address.cast::<List>();
+                list_delete_all(list);
+                state_read_list(file, list);
+            }
+            None => skip_value(file),
+        }),
+        _ => skip_value(file),
+    });
+}
+
+unsafe fn find<'a>(
+    entries: &'a [SavedEntry],
+    key: &[u8],
+) -> Option<&'a SavedEntry> {
+    entries.iter().find(|entry| entry.key.as_bytes() == key)
+}
+
+unsafe fn load_var(file: *mut u8, var: &SavedEntry) {
+    let value = state_read_any(file);
+    let address = var.address.cast::<Any>();
+    if var.is_num {
+        *address = Any {
+            low: 2,
+            high: any_to_double(value).to_bits(),
+        };
+    } else {
+        drop_value(*address);
+        *address = value;
+    }
+}
+
+/// Reads an object, calling `f` with every key to read its value. Anything
+/// that isn't an object is skipped instead.
+unsafe fn read_object(file: *mut u8, mut f: impl FnMut(&[u8])) {
+    let c = next_char(file);
+    if c != i32::from(b'{') {
+        skip_rest_of_value(file, c);
+        return;
+    }
+    loop {
+        let c = next_char(file);
+        if c == i32::from(b',') {
+            continue;
+        }
+        if c != i32::from(b'"') {
+            // Either the end of the object or of the file.
+            return;
+        }
+        let key = state_read_value(file, c).as_cow();
+        if next_char(file) != i32::from(b':') {
+            key.drop();
+            return;
+        }
+        f(key.as_bytes());
+        key.drop();
+    }
+}
+
+unsafe fn skip_value(file: *mut u8) {
+    let c = next_char(file);
+    skip_rest_of_value(file, c);
+}
+
+/// Skips the value starting with the already consumed character `c`.
+unsafe fn skip_rest_of_value(file: *mut u8, c: i32) {
+    if c == -1 {
+        return;
+    }
+    if c == i32::from(b'[') || c == i32::from(b'{') {
+        let mut depth = 1_usize;
+        while depth != 0 {
+            match u8::try_from(next_char(file)) {
+                Ok(b'[' | b'{') => depth += 1,
+                Ok(b']' | b'}') => depth -= 1,
+                Ok(c @ b'"') => drop_value(state_read_value(file, c.into())),
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+    } else {
+        drop_value(state_read_value(file, c));
+    }
+}
+
+/// Returns the next character that isn't whitespace, or -1 at the end of the
+/// file.
+unsafe fn next_char(file: *mut u8) -> i32 {
+    loop {
+        let c = fgetc(file);
+        if !matches!(u8::try_from(c), Ok(b' ' | b'\n' | b'\t' | b'\r')) {
+            return c;
+        }
+    }
+}
+
+unsafe fn drop_value(value: Any) {
+    if value.is_str() {
+        value.as_cow().drop();
+    }
+}
+
+extern "C" {
+    fn fgetc(stream: *mut u8) -> i32;
+    fn state_read_any(file: *mut u8) -> Any;
+    fn state_read_value(file: *mut u8, c: i32) -> Any;
+    fn state_read_list(file: *mut u8, list: &mut List);
+    fn list_delete_all(list: &mut List);
+}
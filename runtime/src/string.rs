@@ -0,0 +1,301 @@
+use crate::{
+    bool_to_str, double_to_char_index, format_number, malloc, Any, Cow,
+};
+use std::ptr;
+
+/// A static string, which is stored after a padding byte in an array with an
+/// alignment of two so that it gets an odd address.
+#[repr(C, align(2))]
+pub struct StaticStr<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> StaticStr<N> {
+    pub fn cow(&'static self) -> Cow {
+        Cow {
+            ptr: self.0.as_ptr().wrapping_add(1),
+            len: N - 1,
+        }
+    }
+}
+
+pub static EMPTY: StaticStr<1> = StaticStr(*b"\0");
+
+/// Copies `bytes` into a new owned string.
+///
+/// # Safety
+///
+/// Calls `malloc`.
+pub unsafe fn alloc_str(bytes: &[u8]) -> Cow {
+    let ptr = malloc(bytes.len());
+    ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+    Cow {
+        ptr,
+        len: bytes.len(),
+    }
+}
+
+/// Returns the number of characters in a string, counting every byte that
+/// doesn't continue a UTF-8 sequence.
+///
+/// # Safety
+///
+/// `s` must be a valid string.
+#[no_mangle]
+pub unsafe extern "C" fn str_length(s: Cow) -> usize {
+    char_count(s.as_bytes())
+}
+
+/// Like [`str_length`], but takes any value and borrows it, so a variable
+/// doesn't have to be cloned into a string first.
+///
+/// # Safety
+///
+/// `value` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn any_str_length(value: Any) -> usize {
+    with_any_bytes(value, char_count)
+}
+
+/// Returns the letter at `index` in a string, converting the index with
+/// [`double_to_char_index`]. The letter is empty if the index is out of
+/// bounds.
+///
+/// # Safety
+///
+/// `s` must be a valid string.
+#[no_mangle]
+pub unsafe extern "C" fn char_at(s: Cow, index: f64) -> Cow {
+    letter_at(s.as_bytes(), double_to_char_index(index))
+}
+
+/// Like [`char_at`], but with a one-based index that the compiler knows is a
+/// whole number, so it doesn't have to be converted.
+///
+/// # Safety
+///
+/// `s` must be a valid string.
+#[no_mangle]
+pub unsafe extern "C" fn char_at_index(s: Cow, index: usize) -> Cow {
+    letter_at(s.as_bytes(), index)
+}
+
+/// Like [`char_at`], but takes any value and borrows it.
+///
+/// # Safety
+///
+/// `value` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn any_char_at(value: Any, index: f64) -> Cow {
+    any_char_at_index(value, double_to_char_index(index))
+}
+
+/// Like [`any_char_at`], but with an index like [`char_at_index`].
+///
+/// # Safety
+///
+/// `value` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn any_char_at_index(value: Any, index: usize) -> Cow {
+    with_any_bytes(value, |bytes| letter_at(bytes, index))
+}
+
+fn char_count(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&byte| byte & 0xc0 != 0x80).count()
+}
+
+fn letter_at(bytes: &[u8], index: usize) -> Cow {
+    let Some(index) = index.checked_sub(1) else {
+        return EMPTY.cow();
+    };
+    let mut starts = bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, &byte)| byte & 0xc0 != 0x80)
+        .map(|(i, _)| i);
+    let Some(start) = starts.nth(index) else {
+        return EMPTY.cow();
+    };
+    let end = starts.next().unwrap_or(bytes.len());
+    // SAFETY: The letter is copied into a new allocation.
+    unsafe { alloc_str(&bytes[start..end]) }
+}
+
+/// Calls `f` with the bytes of a value converted to a string. Only numbers
+/// need a temporary allocation.
+///
+/// # Safety
+///
+/// `value` must be valid.
+pub(crate) unsafe fn with_any_bytes<T>(
+    value: Any,
+    f: impl FnOnce(&[u8]) -> T,
+) -> T {
+    match value.low {
+        0 | 1 => f(bool_to_str(value.low == 1).as_bytes()),
+        2 => f(format_number(f64::from_bits(value.high)).as_bytes()),
+        _ => f(value.as_cow().as_bytes()),
+    }
+}
+
+/// Compares a value to a string literal that isn't a number, which Scratch
+/// does as strings without regard to case. The compiler converts the literal
+/// to uppercase, so only the bytes of the value are converted here, and only
+/// ASCII letters are. The value is consumed.
+///
+/// # Safety
+///
+/// `value` and `literal` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn any_eq_folded_lit(value: Any, literal: Cow) -> bool {
+    let literal = literal.as_bytes();
+    let eq = with_any_bytes(value, |bytes| {
+        bytes.len() == literal.len()
+            && bytes
+                .iter()
+                .zip(literal)
+                .all(|(byte, folded)| byte.to_ascii_uppercase() == *folded)
+    });
+    if value.is_str() {
+        value.as_cow().drop();
+    }
+    eq
+}
+
+/// # Safety
+///
+/// `s` must be a valid string.
+#[no_mangle]
+pub unsafe extern "C" fn clone_cow(s: Cow) -> Cow {
+    if s.is_owned() {
+        alloc_str(s.as_bytes())
+    } else {
+        s
+    }
+}
+
+/// # Safety
+///
+/// `value` must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn clone_any(value: Any) -> Any {
+    if value.is_str() {
+        clone_cow(value.as_cow()).into()
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::double_to_cow;
+
+    const NUMBERS: &[f64] = &[0.0, -1.5, 0.1 + 0.2, 1e21, f64::NAN];
+
+    static TEST: StaticStr<7> = StaticStr(*b"\0h\xc3\xa9llo");
+
+    fn number(n: f64) -> Any {
+        Any {
+            low: 2,
+            high: n.to_bits(),
+        }
+    }
+
+    fn boolean(b: bool) -> Any {
+        Any {
+            low: u64::from(b),
+            high: 0,
+        }
+    }
+
+    /// Reads and drops a string.
+    fn take(s: Cow) -> String {
+        // SAFETY: The string is only read before being dropped.
+        unsafe {
+            let got = String::from_utf8_lossy(s.as_bytes()).into_owned();
+            s.drop();
+            got
+        }
+    }
+
+    #[test]
+    fn any_str_length_counts_converted_chars() {
+        for &n in NUMBERS {
+            let expected = take(double_to_cow(n)).chars().count();
+            // SAFETY: Numbers are always valid.
+            assert_eq!(unsafe { any_str_length(number(n)) }, expected, "{n}");
+        }
+        for b in [false, true] {
+            let expected = b.to_string().len();
+            // SAFETY: Booleans are always valid.
+            assert_eq!(unsafe { any_str_length(boolean(b)) }, expected);
+        }
+        // SAFETY: Static strings are always valid.
+        let got = unsafe { any_str_length(TEST.cow().into()) };
+        assert_eq!(got, 5);
+    }
+
+    #[test]
+    fn any_char_at_matches_char_at() {
+        let s = "héllo 1.5";
+        // SAFETY: The string was just allocated.
+        let value = Any::from(unsafe { alloc_str(s.as_bytes()) });
+        for index in [0.0, 1.0, 2.0, 2.9, 9.0, 10.0, f64::NAN] {
+            // SAFETY: The value is only borrowed until it is dropped below.
+            let got = take(unsafe { any_char_at(value, index) });
+            let expected = take(unsafe { char_at(value.as_cow(), index) });
+            assert_eq!(got, expected, "{index}");
+        }
+        for &n in NUMBERS {
+            // SAFETY: Numbers are always valid.
+            let got = take(unsafe { any_char_at(number(n), 1.0) });
+            let expected = take(double_to_cow(n));
+            assert!(expected.starts_with(&got), "{n}");
+        }
+        // SAFETY: The value isn't used anymore.
+        unsafe { value.as_cow().drop() };
+    }
+
+    #[test]
+    fn char_at_below_one_is_empty() {
+        // SAFETY: Static strings are always valid.
+        let s = TEST.cow();
+        for index in [0.0, 0.5, 0.999, -0.5] {
+            // SAFETY: The string is only borrowed.
+            assert_eq!(take(unsafe { char_at(s, index) }), "", "{index}");
+        }
+        // SAFETY: The string is only borrowed.
+        assert_eq!(take(unsafe { char_at(s, 1.5) }), "h");
+    }
+
+    #[test]
+    fn char_at_index_matches_char_at() {
+        // SAFETY: Static strings are always valid.
+        let s = TEST.cow();
+        for index in 0..8 {
+            // SAFETY: The string is only borrowed.
+            let got = take(unsafe { char_at_index(s, index) });
+            // SAFETY: The string is only borrowed.
+            let expected = take(unsafe { char_at(s, index as f64) });
+            assert_eq!(got, expected, "{index}");
+        }
+    }
+
+    #[test]
+    fn any_eq_folded_lit_ignores_case() {
+        static HELLO: StaticStr<6> = StaticStr(*b"\0HELLO");
+        static TRUE: StaticStr<5> = StaticStr(*b"\0TRUE");
+        static INFINITY: StaticStr<9> = StaticStr(*b"\0INFINITY");
+        // SAFETY: The strings are allocated here and consumed by the calls.
+        unsafe {
+            assert!(any_eq_folded_lit(alloc_str(b"hElLo").into(), HELLO.cow()));
+            assert!(!any_eq_folded_lit(alloc_str(b"hell").into(), HELLO.cow()));
+        }
+        // SAFETY: Booleans, numbers and static strings are always valid.
+        unsafe {
+            assert!(any_eq_folded_lit(boolean(true), TRUE.cow()));
+            assert!(!any_eq_folded_lit(boolean(false), TRUE.cow()));
+            assert!(any_eq_folded_lit(number(f64::INFINITY), INFINITY.cow()));
+            assert!(!any_eq_folded_lit(number(1.0), INFINITY.cow()));
+        }
+    }
+}
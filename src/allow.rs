@@ -0,0 +1,70 @@
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result, Warning},
+};
+use codemap::Span;
+
+/// Warnings turned off with `(allow name...)`, which applies to everything in
+/// the node it appears in, or to the whole file when it is a top level item.
+#[derive(Default)]
+pub struct Allowed {
+    regions: Vec<(Span, String)>,
+}
+
+impl Allowed {
+    /// Removes the `allow` forms from the items of a file and remembers them.
+    /// Returns warnings for names that aren't warnings.
+    pub fn collect(
+        &mut self,
+        file: Span,
+        asts: &mut Vec<Ast>,
+    ) -> Result<Vec<Warning>> {
+        let mut unknown = Vec::new();
+        self.collect_in(file, asts, &mut unknown)?;
+        Ok(unknown)
+    }
+
+    fn collect_in(
+        &mut self,
+        region: Span,
+        asts: &mut Vec<Ast>,
+        unknown: &mut Vec<Warning>,
+    ) -> Result<()> {
+        for allow in
+            asts.extract_if(.., |ast| ast.is_the_function_call("allow"))
+        {
+            let Ast::Node(_, names, _) = allow else {
+                unreachable!()
+            };
+            for name in names {
+                let Ast::Sym(name, span) = name else {
+                    return Err(Box::new(Error::InvalidAllow {
+                        span: name.span(),
+                    }));
+                };
+                if !Warning::NAMES.contains(&&*name) {
+                    unknown.push(Warning::UnknownWarning {
+                        span,
+                        name: name.clone(),
+                    });
+                }
+                self.regions.push((region, name));
+            }
+        }
+        for ast in asts {
+            if let Ast::Node(_, tail, span) = ast {
+                self.collect_in(*span, tail, unknown)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, warning: &Warning) -> bool {
+        let span = warning.span();
+        self.regions.iter().any(|(region, name)| {
+            name == warning.name()
+                && region.low() <= span.low()
+                && span.high() <= region.high()
+        })
+    }
+}
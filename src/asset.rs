@@ -1,5 +1,9 @@
+use crate::diagnostic::{Error, Result};
+use codemap::Span;
 use serde::Serialize;
-use std::{fs::File, io::Read, path::Path};
+use std::{fs, path::Path};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -8,22 +12,79 @@ pub struct Asset {
     name: String,
     pub md5ext: String,
     data_format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rotation_center_x: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rotation_center_y: Option<u32>,
+    /// The contents of the file, which are stored in the sb3 file under
+    /// `md5ext`.
+    #[serde(skip)]
+    pub data: Vec<u8>,
 }
 
 impl Asset {
-    pub fn new(name: &str, path: &Path) -> Self {
-        // TODO: Error handling
-        let mut file = File::open(path).unwrap();
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf).unwrap();
-        let md5_sum = md5::compute(&buf);
-        let extension = path.extension().unwrap().to_str().unwrap().to_owned();
+    /// Reads a costume and checks that it is a PNG or SVG image, since those
+    /// are the formats Scratch can load. `span` is where the path was given.
+    pub fn new(name: &str, path: &Path, span: Span) -> Result<Self> {
+        let data =
+            fs::read(path).map_err(|inner| Error::CouldNotReadCostume {
+                span,
+                path: path.to_owned(),
+                inner,
+            })?;
+        let invalid = |problem| {
+            Box::new(Error::InvalidCostume {
+                span,
+                path: path.to_owned(),
+                problem,
+            })
+        };
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let (data_format, center) = match extension.as_deref() {
+            Some("png") => {
+                let size = png_size(&data)
+                    .ok_or_else(|| invalid("the file is not a PNG image"))?;
+                ("png", Some(size))
+            }
+            Some("svg") => {
+                if !is_svg(&data) {
+                    return Err(invalid("the file is not an SVG image"));
+                }
+                ("svg", None)
+            }
+            _ => {
+                return Err(invalid("costumes must be `.png` or `.svg` images"))
+            }
+        };
+        let md5_sum = md5::compute(&data);
 
-        Self {
+        Ok(Self {
             asset_id: format!("{md5_sum:x}"),
             name: name.to_owned(),
-            md5ext: format!("{md5_sum:x}.{extension}"),
-            data_format: extension,
-        }
+            md5ext: format!("{md5_sum:x}.{data_format}"),
+            data_format: data_format.to_owned(),
+            rotation_center_x: center.map(|(width, _)| width / 2),
+            rotation_center_y: center.map(|(_, height)| height / 2),
+            data,
+        })
     }
 }
+
+/// Reads the width and height from the header of a PNG image.
+fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    let rest = data.strip_prefix(PNG_SIGNATURE)?;
+    // The first chunk is always `IHDR`, which starts with the size.
+    let (_length, rest) = rest.split_first_chunk::<4>()?;
+    let (kind, rest) = rest.split_first_chunk::<4>()?;
+    let (width, rest) = rest.split_first_chunk::<4>()?;
+    let (height, _) = rest.split_first_chunk::<4>()?;
+    (kind == b"IHDR")
+        .then(|| (u32::from_be_bytes(*width), u32::from_be_bytes(*height)))
+}
+
+fn is_svg(data: &[u8]) -> bool {
+    std::str::from_utf8(data).is_ok_and(|text| text.contains("<svg"))
+}
@@ -0,0 +1,340 @@
+use crate::{
+    diagnostic::{Error, Result, Warning},
+    ir::{
+        expr::Expr, proc::Procedure, sprite::Sprite, statement::Statement,
+        Program,
+    },
+};
+use codemap::Span;
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+};
+
+/// Finds reads of variables and lists that nothing ever assigns to or adds
+/// items to, which always read the initial state: 0 for variables and nothing
+/// for lists. Each of them is reported once, at its first read. In strict mode
+/// the first one is an error. Nothing is reported if the program contains
+/// `load-state` or `asm`, since those can change any variable.
+pub fn check(program: &Program, strict: bool) -> Result<Vec<Warning>> {
+    let walker = walk(program);
+    if walker.opaque {
+        return Ok(Vec::new());
+    }
+
+    let mut unassigned = walker
+        .reads
+        .into_iter()
+        .filter(|(item, _)| !walker.assigned.contains(item))
+        .map(|((_, kind, name), span)| (span, kind, name))
+        .collect::<Vec<_>>();
+    unassigned.sort_by_key(|&(span, ..)| span);
+    if strict {
+        if let Some(&(span, kind, name)) = unassigned.first() {
+            return Err(Box::new(Error::NeverAssigned {
+                span,
+                name: name.to_owned(),
+                kind: kind.to_str(),
+            }));
+        }
+    }
+    Ok(unassigned
+        .into_iter()
+        .map(|(span, kind, name)| Warning::NeverAssigned {
+            span,
+            name: name.to_owned(),
+            kind: kind.to_str(),
+        })
+        .collect())
+}
+
+/// Finds lists that items are added to but that are never read, for the
+/// `lint` subcommand. Each of them is reported once, where items are first
+/// added to it. Nothing is reported if the program contains `save-state`,
+/// which reads every list, or `load-state` or `asm`.
+pub fn unused_lists(program: &Program) -> Vec<Warning> {
+    let walker = walk(program);
+    if walker.opaque || walker.saved {
+        return Vec::new();
+    }
+    let mut unused = walker
+        .added
+        .into_iter()
+        .filter(|item| !walker.reads.contains_key(&item.0))
+        .collect::<Vec<_>>();
+    unused.sort_by_key(|&(_, span)| span);
+    unused
+        .into_iter()
+        .map(|((_, _, name), span)| Warning::UnusedListItems {
+            span,
+            name: name.to_owned(),
+        })
+        .collect()
+}
+
+fn walk(program: &Program) -> Walker<'_> {
+    let mut walker = Walker::default();
+    let sprites = iter::once(("Stage", &program.stage)).chain(
+        program
+            .sprites
+            .iter()
+            .map(|(name, sprite)| (&**name, sprite)),
+    );
+    for (sprite_name, sprite) in sprites {
+        let scope = if sprite_name == "Stage" {
+            Scope::Global
+        } else {
+            Scope::Sprite(sprite_name)
+        };
+        walker.initialized(scope, sprite);
+        for (proc_name, procs) in &sprite.procedures {
+            for (index, proc) in procs.iter().enumerate() {
+                let local = Scope::Local(sprite_name, proc_name, index);
+                walker.initialized_locals(local, proc);
+                walker.proc = Some(Resolver {
+                    stage: &program.stage,
+                    sprite,
+                    sprite_scope: scope,
+                    proc,
+                    local,
+                });
+                walker.stmt(&proc.body);
+            }
+        }
+    }
+    walker
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Scope<'a> {
+    Global,
+    Sprite(&'a str),
+    /// The locals of a procedure, by sprite, name and index among the
+    /// procedures with that name.
+    Local(&'a str, &'a str, usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Variable,
+    List,
+}
+
+impl Kind {
+    const fn to_str(self) -> &'static str {
+        match self {
+            Self::Variable => "variable",
+            Self::List => "list",
+        }
+    }
+}
+
+type Item<'a> = (Scope<'a>, Kind, &'a str);
+
+/// Finds what a name refers to in a procedure.
+struct Resolver<'a> {
+    stage: &'a Sprite,
+    sprite: &'a Sprite,
+    sprite_scope: Scope<'a>,
+    proc: &'a Procedure,
+    local: Scope<'a>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&self, name: &'a str, kind: Kind) -> Option<Item<'a>> {
+        let is_param =
+            self.proc.params.iter().any(
+                |(param, _)| matches!(param, Expr::Sym(p, _) if p == name),
+            );
+        if is_param {
+            return None;
+        }
+        let (locals, sprite, stage) = match kind {
+            Kind::Variable => (
+                &self.proc.variables,
+                &self.sprite.variables,
+                &self.stage.variables,
+            ),
+            Kind::List => {
+                (&self.proc.lists, &self.sprite.lists, &self.stage.lists)
+            }
+        };
+        let scope = if locals.contains(name) {
+            self.local
+        } else if sprite.contains(name) {
+            self.sprite_scope
+        } else if stage.contains(name) {
+            Scope::Global
+        } else {
+            return None;
+        };
+        Some((scope, kind, name))
+    }
+
+    /// Resolves a symbol that is used as a value, which is a variable if there
+    /// is one with that name and otherwise a list.
+    fn resolve_any(&self, name: &'a str) -> Option<Item<'a>> {
+        self.resolve(name, Kind::Variable)
+            .or_else(|| self.resolve(name, Kind::List))
+    }
+}
+
+#[derive(Default)]
+struct Walker<'a> {
+    proc: Option<Resolver<'a>>,
+    assigned: HashSet<Item<'a>>,
+    /// The first read of every variable and list.
+    reads: HashMap<Item<'a>, Span>,
+    /// Where items are first added to every list that statements add to.
+    added: HashMap<Item<'a>, Span>,
+    /// Whether something that can change any variable was found.
+    opaque: bool,
+    /// Whether `save-state` was found, which reads every variable and list.
+    saved: bool,
+}
+
+impl<'a> Walker<'a> {
+    fn initialized(&mut self, scope: Scope<'a>, sprite: &'a Sprite) {
+        self.assigned.extend(
+            sprite
+                .initial_values
+                .keys()
+                .map(|name| (scope, Kind::Variable, &**name)),
+        );
+        self.assigned.extend(
+            sprite
+                .initial_items
+                .keys()
+                .map(|name| (scope, Kind::List, &**name)),
+        );
+    }
+
+    fn initialized_locals(&mut self, scope: Scope<'a>, proc: &'a Procedure) {
+        self.assigned.extend(
+            proc.initial_values
+                .keys()
+                .map(|name| (scope, Kind::Variable, &**name)),
+        );
+        self.assigned.extend(
+            proc.initial_items
+                .keys()
+                .map(|name| (scope, Kind::List, &**name)),
+        );
+    }
+
+    fn assign(&mut self, name: &'a str, kind: Kind, span: Span) {
+        if let Some(item) = self.proc.as_ref().unwrap().resolve(name, kind) {
+            self.assigned.insert(item);
+            if kind == Kind::List {
+                self.added
+                    .entry(item)
+                    .and_modify(|first| *first = (*first).min(span))
+                    .or_insert(span);
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a Statement) {
+        match stmt {
+            Statement::ProcCall {
+                proc_name, args, ..
+            } => {
+                // The arguments that are assigned to, and what they are. The
+                // lists of `replace` and `delete` are neither read nor
+                // assigned to since those can't make an empty list hold
+                // anything.
+                let (targets, kind) = match &**proc_name {
+                    ":=" | "+=" => (0..1, Some(Kind::Variable)),
+                    "append" => (0..1, Some(Kind::List)),
+                    "json-parse-into-lists" => (1..3, Some(Kind::List)),
+                    "replace" | "delete" | "delete-all" => (0..1, None),
+                    "load-state" | "asm" => {
+                        self.opaque = true;
+                        (0..0, None)
+                    }
+                    "save-state" => {
+                        self.saved = true;
+                        (0..0, None)
+                    }
+                    _ => (0..0, None),
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    match arg {
+                        Expr::Sym(name, span) if targets.contains(&i) => {
+                            if let Some(kind) = kind {
+                                self.assign(name, kind, *span);
+                            }
+                        }
+                        _ => self.expr(arg),
+                    }
+                }
+            }
+            Statement::Do(stmts) => {
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+            }
+            Statement::IfElse {
+                condition,
+                then,
+                else_,
+                ..
+            } => {
+                self.expr(condition);
+                self.stmt(then);
+                self.stmt(else_);
+            }
+            Statement::For {
+                counter: (name, span),
+                times,
+                body,
+            } => {
+                self.assign(name, Kind::Variable, *span);
+                self.expr(times);
+                self.stmt(body);
+            }
+            Statement::Repeat {
+                times: expr, body, ..
+            }
+            | Statement::Until {
+                condition: expr,
+                body,
+                ..
+            }
+            | Statement::While {
+                condition: expr,
+                body,
+                ..
+            } => {
+                self.expr(expr);
+                self.stmt(body);
+            }
+            Statement::Forever(body, _) => self.stmt(body),
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Imm(_) => {}
+            Expr::Sym(name, span) => {
+                let item = self.proc.as_ref().unwrap().resolve_any(name);
+                if let Some(item) = item {
+                    self.reads
+                        .entry(item)
+                        .and_modify(|first| *first = (*first).min(*span))
+                        .or_insert(*span);
+                }
+            }
+            Expr::FuncCall(_, _, args) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::AddSub(a, b, _) | Expr::MulDiv(a, b, _) => {
+                for term in a.iter().chain(b) {
+                    self.expr(term);
+                }
+            }
+        }
+    }
+}
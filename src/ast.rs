@@ -1,4 +1,5 @@
 use codemap::Span;
+use std::{fmt, iter};
 
 #[derive(Debug, Clone)]
 pub enum Ast {
@@ -6,13 +7,21 @@ pub enum Ast {
     Bool(bool, Span),
     String(String, Span),
     Sym(String, Span),
+    /// A color like `#ff8000`, holding the hex digits.
+    Color(String, Span),
     Node(Box<Self>, Vec<Self>, Span),
     Unquote(Box<Self>, Span),
+    /// Code as data, which macros are not expanded in.
+    Quote(Box<Self>, Span),
 }
 
 impl Ast {
+    pub fn is_sym(&self, name: &str) -> bool {
+        matches!(self, Self::Sym(sym, _) if sym == name)
+    }
+
     pub fn is_the_function_call(&self, func_name: &str) -> bool {
-        matches!(self, Self::Node(box Self::Sym(sym, ..), ..) if sym == func_name)
+        matches!(self, Self::Node(head, ..) if head.is_sym(func_name))
     }
 
     pub const fn span(&self) -> Span {
@@ -21,8 +30,10 @@ impl Ast {
             | Self::Bool(_, span)
             | Self::String(_, span)
             | Self::Sym(_, span)
+            | Self::Color(_, span)
             | Self::Node(_, _, span)
-            | Self::Unquote(_, span) => span,
+            | Self::Unquote(_, span)
+            | Self::Quote(_, span) => span,
         }
     }
 
@@ -34,7 +45,9 @@ impl Ast {
             Self::Num(..)
             | Self::Bool(..)
             | Self::String(..)
-            | Self::Sym(..) => {}
+            | Self::Sym(..)
+            | Self::Color(..)
+            | Self::Quote(..) => {}
             Self::Node(head, tail, _) => {
                 head.traverse_postorder_mut(f)?;
                 for branch in tail {
@@ -45,13 +58,149 @@ impl Ast {
         }
         f(self)
     }
+
+    /// A list, quote or unquote that is nested more than `limit` deep, if
+    /// there is one. This uses a worklist instead of recursing, since it is
+    /// what stops the recursive passes from overflowing the stack.
+    pub fn too_deep(&self, limit: usize) -> Option<Span> {
+        let mut worklist = vec![(self, 1)];
+        while let Some((ast, depth)) = worklist.pop() {
+            match ast {
+                Self::Num(..)
+                | Self::Bool(..)
+                | Self::String(..)
+                | Self::Sym(..)
+                | Self::Color(..) => {}
+                Self::Node(_, _, span)
+                | Self::Unquote(_, span)
+                | Self::Quote(_, span)
+                    if depth > limit =>
+                {
+                    return Some(*span)
+                }
+                Self::Node(head, tail, _) => worklist.extend(
+                    iter::once(&**head)
+                        .chain(tail)
+                        .map(|branch| (branch, depth + 1)),
+                ),
+                Self::Unquote(inner, _) | Self::Quote(inner, _) => {
+                    worklist.push((inner, depth + 1));
+                }
+            }
+        }
+        None
+    }
 }
 
-pub fn all_symbols(asts: Vec<Ast>) -> Result<Vec<String>, Ast> {
-    asts.into_iter()
-        .map(|ast| match ast {
-            Ast::Sym(sym, ..) => Ok(sym),
-            _ => Err(ast),
-        })
-        .collect()
+/// A pass that looks at every node of an AST. `visit` is called for each node
+/// and goes on to its children with [`walk`] by default, so a pass only
+/// overrides it to look at the nodes it cares about and calls `walk` to go on.
+pub trait Visit {
+    fn visit(&mut self, ast: &Ast) {
+        walk(self, ast);
+    }
+}
+
+/// Visits the children of a node.
+pub fn walk<V: Visit + ?Sized>(visitor: &mut V, ast: &Ast) {
+    match ast {
+        Ast::Num(..)
+        | Ast::Bool(..)
+        | Ast::String(..)
+        | Ast::Sym(..)
+        | Ast::Color(..) => {}
+        Ast::Node(head, tail, _) => {
+            visitor.visit(head);
+            for branch in tail {
+                visitor.visit(branch);
+            }
+        }
+        Ast::Unquote(inner, _) | Ast::Quote(inner, _) => visitor.visit(inner),
+    }
+}
+
+/// A pass that rebuilds an AST and can fail. `fold` is called for each node
+/// and rebuilds it from its folded children with [`fold_children`] by
+/// default, so a pass only overrides it to replace the nodes it cares about.
+pub trait Fold {
+    type Error;
+
+    fn fold(&mut self, ast: Ast) -> Result<Ast, Self::Error> {
+        fold_children(self, ast)
+    }
+}
+
+/// Rebuilds a node from its folded children.
+pub fn fold_children<F: Fold + ?Sized>(
+    folder: &mut F,
+    ast: Ast,
+) -> Result<Ast, F::Error> {
+    Ok(match ast {
+        Ast::Num(..)
+        | Ast::Bool(..)
+        | Ast::String(..)
+        | Ast::Sym(..)
+        | Ast::Color(..) => ast,
+        Ast::Node(mut head, tail, span) => {
+            *head = folder.fold(*head)?;
+            Ast::Node(
+                head,
+                tail.into_iter()
+                    .map(|branch| folder.fold(branch))
+                    .collect::<Result<_, _>>()?,
+                span,
+            )
+        }
+        Ast::Unquote(mut inner, span) => {
+            *inner = folder.fold(*inner)?;
+            Ast::Unquote(inner, span)
+        }
+        Ast::Quote(mut inner, span) => {
+            *inner = folder.fold(*inner)?;
+            Ast::Quote(inner, span)
+        }
+    })
+}
+
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Num(num, _) => write!(f, "{num}"),
+            Self::Bool(b, _) => write!(f, "{b}"),
+            Self::String(s, _) => write!(f, "{s:?}"),
+            Self::Sym(sym, _) => f.write_str(sym),
+            Self::Color(digits, _) => write!(f, "#{digits}"),
+            Self::Node(head, tail, _) => {
+                write!(f, "({head}")?;
+                for branch in tail {
+                    write!(f, " {branch}")?;
+                }
+                f.write_str(")")
+            }
+            Self::Unquote(unquoted, _) => write!(f, ",{unquoted}"),
+            Self::Quote(quoted, _) => write!(f, "'{quoted}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codemap::CodeMap;
+
+    #[test]
+    fn quote_chains_are_too_deep() {
+        let mut code_map = CodeMap::new();
+        let span = code_map.add_file(String::new(), " ".repeat(300)).span;
+        let chain = (0..300).fold(Ast::Sym("x".to_owned(), span), |ast, i| {
+            let span = span.subspan(i, i + 1);
+            if i % 2 == 0 {
+                Ast::Quote(Box::new(ast), span)
+            } else {
+                Ast::Unquote(Box::new(ast), span)
+            }
+        });
+        assert_eq!(chain.too_deep(300), None);
+        assert_eq!(chain.too_deep(256), Some(span.subspan(43, 44)));
+    }
 }
@@ -1,18 +1,302 @@
+mod cfg;
 mod sb3;
+mod stamp;
 mod x86_64;
 
+pub use sb3::{load_extensions, Sb3Extension};
+pub use stamp::Stamp;
+
 use crate::{
-    diagnostic::Result,
+    diagnostic::{Error, Result},
     ir::Program,
-    opts::{Opts, Target},
+    opts::{Artifact, Opts, Profile, Target},
+};
+use codemap::CodeMap;
+use std::{
+    collections::hash_map::RandomState,
+    env, fs,
+    hash::{BuildHasher, Hasher},
+    io,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    time::{SystemTime, UNIX_EPOCH},
 };
-use std::path::Path;
 
-pub fn write_program(program: &Program, opts: &Opts) -> Result<()> {
+pub fn write_program(
+    program: &Program,
+    opts: &Opts,
+    code_map: &CodeMap,
+    stamp: Option<&Stamp>,
+    extensions: &[Box<dyn Sb3Extension>],
+) -> Result<()> {
+    let artifacts = opts.artifacts();
+    if let Some(unsupported) = artifacts
+        .iter()
+        .find(|artifact| !artifact.is_supported_by(opts.target))
+    {
+        return Err(Box::new(Error::ArtifactNotSupportedByTarget {
+            artifact: unsupported.to_str(),
+            target: opts.target.to_str(),
+        }));
+    }
+    let wants = |artifact| artifacts.contains(&artifact);
+    let out = |artifact: Artifact| opts.out_dir.join(artifact.file_name());
+
+    fs::create_dir_all(&opts.out_dir).map_err(|err| {
+        Error::CouldNotWriteFile {
+            path: opts.out_dir.clone(),
+            inner: err,
+        }
+    })?;
+
+    if wants(Artifact::Ir) {
+        write_file(&out(Artifact::Ir), format!("{program:#?}\n"))?;
+    }
+
+    if wants(Artifact::Cfg) {
+        write_file(&out(Artifact::Cfg), cfg::program_to_dot(program))?;
+    }
+
     match opts.target {
-        Target::SB3 => sb3::write_sb3_file(program, Path::new("project.sb3")),
+        Target::SB3 => {
+            if wants(Artifact::Sb3) {
+                sb3::write_sb3_file(
+                    program,
+                    &out(Artifact::Sb3),
+                    opts,
+                    stamp,
+                    extensions,
+                )?;
+            }
+        }
         Target::X86_64 => {
-            x86_64::write_object_file(program, Path::new("project.o"))
+            if !(wants(Artifact::Obj)
+                || wants(Artifact::Exe)
+                || wants(Artifact::Asm)
+                || wants(Artifact::Symbols))
+            {
+                return Ok(());
+            }
+            // Intermediate files go in a temporary directory so that files
+            // the user already has in the output directory are left alone.
+            let temp_dir = TempDir::new()?;
+            let object = if wants(Artifact::Obj) {
+                out(Artifact::Obj)
+            } else {
+                temp_dir.join(Artifact::Obj.file_name())
+            };
+            let output = x86_64::compile(
+                program,
+                code_map,
+                opts,
+                wants(Artifact::Asm) && !opts.annotate_asm,
+            )?;
+            let asm = match &output.source_map {
+                Some(source_map) if wants(Artifact::Asm) => {
+                    // Only the object file has the final addresses.
+                    write_file(&object, &output.object)?;
+                    let disassembly = tool_output(
+                        Command::new("objdump")
+                            .args(["-d", "-r", "--no-show-raw-insn"])
+                            .args(["-M", "intel"])
+                            .arg(&object),
+                    )?;
+                    Some(x86_64::annotate(&disassembly, source_map, code_map))
+                }
+                _ => output.asm,
+            };
+            if let Some(mut asm) = asm {
+                if let Some(stamp) = stamp {
+                    asm.insert_str(0, &stamp.asm_comment());
+                }
+                write_file(&out(Artifact::Asm), asm)?;
+            }
+            if wants(Artifact::Symbols) {
+                write_file(&out(Artifact::Symbols), output.symbols)?;
+            }
+            if wants(Artifact::Obj) || wants(Artifact::Exe) {
+                write_file(&object, output.object)?;
+            }
+            if wants(Artifact::Obj) {
+                if let Some(inline_asm) = &output.inline_asm {
+                    // The object file can't be linked without these.
+                    write_file(
+                        &opts.out_dir.join("project-inline.s"),
+                        inline_asm,
+                    )?;
+                }
+            }
+            if wants(Artifact::Obj) && opts.profile == Profile::Debug {
+                // Debug builds also have to be linked with `--wrap` for the
+                // allocation functions.
+                write_file(
+                    &opts.out_dir.join("leak-check.s"),
+                    x86_64::LEAK_CHECK,
+                )?;
+            }
+            if wants(Artifact::Exe) {
+                link_executable(
+                    &object,
+                    &out(Artifact::Exe),
+                    &temp_dir,
+                    output.inline_asm.as_deref(),
+                    opts.profile,
+                    stamp,
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    fs::write(path, contents).map_err(|err| {
+        Box::new(Error::CouldNotWriteFile {
+            path: path.to_owned(),
+            inner: err,
+        })
+    })
+}
+
+fn link_executable(
+    object: &Path,
+    exe: &Path,
+    temp_dir: &TempDir,
+    inline_asm: Option<&str>,
+    profile: Profile,
+    stamp: Option<&Stamp>,
+) -> Result<()> {
+    let prelude_source = temp_dir.join("prelude.s");
+    let prelude_object = temp_dir.join("prelude.o");
+    let runtime = temp_dir.join("libruntime.a");
+    let mut prelude = x86_64::PRELUDE.to_owned();
+    if let Some(inline_asm) = inline_asm {
+        prelude.push_str(inline_asm);
+    }
+    if profile == Profile::Debug {
+        prelude.push_str(x86_64::LEAK_CHECK);
+    }
+    if let Some(stamp) = stamp {
+        prelude.push_str(&stamp.elf_note());
+    }
+    write_file(&prelude_source, prelude)?;
+    write_file(&runtime, x86_64::RUNTIME)?;
+    run_tool(
+        Command::new("nasm")
+            .arg("-felf64")
+            .args(match profile {
+                Profile::Debug => &["-dCHECK_ALIGNMENT"][..],
+                Profile::Release => &[],
+            })
+            .arg(&prelude_source)
+            .arg("-o")
+            .arg(&prelude_object),
+    )?;
+    run_tool(
+        Command::new("cc")
+            .arg(object)
+            .arg(&prelude_object)
+            .arg(&runtime)
+            // The native libraries needed by the Rust standard library.
+            .args(["-lgcc_s", "-lutil", "-lrt", "-lpthread", "-lm", "-ldl"])
+            .args(match profile {
+                Profile::Debug => &[
+                    "-Wl,--wrap=malloc",
+                    "-Wl,--wrap=calloc",
+                    "-Wl,--wrap=realloc",
+                    "-Wl,--wrap=free",
+                ][..],
+                Profile::Release => &[],
+            })
+            .arg("-o")
+            .arg(exe),
+    )?;
+    Ok(())
+}
+
+/// A directory for intermediate files, which is removed with everything in
+/// it when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a directory with a random name that only the current user can
+    /// access. This fails if the name is already taken instead of using what
+    /// is there, since anyone could have created it in a shared `/tmp`.
+    fn new() -> Result<Self> {
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        let mut attempts = 0;
+        loop {
+            let path = env::temp_dir()
+                .join(format!("scratch-compiler-{:016x}", random_suffix()));
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(err)
+                    if err.kind() == io::ErrorKind::AlreadyExists
+                        && attempts < 100 =>
+                {
+                    attempts += 1;
+                }
+                Err(err) => {
+                    return Err(Box::new(Error::CouldNotWriteFile {
+                        path,
+                        inner: err,
+                    }))
+                }
+            }
         }
     }
+
+    fn join(&self, file_name: &str) -> PathBuf {
+        self.0.join(file_name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A number that another process can't guess, from the random keys of the
+/// standard library's hash maps.
+fn random_suffix() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(process::id());
+    if let Ok(elapsed) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    hasher.finish()
+}
+
+fn run_tool(command: &mut Command) -> Result<()> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let status = command.status().map_err(|err| Error::CouldNotRunTool {
+        tool: tool.clone(),
+        inner: err,
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Box::new(Error::ToolFailed { tool, status }))
+    }
+}
+
+/// Like `run_tool`, but returns what the tool printed.
+fn tool_output(command: &mut Command) -> Result<String> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|err| Error::CouldNotRunTool {
+        tool: tool.clone(),
+        inner: err,
+    })?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(Box::new(Error::ToolFailed {
+            tool,
+            status: output.status,
+        }))
+    }
 }
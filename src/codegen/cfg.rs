@@ -0,0 +1,259 @@
+//! Control flow graphs of the procedures in the DOT format of Graphviz, for
+//! `--emit cfg`. Statements that run one after another are grouped into
+//! basic blocks, and blocks that end in a branch or a loop end with its
+//! condition.
+
+use crate::ir::{expr::Expr, statement::Statement, Program};
+use sb3_stuff::Value;
+use std::{fmt::Write, iter, mem};
+
+/// A graph with a cluster for every procedure.
+pub fn program_to_dot(program: &Program) -> String {
+    let mut graph = Graph {
+        dot: String::from(
+            "digraph cfg {\n  node [shape=box, fontname=monospace];\n",
+        ),
+        nodes: 0,
+        clusters: 0,
+    };
+    let sprites = iter::once(("Stage", &program.stage)).chain(
+        program
+            .sprites
+            .iter()
+            .map(|(name, sprite)| (&**name, sprite)),
+    );
+    for (sprite_name, sprite) in sprites {
+        for (proc_name, procs) in &sprite.procedures {
+            for proc in procs {
+                graph.procedure(
+                    &format!("{sprite_name}: {proc_name}"),
+                    &proc.body,
+                );
+            }
+        }
+    }
+    graph.dot.push_str("}\n");
+    graph.dot
+}
+
+struct Graph {
+    dot: String,
+    nodes: usize,
+    clusters: usize,
+}
+
+/// The basic block being built and the edges that lead into it, with their
+/// labels.
+struct Block {
+    lines: Vec<String>,
+    preds: Vec<(usize, Option<&'static str>)>,
+    /// The blocks that end the procedure early with a `stop` block.
+    stops: Vec<usize>,
+}
+
+impl Graph {
+    fn procedure(&mut self, name: &str, body: &Statement) {
+        writeln!(
+            self.dot,
+            "  subgraph cluster_{} {{\n    label=\"{}\";",
+            self.clusters,
+            escape(name),
+        )
+        .unwrap();
+        self.clusters += 1;
+        let entry = self.node("oval", "start");
+        let mut block = Block {
+            lines: Vec::new(),
+            preds: vec![(entry, None)],
+            stops: Vec::new(),
+        };
+        self.statement(body, &mut block);
+        self.finish_block(&mut block);
+        let exit = self.node("oval", "end");
+        self.connect(&mut block, exit);
+        for stop in block.stops {
+            writeln!(self.dot, "    n{stop} -> n{exit};").unwrap();
+        }
+        self.dot.push_str("  }\n");
+    }
+
+    fn statement(&mut self, stmt: &Statement, block: &mut Block) {
+        match stmt {
+            Statement::ProcCall {
+                proc_name, args, ..
+            } => {
+                block.lines.push(call_label(proc_name, args));
+                if matches!(&**proc_name, "stop-this-script" | "stop-all") {
+                    // Nothing after this runs.
+                    let stop = self.finish_block(block).unwrap();
+                    block.preds.clear();
+                    block.stops.push(stop);
+                }
+            }
+            Statement::Do(stmts) => {
+                for stmt in stmts {
+                    self.statement(stmt, block);
+                }
+            }
+            Statement::IfElse {
+                condition,
+                then,
+                else_,
+                ..
+            } => {
+                block.lines.push(format!("if {}", expr_label(condition)));
+                let branch = self.finish_block(block).unwrap();
+                block.preds = vec![(branch, Some("then"))];
+                self.statement(then, block);
+                self.finish_block(block);
+                let after_then = mem::take(&mut block.preds);
+                block.preds = vec![(branch, Some("else"))];
+                self.statement(else_, block);
+                self.finish_block(block);
+                block.preds.extend(after_then);
+            }
+            Statement::Repeat { times, body, .. } => {
+                let header = format!("repeat {}", expr_label(times));
+                self.lower_loop(header, body, true, block);
+            }
+            Statement::Forever(body, _) => {
+                self.lower_loop("forever".to_owned(), body, false, block);
+            }
+            Statement::Until {
+                condition, body, ..
+            } => {
+                let header = format!("until {}", expr_label(condition));
+                self.lower_loop(header, body, true, block);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let header = format!("while {}", expr_label(condition));
+                self.lower_loop(header, body, true, block);
+            }
+            Statement::For {
+                counter: (counter, _),
+                times,
+                body,
+            } => {
+                let header = format!("for {counter} {}", expr_label(times));
+                self.lower_loop(header, body, true, block);
+            }
+        }
+    }
+
+    /// A loop gets a block of its own for its header, which the end of the
+    /// body jumps back to. Only loops that can end have an edge out of it.
+    fn lower_loop(
+        &mut self,
+        header: String,
+        body: &Statement,
+        can_end: bool,
+        block: &mut Block,
+    ) {
+        self.finish_block(block);
+        block.lines.push(header);
+        let header = self.finish_block(block).unwrap();
+        block.preds = vec![(header, Some("body"))];
+        self.statement(body, block);
+        self.finish_block(block);
+        self.connect(block, header);
+        if can_end {
+            block.preds.push((header, Some("done")));
+        }
+    }
+
+    /// Adds the block being built to the graph, unless it is empty, and
+    /// starts a new one after it.
+    fn finish_block(&mut self, block: &mut Block) -> Option<usize> {
+        if block.lines.is_empty() {
+            return None;
+        }
+        let label = block
+            .lines
+            .drain(..)
+            .map(|line| escape(&line) + "\\l")
+            .collect::<String>();
+        let node = self.node("box", &label);
+        self.connect(block, node);
+        block.preds.push((node, None));
+        Some(node)
+    }
+
+    /// Adds the edges into the block being built, which end at `node`.
+    fn connect(&mut self, block: &mut Block, node: usize) {
+        for (pred, label) in block.preds.drain(..) {
+            match label {
+                Some(label) => writeln!(
+                    self.dot,
+                    "    n{pred} -> n{node} [label=\"{label}\"];"
+                ),
+                None => writeln!(self.dot, "    n{pred} -> n{node};"),
+            }
+            .unwrap();
+        }
+    }
+
+    /// Adds a node with a label that has already been escaped.
+    fn node(&mut self, shape: &str, label: &str) -> usize {
+        let node = self.nodes;
+        self.nodes += 1;
+        writeln!(self.dot, "    n{node} [shape={shape}, label=\"{label}\"];")
+            .unwrap();
+        node
+    }
+}
+
+fn call_label(name: &str, args: &[Expr]) -> String {
+    let mut label = format!("({name}");
+    for arg in args {
+        label.push(' ');
+        label.push_str(&expr_label(arg));
+    }
+    label.push(')');
+    label
+}
+
+fn expr_label(expr: &Expr) -> String {
+    match expr {
+        Expr::Imm(Value::Num(num)) => num.to_string(),
+        Expr::Imm(Value::String(s)) => format!("{s:?}"),
+        Expr::Imm(Value::Bool(b)) => b.to_string(),
+        Expr::Sym(sym, _) => sym.to_string(),
+        Expr::FuncCall(name, _, args) => call_label(name, args),
+        Expr::AddSub(positives, negatives, _) => {
+            terms_label("+", "-", positives, negatives)
+        }
+        Expr::MulDiv(numerators, denominators, _) => {
+            terms_label("*", "/", numerators, denominators)
+        }
+    }
+}
+
+/// A sum or product, with the terms that are subtracted or divided by
+/// after it like `(- (+ a b) c)`.
+fn terms_label(
+    op: &str,
+    inverse: &str,
+    terms: &[Expr],
+    inverse_terms: &[Expr],
+) -> String {
+    if inverse_terms.is_empty() {
+        return call_label(op, terms);
+    }
+    let mut label = format!("({inverse} ");
+    label.push_str(&match terms {
+        [term] => expr_label(term),
+        _ => call_label(op, terms),
+    });
+    for term in inverse_terms {
+        label.push(' ');
+        label.push_str(&expr_label(term));
+    }
+    label.push(')');
+    label
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
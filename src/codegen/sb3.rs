@@ -1,10 +1,13 @@
 mod expr;
+mod extension;
 mod reporter;
 mod sprite;
 mod statement;
 
+pub use extension::{load_extensions, Sb3Extension};
+
+use super::Stamp;
 use crate::{
-    asset::Asset,
     diagnostic::{Error, Result},
     ir::{
         expr::Expr,
@@ -12,6 +15,7 @@ use crate::{
         Program,
         {proc::Procedure, statement::Statement},
     },
+    opts::Opts,
     uid::Uid,
 };
 use codemap::Span;
@@ -20,22 +24,32 @@ use sb3_stuff::Value;
 use serde_json::{json, Value as Json};
 use std::{
     borrow::Cow,
-    cell::RefCell,
-    collections::HashMap,
-    fs::{self, File},
-    io::{self, Cursor},
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Cursor, Write},
     iter,
-    path::Path,
+    path::{Path, PathBuf},
 };
 use zip::{write::FileOptions, ZipWriter};
 
-pub fn write_sb3_file(program: &Program, path: &Path) -> Result<()> {
+pub fn write_sb3_file(
+    program: &Program,
+    path: &Path,
+    opts: &Opts,
+    stamp: Option<&Stamp>,
+    extensions: &[Box<dyn Sb3Extension>],
+) -> Result<()> {
     // TODO: Error handling
     let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
     zip.start_file("project.json", FileOptions::default())
         .map_err(|err| Error::CouldNotCreateProjectJson { inner: err })?;
 
-    let uid_gen = crate::uid::Generator::default();
+    let renamer = Renamer {
+        minify: opts.minify,
+        count: Cell::new(0),
+    };
 
     let global_vars = program
         .stage
@@ -45,8 +59,8 @@ pub fn write_sb3_file(program: &Program, path: &Path) -> Result<()> {
             (
                 &**var,
                 Mangled {
-                    name: Cow::Borrowed(var),
-                    id: uid_gen.new_uid(),
+                    name: renamer.rename(Cow::Borrowed(var)),
+                    id: stable_id("variable", &["Stage", var]),
                 },
             )
         })
@@ -59,15 +73,17 @@ pub fn write_sb3_file(program: &Program, path: &Path) -> Result<()> {
             (
                 &**list,
                 Mangled {
-                    name: Cow::Borrowed(list),
-                    id: uid_gen.new_uid(),
+                    name: renamer.rename(Cow::Borrowed(list)),
+                    id: stable_id("list", &["Stage", list]),
                 },
             )
         })
         .collect::<HashMap<_, _>>();
 
     let mut ctx = SerCtx {
-        uid_gen,
+        uid_gen: crate::uid::Generator::default(),
+        sprite_name: "Stage",
+        assets: BTreeMap::new(),
         blocks: RefCell::default(),
         custom_procs: HashMap::new(),
         proc_args: Vec::new(),
@@ -77,34 +93,51 @@ pub fn write_sb3_file(program: &Program, path: &Path) -> Result<()> {
         sprite_lists: HashMap::new(),
         global_vars,
         global_lists,
+        script_sizes: Vec::new(),
+        renamer,
+        extensions,
     };
-    let targets = iter::once(("Stage", &program.stage))
+    let mut targets = iter::once(("Stage", &program.stage))
         .chain(program.sprites.iter().map(|(name, spr)| (&**name, spr)))
         .map(|(name, spr)| ctx.serialize_sprite(name, spr))
         .collect::<Result<Vec<_>>>()?;
-
-    serde_json::to_writer(
-        &mut zip,
-        &json!({
-            "meta": {
-                "semver": "3.0.0",
+    if let Some(stamp) = stamp {
+        // A comment on the workspace of the stage, not attached to a block.
+        targets[0]["comments"] = json!({
+            "stamp": {
+                "blockId": null,
+                "x": 0,
+                "y": 0,
+                "width": 300,
+                "height": 100,
+                "minimized": false,
+                "text": stamp.text(),
             },
-            "targets": targets,
-        }),
-    )
-    .unwrap();
-
-    for (name, path) in program
-        .sprites
-        .values()
-        .chain(iter::once(&program.stage))
-        .flat_map(|sprite| &sprite.costumes)
-    {
-        let asset = Asset::new(name, path);
-        let mut file = File::open(path).unwrap();
-        zip.start_file(asset.md5ext, FileOptions::default())
-            .unwrap();
-        io::copy(&mut file, &mut zip).unwrap();
+        });
+    }
+
+    let mut project = json!({
+        "meta": {
+            "semver": "3.0.0",
+        },
+        "targets": targets,
+    });
+    if !extensions.is_empty() {
+        // TurboWarp loads custom extensions from `extensionURLs`.
+        project["extensions"] = extensions.iter().map(|ext| ext.id()).collect();
+        project["extensionURLs"] = extensions
+            .iter()
+            .filter_map(|ext| Some((ext.id().to_owned(), json!(ext.url()?))))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+    let project_json = serde_json::to_vec(&project).unwrap();
+    check_limits(&mut ctx.script_sizes, project_json.len(), opts)?;
+    zip.write_all(&project_json).unwrap();
+
+    for (md5ext, data) in ctx.assets {
+        zip.start_file(md5ext, FileOptions::default()).unwrap();
+        zip.write_all(&data).unwrap();
     }
 
     let buf = zip
@@ -116,8 +149,72 @@ pub fn write_sb3_file(program: &Program, path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// How big the blocks of a script are in `project.json`.
+struct ScriptSize {
+    span: Span,
+    blocks: usize,
+    bytes: usize,
+}
+
+/// Checks the size of the project against `--max-blocks` and
+/// `--max-json-size`.
+fn check_limits(
+    scripts: &mut [ScriptSize],
+    json_size: usize,
+    opts: &Opts,
+) -> Result<()> {
+    let blocks = scripts.iter().map(|script| script.blocks).sum::<usize>();
+    if let Some(limit) = opts.max_blocks.filter(|&limit| blocks > limit) {
+        return Err(too_big(
+            scripts,
+            "--max-blocks",
+            "blocks",
+            blocks,
+            limit,
+            |script| script.blocks,
+        ));
+    }
+    if let Some(limit) = opts.max_json_size.filter(|&limit| json_size > limit) {
+        return Err(too_big(
+            scripts,
+            "--max-json-size",
+            "bytes",
+            json_size,
+            limit,
+            |script| script.bytes,
+        ));
+    }
+    Ok(())
+}
+
+/// An error that points at the biggest scripts by `size_of`.
+fn too_big(
+    scripts: &mut [ScriptSize],
+    option: &'static str,
+    unit: &'static str,
+    size: usize,
+    limit: usize,
+    size_of: impl Fn(&ScriptSize) -> usize,
+) -> Box<Error> {
+    scripts.sort_by_key(|script| Reverse(size_of(script)));
+    Box::new(Error::ProjectTooBig {
+        option,
+        unit,
+        size,
+        limit,
+        biggest: scripts
+            .iter()
+            .take(5)
+            .map(|script| (script.span, size_of(script)))
+            .collect(),
+    })
+}
+
 struct SerCtx<'a> {
     uid_gen: crate::uid::Generator,
+    sprite_name: &'a str,
+    /// The contents of the costumes by their file names.
+    assets: BTreeMap<String, Vec<u8>>,
     blocks: RefCell<HashMap<Uid, Json>>,
     custom_procs: HashMap<&'a str, CustomProcedure>,
     proc_args: Vec<&'a str>,
@@ -127,12 +224,61 @@ struct SerCtx<'a> {
     sprite_lists: HashMap<&'a str, Mangled<'a>>,
     global_vars: HashMap<&'a str, Mangled<'a>>,
     global_lists: HashMap<&'a str, Mangled<'a>>,
+    script_sizes: Vec<ScriptSize>,
+    renamer: Renamer,
+    extensions: &'a [Box<dyn Sb3Extension>],
+}
+
+/// Gives variables, lists, custom blocks and parameters short names that don't
+/// tell what they are for when `--minify` is given.
+struct Renamer {
+    minify: bool,
+    /// How many names have been given out.
+    count: Cell<usize>,
+}
+
+impl Renamer {
+    fn rename<'a>(&self, name: Cow<'a, str>) -> Cow<'a, str> {
+        if !self.minify {
+            return name;
+        }
+        let count = self.count.get();
+        self.count.set(count + 1);
+        Cow::Owned(short_name(count))
+    }
+
+    /// The name of the parameter at `index` of a custom block. Parameters
+    /// only need to be different from the others of the same block.
+    fn param<'a>(&self, index: usize, name: &'a str) -> Cow<'a, str> {
+        if self.minify {
+            Cow::Owned(short_name(index))
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+}
+
+/// Names made of lowercase letters, in the order `a` to `z`, then `ba` to `zz`
+/// and so on.
+fn short_name(mut n: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'a' + (n % 26) as u8);
+        n /= 26;
+        if n == 0 {
+            break;
+        }
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
 }
 
 struct BuiltProcs<'a> {
     blocks: HashMap<Uid, Json>,
-    local_vars: Vec<Mangled<'a>>,
-    local_lists: Vec<Mangled<'a>>,
+    /// Local variables along with their initial values.
+    local_vars: Vec<(Mangled<'a>, Option<&'a Value>)>,
+    /// Local lists along with their initial items.
+    local_lists: Vec<(Mangled<'a>, Option<&'a Vec<Value>>)>,
 }
 
 impl<'a> SerCtx<'a> {
@@ -142,15 +288,32 @@ impl<'a> SerCtx<'a> {
 
     pub fn serialize_procs(
         &mut self,
-        procs: &'a HashMap<String, Vec<Procedure>>,
+        procs: &'a BTreeMap<String, Vec<Procedure>>,
     ) -> Result<BuiltProcs<'a>> {
         let mut local_vars = vec![];
         let mut local_lists = vec![];
         for (name, procs) in procs {
-            for proc in procs {
-                self.serialize_proc(name, proc)?;
-                local_vars.extend(self.local_vars.values().cloned());
-                local_lists.extend(self.local_lists.values().cloned());
+            for (index, proc) in procs.iter().enumerate() {
+                let other_blocks = self.blocks.take();
+                self.serialize_proc(name, index, proc)?;
+                let blocks = self.blocks.replace(other_blocks);
+                self.script_sizes.push(ScriptSize {
+                    span: proc.span,
+                    blocks: blocks.len(),
+                    bytes: blocks
+                        .values()
+                        .map(|block| block.to_string().len())
+                        .sum(),
+                });
+                self.blocks.borrow_mut().extend(blocks);
+                local_vars.extend(self.local_vars.iter().map(|(name, var)| {
+                    (var.clone(), proc.initial_values.get(*name))
+                }));
+                local_lists.extend(self.local_lists.iter().map(
+                    |(name, list)| {
+                        (list.clone(), proc.initial_items.get(*name))
+                    },
+                ));
             }
         }
         Ok(BuiltProcs {
@@ -163,35 +326,29 @@ impl<'a> SerCtx<'a> {
     fn serialize_proc(
         &mut self,
         name: &str,
+        index: usize,
         proc: &'a Procedure,
     ) -> Result<()> {
+        let sprite_name = self.sprite_name;
+        let index = index.to_string();
+        let local = |kind, var_name: &str| {
+            let id = stable_id(kind, &[sprite_name, name, &index, var_name]);
+            Mangled {
+                name: self
+                    .renamer
+                    .rename(Cow::Owned(format!("local {id} {var_name}"))),
+                id,
+            }
+        };
         self.local_vars = proc
             .variables
             .iter()
-            .map(|name| {
-                let id = self.new_uid();
-                (
-                    &**name,
-                    Mangled {
-                        name: Cow::Owned(format!("local {id} {name}")),
-                        id,
-                    },
-                )
-            })
+            .map(|var| (&**var, local("variable", var)))
             .collect();
         self.local_lists = proc
             .lists
             .iter()
-            .map(|name| {
-                let id = self.new_uid();
-                (
-                    &**name,
-                    Mangled {
-                        name: Cow::Owned(format!("local {id} {name}")),
-                        id,
-                    },
-                )
-            })
+            .map(|list| (&**list, local("list", list)))
             .collect();
 
         let this = self.new_uid();
@@ -248,6 +405,41 @@ impl<'a> SerCtx<'a> {
                     }),
                 );
             }
+            "when-timer-greater-than" | "when-loudness-greater-than" => {
+                let [(threshold, _)] = &proc.params[..] else {
+                    return Err(Box::new(Error::BuiltinProcWrongArgCount {
+                        span: proc.span,
+                        proc_name: name.to_owned(),
+                        expected: 1,
+                        got: proc.params.len(),
+                    }));
+                };
+                let menu = if name == "when-timer-greater-than" {
+                    "TIMER"
+                } else {
+                    "LOUDNESS"
+                };
+                let value =
+                    self.serialize_expr(threshold, this)?.with_empty_shadow();
+                let (body, _) = self.serialize_stmt(&proc.body, this, None)?;
+                self.emit_block(
+                    this,
+                    json!({
+                        "opcode": "event_whengreaterthan",
+                        "next": body,
+                        "parent": null,
+                        "inputs": {
+                            "VALUE": value,
+                        },
+                        "fields": {
+                            "WHENGREATERTHANMENU": [menu, null],
+                        },
+                        "topLevel": true,
+                        "x": 0,
+                        "y": 0,
+                    }),
+                );
+            }
             _ => {
                 self.proc_args = proc
                     .params
@@ -261,20 +453,25 @@ impl<'a> SerCtx<'a> {
                     .collect::<Vec<_>>();
 
                 let prototype_id = self.new_uid();
-                let param_ids: Vec<Uid> = self
-                    .custom_procs
-                    .get(name)
-                    .unwrap()
-                    .params
-                    .iter()
-                    .map(|(_, uid)| *uid)
-                    .collect();
-
-                let proccode =
-                    format!("{name}{}", " %s".repeat(proc.params.len()));
+                let custom_proc = self.custom_procs.get(name).unwrap();
+                let warp = custom_proc.warp;
+                let param_ids: Vec<Uid> =
+                    custom_proc.params.iter().map(|(_, uid)| *uid).collect();
+
+                let proccode = format!(
+                    "{}{}",
+                    custom_proc.name,
+                    " %s".repeat(proc.params.len())
+                );
                 let argumentids = serde_json::to_string(&param_ids).unwrap();
+                let argumentnames = self
+                    .proc_args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| self.renamer.param(i, arg))
+                    .collect::<Vec<_>>();
                 let argumentnames =
-                    serde_json::to_string(&self.proc_args).unwrap();
+                    serde_json::to_string(&argumentnames).unwrap();
                 let argumentdefaults =
                     serde_json::to_string(&[""].repeat(proc.params.len()))
                         .unwrap();
@@ -319,7 +516,7 @@ impl<'a> SerCtx<'a> {
                             "argumentids": argumentids,
                             "argumentnames": argumentnames,
                             "argumentdefaults": argumentdefaults,
-                            "warp": true,
+                            "warp": warp,
                         },
                     }),
                 );
@@ -454,6 +651,10 @@ impl<'a> SerCtx<'a> {
                         *param_name,
                         self.serialize_expr(arg, parent)?.without_shadow(),
                     )),
+                    Param::Color(param_name) => Some((
+                        *param_name,
+                        self.serialize_expr(arg, parent)?.with_color_shadow(),
+                    )),
                     _ => None,
                 })
             })
@@ -512,6 +713,7 @@ enum Param<'a> {
     String(&'a str),
     Number(&'a str),
     Bool(&'a str),
+    Color(&'a str),
     Var(&'a str),
     List(&'a str),
 }
@@ -528,5 +730,27 @@ struct Call<'name, 'a> {
 #[derive(Clone)]
 struct Mangled<'a> {
     name: Cow<'a, str>,
-    id: Uid,
+    id: String,
+}
+
+/// The ID of a variable or list, which only depends on where it was declared
+/// so that it stays the same when the rest of the project changes. Every part
+/// of the path is prefixed by its length so different paths never share a
+/// hash input.
+fn stable_id(kind: &str, path: &[&str]) -> String {
+    let parts = path
+        .iter()
+        .map(|part| format!(" {}:{part}", part.len()))
+        .collect::<String>();
+    let digest = format!("{:x}", md5::compute(format!("{kind}{parts}")));
+    digest[..16].to_owned()
+}
+
+/// The costumes of a sprite ordered by name.
+fn sorted_costumes(
+    costumes: &HashMap<String, PathBuf>,
+) -> Vec<(&String, &PathBuf)> {
+    let mut costumes = costumes.iter().collect::<Vec<_>>();
+    costumes.sort_unstable_by_key(|&(name, _)| name);
+    costumes
 }
@@ -20,13 +20,18 @@ impl SerCtx<'_> {
                 "y-pos" => self.simple_symbol("motion_yposition", parent),
                 "timer" => self.simple_symbol("sensing_timer", parent),
                 "answer" => self.simple_symbol("sensing_answer", parent),
+                "video-motion" => self.video_sensing("motion", parent)?,
+                "video-direction" => self.video_sensing("direction", parent)?,
                 _ => {
-                    if self.proc_args.contains(&&**sym) {
+                    if let Some(index) =
+                        self.proc_args.iter().position(|arg| *arg == &**sym)
+                    {
+                        let name = self.renamer.param(index, sym);
                         self.emit_non_shadow(
                             "argument_reporter_string_number",
                             parent,
                             &[],
-                            &[("VALUE", &|_| Ok(json!([**sym, null])))],
+                            &[("VALUE", &|_| Ok(json!([name, null])))],
                         )?
                     } else if let Some(var) = self.lookup_var(sym).cloned() {
                         Reporter::Variable(var)
@@ -43,10 +48,10 @@ impl SerCtx<'_> {
             Expr::FuncCall(func_name, span, args) => {
                 self.serialize_func_call(func_name, args, parent, *span)?
             }
-            Expr::AddSub(positives, negatives) => {
+            Expr::AddSub(positives, negatives, _) => {
                 self.serialize_add_sub(positives, negatives, parent)?
             }
-            Expr::MulDiv(numerators, denominators) => {
+            Expr::MulDiv(numerators, denominators, _) => {
                 self.serialize_mul_div(numerators, denominators, parent)?
             }
         })
@@ -143,6 +148,25 @@ impl SerCtx<'_> {
         self.emit_non_shadow(opcode, parent, &[], &[]).unwrap()
     }
 
+    /// The video motion or direction under the current sprite, or anywhere
+    /// on the stage.
+    fn video_sensing(&self, attribute: &str, parent: Uid) -> Result<Reporter> {
+        let subject = if self.sprite_name == "Stage" {
+            "Stage"
+        } else {
+            "this sprite"
+        };
+        self.emit_non_shadow(
+            "videoSensing_videoOn",
+            parent,
+            &[
+                ("ATTRIBUTE", &|_| Ok(json!([1, [10, attribute]]))),
+                ("SUBJECT", &|_| Ok(json!([1, [10, subject]]))),
+            ],
+            &[],
+        )
+    }
+
     fn serialize_func_call(
         &self,
         func_name: &'static str,
@@ -167,7 +191,9 @@ impl SerCtx<'_> {
             }
         }
         match func_name {
-            "!!" => func!(data_itemoflist(LIST: List, INDEX: Number)),
+            "!!" | "%static-get" => {
+                func!(data_itemoflist(LIST: List, INDEX: Number))
+            }
             "++" => self.associative1(
                 "operator_join",
                 "STRING1",
@@ -213,6 +239,7 @@ impl SerCtx<'_> {
             "asin" => self.mathop("asin", parent, args, span),
             "acos" => self.mathop("acos", parent, args, span),
             "atan" => self.mathop("atan", parent, args, span),
+            "round-to" => self.round_to(parent, args, span),
             "pressing-key" => func!(sensing_keypressed(KEY_OPTION: String)),
             "to-num" => match args {
                 [arg] => self.emit_non_shadow(
@@ -231,6 +258,31 @@ impl SerCtx<'_> {
                     got: args.len(),
                 })),
             },
+            "to-bool" => match args {
+                // Scratch has no block for this, but `not` converts its operand
+                // to a boolean before negating it.
+                [arg] => self.emit_non_shadow(
+                    "operator_not",
+                    parent,
+                    &[("OPERAND", &|parent| {
+                        Ok(self
+                            .emit_non_shadow(
+                                "operator_not",
+                                parent,
+                                &[("OPERAND", &self.shadowless_input(arg))],
+                                &[],
+                            )?
+                            .without_shadow())
+                    })],
+                    &[],
+                ),
+                _ => Err(Box::new(Error::FunctionWrongArgCount {
+                    span,
+                    func_name,
+                    expected: 1,
+                    got: args.len(),
+                })),
+            },
             "random" => match args {
                 [low, high] => self.emit_non_shadow(
                     "operator_random",
@@ -248,6 +300,37 @@ impl SerCtx<'_> {
                     got: args.len(),
                 })),
             },
+            "call-extern" => Err(Box::new(Error::ExternNotSupported {
+                span,
+                target: "sb3",
+            })),
+            "call-extension" => {
+                let (opcode, params, args) =
+                    self.extension_call(args, span, true)?;
+                self.simple_function(
+                    Call {
+                        name: func_name,
+                        opcode,
+                        parent,
+                        args,
+                        span,
+                    },
+                    &params,
+                )
+            }
+            // Literal patterns matched against literals are folded.
+            "str-match" => Err(Box::new(Error::NativeOnlyProc {
+                span,
+                proc_name: "str-match",
+                target: "sb3",
+            })),
+            "format-decimal" | "to-hex" | "to-binary" => {
+                Err(Box::new(Error::NativeOnlyProc {
+                    span,
+                    proc_name: func_name,
+                    target: "sb3",
+                }))
+            }
             _ => Err(Box::new(Error::UnknownFunction {
                 span,
                 func_name: func_name.to_owned(),
@@ -279,6 +362,45 @@ impl SerCtx<'_> {
         )
     }
 
+    /// Serializes `(round-to n places)` as `n * 10^places` rounded by Scratch's
+    /// `round` block and divided by `10^places` again.
+    fn round_to(
+        &self,
+        parent: Uid,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Reporter> {
+        let [n, places] = args else {
+            return Err(Box::new(Error::FunctionWrongArgCount {
+                span,
+                func_name: "round-to",
+                expected: 2,
+                got: args.len(),
+            }));
+        };
+        let scale = Expr::FuncCall("ten^", span, vec![places.clone()]);
+        let scaled =
+            Expr::MulDiv(vec![n.clone(), scale.clone()], Vec::new(), span);
+        self.emit_non_shadow(
+            "operator_divide",
+            parent,
+            &[
+                ("NUM1", &|parent| {
+                    Ok(self
+                        .emit_non_shadow(
+                            "operator_round",
+                            parent,
+                            &[("NUM", &self.empty_shadow_input(&scaled))],
+                            &[],
+                        )?
+                        .with_empty_shadow())
+                }),
+                ("NUM2", &self.empty_shadow_input(&scale)),
+            ],
+            &[],
+        )
+    }
+
     fn associative1(
         &self,
         opcode: &str,
@@ -0,0 +1,185 @@
+//! Blocks of Scratch extensions that the compiler doesn't know about, like
+//! TurboWarp's custom extensions, which are called with
+//! `(call-extension "opcode" args...)`.
+
+use super::{Param, SerCtx};
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::{
+        expr::Expr,
+        typ::{split_annotation, Type},
+    },
+    parser, source,
+};
+use codemap::{CodeMap, Span};
+use sb3_stuff::Value;
+use std::{collections::HashMap, path::PathBuf};
+
+/// An extension whose blocks can be called with `call-extension`.
+pub trait Sb3Extension {
+    /// The ID of the extension, which the opcodes of its blocks start with.
+    fn id(&self) -> &str;
+
+    /// Where the editor loads the extension from, or `None` if it is built
+    /// into the editor.
+    fn url(&self) -> Option<&str>;
+
+    /// The block with the given opcode, including the ID of the extension.
+    fn block(&self, opcode: &str) -> Option<&Block>;
+}
+
+pub struct Block {
+    /// Whether the block is a reporter rather than a statement.
+    pub reporter: bool,
+    /// The inputs in the order that the arguments are given in. Inputs
+    /// without a type take strings.
+    pub inputs: Vec<(String, Option<Type>)>,
+}
+
+/// An extension described in a file given with `--extension`, like
+/// `(extension "fetch" "https://extensions.turbowarp.org/fetch.js"
+/// (reporter "fetch" URL))`. Opcodes are given without the ID.
+struct Manifest {
+    id: String,
+    url: Option<String>,
+    blocks: HashMap<String, Block>,
+}
+
+impl Sb3Extension for Manifest {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    fn block(&self, opcode: &str) -> Option<&Block> {
+        self.blocks.get(opcode)
+    }
+}
+
+/// Reads the extensions from the files.
+pub fn load_extensions(
+    paths: &[PathBuf],
+    code_map: &mut CodeMap,
+    max_nesting: usize,
+) -> Result<Vec<Box<dyn Sb3Extension>>> {
+    let mut extensions = Vec::<Box<dyn Sb3Extension>>::new();
+    for path in paths {
+        let source = source::read(path, None)?;
+        let file = code_map.add_file(path.display().to_string(), source);
+        for ast in parser::program(&file, max_nesting)? {
+            extensions.push(Box::new(Manifest::from_ast(ast)?));
+        }
+    }
+    Ok(extensions)
+}
+
+impl Manifest {
+    fn from_ast(ast: Ast) -> Result<Self> {
+        let span = ast.span();
+        let invalid = || Box::new(Error::InvalidExtensionManifest { span });
+        let Ast::Node(head, tail, _) = ast else {
+            return Err(invalid());
+        };
+        if !head.is_sym("extension") {
+            return Err(invalid());
+        }
+        let mut tail = tail.into_iter().peekable();
+        let Some(Ast::String(id, _)) = tail.next() else {
+            return Err(invalid());
+        };
+        let url = match tail.peek() {
+            Some(Ast::String(url, _)) => {
+                let url = url.clone();
+                tail.next();
+                Some(url)
+            }
+            _ => None,
+        };
+        let mut blocks = HashMap::new();
+        for block in tail {
+            let block_span = block.span();
+            let Ast::Node(head, params, _) = block else {
+                return Err(invalid());
+            };
+            let Ast::Sym(kind, _) = *head else {
+                return Err(invalid());
+            };
+            let reporter = match &*kind {
+                "reporter" => true,
+                "block" => false,
+                _ => return Err(invalid()),
+            };
+            let mut params = params.into_iter();
+            let Some(Ast::String(opcode, _)) = params.next() else {
+                return Err(Box::new(Error::InvalidExtensionManifest {
+                    span: block_span,
+                }));
+            };
+            let inputs = params
+                .map(|param| match split_annotation(param)? {
+                    (Ast::Sym(name, _), typ) => Ok((name, typ)),
+                    (other, _) => {
+                        Err(Box::new(Error::InvalidExtensionManifest {
+                            span: other.span(),
+                        }))
+                    }
+                })
+                .collect::<Result<_>>()?;
+            blocks.insert(format!("{id}_{opcode}"), Block { reporter, inputs });
+        }
+        Ok(Self { id, url, blocks })
+    }
+}
+
+impl<'a> SerCtx<'a> {
+    /// Looks up the block that `(call-extension "opcode" args...)` calls.
+    /// Returns its opcode, the parameters for the arguments after the opcode,
+    /// and those arguments.
+    pub(super) fn extension_call<'e>(
+        &self,
+        args: &'e [Expr],
+        span: Span,
+        reporter: bool,
+    ) -> Result<(&'e str, Vec<Param<'a>>, &'e [Expr])> {
+        let (opcode, args) = match args {
+            [Expr::Imm(Value::String(opcode)), args @ ..] => (&**opcode, args),
+            [_, ..] => {
+                return Err(Box::new(Error::ExtensionOpcodeMustBeString {
+                    span,
+                }))
+            }
+            [] => {
+                return Err(Box::new(Error::FunctionWrongArgCount {
+                    span,
+                    func_name: "call-extension",
+                    expected: 1,
+                    got: 0,
+                }))
+            }
+        };
+        let block = self
+            .extensions
+            .iter()
+            .find_map(|extension| extension.block(opcode))
+            .filter(|block| block.reporter == reporter)
+            .ok_or_else(|| Error::UnknownExtensionBlock {
+                span,
+                opcode: opcode.to_owned(),
+                kind: if reporter { "reporter" } else { "block" },
+            })?;
+        let params = block
+            .inputs
+            .iter()
+            .map(|(name, typ)| match typ {
+                Some(Type::Num) => Param::Number(name),
+                Some(Type::Bool) => Param::Bool(name),
+                Some(Type::Str) | None => Param::String(name),
+            })
+            .collect();
+        Ok((opcode, params, args))
+    }
+}
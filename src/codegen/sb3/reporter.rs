@@ -19,6 +19,18 @@ impl Reporter<'_> {
         }
     }
 
+    /// Like `with_empty_shadow`, but for inputs that show a color picker.
+    /// Numbers are colors of the form `0xRRGGBB`.
+    pub fn with_color_shadow(&self) -> Json {
+        match self {
+            Self::Literal(Value::Num(n)) => {
+                json!([1, [9, format!("#{:06x}", *n as u32 & 0xff_ffff)]])
+            }
+            Self::Literal(lit) => json!([1, [9, lit.to_cow_str()]]),
+            _ => json!([3, self.inner_json(), [9, "#000000"]]),
+        }
+    }
+
     pub fn without_shadow(&self) -> Json {
         let json = self.inner_json();
         if self.is_shadow() {
@@ -1,79 +1,97 @@
-use super::{Mangled, SerCtx};
+use super::{sorted_costumes, stable_id, Mangled, SerCtx};
 use crate::{
     asset::Asset,
     diagnostic::{Error, Result},
-    ir::{expr::Expr, proc::CustomProcedure, sprite::Sprite},
+    ir::{
+        expr::Expr,
+        proc::{is_event, CustomProcedure},
+        sprite::Sprite,
+    },
 };
+use sb3_stuff::Value;
 use serde_json::{json, Value as Json};
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, mem};
 
 impl<'a> SerCtx<'a> {
     pub fn serialize_sprite(
         &mut self,
-        name: &str,
+        name: &'a str,
         sprite: &'a Sprite,
     ) -> Result<Json> {
-        let variables = sprite
-            .variables
-            .iter()
-            .map(|var| {
-                (
-                    &**var,
-                    Mangled {
-                        name: Cow::Borrowed(var),
-                        id: self.new_uid(),
-                    },
-                )
-            })
-            .collect::<HashMap<_, _>>();
-        let lists = sprite
-            .lists
-            .iter()
-            .map(|lst| {
-                (
-                    &**lst,
-                    Mangled {
-                        name: Cow::Borrowed(lst),
-                        id: self.new_uid(),
-                    },
-                )
-            })
-            .collect::<HashMap<_, _>>();
+        self.sprite_name = name;
+        let is_stage = name == "Stage";
+        // The stage declares the globals with the ids that blocks use to refer
+        // to them. Sprite variables that share a name with a global are
+        // renamed since Scratch requires names to be unique, which is also
+        // what Scratch does when loading such a project.
+        let variables = if is_stage {
+            self.global_vars.clone()
+        } else {
+            sprite
+                .variables
+                .iter()
+                .map(|var| (&**var, self.sprite_mangled(name, var, false)))
+                .collect()
+        };
+        let lists = if is_stage {
+            self.global_lists.clone()
+        } else {
+            sprite
+                .lists
+                .iter()
+                .map(|lst| (&**lst, self.sprite_mangled(name, lst, true)))
+                .collect()
+        };
 
-        let mangled_var = |mangled: &Mangled| {
-            (mangled.id.to_string(), json!([mangled.name, 0]))
+        let mangled_var = |mangled: &Mangled, initial_value: Option<&Value>| {
+            let initial_value = initial_value.map_or(json!(0), value_json);
+            (mangled.id.clone(), json!([mangled.name, initial_value]))
         };
-        let mangled_list = |mangled: &Mangled| {
-            (mangled.id.to_string(), json!([mangled.name, []]))
+        let mangled_list = |mangled: &Mangled, items: Option<&Vec<Value>>| {
+            let items = items.map_or_else(Vec::new, |items| {
+                items.iter().map(value_json).collect()
+            });
+            (mangled.id.clone(), json!([mangled.name, items]))
         };
 
-        let mut var_initializers =
-            variables.values().map(mangled_var).collect::<Json>();
-        let mut list_initializers =
-            lists.values().map(mangled_list).collect::<Json>();
+        let mut var_initializers = variables
+            .iter()
+            .map(|(name, var)| {
+                mangled_var(var, sprite.initial_values.get(*name))
+            })
+            .collect::<Json>();
+        let mut list_initializers = lists
+            .iter()
+            .map(|(name, list)| {
+                mangled_list(list, sprite.initial_items.get(*name))
+            })
+            .collect::<Json>();
 
-        if name != "Stage" {
+        if !is_stage {
             // Variables and lists belonging to the stage are considered global,
             // so excluding them here prevents them from being defined twice.
             self.sprite_vars = variables;
             self.sprite_lists = lists;
         }
 
-        let costumes = sprite
-            .costumes
-            .iter()
+        let costumes = sorted_costumes(&sprite.costumes)
+            .into_iter()
             .map(|(name, path)| {
-                serde_json::to_value(Asset::new(name, path)).unwrap()
+                let mut asset =
+                    Asset::new(name, path, sprite.costume_spans[name])?;
+                // Costumes that are the same image are only stored once.
+                self.assets
+                    .entry(asset.md5ext.clone())
+                    .or_insert_with(|| mem::take(&mut asset.data));
+                Ok(serde_json::to_value(asset).unwrap())
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
 
         self.custom_procs = sprite
             .procedures
             .iter()
             .map(|(name, proc)| match &**name {
-                "when-flag-clicked" | "when-cloned" | "when-received" => {
-                    Ok(None)
-                }
+                name if is_event(name) => Ok(None),
                 _ => {
                     assert_eq!(
                         1,
@@ -92,25 +110,39 @@ impl<'a> SerCtx<'a> {
                             }),
                         })
                         .collect::<std::result::Result<_, _>>()?;
-                    Ok(Some((&**name, CustomProcedure { params })))
+                    let warp = !proc[0].refresh;
+                    let display_name =
+                        self.renamer.rename(Cow::Borrowed(name)).into_owned();
+                    Ok(Some((
+                        &**name,
+                        CustomProcedure {
+                            name: display_name,
+                            params,
+                            warp,
+                        },
+                    )))
                 }
             })
             .filter_map(Result::transpose)
             .collect::<Result<_>>()?;
 
         let procs = self.serialize_procs(&sprite.procedures)?;
-        var_initializers
-            .as_object_mut()
-            .unwrap()
-            .extend(procs.local_vars.iter().map(mangled_var));
-        list_initializers
-            .as_object_mut()
-            .unwrap()
-            .extend(procs.local_lists.iter().map(mangled_list));
+        var_initializers.as_object_mut().unwrap().extend(
+            procs
+                .local_vars
+                .iter()
+                .map(|(var, initial_value)| mangled_var(var, *initial_value)),
+        );
+        list_initializers.as_object_mut().unwrap().extend(
+            procs
+                .local_lists
+                .iter()
+                .map(|(list, items)| mangled_list(list, *items)),
+        );
 
         Ok(json!({
             "name": name,
-            "isStage": name == "Stage",
+            "isStage": is_stage,
             "variables": var_initializers,
             "lists": list_initializers,
             "costumes": costumes,
@@ -119,4 +151,33 @@ impl<'a> SerCtx<'a> {
             "blocks": procs.blocks,
         }))
     }
+
+    fn sprite_mangled(
+        &self,
+        sprite_name: &str,
+        name: &'a str,
+        is_list: bool,
+    ) -> Mangled<'a> {
+        let (globals, kind) = if is_list {
+            (&self.global_lists, "list")
+        } else {
+            (&self.global_vars, "variable")
+        };
+        Mangled {
+            name: self.renamer.rename(if globals.contains_key(name) {
+                Cow::Owned(format!("{sprite_name}: {name}"))
+            } else {
+                Cow::Borrowed(name)
+            }),
+            id: stable_id(kind, &[sprite_name, name]),
+        }
+    }
+}
+
+fn value_json(value: &Value) -> Json {
+    match value {
+        Value::Num(n) if n.is_finite() => json!(n),
+        Value::Bool(b) => json!(b),
+        _ => json!(value.to_cow_str()),
+    }
 }
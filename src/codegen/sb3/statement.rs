@@ -90,7 +90,7 @@ impl SerCtx<'_> {
 
                 (Some(this), Some(this))
             }
-            Statement::Repeat { times, body } => self.emit_stacking(
+            Statement::Repeat { times, body, .. } => self.emit_stacking(
                 "control_repeat",
                 parent,
                 next,
@@ -100,7 +100,7 @@ impl SerCtx<'_> {
                 ],
                 &[],
             )?,
-            Statement::Forever(body) => {
+            Statement::Forever(body, _) => {
                 assert!(next.is_none());
                 self.emit_stacking(
                     "control_forever",
@@ -110,7 +110,9 @@ impl SerCtx<'_> {
                     &[],
                 )?
             }
-            Statement::Until { condition, body } => self.emit_stacking(
+            Statement::Until {
+                condition, body, ..
+            } => self.emit_stacking(
                 "control_repeat_until",
                 parent,
                 next,
@@ -120,7 +122,9 @@ impl SerCtx<'_> {
                 ],
                 &[],
             )?,
-            Statement::While { condition, body } => self.emit_stacking(
+            Statement::While {
+                condition, body, ..
+            } => self.emit_stacking(
                 "control_while",
                 parent,
                 next,
@@ -183,13 +187,60 @@ impl SerCtx<'_> {
         };
 
         match proc_name {
+            "asm" => Err(Box::new(Error::InlineAsmNotSupported {
+                span,
+                target: "sb3",
+            })),
+            "call-extern" => Err(Box::new(Error::ExternNotSupported {
+                span,
+                target: "sb3",
+            })),
+            "call-extension" => {
+                let (opcode, params, args) =
+                    self.extension_call(args, span, false)?;
+                self.simple_proc(
+                    Call {
+                        args,
+                        name: proc_name,
+                        opcode,
+                        parent,
+                        span,
+                    },
+                    &params,
+                    next,
+                )
+            }
+            "save-state" => Err(Box::new(Error::NativeOnlyProc {
+                span,
+                proc_name: "save-state",
+                target: "sb3",
+            })),
+            "load-state" => Err(Box::new(Error::NativeOnlyProc {
+                span,
+                proc_name: "load-state",
+                target: "sb3",
+            })),
+            "print-fixed" => Err(Box::new(Error::NativeOnlyProc {
+                span,
+                proc_name: "print-fixed",
+                target: "sb3",
+            })),
             "erase-all" => proc!(pen_clear()),
             "stamp" => proc!(pen_stamp()),
             "pen-down" => proc!(pen_penDown()),
             "pen-up" => proc!(pen_penUp()),
             "set-pen-size" => proc!(pen_setPenSizeTo(SIZE: Number)),
-            // TODO: Color parameters
-            "set-pen-color" => proc!(pen_setPenColorToColor(COLOR: Number)),
+            "set-pen-color" => proc!(pen_setPenColorToColor(COLOR: Color)),
+            "play-note" => {
+                proc!(music_playNoteForBeats(NOTE: Number, BEATS: Number))
+            }
+            "rest" => proc!(music_restForBeats(BEATS: Number)),
+            "set-instrument" => proc!(music_setInstrument(INSTRUMENT: Number)),
+            "set-tempo" => proc!(music_setTempo(TEMPO: Number)),
+            "set-video" => proc!(videoSensing_videoToggle(VIDEO_STATE: String)),
+            "set-video-transparency" => proc!(
+                videoSensing_setVideoTransparency(TRANSPARENCY: Number)
+            ),
             "set-xy" => proc!(motion_gotoxy(X: Number, Y: Number)),
             "set-size" => proc!(looks_setsizeto(SIZE: Number)),
             "set-costume" => proc!(looks_switchcostumeto(COSTUME: String)),
@@ -205,6 +256,7 @@ impl SerCtx<'_> {
             "set-y" => proc!(motion_sety(Y: Number)),
             "wait" => proc!(control_wait(DURATION: Number)),
             "ask" => proc!(sensing_askandwait(QUESTION: String)),
+            "speak" => proc!(text2speech_speakAndWait(WORDS: String)),
             "send-broadcast-sync" => match args {
                 [name] => {
                     let broadcast_input = |parent| {
@@ -309,7 +361,7 @@ impl SerCtx<'_> {
             .collect::<Result<_>>()?;
 
         let proccode =
-            format!("{proc_name}{}", " %s".repeat(proc.params.len()));
+            format!("{}{}", proc.name, " %s".repeat(proc.params.len()));
         let param_ids: Vec<Uid> =
             proc.params.iter().map(|(_, uid)| *uid).collect();
         let argumentids = serde_json::to_string(&param_ids).unwrap();
@@ -328,7 +380,7 @@ impl SerCtx<'_> {
                       "children": [],
                       "proccode": proccode,
                       "argumentids": argumentids,
-                      "warp": "true",
+                      "warp": proc.warp.to_string(),
                 }
             }),
         );
@@ -0,0 +1,91 @@
+//! Build metadata that `--stamp` embeds in the outputs, so that a bug report
+//! about an old build can be traced back to the compiler and source code that
+//! made it.
+
+use std::{
+    iter,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The name of the ELF note, which tells who defined its type.
+const NOTE_NAME: &str = "scratch-compiler";
+
+pub struct Stamp {
+    text: String,
+}
+
+impl Stamp {
+    /// Stamps a build of the given source code, made now.
+    pub fn new(source: &str) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        Self {
+            text: format!(
+                "scratch-compiler {}\nsource md5: {:x}\nbuilt: {}",
+                env!("CARGO_PKG_VERSION"),
+                md5::compute(source),
+                utc_timestamp(now),
+            ),
+        }
+    }
+
+    pub(super) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The stamp as a block of comments for the top of the disassembly.
+    pub(super) fn asm_comment(&self) -> String {
+        self.text
+            .lines()
+            .map(|line| format!("; {line}\n"))
+            .chain(iter::once("\n".to_owned()))
+            .collect()
+    }
+
+    /// NASM source for a `.note.scratch-compiler` section that holds the
+    /// stamp, which `readelf --notes` shows. It is assembled into the prelude.
+    pub(super) fn elf_note(&self) -> String {
+        let desc = self
+            .text
+            .bytes()
+            .map(|byte| byte.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "\nsection .note.scratch-compiler note alloc noexec nowrite \
+             align=4\n    dd {}, {}, 1\n    db \"{NOTE_NAME}\", 0\n    \
+             align 4, db 0\n    db {desc}\n    align 4, db 0\n",
+            NOTE_NAME.len() + 1,
+            self.text.len(),
+        )
+    }
+}
+
+/// Formats seconds since the Unix epoch as an ISO 8601 date and time in UTC.
+fn utc_timestamp(secs: u64) -> String {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let time = secs % 86400;
+    format!(
+        "{year}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+    )
+}
@@ -1,17 +1,38 @@
+mod annotate;
 mod broadcast;
 mod expr;
+mod ffi;
+mod mangle;
+mod pattern;
+mod range;
+mod save;
 mod statement;
+mod static_data;
+mod switch;
+mod table;
+mod temporary;
 mod typ;
 
 use crate::{
     diagnostic::{Error, Result},
-    ir::{self, expr::Expr, proc::Procedure, sprite::Sprite},
+    ir::{
+        self,
+        expr::Expr,
+        ffi::ExternFunction,
+        proc::{is_event, Procedure},
+        sprite::Sprite,
+        table::Table,
+        typ::Type,
+    },
+    opts::{Opts, Profile},
 };
+pub use annotate::annotate;
+
 use broadcast::Broadcasts;
-use codemap::Span;
+use codemap::{CodeMap, Span};
 use cranelift::{
     codegen::{
-        ir::{FuncRef, Function, Inst, UserFuncName},
+        ir::{FuncRef, Function, Inst, SourceLoc, UserFuncName},
         Context,
     },
     prelude::{
@@ -21,19 +42,64 @@ use cranelift::{
     },
 };
 use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
-use cranelift_object::{ObjectBuilder, ObjectModule};
+use cranelift_object::{ObjectBuilder, ObjectModule, ObjectProduct};
+use mangle::Kind;
+use range::Counter;
+use save::Saved;
 use sb3_stuff::Value as Immediate;
+use static_data::StaticList;
 use std::{
-    borrow::Cow, collections::HashMap, fs::File, io::Write, iter, path::Path,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    mem,
+    ops::Range,
 };
+use table::define_table;
+use temporary::Temporary;
+use typ::MixedSizeValue;
+
+/// The runtime routines the generated code calls into, to be assembled with
+/// NASM and linked alongside the object file.
+pub const PRELUDE: &str = include_str!("x86_64/prelude.s");
 
-pub fn write_object_file(program: &ir::Program, path: &Path) -> Result<()> {
+/// The rest of the runtime, written in Rust in the `runtime` crate and built
+/// into a static library by the build script.
+pub const RUNTIME: &[u8] = include_bytes!(env!("RUNTIME_LIB"));
+
+/// Allocation tracking for debug builds, assembled together with the prelude.
+pub const LEAK_CHECK: &str = include_str!("x86_64/leak_check.s");
+
+pub struct Output {
+    pub object: Vec<u8>,
+    /// Disassembly of every generated function, if it was requested.
+    pub asm: Option<String>,
+    /// NASM source for the `asm` blocks of the program, which the object file
+    /// calls into. `None` if the program doesn't contain any.
+    pub inline_asm: Option<String>,
+    /// The symbols of the variables, lists and procedures, one per line.
+    pub symbols: String,
+    /// With `--annotate-asm`, the spans of the statements by the addresses
+    /// of their instructions in `.text`, sorted by address.
+    pub source_map: Option<Vec<(Range<u64>, Span)>>,
+}
+
+pub fn compile<'a>(
+    program: &'a ir::Program,
+    code_map: &'a CodeMap,
+    opts: &Opts,
+    emit_asm: bool,
+) -> Result<Output> {
     env_logger::init();
 
     let mut settings = settings::builder();
     settings.enable("enable_verifier").unwrap();
     settings.enable("is_pic").unwrap();
-    settings.set("opt_level", "speed_and_size").unwrap();
+    let opt_level = match opts.profile {
+        Profile::Debug => "none",
+        Profile::Release => "speed_and_size",
+    };
+    settings.set("opt_level", opt_level).unwrap();
     settings.enable("unwind_info").unwrap();
     let flags = settings::Flags::new(settings);
     let isa = isa::lookup_by_name("x86_64-unknown-linux-gnu")
@@ -52,27 +118,23 @@ pub fn write_object_file(program: &ir::Program, path: &Path) -> Result<()> {
     let mut object_module = ObjectModule::new(object_builder);
     let mut func_ctx = FunctionBuilderContext::new();
 
-    let global_vars = program
+    let global_types = program
         .stage
-        .variables
+        .annotations
         .iter()
-        .map(String::as_str)
-        .zip(iter::repeat_with(|| {
-            object_module.declare_anonymous_data(true, false).unwrap()
-        }))
+        .map(|(name, typ)| (&**name, *typ))
         .collect();
 
-    let global_lists = program
+    let global_externs = program
         .stage
-        .lists
+        .externs
         .iter()
-        .map(String::as_str)
-        .zip(iter::repeat_with(|| {
-            object_module.declare_anonymous_data(true, false).unwrap()
-        }))
+        .map(|(name, function)| (&**name, function))
         .collect();
 
     let mut p = Program {
+        code_map,
+        profile: opts.profile,
         target_frontend_config,
         object_module,
         data_ctx: DataDescription::new(),
@@ -80,26 +142,85 @@ pub fn write_object_file(program: &ir::Program, path: &Path) -> Result<()> {
         variable_counter: 0,
         extern_function_signatures: extern_function_signatures(),
         extern_functions: HashMap::new(),
-        local_vars: HashMap::new(),
-        local_lists: HashMap::new(),
-        sprite_vars: HashMap::new(),
-        sprite_lists: HashMap::new(),
-        global_vars,
-        global_lists,
-        static_strs: HashMap::new(),
+        local_vars: BTreeMap::new(),
+        local_lists: BTreeMap::new(),
+        sprite_vars: BTreeMap::new(),
+        sprite_lists: BTreeMap::new(),
+        global_vars: BTreeMap::new(),
+        global_lists: BTreeMap::new(),
+        sprite_tables: BTreeMap::new(),
+        global_tables: BTreeMap::new(),
+        local_types: HashMap::new(),
+        sprite_types: HashMap::new(),
+        global_types,
+        sprite_externs: HashMap::new(),
+        global_externs,
+        extern_imports: HashMap::new(),
+        static_strs: BTreeMap::new(),
+        pattern_tables: HashMap::new(),
+        initial_values: Vec::new(),
+        initial_items: Vec::new(),
+        static_lists: HashMap::new(),
+        saved_vars: Vec::new(),
+        saved_lists: Vec::new(),
+        state_functions: None,
+        all_vars: Vec::new(),
+        all_lists: Vec::new(),
         custom_procs: HashMap::new(),
-        proc_params: HashMap::new(),
-        broadcasts: HashMap::new(),
+        proc_params: BTreeMap::new(),
+        counters: HashMap::new(),
+        temporaries: Vec::new(),
+        broadcasts: BTreeMap::new(),
         answer: None,
         main_broadcast_handler: None,
         uses_drand48: false,
         stop_block: None,
+        asm: emit_asm.then(String::new),
+        inline_asm: Vec::new(),
+        source_spans: opts.annotate_asm.then(Vec::new),
+        srcloc: SourceLoc::default(),
+        source_ranges: Vec::new(),
+        sprite_name: "Stage",
+        symbols: BTreeMap::new(),
     };
 
+    for var_name in &program.stage.variables {
+        let id = p.declare_named_data(Kind::Variable, &["Stage", var_name]);
+        p.global_vars.insert(var_name, id);
+    }
+    for list_name in &program.stage.lists {
+        let id = p.declare_named_data(Kind::List, &["Stage", list_name]);
+        p.global_lists.insert(list_name, id);
+    }
+    for (table_name, table) in &program.stage.tables {
+        let id = p.declare_named_data(Kind::Table, &["Stage", table_name]);
+        define_table(id, table, &mut p.data_ctx, &mut p.object_module);
+        p.global_tables.insert(table_name, (id, table));
+    }
+
+    add_initializers(
+        &p.global_vars,
+        &p.global_lists,
+        &program.stage.initial_values,
+        &program.stage.initial_items,
+        &mut p.initial_values,
+        &mut p.initial_items,
+    );
+    p.add_saved(
+        None,
+        program.stage.variables.iter(),
+        program.stage.lists.iter(),
+    );
+    p.all_vars.extend(p.global_vars.values());
+    p.all_lists.extend(p.global_lists.values());
     p.generate_sprite(&program.stage, "Stage", &mut ctx, &mut func_ctx)?;
     for (name, sprite) in &program.sprites {
         p.generate_sprite(sprite, name, &mut ctx, &mut func_ctx)?;
     }
+    let initializer = p.generate_initializer(&mut ctx, &mut func_ctx);
+    p.generate_state_functions(&mut ctx, &mut func_ctx);
+    let destructor = (p.profile == Profile::Debug)
+        .then(|| p.generate_destructor(&mut ctx, &mut func_ctx));
     let main_signature = Signature {
         params: Vec::new(),
         returns: vec![AbiParam::new(I32)],
@@ -115,6 +236,13 @@ pub fn write_object_file(program: &ir::Program, path: &Path) -> Result<()> {
     fb.switch_to_block(block);
     fb.seal_block(block);
 
+    if let Some(destructor) = destructor {
+        let func_ref =
+            p.object_module.declare_func_in_func(destructor, fb.func);
+        let destructor = fb.ins().func_addr(I64, func_ref);
+        p.call_extern("leak_check_init", &[destructor], &mut fb);
+    }
+
     if p.uses_drand48 {
         let tloc = fb.ins().iconst(I64, 0);
         let time = p.call_extern("time", &[tloc], &mut fb);
@@ -122,7 +250,14 @@ pub fn write_object_file(program: &ir::Program, path: &Path) -> Result<()> {
         p.call_extern("srand48", &[time], &mut fb);
     }
 
-    for entry_point in &p.entry_points {
+    if let Some(initializer) = initializer {
+        let func_ref =
+            p.object_module.declare_func_in_func(initializer, fb.func);
+        fb.ins().call(func_ref, &[]);
+    }
+
+    p.entry_points.sort_by(|(a, _), (b, _)| a.run_order(b));
+    for (_, entry_point) in &p.entry_points {
         let func_ref =
             p.object_module.declare_func_in_func(*entry_point, fb.func);
         fb.ins().call(func_ref, &[]);
@@ -130,13 +265,38 @@ pub fn write_object_file(program: &ir::Program, path: &Path) -> Result<()> {
     let exit_code = fb.ins().iconst(I32, 0);
     fb.ins().return_(&[exit_code]);
     fb.finalize();
+    let program_main = p
+        .object_module
+        .declare_anonymous_function(&main_signature)
+        .unwrap();
+    p.define_function(program_main, &mut ctx);
+
+    // The program runs on a thread of its own, whose stack size can be chosen.
+    ctx.clear();
+    ctx.func = Function::with_name_signature(
+        UserFuncName::default(),
+        main_signature.clone(),
+    );
+    let mut fb = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+    let block = fb.create_block();
+    fb.switch_to_block(block);
+    fb.seal_block(block);
+    let func_ref = p.object_module.declare_func_in_func(program_main, fb.func);
+    let program_main = fb.ins().func_addr(I64, func_ref);
+    if opts.unbuffered {
+        p.call_extern("rt_unbuffered_stdout", &[], &mut fb);
+    }
+    let stack_size = fb.ins().iconst(I64, opts.stack_size.0 as i64);
+    let exit_code =
+        p.call_extern("run_with_stack", &[program_main, stack_size], &mut fb);
+    let exit_code = fb.inst_results(exit_code)[0];
+    fb.ins().return_(&[exit_code]);
+    fb.finalize();
     let main_func_id = p
         .object_module
         .declare_function("main", Linkage::Export, &main_signature)
         .unwrap();
-    p.object_module
-        .define_function(main_func_id, &mut ctx)
-        .unwrap();
+    p.define_function(main_func_id, &mut ctx);
 
     for &var_id in p.global_vars.values() {
         define_variable(var_id, &mut p.data_ctx, &mut p.object_module);
@@ -166,70 +326,183 @@ pub fn write_object_file(program: &ir::Program, path: &Path) -> Result<()> {
         p.object_module.define_data(*id, &p.data_ctx).unwrap();
     }
 
-    let object_bytes = p.object_module.finish().emit().unwrap();
-    let mut file = File::create(path).unwrap();
-    file.write_all(&object_bytes).unwrap();
+    let inline_asm = (!p.inline_asm.is_empty()).then(|| {
+        let mut source = String::from("default rel\nsection .text\n");
+        for (i, code) in p.inline_asm.iter().enumerate() {
+            let symbol = inline_asm_symbol(i);
+            writeln!(source, "\nglobal {symbol}\n{symbol}:\n{code}\n    ret")
+                .unwrap();
+        }
+        source
+    });
 
-    Ok(())
+    let symbols = p.symbol_map();
+    let product = p.object_module.finish();
+    let source_map = p
+        .source_spans
+        .map(|spans| source_map(&product, &p.source_ranges, &spans));
+    Ok(Output {
+        asm: p.asm,
+        inline_asm,
+        symbols,
+        source_map,
+        object: product.emit().unwrap(),
+    })
+}
+
+/// Turns the ranges of the statements within each function into ranges of
+/// addresses in `.text`.
+fn source_map(
+    product: &ObjectProduct,
+    source_ranges: &[(FuncId, Vec<(Range<u32>, SourceLoc)>)],
+    spans: &[Span],
+) -> Vec<(Range<u64>, Span)> {
+    let mut source_map = Vec::new();
+    for (func_id, ranges) in source_ranges {
+        let (symbol, _) = product.functions[*func_id].unwrap();
+        let start = product.object.symbol(symbol).value;
+        for (range, srcloc) in ranges {
+            source_map.push((
+                start + u64::from(range.start)..start + u64::from(range.end),
+                spans[srcloc.bits() as usize],
+            ));
+        }
+    }
+    source_map.sort_by_key(|(range, _)| range.start);
+    source_map
+}
+
+/// Each `asm` block becomes a function that is called where the block
+/// appears.
+fn inline_asm_symbol(index: usize) -> String {
+    format!("scratch_inline_asm_{index}")
 }
 
 struct Program<'a> {
+    code_map: &'a CodeMap,
+    profile: Profile,
     target_frontend_config: TargetFrontendConfig,
     object_module: ObjectModule,
     data_ctx: DataDescription,
-    entry_points: Vec<FuncId>,
+    /// The scripts that run when the flag is clicked.
+    entry_points: Vec<(&'a Procedure, FuncId)>,
     variable_counter: u32,
     extern_function_signatures: HashMap<&'static str, Signature>,
     extern_functions: HashMap<&'static str, FuncId>,
-    local_vars: HashMap<&'a str, DataId>,
-    local_lists: HashMap<&'a str, DataId>,
-    sprite_vars: HashMap<&'a str, DataId>,
-    sprite_lists: HashMap<&'a str, DataId>,
-    global_vars: HashMap<&'a str, DataId>,
-    global_lists: HashMap<&'a str, DataId>,
-    static_strs: HashMap<Cow<'a, str>, DataId>,
+    // Maps that are iterated over while generating code are ordered so that
+    // the same program always gives the same object file.
+    local_vars: BTreeMap<&'a str, DataId>,
+    local_lists: BTreeMap<&'a str, DataId>,
+    sprite_vars: BTreeMap<&'a str, DataId>,
+    sprite_lists: BTreeMap<&'a str, DataId>,
+    global_vars: BTreeMap<&'a str, DataId>,
+    global_lists: BTreeMap<&'a str, DataId>,
+    sprite_tables: BTreeMap<&'a str, (DataId, &'a Table)>,
+    global_tables: BTreeMap<&'a str, (DataId, &'a Table)>,
+    local_types: HashMap<&'a str, Type>,
+    sprite_types: HashMap<&'a str, Type>,
+    global_types: HashMap<&'a str, Type>,
+    sprite_externs: HashMap<&'a str, &'a ExternFunction>,
+    global_externs: HashMap<&'a str, &'a ExternFunction>,
+    /// Functions declared with `extern` that have been called.
+    extern_imports: HashMap<&'a str, FuncId>,
+    static_strs: BTreeMap<Cow<'a, str>, DataId>,
+    /// Tables for the literal patterns of `str-match`.
+    pattern_tables: HashMap<&'a str, DataId>,
+    initial_values: Vec<(DataId, &'a Immediate)>,
+    initial_items: Vec<(DataId, &'a [Immediate])>,
+    /// Arrays of the initial items of lists, for `%static-get`.
+    static_lists: HashMap<DataId, StaticList>,
+    saved_vars: Vec<Saved>,
+    saved_lists: Vec<Saved>,
+    state_functions: Option<(FuncId, FuncId)>,
+    /// Every variable and list, which debug builds free at exit before
+    /// checking for leaks.
+    all_vars: Vec<DataId>,
+    all_lists: Vec<DataId>,
     custom_procs: HashMap<&'a str, CustomProc<'a>>,
-    proc_params: HashMap<&'a str, (Value, Value)>,
+    proc_params: BTreeMap<&'a str, MixedSizeValue>,
+    /// The counters of the `for` loops being generated that aren't assigned
+    /// to in their bodies.
+    counters: HashMap<&'a str, Counter>,
+    temporaries: Vec<Temporary>,
     broadcasts: Broadcasts<'a>,
     main_broadcast_handler: Option<FuncId>,
     answer: Option<DataId>,
     uses_drand48: bool,
     stop_block: Option<Block>,
+    asm: Option<String>,
+    inline_asm: Vec<String>,
+    /// The spans of the statements that have been generated, indexed by
+    /// their source locations, if `--annotate-asm` was given.
+    source_spans: Option<Vec<Span>>,
+    /// The source location of the statement being generated.
+    srcloc: SourceLoc,
+    /// The code ranges of the statements in each function that has been
+    /// defined.
+    source_ranges: Vec<(FuncId, Vec<(Range<u32>, SourceLoc)>)>,
+    /// The sprite being generated.
+    sprite_name: &'a str,
+    /// Descriptions of the named symbols, for the symbol map.
+    symbols: BTreeMap<String, String>,
 }
 
 impl<'a> Program<'a> {
     fn generate_sprite(
         &mut self,
         sprite: &'a Sprite,
-        name: &str,
+        name: &'a str,
         ctx: &mut Context,
         func_ctx: &mut FunctionBuilderContext,
     ) -> Result<()> {
+        self.sprite_name = name;
         self.sprite_vars.clear();
-        self.sprite_vars.extend(
+        for var_name in &sprite.variables {
+            let id = self.declare_named_data(Kind::Variable, &[name, var_name]);
+            self.sprite_vars.insert(var_name, id);
+        }
+
+        self.sprite_types.clear();
+        self.sprite_types.extend(
+            sprite.annotations.iter().map(|(name, typ)| (&**name, *typ)),
+        );
+
+        self.sprite_externs.clear();
+        self.sprite_externs.extend(
             sprite
-                .variables
+                .externs
                 .iter()
-                .map(String::as_str)
-                .zip(iter::repeat_with(|| {
-                    self.object_module
-                        .declare_anonymous_data(true, false)
-                        .unwrap()
-                })),
+                .map(|(name, function)| (&**name, function)),
         );
 
         self.sprite_lists.clear();
-        self.sprite_lists
-            .extend(sprite.lists.iter().map(String::as_str).zip(
-                iter::repeat_with(|| {
-                    self.object_module
-                        .declare_anonymous_data(true, false)
-                        .unwrap()
-                }),
-            ));
+        for list_name in &sprite.lists {
+            let id = self.declare_named_data(Kind::List, &[name, list_name]);
+            self.sprite_lists.insert(list_name, id);
+        }
 
-        // Prevent duplicate definitions of global variables/lists.
+        self.sprite_tables.clear();
+        for (table_name, table) in &sprite.tables {
+            let id = self.declare_named_data(Kind::Table, &[name, table_name]);
+            self.sprite_tables.insert(table_name, (id, table));
+        }
+
+        // Prevent duplicate definitions of global variables/lists/tables.
         if name != "Stage" {
+            add_initializers(
+                &self.sprite_vars,
+                &self.sprite_lists,
+                &sprite.initial_values,
+                &sprite.initial_items,
+                &mut self.initial_values,
+                &mut self.initial_items,
+            );
+            self.add_saved(
+                Some(name),
+                sprite.variables.iter(),
+                sprite.lists.iter(),
+            );
+
             for &var_id in self.sprite_vars.values() {
                 define_variable(
                     var_id,
@@ -245,16 +518,36 @@ impl<'a> Program<'a> {
                     &mut self.object_module,
                 );
             }
+
+            for &(table_id, table) in self.sprite_tables.values() {
+                define_table(
+                    table_id,
+                    table,
+                    &mut self.data_ctx,
+                    &mut self.object_module,
+                );
+            }
+
+            self.all_vars.extend(self.sprite_vars.values());
+            self.all_lists.extend(self.sprite_lists.values());
         }
 
         self.custom_procs = sprite
             .procedures
             .iter()
-            .map(|(name, proc)| Ok(match &**name {
-                "when-flag-clicked" | "when-cloned" | "when-received" => None,
+            .map(|(proc_name, proc)| Ok(match &**proc_name {
+                "when-timer-greater-than" | "when-loudness-greater-than" => {
+                    return Err(Box::new(Error::EventNotSupported {
+                        span: proc[0].span,
+                        event: proc_name.clone(),
+                        target: "x86_64",
+                    }));
+                }
+                name if is_event(name) => None,
                 _ => {
                     let [proc] = &proc[..] else {
-                        todo!("duplicate definition of custom procdeure `{name}`");
+                        // Duplicates are reported while lowering (E0071).
+                        unreachable!();
                     };
 
                     let param_names = proc.params.iter().map(|(param, span)| {
@@ -266,28 +559,44 @@ impl<'a> Program<'a> {
                                 },
                             )),
                         }
-                    }).collect::<Result<_>>()?;
-                    let params = iter::repeat_with(|| AbiParam::new(I64))
-                        .take(proc.params.len() * 2)
+                    }).collect::<Result<Vec<_>>>()?;
+                    let param_types = param_names
+                        .iter()
+                        .map(|param_name| {
+                            proc.annotations.get(*param_name).copied()
+                        })
+                        .collect::<Vec<_>>();
+                    let params = param_types
+                        .iter()
+                        .flat_map(|typ| match typ {
+                            Some(Type::Num) => vec![AbiParam::new(F64)],
+                            Some(Type::Bool) => vec![AbiParam::new(I8)],
+                            Some(Type::Str) | None => {
+                                vec![AbiParam::new(I64); 2]
+                            }
+                        })
                         .collect();
-                    let id = self
-                        .object_module
-                        .declare_anonymous_function(
-                            &Signature {
-                                params,
-                                returns: Vec::new(),
-                                call_conv: CallConv::SystemV
-                            },
-                        ).unwrap();
-                    Some((&**name, CustomProc { id, param_names }))
+                    let id = self.declare_named_function(
+                        Kind::Proc,
+                        &[name, proc_name, "0"],
+                        &Signature {
+                            params,
+                            returns: Vec::new(),
+                            call_conv: CallConv::SystemV
+                        },
+                    );
+                    Some((
+                        &**proc_name,
+                        CustomProc { id, param_names, param_types },
+                    ))
                 }
             }))
             .filter_map(Result::transpose)
             .collect::<Result<_>>()?;
 
         for (name, procs) in &sprite.procedures {
-            for proc in procs {
-                self.generate_proc(name, proc, ctx, func_ctx)?;
+            for (i, proc) in procs.iter().enumerate() {
+                self.generate_proc(name, i, proc, ctx, func_ctx)?;
             }
         }
 
@@ -297,29 +606,34 @@ impl<'a> Program<'a> {
     fn generate_proc(
         &mut self,
         name: &str,
+        index: usize,
         proc: &'a Procedure,
         ctx: &mut Context,
         func_ctx: &mut FunctionBuilderContext,
     ) -> Result<()> {
+        let index = index.to_string();
+        let path = [self.sprite_name, name, &index];
         self.local_vars.clear();
-        self.local_vars
-            .extend(proc.variables.iter().map(String::as_str).zip(
-                iter::repeat_with(|| {
-                    self.object_module
-                        .declare_anonymous_data(true, false)
-                        .unwrap()
-                }),
-            ));
+        for var_name in &proc.variables {
+            let id = self.declare_named_data(
+                Kind::Variable,
+                &[&path[..], &[var_name.as_str()]].concat(),
+            );
+            self.local_vars.insert(var_name, id);
+        }
+
+        self.local_types.clear();
+        self.local_types
+            .extend(proc.annotations.iter().map(|(name, typ)| (&**name, *typ)));
 
         self.local_lists.clear();
-        self.local_lists
-            .extend(proc.lists.iter().map(String::as_str).zip(
-                iter::repeat_with(|| {
-                    self.object_module
-                        .declare_anonymous_data(true, false)
-                        .unwrap()
-                }),
-            ));
+        for list_name in &proc.lists {
+            let id = self.declare_named_data(
+                Kind::List,
+                &[&path[..], &[list_name.as_str()]].concat(),
+            );
+            self.local_lists.insert(list_name, id);
+        }
 
         for &var_id in self.local_vars.values() {
             define_variable(
@@ -333,6 +647,18 @@ impl<'a> Program<'a> {
             define_list(list_id, &mut self.data_ctx, &mut self.object_module);
         }
 
+        self.all_vars.extend(self.local_vars.values());
+        self.all_lists.extend(self.local_lists.values());
+
+        add_initializers(
+            &self.local_vars,
+            &self.local_lists,
+            &proc.initial_values,
+            &proc.initial_items,
+            &mut self.initial_values,
+            &mut self.initial_items,
+        );
+
         ctx.clear();
         self.proc_params.clear();
         self.stop_block = None;
@@ -341,11 +667,9 @@ impl<'a> Program<'a> {
             "when-flag-clicked" => {
                 assert!(proc.params.is_empty());
                 let signature = Signature::new(CallConv::SystemV);
-                let func_id = self
-                    .object_module
-                    .declare_anonymous_function(&signature)
-                    .unwrap();
-                self.entry_points.push(func_id);
+                let func_id =
+                    self.declare_named_function(Kind::Proc, &path, &signature);
+                self.entry_points.push((proc, func_id));
                 ctx.func = Function::with_name_signature(
                     UserFuncName::default(),
                     signature,
@@ -358,7 +682,7 @@ impl<'a> Program<'a> {
                     fb.ins().return_(&[]);
                 }
                 fb.finalize();
-                self.object_module.define_function(func_id, ctx).unwrap();
+                self.define_function(func_id, ctx);
             }
             "when-received" => {
                 let [(Expr::Imm(Immediate::String(broadcast_name)), _)] =
@@ -367,24 +691,22 @@ impl<'a> Program<'a> {
                     todo!();
                 };
                 let signature = Signature::new(CallConv::SystemV);
-                let func_id = self
-                    .object_module
-                    .declare_anonymous_function(&signature)
-                    .unwrap();
+                let func_id =
+                    self.declare_named_function(Kind::Proc, &path, &signature);
+                if !self.broadcasts.contains_key(&**broadcast_name) {
+                    let handler = self.declare_named_function(
+                        Kind::BroadcastHandler,
+                        &[broadcast_name],
+                        &signature,
+                    );
+                    self.broadcasts
+                        .insert(broadcast_name, (handler, Vec::new()));
+                }
                 self.broadcasts
-                    .entry(broadcast_name)
-                    .or_insert_with(|| {
-                        (
-                            self.object_module
-                                .declare_anonymous_function(&Signature::new(
-                                    CallConv::SystemV,
-                                ))
-                                .unwrap(),
-                            Vec::new(),
-                        )
-                    })
+                    .get_mut(&**broadcast_name)
+                    .unwrap()
                     .1
-                    .push(func_id);
+                    .push((proc, func_id));
                 ctx.func = Function::with_name_signature(
                     UserFuncName::default(),
                     signature,
@@ -397,7 +719,7 @@ impl<'a> Program<'a> {
                     fb.ins().return_(&[]);
                 }
                 fb.finalize();
-                self.object_module.define_function(func_id, ctx).unwrap();
+                self.define_function(func_id, ctx);
             }
             _ => {
                 let func_id = self.custom_procs[name].id;
@@ -416,19 +738,23 @@ impl<'a> Program<'a> {
                 fb.switch_to_block(entry);
                 fb.seal_block(entry);
                 fb.append_block_params_for_function_params(entry);
-                self.proc_params.extend(
-                    proc.params
-                        .iter()
-                        .map(|(param, _)| match param {
-                            Expr::Sym(param, _) => &**param,
-                            _ => unreachable!(),
-                        })
-                        .zip(
-                            fb.block_params(entry)
-                                .chunks_exact(2)
-                                .map(|chunk| (chunk[0], chunk[1])),
-                        ),
-                );
+                self.check_stack(name, &mut fb);
+                let mut block_params = fb.block_params(entry).iter().copied();
+                for (param, _) in &proc.params {
+                    let Expr::Sym(param, _) = param else {
+                        unreachable!();
+                    };
+                    let value = match self.local_types.get(&**param) {
+                        Some(Type::Num | Type::Bool) => {
+                            MixedSizeValue::Single(block_params.next().unwrap())
+                        }
+                        Some(Type::Str) | None => MixedSizeValue::Pair([
+                            block_params.next().unwrap(),
+                            block_params.next().unwrap(),
+                        ]),
+                    };
+                    self.proc_params.insert(param, value);
+                }
                 if !self.proc_params.is_empty() {
                     self.stop_block = Some(fb.create_block());
                 }
@@ -437,26 +763,240 @@ impl<'a> Program<'a> {
                         fb.ins().jump(stop_block, &[]);
                         fb.switch_to_block(stop_block);
                         fb.seal_block(stop_block);
-                        let params = fb
-                            .block_params(entry)
-                            .iter()
-                            .copied()
-                            .step_by(2)
+                        let boxed_params = self
+                            .proc_params
+                            .values()
+                            .filter_map(|param| match param {
+                                MixedSizeValue::Pair([low, _]) => Some(*low),
+                                MixedSizeValue::Single(_) => None,
+                            })
                             .collect::<Vec<_>>();
-                        for param in params {
+                        for param in boxed_params {
                             self.call_extern("drop_any", &[param], &mut fb);
                         }
                     }
                     fb.ins().return_(&[]);
                 }
                 fb.finalize();
-                self.object_module.define_function(func_id, ctx).unwrap();
+                self.define_function(func_id, ctx);
             }
         }
 
         Ok(())
     }
 
+    /// Adds sprite-level or, if `sprite_name` is `None`, global variables and
+    /// lists to save files.
+    fn add_saved<'v>(
+        &mut self,
+        sprite_name: Option<&str>,
+        vars: impl Iterator<Item = &'v String>,
+        lists: impl Iterator<Item = &'v String>,
+    ) {
+        let (var_ids, list_ids, types) = match sprite_name {
+            Some(_) => {
+                (&self.sprite_vars, &self.sprite_lists, &self.sprite_types)
+            }
+            None => (&self.global_vars, &self.global_lists, &self.global_types),
+        };
+        let key = |name: &str| match sprite_name {
+            Some(sprite_name) => format!("{sprite_name}/{name}"),
+            None => name.to_owned(),
+        };
+        self.saved_vars.extend(vars.map(|name| Saved {
+            key: key(name),
+            id: var_ids[&**name],
+            is_num: types.get(&**name) == Some(&Type::Num),
+        }));
+        self.saved_lists.extend(lists.map(|name| Saved {
+            key: key(name),
+            id: list_ids[&**name],
+            is_num: false,
+        }));
+    }
+
+    /// Generates a function that gives variables and lists their initial
+    /// values. It is called before any script starts.
+    fn generate_initializer(
+        &mut self,
+        ctx: &mut Context,
+        func_ctx: &mut FunctionBuilderContext,
+    ) -> Option<FuncId> {
+        if self.initial_values.is_empty() && self.initial_items.is_empty() {
+            return None;
+        }
+
+        let signature = Signature::new(CallConv::SystemV);
+        let func_id = self
+            .object_module
+            .declare_anonymous_function(&signature)
+            .unwrap();
+        ctx.clear();
+        ctx.func =
+            Function::with_name_signature(UserFuncName::default(), signature);
+        let mut fb = FunctionBuilder::new(&mut ctx.func, func_ctx);
+        let entry = fb.create_block();
+        fb.switch_to_block(entry);
+        fb.seal_block(entry);
+
+        // Variables start out as 0, which doesn't need to be dropped.
+        let mem_flags = MemFlags::trusted();
+        for (var_id, value) in mem::take(&mut self.initial_values) {
+            let var = self.object_module.declare_data_in_func(var_id, fb.func);
+            let var = fb.ins().global_value(I64, var);
+            let (low, high) = self.generate_any_imm(value, &mut fb);
+            fb.ins().store(mem_flags, low, var, 0);
+            fb.ins().store(mem_flags, high, var, 8);
+        }
+
+        for (list_id, items) in mem::take(&mut self.initial_items) {
+            let list =
+                self.object_module.declare_data_in_func(list_id, fb.func);
+            let list = fb.ins().global_value(I64, list);
+            for item in items {
+                let (low, high) = self.generate_any_imm(item, &mut fb);
+                self.call_extern("list_append", &[list, low, high], &mut fb);
+            }
+        }
+
+        fb.ins().return_(&[]);
+        fb.finalize();
+        self.define_function(func_id, ctx);
+        Some(func_id)
+    }
+
+    /// Generates a function that frees every variable and list, so that what
+    /// is still allocated after it has run can be reported as leaked.
+    fn generate_destructor(
+        &mut self,
+        ctx: &mut Context,
+        func_ctx: &mut FunctionBuilderContext,
+    ) -> FuncId {
+        let signature = Signature::new(CallConv::SystemV);
+        let func_id = self
+            .object_module
+            .declare_anonymous_function(&signature)
+            .unwrap();
+        ctx.clear();
+        ctx.func =
+            Function::with_name_signature(UserFuncName::default(), signature);
+        let mut fb = FunctionBuilder::new(&mut ctx.func, func_ctx);
+        let entry = fb.create_block();
+        fb.switch_to_block(entry);
+        fb.seal_block(entry);
+
+        let mem_flags = MemFlags::trusted();
+        for var_id in
+            mem::take(&mut self.all_vars).into_iter().chain(self.answer)
+        {
+            let var = self.data_address(var_id, &mut fb);
+            let low = fb.ins().load(I64, mem_flags, var, 0);
+            self.call_extern("drop_any", &[low], &mut fb);
+        }
+
+        for list_id in mem::take(&mut self.all_lists) {
+            let list = self.data_address(list_id, &mut fb);
+            self.call_extern("list_delete_all", &[list], &mut fb);
+            let items = fb.ins().load(I64, mem_flags, list, 0);
+            self.call_extern("free", &[items], &mut fb);
+        }
+
+        fb.ins().return_(&[]);
+        fb.finalize();
+        self.define_function(func_id, ctx);
+        func_id
+    }
+
+    /// Generates a call to `runtime_error` in debug builds, reporting
+    /// `message` at `span`. The current block is terminated either way.
+    fn trap(&mut self, message: &str, span: Span, fb: &mut FunctionBuilder) {
+        if self.profile == Profile::Debug {
+            let loc = self.code_map.look_up_span(span);
+            self.runtime_error(
+                format!(
+                    "{message}\n  --> {}:{}:{}",
+                    loc.file.name(),
+                    loc.begin.line + 1,
+                    loc.begin.column + 1,
+                ),
+                fb,
+            );
+        } else {
+            fb.ins().trap(TrapCode::UnreachableCodeReached);
+        }
+    }
+
+    /// Generates a call to `runtime_error`, which terminates the current
+    /// block.
+    fn runtime_error(&mut self, message: String, fb: &mut FunctionBuilder) {
+        let (ptr, len) = self.allocate_static_str(Cow::Owned(message), fb);
+        self.call_extern("runtime_error", &[ptr, len], fb);
+        fb.ins().trap(TrapCode::UnreachableCodeReached);
+    }
+
+    /// Generates a check at the start of a custom procedure that there is
+    /// enough stack left to run it, which stops runaway recursion with an
+    /// error before it overflows the stack.
+    fn check_stack(&mut self, proc_name: &str, fb: &mut FunctionBuilder) {
+        let stack_limit = self
+            .object_module
+            .declare_data("stack_limit", Linkage::Import, true, false)
+            .unwrap();
+        let stack_limit = self
+            .object_module
+            .declare_data_in_func(stack_limit, fb.func);
+        let stack_limit = fb.ins().global_value(I64, stack_limit);
+        let stack_limit =
+            fb.ins().load(I64, MemFlags::trusted(), stack_limit, 0);
+        let stack_pointer = fb.ins().get_stack_pointer(I64);
+        let is_too_deep =
+            fb.ins()
+                .icmp(IntCC::UnsignedLessThan, stack_pointer, stack_limit);
+        let too_deep = fb.create_block();
+        let ok = fb.create_block();
+        fb.ins().brif(is_too_deep, too_deep, &[], ok, &[]);
+        fb.switch_to_block(too_deep);
+        fb.seal_block(too_deep);
+        self.runtime_error(
+            format!(
+                "recursion too deep in `{proc_name}`, try a bigger \
+                `--stack-size`"
+            ),
+            fb,
+        );
+        fb.switch_to_block(ok);
+        fb.seal_block(ok);
+    }
+
+    fn define_function(&mut self, func_id: FuncId, ctx: &mut Context) {
+        ctx.set_disasm(self.asm.is_some());
+        self.object_module.define_function(func_id, ctx).unwrap();
+        if self.source_spans.is_some() {
+            let ranges = ctx
+                .compiled_code()
+                .unwrap()
+                .buffer
+                .get_srclocs_sorted()
+                .iter()
+                .filter(|srcloc| !srcloc.loc.is_default())
+                .map(|srcloc| (srcloc.start..srcloc.end, srcloc.loc))
+                .collect();
+            self.source_ranges.push((func_id, ranges));
+        }
+        if let Some(asm) = &mut self.asm {
+            let vcode = ctx
+                .compiled_code()
+                .and_then(|code| code.vcode.as_deref())
+                .unwrap_or_default();
+            let decl =
+                self.object_module.declarations().get_function_decl(func_id);
+            match &decl.name {
+                Some(name) => writeln!(asm, "{name}:\n{vcode}").unwrap(),
+                None => writeln!(asm, "{func_id}:\n{vcode}").unwrap(),
+            }
+        }
+    }
+
     fn new_variable(&mut self) -> Variable {
         self.variable_counter += 1;
         Variable::from_u32(self.variable_counter - 1)
@@ -468,11 +1008,7 @@ impl<'a> Program<'a> {
         fb: &mut FunctionBuilder,
     ) -> (Value, Value) {
         let len = s.len();
-        let data_id = *self.static_strs.entry(s).or_insert_with(|| {
-            self.object_module
-                .declare_anonymous_data(false, false)
-                .unwrap()
-        });
+        let data_id = self.static_str_id(s);
         let global_value =
             self.object_module.declare_data_in_func(data_id, fb.func);
         // Offset by 1 byte since static strs start on odd addresses.
@@ -524,13 +1060,56 @@ impl<'a> Program<'a> {
         Some(fb.ins().global_value(I64, global_value))
     }
 
+    /// The type of a parameter or variable whose value can be used without
+    /// going through an `Any`.
+    fn unboxed_type(&self, name: &str) -> Option<Type> {
+        if name == "answer" {
+            None
+        } else if let Some(param) = self.proc_params.get(name) {
+            match param {
+                MixedSizeValue::Single(_) => {
+                    self.local_types.get(name).copied()
+                }
+                MixedSizeValue::Pair(_) => None,
+            }
+        } else {
+            // Only numbers can be stored unboxed in variables since they are
+            // initialized to 0.
+            let types = if self.local_vars.contains_key(name) {
+                &self.local_types
+            } else if self.sprite_vars.contains_key(name) {
+                &self.sprite_types
+            } else {
+                &self.global_types
+            };
+            types.get(name).copied().filter(|typ| *typ == Type::Num)
+        }
+    }
+
+    /// The data of a static string, which is defined once the whole program
+    /// has been generated. The string starts 1 byte into it.
+    fn static_str_id(&mut self, s: Cow<'a, str>) -> DataId {
+        *self.static_strs.entry(s).or_insert_with(|| {
+            self.object_module
+                .declare_anonymous_data(false, false)
+                .unwrap()
+        })
+    }
+
     fn lookup_list(
         &self,
         name: &str,
         span: Span,
         fb: &mut FunctionBuilder,
     ) -> Result<Value> {
-        let data_id = *self
+        let data_id = self.list_id(name, span)?;
+        let global_value =
+            self.object_module.declare_data_in_func(data_id, fb.func);
+        Ok(fb.ins().global_value(I64, global_value))
+    }
+
+    fn list_id(&self, name: &str, span: Span) -> Result<DataId> {
+        Ok(*self
             .local_lists
             .get(name)
             .or_else(|| self.sprite_lists.get(name))
@@ -538,10 +1117,7 @@ impl<'a> Program<'a> {
             .ok_or_else(|| Error::UnknownList {
                 span,
                 list_name: name.into(),
-            })?;
-        let global_value =
-            self.object_module.declare_data_in_func(data_id, fb.func);
-        Ok(fb.ins().global_value(I64, global_value))
+            })?)
     }
 
     fn answer(&mut self, fb: &mut FunctionBuilder) -> Value {
@@ -603,59 +1179,117 @@ fn extern_function_signatures() -> HashMap<&'static str, Signature> {
     }
 
     HashMap::from([
+        sig! { "any_char_at": I64, I64, F64 -> I64, I64 },
+        sig! { "any_char_at_index": I64, I64, I64 -> I64, I64 },
         sig! { "any_eq_any": I64, I64, I64, I64 -> I8 },
         sig! { "any_eq_bool": I64, I64, I8 -> I8 },
         sig! { "any_eq_double": I64, I64, F64 -> I8 },
+        sig! { "any_eq_folded_lit": I64, I64, I64, I64 -> I8 },
         sig! { "any_eq_str": I64, I64, I64, I64 -> I8 },
         sig! { "any_lt_any": I64, I64, I64, I64 -> I8 },
         sig! { "any_lt_bool": I64, I64, I8 -> I8 },
         sig! { "any_lt_double": I64, I64, F64 -> I8 },
         sig! { "any_lt_str": I64, I64, I64, I64 -> I8 },
+        sig! { "any_str_length": I64, I64 -> I64 },
         sig! { "any_to_bool": I64, I64 -> I8 },
         sig! { "any_to_cow": I64, I64 -> I64, I64 },
         sig! { "any_to_double": I64, I64 -> F64 },
         sig! { "ask": I64, I64 -> I64, I64 },
         sig! { "bool_lt_any": I8, I64, I64 -> I8 },
         sig! { "bool_to_str": I8 -> I64, I64 },
-        sig! { "char_at": I64, I64, I64 -> I64, I64 },
+        sig! { "char_at": I64, I64, F64 -> I64, I64 },
+        sig! { "char_at_index": I64, I64, I64 -> I64, I64 },
         sig! { "clone_any": I64, I64 -> I64, I64 },
         sig! { "clone_cow": I64, I64 -> I64, I64 },
+        sig! { "cstr_to_cow": I64 -> I64, I64 },
         sig! { "double_lt_any": I64, I64, F64 -> I8 },
         sig! { "double_to_cow": F64 -> I64, I64 },
         sig! { "drop_any": I64 -> },
         sig! { "drop_cow": I64 -> },
         sig! { "exit": I32 -> },
-        sig! { "fmod": F64, F64 -> F64 },
+        sig! { "fclose": I64 -> I32 },
+        sig! { "fopen": I64, I64 -> I64 },
+        sig! { "format_decimal": F64, I64 -> I64, I64 },
+        sig! { "format_radix": I64, I32, I64 -> I64, I64 },
         sig! { "free": I64 -> },
+        sig! { "fwrite": I64, I64, I64, I64 -> I64 },
+        sig! { "json_get": I64, I64, I64, I64 -> I64, I64 },
+        sig! { "json_parse_into_lists": I64, I64, I64, I64 -> },
+        sig! { "json_set": I64, I64, I64, I64, I64, I64 -> I64, I64 },
+        sig! { "leak_check_init": I64 -> },
         sig! { "list_append": I64, I64, I64 -> },
         sig! { "list_delete": I64, I64, I64 -> },
         sig! { "list_delete_all": I64 -> },
         sig! { "list_get": I64, I64, I64 -> I64, I64 },
+        sig! { "list_get_index": I64, I64 -> I64, I64 },
+        sig! { "list_index": I64, I64, I64 -> I64 },
         sig! { "list_replace": I64, I64, I64, I64, I64 -> },
         sig! { "malloc": I64 -> I64 },
         sig! { "random_between": F64, F64 -> F64 },
+        sig! { "rt_eprint_any": I64, I64 -> },
+        sig! { "rt_eprint_str": I64, I64 -> },
+        sig! { "rt_flush": -> },
+        sig! { "rt_print_any": I64, I64 -> },
+        sig! { "rt_print_fixed": F64, F64 -> },
+        sig! { "rt_print_newline": -> },
+        sig! { "rt_print_str": I64, I64 -> },
+        sig! { "rt_speak": I64, I64 -> },
+        sig! { "rt_unbuffered_stdout": -> },
+        sig! { "run_with_stack": I64, I64 -> I32 },
+        sig! { "runtime_error": I64, I64 -> },
+        sig! { "scratch_mod": F64, F64 -> F64 },
         sig! { "srand48": I64 -> },
+        sig! { "state_load": I64, I64, I64, I64, I64 -> },
+        sig! { "state_write_any": I64, I64, I64 -> },
+        sig! { "state_write_list": I64, I64 -> },
+        sig! { "state_write_str": I64, I64, I64 -> },
         sig! { "str_eq_str": I64, I64, I64, I64 -> I8 },
         sig! { "str_length": I64, I64 -> I64 },
+        sig! { "str_match": I64, I64, I64 -> I8 },
+        sig! { "str_match_dynamic": I64, I64, I64, I64 -> I8 },
         sig! { "str_lt_any": I64, I64, I64, I64 -> I8 },
         sig! { "str_lt_str": I64, I64, I64, I64 -> I8 },
+        sig! { "strndup": I64, I64 -> I64 },
         sig! { "time": I64 -> I64 },
         sig! { "wait_seconds": F64 -> },
-        sig! { "write": I32, I64, I64 -> I64 },
         sig! { "log": F64 -> F64 },
         sig! { "log10": F64 -> F64 },
         sig! { "exp": F64 -> F64 },
-        sig! { "exp10": F64 -> F64 },
+        sig! { "pow": F64, F64 -> F64 },
         sig! { "sin": F64 -> F64 },
         sig! { "cos": F64 -> F64 },
         sig! { "tan": F64 -> F64 },
         sig! { "asin": F64 -> F64 },
         sig! { "acos": F64 -> F64 },
         sig! { "atan": F64 -> F64 },
+        sig! { "atan2": F64, F64 -> F64 },
     ])
 }
 
 struct CustomProc<'a> {
     id: FuncId,
     param_names: Vec<&'a str>,
+    param_types: Vec<Option<Type>>,
+}
+
+/// Records the initial values of the given variables and lists, which are set
+/// by [`Program::generate_initializer`].
+fn add_initializers<'a>(
+    vars: &BTreeMap<&'a str, DataId>,
+    lists: &BTreeMap<&'a str, DataId>,
+    initial_values: &'a HashMap<String, Immediate>,
+    initial_items: &'a HashMap<String, Vec<Immediate>>,
+    value_initializers: &mut Vec<(DataId, &'a Immediate)>,
+    item_initializers: &mut Vec<(DataId, &'a [Immediate])>,
+) {
+    // Going over the ordered maps keeps the initializer the same every time.
+    value_initializers.extend(
+        vars.iter()
+            .filter_map(|(name, &id)| Some((id, initial_values.get(*name)?))),
+    );
+    item_initializers.extend(
+        lists
+            .iter()
+            .filter_map(|(name, &id)| Some((id, &**initial_items.get(*name)?))),
+    );
 }
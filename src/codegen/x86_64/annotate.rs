@@ -0,0 +1,55 @@
+//! Disassembly for `--annotate-asm`, where the source code of each statement
+//! is a comment above its instructions.
+
+use codemap::{CodeMap, Span};
+use std::ops::Range;
+
+/// Adds the source code of the statements to the output of `objdump -d`.
+/// `source_map` gives the spans of the statements by the addresses of their
+/// instructions, sorted by address.
+pub fn annotate(
+    disassembly: &str,
+    source_map: &[(Range<u64>, Span)],
+    code_map: &CodeMap,
+) -> String {
+    let mut annotated = String::new();
+    let mut current = None;
+    for line in disassembly.lines() {
+        if let Some(address) = instruction_address(line) {
+            let i =
+                source_map.partition_point(|(range, _)| range.end <= address);
+            let span = source_map
+                .get(i)
+                .filter(|(range, _)| range.contains(&address))
+                .map(|(_, span)| *span);
+            if let Some(span) = span.filter(|_| span != current) {
+                annotated.push_str(&source_comment(span, code_map));
+            }
+            current = span;
+        } else if line.ends_with(">:") {
+            // The start of a function.
+            current = None;
+        }
+        annotated.push_str(line);
+        annotated.push('\n');
+    }
+    annotated
+}
+
+/// The address of an instruction line like `  1c:\tmov rax,rdi`. Relocations
+/// have a space after the colon instead of a tab, so they are left out.
+fn instruction_address(line: &str) -> Option<u64> {
+    let (address, _) = line.split_once(":\t")?;
+    u64::from_str_radix(address.trim_start(), 16).ok()
+}
+
+/// The first line of a statement as a comment that says where it is.
+fn source_comment(span: Span, code_map: &CodeMap) -> String {
+    let loc = code_map.look_up_span(span);
+    format!(
+        "\n; {}:{}: {}\n",
+        loc.file.name(),
+        loc.begin.line + 1,
+        loc.file.source_line(loc.begin.line).trim(),
+    )
+}
@@ -1,4 +1,5 @@
 use super::Program;
+use crate::ir::proc::Procedure;
 use cranelift::{
     codegen::{
         ir::{Function, UserFuncName},
@@ -7,9 +8,11 @@ use cranelift::{
     prelude::{isa::CallConv, types::*, *},
 };
 use cranelift_module::{FuncId, Module};
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::BTreeMap};
 
-pub(super) type Broadcasts<'a> = HashMap<&'a str, (FuncId, Vec<FuncId>)>;
+/// The handler of every broadcast, along with the scripts it starts.
+pub(super) type Broadcasts<'a> =
+    BTreeMap<&'a str, (FuncId, Vec<(&'a Procedure, FuncId)>)>;
 
 impl Program<'_> {
     pub(super) fn generate_broadcast_handlers(
@@ -21,7 +24,10 @@ impl Program<'_> {
             return;
         };
 
-        for (handler_id, receievers) in self.broadcasts.values() {
+        for (handler_id, mut receievers) in
+            self.broadcasts.clone().into_values()
+        {
+            receievers.sort_by(|(a, _), (b, _)| a.run_order(b));
             ctx.clear();
             ctx.func = Function::with_name_signature(
                 UserFuncName::default(),
@@ -32,17 +38,15 @@ impl Program<'_> {
             let block = fb.create_block();
             fb.switch_to_block(block);
             fb.seal_block(block);
-            for receiver in receievers {
+            for (_, receiver) in receievers {
                 let receiver =
-                    self.object_module.declare_func_in_func(*receiver, fb.func);
+                    self.object_module.declare_func_in_func(receiver, fb.func);
                 fb.ins().call(receiver, &[]);
             }
             fb.ins().return_(&[]);
 
             fb.finalize();
-            self.object_module
-                .define_function(*handler_id, ctx)
-                .unwrap();
+            self.define_function(handler_id, ctx);
         }
 
         ctx.clear();
@@ -86,8 +90,6 @@ impl Program<'_> {
         fb.ins().return_(&[]);
 
         fb.finalize();
-        self.object_module
-            .define_function(main_broadcast_handler, ctx)
-            .unwrap();
+        self.define_function(main_broadcast_handler, ctx);
     }
 }
@@ -1,15 +1,15 @@
 use super::{
-    typ::{expr_type, MixedSizeValue, Typ},
+    typ::{MixedSizeValue, Typ},
     Program,
 };
 use crate::{
     diagnostic::{Error, Result},
-    ir::expr::Expr,
+    ir::{expr::Expr, typ::Type},
 };
 use codemap::Span;
 use cranelift::prelude::{types::*, *};
 use sb3_stuff::Value as Immediate;
-use std::{borrow::Cow, cmp::Ordering};
+use std::{borrow::Cow, cmp::Ordering, f64::consts::PI};
 
 impl<'a> Program<'a> {
     pub(super) fn generate_expr(
@@ -23,12 +23,16 @@ impl<'a> Program<'a> {
                 self.generate_symbol(sym, *sym_span, fb)
             }
             Expr::FuncCall(func_name, span, args) => {
-                self.generate_func_call(func_name, args, *span, fb)
+                let scope = self.temporary_scope();
+                let res =
+                    self.generate_func_call(func_name, args, *span, fb)?;
+                self.drop_temporaries(scope, fb);
+                Ok(res)
             }
-            Expr::AddSub(positives, negatives) => self
+            Expr::AddSub(positives, negatives, _) => self
                 .generate_add_sub(positives, negatives, fb)
                 .map(From::from),
-            Expr::MulDiv(numerators, denominators) => self
+            Expr::MulDiv(numerators, denominators, _) => self
                 .generate_mul_div(numerators, denominators, fb)
                 .map(From::from),
         }
@@ -83,23 +87,43 @@ impl<'a> Program<'a> {
         sym: &str,
         span: Span,
         fb: &mut FunctionBuilder,
+    ) -> Result<MixedSizeValue> {
+        Ok(match self.load_symbol(sym, span, fb)? {
+            MixedSizeValue::Pair(value) => {
+                let cloned = self.call_extern("clone_any", &value, fb);
+                pair(fb.inst_results(cloned)).into()
+            }
+            single @ MixedSizeValue::Single(_) => single,
+        })
+    }
+
+    /// Loads the value of a variable or parameter without cloning it, so it
+    /// can only be borrowed.
+    fn load_symbol(
+        &mut self,
+        sym: &str,
+        span: Span,
+        fb: &mut FunctionBuilder,
     ) -> Result<MixedSizeValue> {
         if sym == "answer" {
             let answer = self.answer(fb);
             let mem_flags = MemFlags::trusted();
             let low = fb.ins().load(I64, mem_flags, answer, 0);
             let high = fb.ins().load(I64, mem_flags, answer, 8);
-            let cloned = self.call_extern("clone_cow", &[low, high], fb);
-            Ok(pair(fb.inst_results(cloned)).into())
-        } else if let Some(param) = self.proc_params.get(sym) {
-            let cloned = self.call_extern("clone_any", &[param.0, param.1], fb);
-            Ok(pair(fb.inst_results(cloned)).into())
+            Ok((low, high).into())
+        } else if let Some(&param) = self.proc_params.get(sym) {
+            // Annotated numbers and booleans are passed unboxed.
+            Ok(param)
         } else if let Some(var) = self.lookup_var(sym, fb) {
             let mem_flags = MemFlags::trusted();
+            if self.unboxed_type(sym) == Some(Type::Num) {
+                // Variables annotated as numbers always hold a number, so
+                // there is no need to check the type tag.
+                return Ok(fb.ins().load(F64, mem_flags, var, 8).into());
+            }
             let low = fb.ins().load(I64, mem_flags, var, 0);
             let high = fb.ins().load(I64, mem_flags, var, 8);
-            let cloned = self.call_extern("clone_any", &[low, high], fb);
-            Ok(pair(fb.inst_results(cloned)).into())
+            Ok((low, high).into())
         } else {
             Err(Box::new(Error::UnknownVarOrList {
                 span,
@@ -108,6 +132,34 @@ impl<'a> Program<'a> {
         }
     }
 
+    /// Generates a value for a builtin that only reads it. Variables are
+    /// borrowed instead of cloned, anything else is dropped along with the
+    /// builtin's other temporaries.
+    pub(super) fn generate_borrowed_any(
+        &mut self,
+        expr: &'a Expr,
+        fb: &mut FunctionBuilder,
+    ) -> Result<[Value; 2]> {
+        if let Expr::Sym(sym, span) = expr {
+            if let MixedSizeValue::Pair(value) =
+                self.load_symbol(sym, *span, fb)?
+            {
+                return Ok(value);
+            }
+        }
+        let (low, high) = self.generate_any_expr(expr, fb)?;
+        self.add_temporary("drop_any", low);
+        Ok([low, high])
+    }
+
+    /// Computes `10^n` with `pow` like Scratch does, since `exp10` is a GNU
+    /// extension that isn't available with every libc.
+    fn ten_to_the(&mut self, n: Value, fb: &mut FunctionBuilder) -> Value {
+        let ten = fb.ins().f64const(10.0);
+        let res = self.call_extern("pow", &[ten, n], fb);
+        fb.inst_results(res)[0]
+    }
+
     fn generate_func_call(
         &mut self,
         func_name: &'static str,
@@ -137,6 +189,14 @@ impl<'a> Program<'a> {
             "!!" => match args {
                 [Expr::Sym(list_name, list_span), index] => {
                     let list = self.lookup_list(list_name, *list_span, fb)?;
+                    if let Some(index) = self.generate_known_index(index, fb) {
+                        let got = self.call_extern(
+                            "list_get_index",
+                            &[index, list],
+                            fb,
+                        );
+                        return Ok(pair(fb.inst_results(got)).into());
+                    }
                     let index = self.generate_any_expr(index, fb)?;
                     let got = self.call_extern(
                         "list_get",
@@ -147,10 +207,16 @@ impl<'a> Program<'a> {
                 }
                 _ => wrong_arg_count(2),
             },
+            "%static-get" => match args {
+                [Expr::Sym(list_name, list_span), index] => Ok(self
+                    .generate_static_get(list_name, *list_span, index, fb)?
+                    .into()),
+                _ => wrong_arg_count(2),
+            },
             "++" => {
                 let args = args
                     .iter()
-                    .map(|arg| self.generate_cow_expr(arg, fb))
+                    .map(|arg| self.generate_temporary_cow(arg, fb))
                     .collect::<Result<Vec<_>>>()?;
                 let total_len = args
                     .iter()
@@ -175,7 +241,6 @@ impl<'a> Program<'a> {
                         let next_dest = fb.ins().iadd(dest_value, *len);
                         fb.def_var(dest, next_dest);
                     }
-                    self.call_extern("drop_cow", &[*ptr], fb);
                 }
 
                 Ok((buf, total_len).into())
@@ -245,37 +310,178 @@ impl<'a> Program<'a> {
             },
             "str-length" => match args {
                 [s] => {
-                    let s = self.generate_cow_expr(s, fb)?;
-                    let len =
-                        self.call_extern("str_length", &<[_; 2]>::from(s), fb);
+                    let len = if let Typ::Any = self.expr_type(s) {
+                        let s = self.generate_borrowed_any(s, fb)?;
+                        self.call_extern("any_str_length", &s, fb)
+                    } else {
+                        let s = self.generate_temporary_cow(s, fb)?;
+                        self.call_extern("str_length", &<[_; 2]>::from(s), fb)
+                    };
                     let len = fb.inst_results(len)[0];
                     let len = fb.ins().fcvt_from_uint(F64, len);
-                    self.call_extern("drop_cow", &[s.0], fb);
                     Ok(len.into())
                 }
                 _ => wrong_arg_count(1),
             },
             "char-at" => match args {
                 [s, index] => {
-                    let s = self.generate_cow_expr(s, fb)?;
-                    let index = self.generate_double_expr(index, fb)?;
-                    let index = fb.ins().fcvt_to_uint_sat(I64, index);
+                    let is_any = matches!(self.expr_type(s), Typ::Any);
+                    let s = if is_any {
+                        self.generate_borrowed_any(s, fb)?
+                    } else {
+                        self.generate_temporary_cow(s, fb)?.into()
+                    };
+                    let (func, index) = match self
+                        .generate_known_index(index, fb)
+                    {
+                        Some(index) if is_any => ("any_char_at_index", index),
+                        Some(index) => ("char_at_index", index),
+                        None => (
+                            if is_any { "any_char_at" } else { "char_at" },
+                            self.generate_double_expr(index, fb)?,
+                        ),
+                    };
+                    let res = self.call_extern(func, &[s[0], s[1], index], fb);
+                    Ok(pair(fb.inst_results(res)).into())
+                }
+                _ => wrong_arg_count(2),
+            },
+            "str-match" => match args {
+                [s, pattern] => self
+                    .generate_str_match(s, pattern, span, fb)
+                    .map(From::from),
+                _ => wrong_arg_count(2),
+            },
+            "json-get" => match args {
+                [json, key] => {
+                    let json = self.generate_temporary_cow(json, fb)?;
+                    let key = self.generate_temporary_cow(key, fb)?;
+                    let res = self.call_extern(
+                        "json_get",
+                        &[json.0, json.1, key.0, key.1],
+                        fb,
+                    );
+                    Ok(pair(fb.inst_results(res)).into())
+                }
+                _ => wrong_arg_count(2),
+            },
+            "json-set" => match args {
+                [json, key, value] => {
+                    let json = self.generate_temporary_cow(json, fb)?;
+                    let key = self.generate_temporary_cow(key, fb)?;
+                    let value = self.generate_temporary_cow(value, fb)?;
+                    let res = self.call_extern(
+                        "json_set",
+                        &[json.0, json.1, key.0, key.1, value.0, value.1],
+                        fb,
+                    );
+                    Ok(pair(fb.inst_results(res)).into())
+                }
+                _ => wrong_arg_count(3),
+            },
+            "round-to" => match args {
+                [n, places] => {
+                    let n = self.generate_double_expr(n, fb)?;
+                    let places = self.generate_double_expr(places, fb)?;
+                    let scale = self.ten_to_the(places, fb);
+                    let scaled = fb.ins().fmul(n, scale);
+                    // Halves are rounded up like Scratch's `round` block.
+                    let half = fb.ins().f64const(0.5);
+                    let scaled = fb.ins().fadd(scaled, half);
+                    let rounded = fb.ins().floor(scaled);
+                    Ok(fb.ins().fdiv(rounded, scale).into())
+                }
+                _ => wrong_arg_count(2),
+            },
+            "format-decimal" => match args {
+                [n, places] => {
+                    let n = self.generate_double_expr(n, fb)?;
+                    let places = self.generate_double_expr(places, fb)?;
+                    let places = fb.ins().fcvt_to_uint_sat(I64, places);
                     let res =
-                        self.call_extern("char_at", &[s.0, s.1, index], fb);
-                    self.call_extern("drop_cow", &[s.0], fb);
+                        self.call_extern("format_decimal", &[n, places], fb);
                     Ok(pair(fb.inst_results(res)).into())
                 }
                 _ => wrong_arg_count(2),
             },
+            "to-hex" | "to-binary" => {
+                let (n, width) = match args {
+                    [n] => (n, None),
+                    [n, width] => (n, Some(width)),
+                    _ => return wrong_arg_count(2),
+                };
+                let n = self.generate_double_expr(n, fb)?;
+                let n = fb.ins().fcvt_to_sint_sat(I64, n);
+                let width = match width {
+                    Some(width) => {
+                        let width = self.generate_double_expr(width, fb)?;
+                        fb.ins().fcvt_to_uint_sat(I64, width)
+                    }
+                    None => fb.ins().iconst(I64, 0),
+                };
+                let bits_per_digit = if func_name == "to-hex" { 4 } else { 1 };
+                let bits_per_digit = fb.ins().iconst(I32, bits_per_digit);
+                let res = self.call_extern(
+                    "format_radix",
+                    &[n, bits_per_digit, width],
+                    fb,
+                );
+                Ok(pair(fb.inst_results(res)).into())
+            }
+            "bit-and" | "bit-or" | "bit-xor" | "bit-shift-left"
+            | "bit-shift-right" => match args {
+                [a, b] => {
+                    let a = self.generate_double_expr(a, fb)?;
+                    let a = to_int32(a, fb);
+                    let b = self.generate_double_expr(b, fb)?;
+                    let b = to_int32(b, fb);
+                    // Shift amounts are masked to five bits like in
+                    // JavaScript.
+                    let res = match func_name {
+                        "bit-and" => fb.ins().band(a, b),
+                        "bit-or" => fb.ins().bor(a, b),
+                        "bit-xor" => fb.ins().bxor(a, b),
+                        "bit-shift-left" => fb.ins().ishl(a, b),
+                        _ => fb.ins().sshr(a, b),
+                    };
+                    Ok(fb.ins().fcvt_from_sint(F64, res).into())
+                }
+                _ => wrong_arg_count(2),
+            },
             "mod" => match args {
                 [a, n] => {
                     let a = self.generate_double_expr(a, fb)?;
                     let n = self.generate_double_expr(n, fb)?;
-                    let res = self.call_extern("fmod", &[a, n], fb);
+                    let res = self.call_extern("scratch_mod", &[a, n], fb);
                     Ok(fb.inst_results(res)[0].into())
                 }
                 _ => wrong_arg_count(2),
             },
+            "min" | "max" => match args {
+                [first, rest @ ..] => {
+                    let mut result = self.generate_double_expr(first, fb)?;
+                    for arg in rest {
+                        let n = self.generate_double_expr(arg, fb)?;
+                        result = if func_name == "min" {
+                            fb.ins().fmin_pseudo(result, n)
+                        } else {
+                            fb.ins().fmax_pseudo(result, n)
+                        };
+                    }
+                    Ok(result.into())
+                }
+                [] => wrong_arg_count(1),
+            },
+            "clamp" => match args {
+                [n, low, high] => {
+                    let n = self.generate_double_expr(n, fb)?;
+                    let low = self.generate_double_expr(low, fb)?;
+                    let high = self.generate_double_expr(high, fb)?;
+                    let n = fb.ins().fmax_pseudo(n, low);
+                    Ok(fb.ins().fmin_pseudo(n, high).into())
+                }
+                _ => wrong_arg_count(3),
+            },
             "abs" => match args {
                 [operand] => {
                     let n = self.generate_double_expr(operand, fb)?;
@@ -307,16 +513,76 @@ impl<'a> Program<'a> {
             "ln" => mathop("log"),
             "log" => mathop("log10"),
             "e^" => mathop("exp"),
-            "ten^" => mathop("exp10"),
-            "sin" | "cos" | "tan" | "asin" | "acos" | "atan" => {
-                mathop(func_name)
-            }
+            "ten^" => match args {
+                [operand] => {
+                    let n = self.generate_double_expr(operand, fb)?;
+                    Ok(self.ten_to_the(n, fb).into())
+                }
+                _ => wrong_arg_count(1),
+            },
+            "sin" | "cos" | "tan" => match args {
+                [operand] => {
+                    let n = self.generate_double_expr(operand, fb)?;
+                    let n = to_radians(n, fb);
+                    let res = self.call_extern(func_name, &[n], fb);
+                    Ok(fb.inst_results(res)[0].into())
+                }
+                _ => wrong_arg_count(1),
+            },
+            "asin" | "acos" | "atan" => match args {
+                [operand] => {
+                    let n = self.generate_double_expr(operand, fb)?;
+                    let res = self.call_extern(func_name, &[n], fb);
+                    Ok(to_degrees(fb.inst_results(res)[0], fb).into())
+                }
+                _ => wrong_arg_count(1),
+            },
+            "dist" | "dir-to" => match args {
+                [x1, y1, x2, y2] => {
+                    let x1 = self.generate_double_expr(x1, fb)?;
+                    let y1 = self.generate_double_expr(y1, fb)?;
+                    let x2 = self.generate_double_expr(x2, fb)?;
+                    let y2 = self.generate_double_expr(y2, fb)?;
+                    let dx = fb.ins().fsub(x2, x1);
+                    let dy = fb.ins().fsub(y2, y1);
+                    if func_name == "dist" {
+                        let dx2 = fb.ins().fmul(dx, dx);
+                        let dy2 = fb.ins().fmul(dy, dy);
+                        let sum = fb.ins().fadd(dx2, dy2);
+                        Ok(fb.ins().sqrt(sum).into())
+                    } else {
+                        // Directions start at 0 for up and go clockwise.
+                        let res = self.call_extern("atan2", &[dx, dy], fb);
+                        Ok(to_degrees(fb.inst_results(res)[0], fb).into())
+                    }
+                }
+                _ => wrong_arg_count(4),
+            },
+            "table-get" => match args {
+                [Expr::Sym(table_name, table_span), index] => Ok(self
+                    .generate_table_get(table_name, *table_span, index, fb)?
+                    .into()),
+                _ => wrong_arg_count(2),
+            },
+            "call-extern" => Ok(self
+                .generate_extern_call(args, span, true, fb)?
+                .expect("extern function should return a value")),
+            "call-extension" => Err(Box::new(Error::ExtensionNotSupported {
+                span,
+                target: "x86_64",
+            })),
             "to-num" => match args {
                 [operand] => {
                     self.generate_double_expr(operand, fb).map(From::from)
                 }
                 _ => wrong_arg_count(1),
             },
+            "to-bool" => match args {
+                [operand] => {
+                    self.generate_bool_expr(operand, fb).map(From::from)
+                }
+                _ => wrong_arg_count(1),
+            },
             "random" => match args {
                 [low, high] => {
                     self.uses_drand48 = true;
@@ -340,17 +606,47 @@ impl<'a> Program<'a> {
         expr: &'a Expr,
         fb: &mut FunctionBuilder,
     ) -> Result<Value> {
-        let res = self.generate_expr(expr, fb)?;
-        match expr_type(expr) {
-            Typ::Double => todo!(),
-            Typ::Bool => Ok(res.single()),
-            Typ::StaticStr(_) => todo!(),
-            Typ::OwnedString => todo!(),
-            Typ::Any => {
-                let inst = self.call_extern("any_to_bool", res.as_slice(), fb);
-                Ok(fb.inst_results(inst)[0])
+        Ok(self.generate_typed_expr(expr, fb)?.into_bool(self, fb))
+    }
+
+    /// Generates a condition as a branch to `then_block` if it is true and to
+    /// `else_block` otherwise. `and`, `or` and `not` become branches instead
+    /// of booleans that are tested again, so they are free in the conditions
+    /// of `if`, `while` and `until`.
+    pub(super) fn generate_branch(
+        &mut self,
+        condition: &'a Expr,
+        then_block: Block,
+        else_block: Block,
+        fb: &mut FunctionBuilder,
+    ) -> Result<()> {
+        if let Expr::FuncCall(func_name, _, args) = condition {
+            match (*func_name, &args[..]) {
+                ("not", [operand]) => {
+                    return self
+                        .generate_branch(operand, else_block, then_block, fb);
+                }
+                ("and" | "or", [rest @ .., last]) => {
+                    for term in rest {
+                        let next_block = fb.create_block();
+                        let (on_true, on_false) = if *func_name == "and" {
+                            (next_block, else_block)
+                        } else {
+                            (then_block, next_block)
+                        };
+                        self.generate_branch(term, on_true, on_false, fb)?;
+                        fb.switch_to_block(next_block);
+                        fb.seal_block(next_block);
+                    }
+                    return self
+                        .generate_branch(last, then_block, else_block, fb);
+                }
+                _ => {}
             }
         }
+        let condition = self.generate_bool_expr(condition, fb)?;
+        fb.ins().brif(condition, then_block, &[], else_block, &[]);
+        Ok(())
     }
 
     pub(super) fn generate_double_expr(
@@ -358,22 +654,7 @@ impl<'a> Program<'a> {
         expr: &'a Expr,
         fb: &mut FunctionBuilder,
     ) -> Result<Value> {
-        let res = self.generate_expr(expr, fb)?;
-        match expr_type(expr) {
-            Typ::Double => Ok(res.single()),
-            Typ::Bool => {
-                let zero = fb.ins().f64const(0.0);
-                let one = fb.ins().f64const(1.0);
-                Ok(fb.ins().select(res.single(), one, zero))
-            }
-            Typ::StaticStr(_) => todo!(),
-            Typ::OwnedString => todo!(),
-            Typ::Any => {
-                let inst =
-                    self.call_extern("any_to_double", res.as_slice(), fb);
-                Ok(fb.inst_results(inst)[0])
-            }
-        }
+        Ok(self.generate_typed_expr(expr, fb)?.into_double(self, fb))
     }
 
     pub(super) fn generate_cow_expr(
@@ -381,23 +662,7 @@ impl<'a> Program<'a> {
         expr: &'a Expr,
         fb: &mut FunctionBuilder,
     ) -> Result<(Value, Value)> {
-        let res = self.generate_expr(expr, fb)?;
-        match expr_type(expr) {
-            Typ::Double => {
-                let inst =
-                    self.call_extern("double_to_cow", &[res.single()], fb);
-                Ok(pair(fb.inst_results(inst)))
-            }
-            Typ::Bool => {
-                let inst = self.call_extern("bool_to_str", &[res.single()], fb);
-                Ok(pair(fb.inst_results(inst)))
-            }
-            Typ::StaticStr(_) | Typ::OwnedString => Ok(res.pair()),
-            Typ::Any => {
-                let inst = self.call_extern("any_to_cow", res.as_slice(), fb);
-                Ok(pair(fb.inst_results(inst)))
-            }
-        }
+        Ok(self.generate_typed_expr(expr, fb)?.into_cow(self, fb))
     }
 
     pub(super) fn generate_any_expr(
@@ -405,18 +670,7 @@ impl<'a> Program<'a> {
         expr: &'a Expr,
         fb: &mut FunctionBuilder,
     ) -> Result<(Value, Value)> {
-        let res = self.generate_expr(expr, fb)?;
-        match expr_type(expr) {
-            Typ::Double => {
-                let bits = fb.ins().bitcast(I64, MemFlags::new(), res.single());
-                Ok((fb.ins().iconst(I64, 2), bits))
-            }
-            Typ::Bool => {
-                let extended = fb.ins().uextend(I64, res.single());
-                Ok((extended, fb.ins().iconst(I64, 0)))
-            }
-            Typ::StaticStr(_) | Typ::OwnedString | Typ::Any => Ok(res.pair()),
-        }
+        Ok(self.generate_typed_expr(expr, fb)?.into_any(fb))
     }
 
     fn generate_imm(
@@ -433,6 +687,27 @@ impl<'a> Program<'a> {
         }
     }
 
+    /// Generates an immediate in the representation of an `Any`.
+    pub(super) fn generate_any_imm(
+        &mut self,
+        imm: &'a Immediate,
+        fb: &mut FunctionBuilder,
+    ) -> (Value, Value) {
+        match imm {
+            Immediate::Num(n) => {
+                let n = fb.ins().f64const(*n);
+                let bits = fb.ins().bitcast(I64, MemFlags::new(), n);
+                (fb.ins().iconst(I64, 2), bits)
+            }
+            Immediate::String(s) => {
+                self.allocate_static_str(Cow::Borrowed(s), fb)
+            }
+            Immediate::Bool(b) => {
+                (fb.ins().iconst(I64, i64::from(*b)), fb.ins().iconst(I64, 0))
+            }
+        }
+    }
+
     fn generate_comparison(
         &mut self,
         mut ordering: Ordering,
@@ -446,8 +721,8 @@ impl<'a> Program<'a> {
         }
         let eq = ordering.is_eq();
 
-        let lhs_type = expr_type(lhs);
-        let rhs_type = expr_type(rhs);
+        let lhs_type = self.expr_type(lhs);
+        let rhs_type = self.expr_type(rhs);
 
         Ok(match (&lhs_type, &rhs_type, eq) {
             (Typ::Double, Typ::Bool, true) | (Typ::Bool, Typ::Double, true) => {
@@ -500,9 +775,6 @@ impl<'a> Program<'a> {
                     }
                 }
             }
-            (Typ::Bool, Typ::StaticStr(_), false) => {
-                todo!()
-            }
             (Typ::StaticStr(s), Typ::Bool, true)
             | (Typ::Bool, Typ::StaticStr(s), true) => {
                 let the_bool = if matches!(lhs_type, Typ::Bool) {
@@ -519,7 +791,6 @@ impl<'a> Program<'a> {
                     fb.ins().iconst(I8, 0)
                 }
             }
-            (Typ::StaticStr(_), Typ::Bool, false) => todo!(),
             (Typ::StaticStr(lhs), Typ::StaticStr(rhs), _) => fb.ins().iconst(
                 I8,
                 i64::from(
@@ -528,15 +799,6 @@ impl<'a> Program<'a> {
                         == ordering,
                 ),
             ),
-            (Typ::Double, Typ::StaticStr(_), _) => todo!(),
-            (Typ::Double, Typ::OwnedString, _) => todo!(),
-            (Typ::Bool, Typ::OwnedString, _) => todo!(),
-            (Typ::StaticStr(_), Typ::Double, _) => todo!(),
-            (Typ::OwnedString, Typ::Double, _) => todo!(),
-            (Typ::OwnedString, Typ::Bool, _) => todo!(),
-            (Typ::OwnedString, Typ::OwnedString, _) => todo!(),
-            (Typ::OwnedString, Typ::Any, _) => todo!(),
-            (Typ::Any, Typ::OwnedString, _) => todo!(),
             (Typ::StaticStr(_), Typ::OwnedString, _)
             | (Typ::OwnedString, Typ::StaticStr(_), _) => {
                 let lhs = self.generate_expr(lhs, fb)?.pair();
@@ -554,6 +816,27 @@ impl<'a> Program<'a> {
                 self.call_extern("free", &[to_free.0], fb);
                 fb.inst_results(inst)[0]
             }
+            (Typ::StaticStr(s), Typ::Any, true)
+            | (Typ::Any, Typ::StaticStr(s), true)
+                if compares_as_string(s) =>
+            {
+                let the_any = if matches!(lhs_type, Typ::Any) {
+                    lhs
+                } else {
+                    rhs
+                };
+                let the_any = self.generate_expr(the_any, fb)?.pair();
+                let folded = self.allocate_static_str(
+                    Cow::Owned(s.to_ascii_uppercase()),
+                    fb,
+                );
+                let inst = self.call_extern(
+                    "any_eq_folded_lit",
+                    &[the_any.0, the_any.1, folded.0, folded.1],
+                    fb,
+                );
+                fb.inst_results(inst)[0]
+            }
             (Typ::StaticStr(_), Typ::Any, _)
             | (Typ::Any, Typ::StaticStr(_), _) => {
                 let lhs_is_str = matches!(lhs_type, Typ::StaticStr(_));
@@ -595,9 +878,10 @@ impl<'a> Program<'a> {
                 );
                 fb.inst_results(inst)[0]
             }
-            (Typ::Any, Typ::Any, _) => {
-                let lhs = self.generate_expr(lhs, fb)?.pair();
-                let rhs = self.generate_expr(rhs, fb)?.pair();
+            // Everything else is compared as two `Any`s.
+            _ => {
+                let lhs = self.generate_typed_expr(lhs, fb)?.into_any(fb);
+                let rhs = self.generate_typed_expr(rhs, fb)?.into_any(fb);
                 let inst = self.call_extern(
                     if eq { "any_eq_any" } else { "any_lt_any" },
                     &[lhs.0, lhs.1, rhs.0, rhs.1],
@@ -609,9 +893,45 @@ impl<'a> Program<'a> {
     }
 }
 
+/// Whether Scratch compares values to a string literal as strings because the
+/// literal isn't a number, so the comparison ignores case. This errs on the
+/// side of `false`.
+fn compares_as_string(literal: &str) -> bool {
+    let trimmed = literal.trim();
+    trimmed.is_empty()
+        || (!matches!(trimmed, "Infinity" | "+Infinity" | "-Infinity")
+            && trimmed
+                .chars()
+                .any(|c| !(c.is_ascii_hexdigit() || "+-.xXoObB".contains(c))))
+}
+
 fn pair(values: &[Value]) -> (Value, Value) {
     match values {
         [v0, v1] => (*v0, *v1),
         _ => unimplemented!(),
     }
 }
+
+/// Scratch measures angles in degrees. These multiply by the same constants as
+/// `f64::to_radians` and `f64::to_degrees`, which constant folding uses.
+fn to_radians(n: Value, fb: &mut FunctionBuilder) -> Value {
+    let scale = fb.ins().f64const(PI / 180.0);
+    fb.ins().fmul(n, scale)
+}
+
+fn to_degrees(n: Value, fb: &mut FunctionBuilder) -> Value {
+    let scale = fb.ins().f64const(180.0 / PI);
+    fb.ins().fmul(n, scale)
+}
+
+/// Truncates a number to a 32-bit integer, wrapping around like JavaScript
+/// unless it is too large for an `i64`. Infinity and NaN become zero.
+fn to_int32(n: Value, fb: &mut FunctionBuilder) -> Value {
+    let magnitude = fb.ins().fabs(n);
+    let infinity = fb.ins().f64const(f64::INFINITY);
+    let is_finite = fb.ins().fcmp(FloatCC::LessThan, magnitude, infinity);
+    let zero = fb.ins().f64const(0.0);
+    let n = fb.ins().select(is_finite, n, zero);
+    let n = fb.ins().fcvt_to_sint_sat(I64, n);
+    fb.ins().ireduce(I32, n)
+}
@@ -0,0 +1,151 @@
+use super::{typ::MixedSizeValue, Program};
+use crate::{
+    diagnostic::{Error, Result},
+    ir::{expr::Expr, ffi::ExternFunction, typ::Type},
+    opts::Profile,
+};
+use codemap::Span;
+use cranelift::prelude::{isa::CallConv, types::*, *};
+use cranelift_module::{Linkage, Module};
+use sb3_stuff::Value as Immediate;
+
+impl<'a> Program<'a> {
+    pub(super) fn lookup_extern(
+        &self,
+        args: &[Expr],
+    ) -> Option<&'a ExternFunction> {
+        let [Expr::Imm(Immediate::String(name)), ..] = args else {
+            return None;
+        };
+        self.sprite_externs
+            .get(&**name)
+            .or_else(|| self.global_externs.get(&**name))
+            .copied()
+    }
+
+    /// Generates `(call-extern "name" args...)`. Returns `None` if the
+    /// function doesn't return anything, which is an error if `needs_result`.
+    pub(super) fn generate_extern_call(
+        &mut self,
+        args: &'a [Expr],
+        span: Span,
+        needs_result: bool,
+        fb: &mut FunctionBuilder,
+    ) -> Result<Option<MixedSizeValue>> {
+        let Some(function) = self.lookup_extern(args) else {
+            return Err(Box::new(match args {
+                [Expr::Imm(Immediate::String(name)), ..] => {
+                    Error::UnknownExtern {
+                        span,
+                        extern_name: name.to_string(),
+                    }
+                }
+                [_, ..] => Error::ExternNameMustBeString { span },
+                [] => Error::FunctionWrongArgCount {
+                    span,
+                    func_name: "call-extern",
+                    expected: 1,
+                    got: 0,
+                },
+            }));
+        };
+        let [Expr::Imm(Immediate::String(name)), args @ ..] = args else {
+            unreachable!();
+        };
+        if args.len() != function.params.len() {
+            return Err(Box::new(Error::ExternFunctionWrongArgCount {
+                span,
+                extern_name: name.to_string(),
+                expected: function.params.len(),
+                got: args.len(),
+            }));
+        }
+        if needs_result && function.returns.is_none() {
+            return Err(Box::new(Error::ExternReturnsNothing {
+                span,
+                extern_name: name.to_string(),
+            }));
+        }
+
+        // Strings are passed as NUL-terminated copies, which are freed once
+        // the function returns.
+        let mut values = Vec::with_capacity(args.len());
+        for (arg, typ) in args.iter().zip(&function.params) {
+            values.push(match typ {
+                Type::Num => self.generate_double_expr(arg, fb)?,
+                Type::Bool => self.generate_bool_expr(arg, fb)?,
+                Type::Str => {
+                    let (ptr, len) = self.generate_temporary_cow(arg, fb)?;
+                    let c_string = self.call_extern("strndup", &[ptr, len], fb);
+                    let c_string = fb.inst_results(c_string)[0];
+                    self.add_temporary("free", c_string);
+                    c_string
+                }
+            });
+        }
+
+        let func_id = if let Some(&func_id) = self.extern_imports.get(&**name) {
+            func_id
+        } else {
+            let abi_param = |typ: &Type| {
+                AbiParam::new(match typ {
+                    Type::Num => F64,
+                    Type::Bool => I8,
+                    Type::Str => I64,
+                })
+            };
+            let signature = Signature {
+                params: function.params.iter().map(abi_param).collect(),
+                returns: function.returns.iter().map(abi_param).collect(),
+                call_conv: CallConv::SystemV,
+            };
+            let conflict = || Error::ExternSignatureConflict {
+                span,
+                extern_name: name.to_string(),
+            };
+            // The runtime may declare it later, which has to agree too.
+            if self
+                .extern_function_signatures
+                .get(&**name)
+                .is_some_and(|runtime| *runtime != signature)
+            {
+                return Err(Box::new(conflict()));
+            }
+            let func_id = self
+                .object_module
+                .declare_function(name, Linkage::Import, &signature)
+                .map_err(|_| conflict())?;
+            self.extern_imports.insert(&**name, func_id);
+            func_id
+        };
+        let func_ref =
+            self.object_module.declare_func_in_func(func_id, fb.func);
+        let call = fb.ins().call(func_ref, &values);
+        let result = fb.inst_results(call).first().copied();
+
+        Ok(match (function.returns, result) {
+            (Some(Type::Num | Type::Bool), Some(result)) => Some(result.into()),
+            (Some(Type::Str), Some(result)) => {
+                if self.profile == Profile::Debug {
+                    let is_null = fb.ins().icmp_imm(IntCC::Equal, result, 0);
+                    let null = fb.create_block();
+                    let not_null = fb.create_block();
+                    fb.ins().brif(is_null, null, &[], not_null, &[]);
+                    fb.switch_to_block(null);
+                    fb.seal_block(null);
+                    self.trap(
+                        &format!("extern function `{name}` returned null"),
+                        span,
+                        fb,
+                    );
+                    fb.switch_to_block(not_null);
+                    fb.seal_block(not_null);
+                }
+                let s = self.call_extern("cstr_to_cow", &[result], fb);
+                let s = fb.inst_results(s);
+                Some((s[0], s[1]).into())
+            }
+            _ => None,
+        })
+    }
+}
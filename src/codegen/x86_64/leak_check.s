@@ -0,0 +1,132 @@
+; Allocation tracking for debug builds, which are linked with `--wrap` for
+; `malloc`, `calloc`, `realloc` and `free` so that the program's calls to them
+; end up here. Tracked blocks start with a header whose second half holds a
+; magic number, which tells them apart from blocks allocated inside of libc
+; (like the buffers of `getline`) that are freed by the program.
+
+default rel
+
+global __wrap_malloc, __wrap_calloc, __wrap_realloc, __wrap_free, leak_check_init
+
+extern __real_malloc, __real_calloc, __real_realloc, __real_free, atexit, fprintf, stderr
+
+HEADER_SIZE equ 16
+MAGIC equ 0x5ca7c4ec4b1eac0d
+
+section .note.GNU-stack noalloc noexec nowrite progbits
+
+section .text
+__wrap_malloc:
+    add rdi, HEADER_SIZE
+    jc .overflow
+    sub rsp, 8
+    call __real_malloc wrt ..plt
+    add rsp, 8
+    jmp track
+.overflow:
+    xor eax, eax
+    ret
+
+__wrap_calloc:
+    mov rax, rdi
+    mul rsi
+    jc .overflow
+    add rax, HEADER_SIZE
+    jc .overflow
+    sub rsp, 8
+    mov edi, 1
+    mov rsi, rax
+    call __real_calloc wrt ..plt
+    add rsp, 8
+    jmp track
+.overflow:
+    xor eax, eax
+    ret
+
+; Marks the block in `rax`, if any, as tracked and returns a pointer past its
+; header.
+track:
+    test rax, rax
+    jz .done
+    mov rcx, MAGIC
+    mov [rax+8], rcx
+    lock inc qword [live_allocations]
+    add rax, HEADER_SIZE
+.done:
+    ret
+
+__wrap_realloc:
+    test rdi, rdi
+    jz .malloc
+    mov rax, MAGIC
+    cmp [rdi-8], rax
+    jne __real_realloc wrt ..plt
+    test rsi, rsi
+    jz .free
+    add rsi, HEADER_SIZE
+    jc .overflow
+    sub rdi, HEADER_SIZE
+    sub rsp, 8
+    call __real_realloc wrt ..plt
+    add rsp, 8
+    ; The header was copied along with the rest of the block.
+    test rax, rax
+    jz .done
+    add rax, HEADER_SIZE
+.done:
+    ret
+.malloc:
+    mov rdi, rsi
+    jmp __wrap_malloc
+.free:
+    sub rsp, 8
+    call __wrap_free
+    add rsp, 8
+.overflow:
+    xor eax, eax
+    ret
+
+__wrap_free:
+    test rdi, rdi
+    jz .done
+    mov rax, MAGIC
+    cmp [rdi-8], rax
+    jne __real_free wrt ..plt
+    mov qword [rdi-8], 0
+    lock dec qword [live_allocations]
+    sub rdi, HEADER_SIZE
+    jmp __real_free wrt ..plt
+.done:
+    ret
+
+; Takes a function that frees all variables and lists, which is called at exit
+; before reporting the allocations that are still live.
+leak_check_init:
+    mov [drop_globals], rdi
+    mov rax, [live_allocations]
+    mov [baseline], rax
+    lea rdi, [report_leaks]
+    jmp atexit wrt ..plt
+
+report_leaks:
+    sub rsp, 8
+    call [drop_globals]
+    mov rdx, [live_allocations]
+    sub rdx, [baseline]
+    jle .done
+    mov rdi, [stderr]
+    lea rsi, [leak_message]
+    xor eax, eax
+    call fprintf wrt ..plt
+.done:
+    add rsp, 8
+    ret
+
+section .rodata
+leak_message: db "leak check: %ld allocation(s) were never freed", 10, 0
+
+section .bss
+alignb 8
+live_allocations: resq 1
+baseline: resq 1
+drop_globals: resq 1
@@ -0,0 +1,117 @@
+//! Symbols for the variables, lists, tables and procedures of the program,
+//! which embed their names so that the disassembly is readable. A symbol is
+//! `_SC`, a letter for the kind of item and then the path to the item, like the
+//! sprite, procedure and variable name. Every part of the path is prefixed by
+//! its length, and characters other than ASCII letters and digits are written
+//! as `_` followed by their code point in hex and another `_`, with `__`
+//! standing for `_` itself. A digit at the start of a part is escaped like
+//! that too, since it would run into the length. That way different items
+//! never get the same symbol.
+
+use super::Program;
+use cranelift::prelude::Signature;
+use cranelift_module::{DataId, FuncId, Linkage, Module};
+use std::fmt::Write;
+
+#[derive(Clone, Copy)]
+pub(super) enum Kind {
+    Variable,
+    List,
+    Table,
+    Proc,
+    BroadcastHandler,
+}
+
+impl Kind {
+    const fn letter(self) -> char {
+        match self {
+            Self::Variable => 'V',
+            Self::List => 'L',
+            Self::Table => 'T',
+            Self::Proc => 'P',
+            Self::BroadcastHandler => 'B',
+        }
+    }
+
+    const fn to_str(self) -> &'static str {
+        match self {
+            Self::Variable => "variable",
+            Self::List => "list",
+            Self::Table => "table",
+            Self::Proc => "procedure",
+            Self::BroadcastHandler => "broadcast handler",
+        }
+    }
+}
+
+fn mangle(kind: Kind, path: &[&str]) -> String {
+    let mut symbol = format!("_SC{}", kind.letter());
+    for part in path {
+        let mut escaped = String::new();
+        for (i, c) in part.chars().enumerate() {
+            match c {
+                'a'..='z' | 'A'..='Z' => escaped.push(c),
+                '0'..='9' if i != 0 => escaped.push(c),
+                '_' => escaped.push_str("__"),
+                _ => write!(escaped, "_{:x}_", u32::from(c)).unwrap(),
+            }
+        }
+        write!(symbol, "{}{escaped}", escaped.len()).unwrap();
+    }
+    symbol
+}
+
+impl Program<'_> {
+    /// Declares the data of a variable, list or table. Declaring the same item
+    /// again gives the same data. Tables are read-only.
+    pub(super) fn declare_named_data(
+        &mut self,
+        kind: Kind,
+        path: &[&str],
+    ) -> DataId {
+        let symbol = mangle(kind, path);
+        let id = self
+            .object_module
+            .declare_data(
+                &symbol,
+                Linkage::Local,
+                !matches!(kind, Kind::Table),
+                false,
+            )
+            .unwrap();
+        self.add_symbol(symbol, kind, path);
+        id
+    }
+
+    pub(super) fn declare_named_function(
+        &mut self,
+        kind: Kind,
+        path: &[&str],
+        signature: &Signature,
+    ) -> FuncId {
+        let symbol = mangle(kind, path);
+        let id = self
+            .object_module
+            .declare_function(&symbol, Linkage::Local, signature)
+            .unwrap();
+        self.add_symbol(symbol, kind, path);
+        id
+    }
+
+    fn add_symbol(&mut self, symbol: String, kind: Kind, path: &[&str]) {
+        if self.symbols.contains_key(&symbol) {
+            return;
+        }
+        let description = format!("{} {}", kind.to_str(), path.join("/"));
+        self.symbols.insert(symbol, description);
+    }
+
+    /// Lists every symbol along with what it is, sorted by symbol.
+    pub(super) fn symbol_map(&self) -> String {
+        let mut map = String::new();
+        for (symbol, description) in &self.symbols {
+            writeln!(map, "{symbol} {description}").unwrap();
+        }
+        map
+    }
+}
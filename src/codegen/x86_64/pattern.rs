@@ -0,0 +1,61 @@
+use super::Program;
+use crate::{
+    diagnostic::{Error, Result},
+    ir::expr::Expr,
+    pattern::Pattern,
+};
+use codemap::Span;
+use cranelift::prelude::{types::*, *};
+use cranelift_module::{DataId, Module};
+use sb3_stuff::Value as Immediate;
+
+impl<'a> Program<'a> {
+    /// Generates `(str-match s pattern)`. Literal patterns are compiled into
+    /// tables ahead of time, others are compiled by the runtime.
+    pub(super) fn generate_str_match(
+        &mut self,
+        s: &'a Expr,
+        pattern: &'a Expr,
+        span: Span,
+        fb: &mut FunctionBuilder,
+    ) -> Result<Value> {
+        let s = self.generate_temporary_cow(s, fb)?;
+        let res = if let Expr::Imm(Immediate::String(pattern)) = pattern {
+            let table = self.pattern_table(pattern, span)?;
+            let table = self.object_module.declare_data_in_func(table, fb.func);
+            let table = fb.ins().global_value(I64, table);
+            self.call_extern("str_match", &[table, s.0, s.1], fb)
+        } else {
+            let pattern = self.generate_temporary_cow(pattern, fb)?;
+            self.call_extern(
+                "str_match_dynamic",
+                &[s.0, s.1, pattern.0, pattern.1],
+                fb,
+            )
+        };
+        Ok(fb.inst_results(res)[0])
+    }
+
+    fn pattern_table(
+        &mut self,
+        pattern: &'a str,
+        span: Span,
+    ) -> Result<DataId> {
+        if let Some(&id) = self.pattern_tables.get(pattern) {
+            return Ok(id);
+        }
+        let table = Pattern::parse(pattern)
+            .map_err(|reason| Error::InvalidPattern { span, reason })?
+            .table();
+        let id = self
+            .object_module
+            .declare_anonymous_data(false, false)
+            .unwrap();
+        self.data_ctx.clear();
+        self.data_ctx.set_align(8);
+        self.data_ctx.define(table.into_boxed_slice());
+        self.object_module.define_data(id, &self.data_ctx).unwrap();
+        self.pattern_tables.insert(pattern, id);
+        Ok(id)
+    }
+}
@@ -1,8 +1,11 @@
 default rel
 
-global drop_any, drop_cow, any_to_cow, str_length, char_at, any_to_bool, any_to_double, clone_any, clone_cow, double_to_cow, list_append, list_get, list_delete, list_delete_all, list_replace, any_eq_str, any_lt_str, any_eq_double, any_lt_double, double_lt_any, any_eq_any, any_lt_any, any_eq_bool, any_eq_true, any_eq_false, double_lt_str, str_lt_double, random_between, str_to_double, str_eq_str, str_eq_double, ask, bool_to_str, wait_seconds
+global drop_any, drop_cow, any_to_cow, cstr_to_cow, list_append, list_delete, list_delete_all, list_replace, any_eq_str, any_lt_str, any_eq_double, any_lt_double, double_lt_any, any_eq_any, any_lt_any, any_eq_bool, any_eq_true, any_eq_false, double_lt_str, str_lt_double, random_between, str_to_double, str_eq_str, str_eq_double, ask, wait_seconds, state_write_any, state_write_str, state_write_list, state_read_any, state_read_value, state_read_list, json_get, json_set, json_parse_into_lists, str_match, str_match_dynamic, format_decimal, format_radix
 
-extern malloc, free, memcpy, memmove, realloc, asprintf, drand48, write, fflush, getline, stdin, stdout, memcmp, memchr, strndup, strtod, nanosleep
+; Defined in the runtime crate.
+extern any_to_double, double_to_cow, double_to_usize, rt_exit, rt_flush, rt_print_str, str_to_number
+
+extern malloc, free, memcpy, memmove, realloc, asprintf, drand48, getline, stdin, memcmp, memchr, strndup, strtod, nanosleep, strlen, fputc, fgetc, ungetc, fprintf, fscanf, fwrite, open_memstream, fclose, fmemopen
 
 %macro staticstr 2+
     [section .rodata]
@@ -11,9 +14,43 @@ extern malloc, free, memcpy, memmove, realloc, asprintf, drand48, write, fflush,
     __?SECT?__
 %endmacro
 
+; Calls a function through the PLT. Debug builds are assembled with
+; `-dCHECK_ALIGNMENT`, which checks that the stack is aligned to 16 bytes
+; before every such call, since a misaligned stack usually only crashes deep
+; inside of libc, if at all.
+%macro ccall 1
+%ifdef CHECK_ALIGNMENT
+    test spl, 15
+    jz %%aligned
+    call misaligned_stack
+%%aligned:
+%endif
+    call %1 wrt ..plt
+%endmacro
+
 section .note.GNU-stack noalloc noexec nowrite progbits
 
 section .text
+%ifdef CHECK_ALIGNMENT
+; Doesn't return, so the address of the offending call is left on the stack.
+misaligned_stack:
+    mov eax, 1
+    mov edi, 2
+    lea rsi, [.message]
+    mov edx, .message_len
+    syscall
+    ud2
+.message: db "runtime error: misaligned stack in a call from the prelude", 10
+.message_len equ $ - .message
+%endif
+
+; Exits with the status in `edi` from a path that isn't implemented yet. The
+; program runs on a thread of its own, so this has to end the whole process,
+; and buffered output has to be flushed first.
+unimplemented:
+    and rsp, -16
+    call rt_exit wrt ..plt
+
 drop_any:
     cmp rdi, 2
     jbe .dont_free
@@ -46,248 +83,43 @@ any_to_cow:
     ret
 .is_number:
     movq xmm0, rsi
-    jmp double_to_cow
+    jmp double_to_cow wrt ..plt
 
 staticstr str_true, db "true"
 staticstr str_false, db "false"
 staticstr str_empty, db ""
 
-str_length:
-    xor eax, eax
-    test rsi, rsi
-    jz .done
-.loop:
-    movzx edx, byte [rdi+rsi-1]
-    and dl, 192
-    cmp dl, 128
-    setne dl
-    add rax, rdx
-    dec rsi
-    jnz .loop
-.done:
-    ret
-
-char_at:
-    test rdx, rdx
-    jz .return_empty_string
-.loop:
-    test rsi, rsi
-    jz .return_empty_string
-    dec rdx
-    jz .found_correct_index
-    test byte [rdi], 0x80
-    jz .one_byte
-    test byte [rdi], 0b00100000
-    jz .two_bytes
-    test byte [rdi], 0b00010000
-    jz .three_bytes
-    add rdi, 4
-    sub rsi, 4
-    jmp .loop
-.one_byte:
-    inc rdi
-    dec rsi
-    jmp .loop
-.two_bytes:
-    add rdi, 2
-    sub rsi, 2
-    jmp .loop
-.three_bytes:
-    add rdi, 3
-    sub rsi, 3
-    jmp .loop
-.found_correct_index:
-    push rdi
-    mov rdi, 4
-    call malloc wrt ..plt
-    pop rdi
-    test byte [rdi], 0x80
-    jz .write_one_byte
-    test byte [rdi], 0b00100000
-    jz .write_two_bytes
-    test byte [rdi], 0b00010000
-    jz .write_three_bytes
-    mov edi, [rdi]
-    mov dword [rax], edi
-    mov rdx, 4
-    ret
-.write_one_byte:
-    mov dil, [rdi]
-    mov [rax], dil
-    mov rdx, 1
-    ret
-.write_two_bytes:
-    mov di, [rdi]
-    mov [rax], di
-    mov rdx, 2
-    ret
-.write_three_bytes:
-    mov si, [rdi]
-    mov [rax], si
-    mov dil, [rdi+2]
-    mov [rax+2], dil
-    mov rdx, 3
-    ret
-.return_empty_string:
-    lea rax, [str_empty]
-    xor edx, edx
-    ret
-
-any_to_bool:
-    cmp rdi, 2
-    jb .done
-    je .is_number
-    push qword 0
-    cmp rsi, 5
-    je .might_be_str_false
-    cmp rsi, 1
-    je .might_be_str_0
-    seta [rsp]
-.drop_parameter:
-    test dil, 1
-    jnz .dont_free
-    call free wrt ..plt
-.dont_free:
-    pop rax
-.done:
-    ret
-.might_be_str_0:
-    cmp byte [rdi], '0'
-    setne [rsp]
-    jmp .drop_parameter
-.might_be_str_false:
-    mov edx, [rdi]
-    and edx, ~0x20202020
-    cmp edx, "FALS"
-    setne [rsp]
-    mov dl, [rdi+4]
-    and dl, ~0x20
-    cmp dl, 'E'
-    setne al
-    or [rsp], al
-    jmp .drop_parameter
-.is_number:
-    xor eax, eax
-    movq xmm0, rsi
-    xorpd xmm1, xmm1
-    ucomisd xmm0, xmm1
-    setne al
-    ret
-
-any_to_double:
-    cmp rdi, 2
-    je .is_number
-    cmp rdi, 1
-    je .is_true
-    jb .is_false
-    test dil, 1
-    jnz str_to_double
-    push rdi
-    call str_to_double
-    mov rdi, [rsp]
-    movsd [rsp], xmm0
-    call free wrt ..plt
-    movsd xmm0, [rsp]
-    add rsp, 8
-    ret
-.is_number:
-    movq xmm0, rsi
-    ret
-.is_true:
-    mov rax, __?float64?__(1.0)
-    movq xmm0, rax
-    ret
-.is_false:
-    xorpd xmm0, xmm0
-    ret
-
-double_to_usize:
-    cvttsd2si rax, xmm0
-    mov rcx, rax
-    sar rcx, 63
-    movapd xmm1, xmm0
-    subsd xmm1, [.LCPI0_0]
-    cvttsd2si rdx, xmm1
-    and rdx, rcx
-    or rdx, rax
-    xor ecx, ecx
-    xorpd xmm1, xmm1
-    ucomisd xmm0, xmm1
-    cmovae rcx, rdx
-    ucomisd xmm0, [.LCPI0_1]
-    mov rax, -1
-    cmovbe rax, rcx
-    ret
-align 8
-.LCPI0_0: dq 0x43e0000000000000
-.LCPI0_1: dq 0x43efffffffffffff
-
-clone_any:
-    cmp rdi, 2
-    jbe clone_cow.done
-clone_cow:
-    test dil, 1
-    jnz .done
+; Copies a NUL-terminated string returned by an extern function.
+cstr_to_cow:
+    test rdi, rdi
+    jz .is_empty
+    push rbx
+    push r12
     sub rsp, 8
-    push rsi
-    push rdi
-    mov rdi, rsi
-    call malloc wrt ..plt
+    mov rbx, rdi
+    ccall strlen
+    test rax, rax
+    jz .pop_empty
+    mov r12, rax
     mov rdi, rax
-    mov rsi, [rsp]
-    mov rdx, [rsp+8]
-    call memcpy wrt ..plt
-    mov rdx, [rsp+8]
-    add rsp, 24
-    ret
-.done:
-    mov rax, rdi
-    mov rdx, rsi
-    ret
-
-double_to_cow:
-    ; FIXME: asprintf does not format numbers exactly like JavaScript
-    xorpd xmm1, xmm1
-    ucomisd xmm0, xmm1
-    jp .is_nan
-    je .is_zero
-    movq rdi, xmm0
-    mov rax, __?float64?__(__?Infinity?__)
-    cmp rdi, rax
-    je .is_infinity
-    mov rax, __?float64?__(-__?Infinity?__)
-    cmp rdi, rax
-    je .is_minus_infinity
-    sub rsp, 8
-    mov rdi, rsp
-    mov eax, 1
-    lea rsi, [.fmt]
-    call asprintf wrt ..plt
-    mov rdx, rax
-    pop rax
-    ret
-.is_infinity:
-    lea rax, [str_Infinity]
-    mov rdx, 8
-    ret
-.is_minus_infinity:
-    lea rax, [str_minus_Infinity]
-    mov rdx, 9
-    ret
-.is_zero:
-    lea rax, [str_0]
-    mov rdx, 1
+    ccall malloc
+    mov rdi, rax
+    mov rsi, rbx
+    mov rdx, r12
+    ccall memcpy
+    mov rdx, r12
+    add rsp, 8
+    pop r12
+    pop rbx
     ret
-.is_nan:
-    lea rax, [str_NaN]
-    mov rdx, 3
+.pop_empty:
+    add rsp, 8
+    pop r12
+    pop rbx
+.is_empty:
+    lea rax, [str_empty]
+    xor edx, edx
     ret
-.fmt: db "%g", 0
-
-staticstr str_Infinity, db "Infinity"
-staticstr str_minus_Infinity, db "-Infinity"
-staticstr str_0, db "0"
-staticstr str_NaN, db "NaN"
 
 list_ensure_extra_capacity:
     mov rax, rdi
@@ -298,7 +130,7 @@ list_ensure_extra_capacity:
     shl rsi, 5
     add rsi, 16
     mov rdi, [rdi]
-    call realloc wrt ..plt
+    ccall realloc
     mov rdi, rax
     pop rax
     mov [rax], rdi
@@ -321,48 +153,6 @@ list_append:
     add rsp, 8
     ret
 
-list_get:
-    cmp rdi, 2
-    jbe .numeric_index
-    cmp rsi, 4
-    jne .numeric_index
-    mov eax, [rdi]
-    and eax, ~0x20202020
-    cmp eax, "LAST"
-    jne .numeric_index
-    test dil, 1
-    jnz .dont_free
-    push rdx
-    call free wrt ..plt
-    pop rdx
-.dont_free:
-    cmp qword [rdx+8], 0
-    jz .out_of_bounds
-    mov rax, [rdx+8]
-    shl rax, 4
-    mov rsi, [rdx]
-    mov rdi, [rsi+rax-16]
-    mov rsi, [rsi+rax-8]
-    jmp clone_any
-.numeric_index:
-    push rdx
-    call any_to_double
-    call double_to_usize
-    pop rdx
-    sub rax, 1
-    jc .out_of_bounds
-    cmp rax, [rdx+8]
-    jae .out_of_bounds
-    shl rax, 4
-    mov rsi, [rdx]
-    mov rdi, [rsi+rax]
-    mov rsi, [rsi+rax+8]
-    jmp clone_any
-.out_of_bounds:
-    lea rax, [str_empty]
-    xor rdx, rdx
-    ret
-
 list_delete:
     cmp rdi, 2
     jbe .numeric_index
@@ -375,7 +165,7 @@ list_delete:
     test dil, 1
     jnz .dont_free
     push rdx
-    call free wrt ..plt
+    ccall free
     pop rdx
 .dont_free:
     mov rax, [rdx+8]
@@ -388,8 +178,8 @@ list_delete:
     jmp drop_any
 .numeric_index:
     push rdx
-    call any_to_double
-    call double_to_usize
+    ccall any_to_double
+    ccall double_to_usize
     pop rdx
     sub rax, 1
     jc .done
@@ -447,7 +237,7 @@ list_replace:
     push r8
     push rcx
     push rdx
-    call free wrt ..plt
+    ccall free
     pop rdx
     pop rcx
     pop r8
@@ -458,8 +248,8 @@ list_replace:
     push r8
     push rcx
     push rdx
-    call any_to_double
-    call double_to_usize
+    ccall any_to_double
+    ccall double_to_usize
     pop rdx
     pop rcx
     pop r8
@@ -488,7 +278,7 @@ any_eq_str:
     call str_eq_str
     pop rdi
     push rax
-    call free wrt ..plt
+    ccall free
     pop rax
     ret
 .number:
@@ -498,15 +288,13 @@ any_eq_str:
     jmp str_eq_double
 .todo:
     ; TODO
-    mov eax, 60
     mov edi, 96
-    syscall
+    jmp unimplemented
 
 any_lt_str:
     ; TODO
-    mov eax, 60
     mov edi, 95
-    syscall
+    jmp unimplemented
 
 any_eq_double:
     xor eax, eax
@@ -543,9 +331,8 @@ any_lt_double:
     ret
 .is_cow:
     ; TODO
-    mov eax, 60
     mov edi, 93
-    syscall
+    jmp unimplemented
 align 8
 .inf: dq __?Infinity?__
 
@@ -571,7 +358,7 @@ double_lt_any:
     call double_lt_str
     pop rdi
     push rax
-    call free wrt ..plt
+    ccall free
     pop rax
     ret
 align 8
@@ -619,9 +406,8 @@ any_eq_any:
     jnz str_eq_double
 .todo:
     ; TODO
-    mov eax, 60
     mov edi, 92
-    syscall
+    jmp unimplemented
 
 any_lt_any:
     cmp rdi, 2
@@ -651,9 +437,8 @@ any_lt_any:
     jnz str_lt_double
 .todo:
     ; TODO
-    mov eax, 60
     mov edi, 91
-    syscall
+    jmp unimplemented
 
 any_eq_bool:
     test dl, dl
@@ -673,7 +458,7 @@ any_eq_true:
     test dil, 1
     jnz .done
     push rax
-    call free wrt ..plt
+    ccall free
     pop rax
 .done:
     ret
@@ -699,7 +484,7 @@ any_eq_false:
     test dil, 1
     jnz .done
     push rax
-    call free wrt ..plt
+    ccall free
     pop rax
 .done:
     ret
@@ -717,9 +502,8 @@ double_lt_str:
     ret
 .not_convertible_to_number:
     ; TODO
-    mov eax, 60
     mov edi, 89
-    syscall
+    jmp unimplemented
 
 str_lt_double:
     sub rsp, 8
@@ -734,9 +518,8 @@ str_lt_double:
     ret
 .not_convertible_to_number:
     ; TODO
-    mov eax, 60
     mov edi, 88
-    syscall
+    jmp unimplemented
 
 random_between:
     ; TODO: perform rounding when both parameters are integers
@@ -744,62 +527,21 @@ random_between:
     subsd xmm1, xmm0
     movsd [rsp], xmm0
     movsd [rsp+8], xmm1
-    call drand48 wrt ..plt
+    ccall drand48
     movsd xmm1, [rsp]
     vfmadd132sd xmm0, xmm1, [rsp+8]
     add rsp, 24
     ret
 
+; Parses the string at `rdi` with length `rsi` into `xmm0`, returning whether
+; it is a number at all.
 str_to_double:
-    ; FIXME: This should trim leading and trailing whitespace.
-    xor eax, eax
-    cmp rsi, 8
-    je .might_be_infinity
-.regular:
-    ; FIXME: strtod does not use the same grammar as JavaScript.
     sub rsp, 8
-    push rsi
-    push rdi
-    mov rdx, rsi
-    xor esi, esi
-    call memchr wrt ..plt
-    test rax, rax
-    jnz .contains_null_byte
-    mov rdi, [rsp]
-    mov rsi, [rsp+8]
-    call strndup wrt ..plt
-    mov rdx, [rsp+8]
-    mov [rsp+8], rax
-    mov rdi, rax
-    add rax, rdx
-    mov [rsp], rax
-    lea rsi, [rsp+16]
-    call strtod wrt ..plt
-    mov rdi, [rsp+8]
-    movsd [rsp+8], xmm0
-    call free wrt ..plt
-    pop rdx
+    mov rdx, rsp
+    ccall str_to_number
     movsd xmm0, [rsp]
-    add rsp, 16
-    cmp rdx, [rsp-8]
-    jne .failed_to_parse
-    mov eax, 1
-    ret
-.contains_null_byte:
-    add rsp, 24
-.failed_to_parse:
-    xor eax, eax
-    xorpd xmm0, xmm0
-    ret
-.might_be_infinity:
-    mov rdx, "Infinity"
-    cmp [rdi], rdx
-    jne .regular
-    movsd xmm0, [.inf]
-    mov eax, 1
+    add rsp, 8
     ret
-align 8
-.inf: dq __?Infinity?__
 
 str_eq_str:
     cmp rsi, rcx
@@ -807,7 +549,7 @@ str_eq_str:
     ; TODO: Case insensitive comparison
     xchg rsi, rdx
     sub rsp, 8
-    call memcmp wrt ..plt
+    ccall memcmp
     add rsp, 8
     test eax, eax
     setz al
@@ -832,7 +574,7 @@ str_eq_double:
     ret
 .not_convertible_to_number:
     movsd xmm0, [rsp+16]
-    call double_to_cow
+    ccall double_to_cow
     pop rdi
     pop rsi
     mov [rsp], rax
@@ -846,18 +588,14 @@ str_eq_double:
     ret
 
 ask:
-    mov rdx, rsi
-    mov rsi, rdi
-    mov edi, 1
     sub rsp, 16
     push qword 0
-    call write wrt ..plt
-    mov rdi, [stdout]
-    call fflush wrt ..plt
+    ccall rt_print_str
+    ccall rt_flush
     mov rdi, rsp
     lea rsi, [rsp+8]
     mov rdx, [stdin]
-    call getline wrt ..plt
+    ccall getline
     mov rdx, rax
     pop rax
     xor edi, edi
@@ -867,16 +605,6 @@ ask:
     add rsp, 16
     ret
 
-bool_to_str:
-    lea rax, [str_true]
-    mov edx, 4
-    lea rcx, [str_false]
-    mov esi, 5
-    test dil, dil
-    cmovz rax, rcx
-    cmovz edx, esi
-    ret
-
 wait_seconds:
     xorpd xmm1, xmm1
     ucomisd xmm0, xmm1
@@ -891,9 +619,1080 @@ wait_seconds:
     mov [rsp+8], rax
     mov rdi, rsp
     xor esi, esi
-    call nanosleep wrt ..plt
+    ccall nanosleep
     add rsp, 24
 .done:
     ret
 align 8
 .billion: dq __?float64?__(1e9)
+
+; Save files are JSON, written by `state_write_*`. Their values are read back
+; by `state_read_*`, which `state_load` in the runtime calls for the entries
+; that the program knows about.
+
+state_write_any:
+    cmp rsi, 2
+    ja state_write_str
+    je .is_number
+    mov rcx, rdi
+    lea rdi, [str_false]
+    mov edx, 5
+    test rsi, rsi
+    jz .write
+    lea rdi, [str_true]
+    mov edx, 4
+.write:
+    mov esi, 1
+    jmp fwrite wrt ..plt
+.is_number:
+    mov rax, rdx
+    shr rax, 52
+    and eax, 0x7ff
+    cmp eax, 0x7ff
+    je .is_not_finite
+    movq xmm0, rdx
+    lea rsi, [.fmt]
+    mov eax, 1
+    jmp fprintf wrt ..plt
+.is_not_finite:
+    ; JSON has no infinities or NaN, but their string forms convert back.
+    push rbx
+    push r12
+    sub rsp, 8
+    mov rbx, rdi
+    movq xmm0, rdx
+    ccall double_to_cow
+    mov r12, rax
+    mov rdi, rbx
+    mov rsi, rax
+    call state_write_str
+    mov rdi, r12
+    add rsp, 8
+    pop r12
+    pop rbx
+    jmp drop_cow
+.fmt: db "%.17g", 0
+
+state_write_str:
+    push rbx
+    push r12
+    push r13
+    push r14
+    sub rsp, 8
+    mov rbx, rdi
+    mov r12, rsi
+    lea r13, [rsi+rdx]
+    mov edi, '"'
+    mov rsi, rbx
+    ccall fputc
+.loop:
+    cmp r12, r13
+    je .done
+    movzx edi, byte [r12]
+    inc r12
+    cmp edi, '"'
+    je .escape
+    cmp edi, '\'
+    je .escape
+    cmp edi, ' '
+    jb .control
+    mov rsi, rbx
+    ccall fputc
+    jmp .loop
+.escape:
+    mov r14d, edi
+    mov edi, '\'
+    mov rsi, rbx
+    ccall fputc
+    mov edi, r14d
+    mov rsi, rbx
+    ccall fputc
+    jmp .loop
+.control:
+    mov edx, edi
+    mov rdi, rbx
+    lea rsi, [.control_fmt]
+    xor eax, eax
+    ccall fprintf
+    jmp .loop
+.done:
+    mov edi, '"'
+    mov rsi, rbx
+    add rsp, 8
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    jmp fputc wrt ..plt
+.control_fmt: db "\u%04x", 0
+
+state_write_list:
+    push rbx
+    push r12
+    push r13
+    mov rbx, rdi
+    mov r12, rsi
+    xor r13d, r13d
+    mov edi, '['
+    mov rsi, rbx
+    ccall fputc
+.loop:
+    cmp r13, [r12+8]
+    je .done
+    test r13, r13
+    jz .write_item
+    mov edi, ','
+    mov rsi, rbx
+    ccall fputc
+.write_item:
+    mov rax, r13
+    shl rax, 4
+    add rax, [r12]
+    mov rdi, rbx
+    mov rsi, [rax]
+    mov rdx, [rax+8]
+    call state_write_any
+    inc r13
+    jmp .loop
+.done:
+    mov edi, ']'
+    mov rsi, rbx
+    pop r13
+    pop r12
+    pop rbx
+    jmp fputc wrt ..plt
+
+; Returns the next character that isn't whitespace or punctuation separating
+; values.
+state_next_char:
+    push rbx
+    mov rbx, rdi
+.loop:
+    mov rdi, rbx
+    ccall fgetc
+    cmp eax, ' '
+    je .loop
+    cmp eax, `\n`
+    je .loop
+    cmp eax, `\t`
+    je .loop
+    cmp eax, `\r`
+    je .loop
+    cmp eax, ','
+    je .loop
+    cmp eax, ':'
+    je .loop
+    cmp eax, '{'
+    je .loop
+    cmp eax, '}'
+    je .loop
+    pop rbx
+    ret
+
+state_read_any:
+    push rdi
+    call state_next_char
+    pop rdi
+    mov esi, eax
+    ; fallthrough
+
+; Reads the value starting with the already consumed character in `esi`.
+state_read_value:
+    push rbx
+    push r12
+    sub rsp, 40
+    mov rbx, rdi
+    mov eax, esi
+    cmp eax, '"'
+    je .string
+    cmp eax, 't'
+    je .true
+    cmp eax, 'f'
+    je .false
+    cmp eax, -1
+    je .zero
+    xor r12d, r12d
+.number_loop:
+    mov [rsp+r12], al
+    inc r12
+    cmp r12, 31
+    je .number_done
+    mov rdi, rbx
+    ccall fgetc
+    lea ecx, [rax-'0']
+    cmp ecx, 9
+    jbe .number_loop
+    cmp eax, '.'
+    je .number_loop
+    cmp eax, '-'
+    je .number_loop
+    cmp eax, '+'
+    je .number_loop
+    cmp eax, 'e'
+    je .number_loop
+    cmp eax, 'E'
+    je .number_loop
+    mov edi, eax
+    mov rsi, rbx
+    ccall ungetc
+.number_done:
+    mov byte [rsp+r12], 0
+    mov rdi, rsp
+    xor esi, esi
+    ccall strtod
+    movq rdx, xmm0
+    mov eax, 2
+    jmp .return
+.zero:
+    mov eax, 2
+    xor edx, edx
+    jmp .return
+.true:
+    mov r12d, 1
+    jmp .skip_word
+.false:
+    xor r12d, r12d
+.skip_word:
+    mov rdi, rbx
+    ccall fgetc
+    lea ecx, [rax-'a']
+    cmp ecx, 25
+    jbe .skip_word
+    mov edi, eax
+    mov rsi, rbx
+    ccall ungetc
+    mov rax, r12
+    xor edx, edx
+    jmp .return
+.string:
+    mov rdi, rsp
+    lea rsi, [rsp+8]
+    ccall open_memstream
+    mov r12, rax
+.string_loop:
+    mov rdi, rbx
+    ccall fgetc
+    cmp eax, -1
+    je .string_done
+    cmp eax, '"'
+    je .string_done
+    cmp eax, '\'
+    jne .string_char
+    mov rdi, rbx
+    ccall fgetc
+    cmp eax, 'u'
+    je .unicode_escape
+    mov ecx, `\n`
+    cmp eax, 'n'
+    cmove eax, ecx
+    mov ecx, `\t`
+    cmp eax, 't'
+    cmove eax, ecx
+    mov ecx, `\r`
+    cmp eax, 'r'
+    cmove eax, ecx
+    jmp .string_char
+.unicode_escape:
+    ; Only control characters are escaped like this when saving.
+    mov dword [rsp+16], 0
+    mov rdi, rbx
+    lea rsi, [.hex_fmt]
+    lea rdx, [rsp+16]
+    xor eax, eax
+    ccall fscanf
+    mov eax, [rsp+16]
+.string_char:
+    mov edi, eax
+    mov rsi, r12
+    ccall fputc
+    jmp .string_loop
+.string_done:
+    mov rdi, r12
+    ccall fclose
+    mov rax, [rsp]
+    mov rdx, [rsp+8]
+    test rdx, rdx
+    jnz .return
+    mov rdi, rax
+    ccall free
+    lea rax, [str_empty]
+    xor edx, edx
+.return:
+    add rsp, 40
+    pop r12
+    pop rbx
+    ret
+.hex_fmt: db "%4x", 0
+
+state_read_list:
+    push rbx
+    push r12
+    sub rsp, 8
+    mov rbx, rdi
+    mov r12, rsi
+    call state_next_char
+    cmp eax, '['
+    jne .done
+.loop:
+    mov rdi, rbx
+    call state_next_char
+    cmp eax, ']'
+    je .done
+    cmp eax, -1
+    je .done
+    mov rdi, rbx
+    mov esi, eax
+    call state_read_value
+    mov rdi, r12
+    mov rsi, rax
+    call list_append
+    jmp .loop
+.done:
+    add rsp, 8
+    pop r12
+    pop rbx
+    ret
+
+; The JSON builtins only look at the top-level object. Strings are decoded,
+; while other values are returned as their JSON text.
+
+; Opens a string for reading, or returns NULL if it's empty since `fmemopen`
+; can't open empty buffers.
+json_open:
+    test rsi, rsi
+    jz .empty
+    lea rdx, [.mode]
+    jmp fmemopen wrt ..plt
+.empty:
+    xor eax, eax
+    ret
+.mode: db "r", 0
+
+; Returns the next character that isn't whitespace.
+json_next_char:
+    push rbx
+    mov rbx, rdi
+.loop:
+    mov rdi, rbx
+    ccall fgetc
+    cmp eax, ' '
+    je .loop
+    cmp eax, `\n`
+    je .loop
+    cmp eax, `\t`
+    je .loop
+    cmp eax, `\r`
+    je .loop
+    pop rbx
+    ret
+
+; Reads the next key of the object and the colon after it. Returns 0 in `rax`
+; once there are no keys left.
+json_next_key:
+    push rbx
+    push r12
+    push r13
+    mov rbx, rdi
+.skip:
+    mov rdi, rbx
+    call json_next_char
+    cmp eax, '{'
+    je .skip
+    cmp eax, ','
+    je .skip
+    cmp eax, '"'
+    jne .end
+    mov rdi, rbx
+    mov esi, eax
+    call state_read_value
+    mov r12, rax
+    mov r13, rdx
+    mov rdi, rbx
+    call json_next_char
+    mov rax, r12
+    mov rdx, r13
+    jmp .return
+.end:
+    xor eax, eax
+    xor edx, edx
+.return:
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+; Reads the value after a key. Strings are decoded unless `esi` is nonzero.
+json_read_value:
+    push rbx
+    push r12
+    sub rsp, 8
+    mov rbx, rdi
+    mov r12d, esi
+    call json_next_char
+    mov rdi, rbx
+    mov esi, eax
+    add rsp, 8
+    test r12d, r12d
+    pop r12
+    pop rbx
+    jnz json_read_raw
+    cmp esi, '"'
+    je state_read_value
+    ; fallthrough
+
+; Copies the value starting with the already consumed character in `esi`.
+; The value ends before the next comma or closing bracket that isn't nested
+; or inside of a string, which is left unread.
+json_read_raw:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 16
+    mov rbx, rdi
+    mov r15d, esi
+    mov rdi, rsp
+    lea rsi, [rsp+8]
+    ccall open_memstream
+    mov r12, rax
+    ; r13 is the nesting depth. Bit 0 of r14 is set inside of strings and
+    ; bit 1 after a backslash in a string.
+    xor r13d, r13d
+    xor r14d, r14d
+.loop:
+    cmp r15d, -1
+    je .done
+    test r14d, 1
+    jnz .in_string
+    cmp r15d, '"'
+    je .string_start
+    cmp r15d, '{'
+    je .open
+    cmp r15d, '['
+    je .open
+    cmp r15d, '}'
+    je .close
+    cmp r15d, ']'
+    je .close
+    cmp r15d, ','
+    jne .write
+    test r13, r13
+    jz .end_of_value
+    jmp .write
+.string_start:
+    mov r14d, 1
+    jmp .write
+.open:
+    inc r13
+    jmp .write
+.close:
+    test r13, r13
+    jz .end_of_value
+    dec r13
+    jmp .write
+.in_string:
+    test r14d, 2
+    jnz .escaped
+    cmp r15d, '\'
+    je .backslash
+    cmp r15d, '"'
+    jne .write
+    xor r14d, r14d
+    jmp .write
+.backslash:
+    mov r14d, 3
+    jmp .write
+.escaped:
+    mov r14d, 1
+.write:
+    mov edi, r15d
+    mov rsi, r12
+    ccall fputc
+    mov rdi, rbx
+    ccall fgetc
+    mov r15d, eax
+    jmp .loop
+.end_of_value:
+    mov edi, r15d
+    mov rsi, rbx
+    ccall ungetc
+.done:
+    mov rdi, r12
+    ccall fclose
+    mov rax, [rsp]
+    mov rdx, [rsp+8]
+.trim:
+    ; Whitespace before the end of the value isn't part of it.
+    test rdx, rdx
+    jz .empty
+    movzx ecx, byte [rax+rdx-1]
+    cmp ecx, ' '
+    ja .return
+    dec rdx
+    jmp .trim
+.empty:
+    mov rdi, rax
+    ccall free
+    lea rax, [str_empty]
+    xor edx, edx
+.return:
+    add rsp, 16
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+json_get:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    mov r12, rdx
+    mov r13, rcx
+    call json_open
+    lea r14, [str_empty]
+    xor r15d, r15d
+    test rax, rax
+    jz .return
+    mov rbx, rax
+.loop:
+    mov rdi, rbx
+    call json_next_key
+    test rax, rax
+    jz .close
+    mov r14, rax
+    mov rdi, rax
+    mov rsi, rdx
+    mov rdx, r12
+    mov rcx, r13
+    call str_eq_str
+    mov r15d, eax
+    mov rdi, r14
+    call drop_cow
+    mov rdi, rbx
+    mov esi, 1
+    test r15b, r15b
+    jz .skip_value
+    xor esi, esi
+    call json_read_value
+    mov r14, rax
+    mov r15, rdx
+    jmp .close
+.skip_value:
+    call json_read_value
+    mov rdi, rax
+    call drop_cow
+    jmp .loop
+.close:
+    test rax, rax
+    jnz .found
+    lea r14, [str_empty]
+    xor r15d, r15d
+.found:
+    mov rdi, rbx
+    ccall fclose
+.return:
+    mov rax, r14
+    mov rdx, r15
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+; Stores the value as a string, replacing the values of matching keys or
+; adding the key at the end if there are none.
+json_set:
+    push rbp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 40
+    mov r13, rdx
+    mov r14, rcx
+    mov r15, r8
+    mov [rsp+16], r9
+    ; Bit 0 of ebp is set once the key has been found and bit 1 once any
+    ; entry has been written.
+    xor ebp, ebp
+    call json_open
+    mov rbx, rax
+    mov rdi, rsp
+    lea rsi, [rsp+8]
+    ccall open_memstream
+    mov r12, rax
+    mov edi, '{'
+    mov rsi, r12
+    ccall fputc
+    test rbx, rbx
+    jz .add_key
+.loop:
+    mov rdi, rbx
+    call json_next_key
+    test rax, rax
+    jz .end_of_input
+    mov [rsp+24], rax
+    mov [rsp+32], rdx
+    call .write_separator
+    mov rdi, r12
+    mov rsi, [rsp+24]
+    mov rdx, [rsp+32]
+    call state_write_str
+    mov edi, ':'
+    mov rsi, r12
+    ccall fputc
+    mov rdi, [rsp+24]
+    mov rsi, [rsp+32]
+    mov rdx, r13
+    mov rcx, r14
+    call str_eq_str
+    mov [rsp+32], rax
+    mov rdi, [rsp+24]
+    call drop_cow
+    mov rdi, rbx
+    mov esi, 1
+    call json_read_value
+    mov [rsp+24], rax
+    test byte [rsp+32], 1
+    jnz .replace
+    mov rdi, rax
+    mov esi, 1
+    mov rcx, r12
+    ccall fwrite
+    jmp .drop_old_value
+.replace:
+    or ebp, 1
+    mov rdi, r12
+    mov rsi, r15
+    mov rdx, [rsp+16]
+    call state_write_str
+.drop_old_value:
+    mov rdi, [rsp+24]
+    call drop_cow
+    jmp .loop
+.end_of_input:
+    mov rdi, rbx
+    ccall fclose
+.add_key:
+    test ebp, 1
+    jnz .finish
+    call .write_separator
+    mov rdi, r12
+    mov rsi, r13
+    mov rdx, r14
+    call state_write_str
+    mov edi, ':'
+    mov rsi, r12
+    ccall fputc
+    mov rdi, r12
+    mov rsi, r15
+    mov rdx, [rsp+16]
+    call state_write_str
+.finish:
+    mov edi, '}'
+    mov rsi, r12
+    ccall fputc
+    mov rdi, r12
+    ccall fclose
+    mov rax, [rsp]
+    mov rdx, [rsp+8]
+    add rsp, 40
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
+    ret
+.write_separator:
+    test ebp, 2
+    jnz .comma
+    or ebp, 2
+    ret
+.comma:
+    sub rsp, 8
+    mov edi, ','
+    mov rsi, r12
+    ccall fputc
+    add rsp, 8
+    ret
+
+json_parse_into_lists:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    mov r14, rdi
+    mov r15, rsi
+    mov r12, rdx
+    mov r13, rcx
+    mov rdi, r12
+    call list_delete_all
+    mov rdi, r13
+    call list_delete_all
+    mov rdi, r14
+    mov rsi, r15
+    call json_open
+    test rax, rax
+    jz .return
+    mov rbx, rax
+.loop:
+    mov rdi, rbx
+    call json_next_key
+    test rax, rax
+    jz .done
+    mov rdi, r12
+    mov rsi, rax
+    call list_append
+    mov rdi, rbx
+    xor esi, esi
+    call json_read_value
+    mov rdi, r13
+    mov rsi, rax
+    call list_append
+    jmp .loop
+.done:
+    mov rdi, rbx
+    ccall fclose
+.return:
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+; Patterns are compiled to the table described by `Pattern::table` in the
+; compiler and matched by simulating every item at once, with one bit of state
+; per item. State `n` accepts.
+
+str_match:
+    add rdx, rsi
+    mov rcx, [rdi]
+    mov r9d, 1
+    shl r9, cl
+    mov r8d, 1
+.loop:
+    ; Optional items can be skipped.
+    xor ecx, ecx
+.closure:
+    cmp rcx, [rdi]
+    jae .closed
+    bt r8, rcx
+    jnc .closure_next
+    bt qword [rdi+16], rcx
+    jnc .closure_next
+    lea r11, [rcx+1]
+    bts r8, r11
+.closure_next:
+    inc rcx
+    jmp .closure
+.closed:
+    test r8, r9
+    jz .not_accepting
+    test byte [rdi+8], 2
+    jz .yes
+    cmp rsi, rdx
+    je .yes
+.not_accepting:
+    cmp rsi, rdx
+    je .no
+    movzx eax, byte [rsi]
+    inc rsi
+    xor r10d, r10d
+    xor ecx, ecx
+.step:
+    cmp rcx, [rdi]
+    jae .stepped
+    bt r8, rcx
+    jnc .step_next
+    mov r11, rcx
+    shl r11, 5
+    bt dword [rdi+r11+32], eax
+    jnc .step_next
+    ; Repeated items stay in the same state.
+    bt qword [rdi+24], rcx
+    jc .stay
+    lea r11, [rcx+1]
+    bts r10, r11
+    jmp .step_next
+.stay:
+    bts r10, rcx
+.step_next:
+    inc rcx
+    jmp .step
+.stepped:
+    mov r8, r10
+    ; Unanchored patterns can start matching anywhere.
+    test byte [rdi+8], 1
+    jnz .loop
+    or r8, 1
+    jmp .loop
+.yes:
+    mov eax, 1
+    ret
+.no:
+    xor eax, eax
+    ret
+
+PATTERN_TABLE_SIZE equ 32 + 63 * 32
+
+str_match_dynamic:
+    push rbx
+    push r12
+    sub rsp, PATTERN_TABLE_SIZE + 8
+    mov rbx, rdi
+    mov r12, rsi
+    mov rdi, rsp
+    mov rsi, rdx
+    mov rdx, rcx
+    call compile_pattern
+    test al, al
+    jz .return
+    mov rdi, rsp
+    mov rsi, rbx
+    mov rdx, r12
+    call str_match
+.return:
+    add rsp, PATTERN_TABLE_SIZE + 8
+    pop r12
+    pop rbx
+    ret
+
+; Compiles the pattern at `rsi` with length `rdx` into the table at `rdi`,
+; following `Pattern::parse` in the compiler. Returns false if the pattern is
+; invalid.
+compile_pattern:
+    push rdi
+    push rsi
+    push rdx
+    ; Zero the table.
+    mov ecx, PATTERN_TABLE_SIZE
+    xor eax, eax
+    rep stosb
+    pop rdx
+    pop rsi
+    pop rdi
+    add rdx, rsi
+    ; r8 is the number of items and r9 the set of the current item. r10 is
+    ; nonzero if the last item has a quantifier.
+    xor r8d, r8d
+    xor r10d, r10d
+    cmp rsi, rdx
+    je .loop
+    cmp byte [rsi], '^'
+    jne .loop
+    or byte [rdi+8], 1
+    inc rsi
+.loop:
+    cmp rsi, rdx
+    je .done
+    movzx eax, byte [rsi]
+    inc rsi
+    cmp eax, '$'
+    jne .not_anchor
+    cmp rsi, rdx
+    jne .not_anchor
+    or byte [rdi+8], 2
+    jmp .done
+.not_anchor:
+    cmp eax, '*'
+    je .quantifier
+    cmp eax, '?'
+    je .quantifier
+    cmp r8, 63
+    je .invalid
+    mov r9, r8
+    shl r9, 5
+    lea r9, [rdi+r9+32]
+    cmp eax, '.'
+    je .any
+    cmp eax, '['
+    je .class
+    cmp eax, '\'
+    jne .single
+    cmp rsi, rdx
+    je .invalid
+    movzx eax, byte [rsi]
+    inc rsi
+.single:
+    bts dword [r9], eax
+    jmp .item_done
+.any:
+    mov rax, -1
+    mov [r9], rax
+    mov [r9+8], rax
+    mov [r9+16], rax
+    mov [r9+24], rax
+    jmp .item_done
+.quantifier:
+    test r8, r8
+    jz .invalid
+    test r10, r10
+    jnz .invalid
+    lea rcx, [r8-1]
+    bts qword [rdi+16], rcx
+    cmp eax, '*'
+    jne .quantified
+    bts qword [rdi+24], rcx
+.quantified:
+    mov r10d, 1
+    jmp .loop
+.class:
+    ; r11 is nonzero for negated classes.
+    xor r11d, r11d
+    cmp rsi, rdx
+    je .invalid
+    cmp byte [rsi], '^'
+    jne .class_loop
+    mov r11d, 1
+    inc rsi
+.class_loop:
+    cmp rsi, rdx
+    je .invalid
+    movzx eax, byte [rsi]
+    inc rsi
+    cmp eax, ']'
+    je .class_done
+    cmp eax, '\'
+    jne .range
+    cmp rsi, rdx
+    je .invalid
+    movzx eax, byte [rsi]
+    inc rsi
+.range:
+    ; `ecx` is the end of the range, which is `eax` unless a `-` follows.
+    lea rcx, [rsi+1]
+    cmp rcx, rdx
+    mov ecx, eax
+    jae .range_loop
+    cmp byte [rsi], '-'
+    jne .range_loop
+    movzx ecx, byte [rsi+1]
+    cmp ecx, ']'
+    je .range_end_is_bracket
+    add rsi, 2
+    cmp ecx, eax
+    jb .invalid
+    jmp .range_loop
+.range_end_is_bracket:
+    mov ecx, eax
+.range_loop:
+    bts dword [r9], eax
+    inc eax
+    cmp eax, ecx
+    jbe .range_loop
+    jmp .class_loop
+.class_done:
+    test r11, r11
+    jz .item_done
+    not qword [r9]
+    not qword [r9+8]
+    not qword [r9+16]
+    not qword [r9+24]
+.item_done:
+    inc r8
+    xor r10d, r10d
+    jmp .loop
+.done:
+    mov [rdi], r8
+    mov eax, 1
+    ret
+.invalid:
+    xor eax, eax
+    ret
+
+; Formats the number in `xmm0` with exactly `rdi` digits after the decimal
+; point, at most 100. Infinity and NaN are formatted like `double_to_cow`.
+format_decimal:
+    movq rax, xmm0
+    shr rax, 52
+    and eax, 0x7ff
+    cmp eax, 0x7ff
+    jne .finite
+    jmp double_to_cow wrt ..plt
+.finite:
+    cmp rdi, 100
+    jbe .clamped
+    mov edi, 100
+.clamped:
+    sub rsp, 24
+    mov edx, edi
+    lea rsi, [.fmt]
+    mov rdi, rsp
+    mov eax, 1
+    ccall asprintf
+    movsxd rdx, eax
+    mov rax, [rsp]
+    add rsp, 24
+    ret
+.fmt: db "%.*f", 0
+
+; Formats the integer in `rdi` in hexadecimal if `esi` is 4 or in binary if it
+; is 1, padded with zeros to at least `rdx` digits, at most 64. Negative
+; numbers get a minus sign in front of the padding.
+format_radix:
+    push rbx
+    push r12
+    sub rsp, 88
+    mov ecx, esi
+    mov r8, rdi
+    xor r9d, r9d
+    test r8, r8
+    jns .positive
+    neg r8
+    mov r9d, 1
+.positive:
+    cmp rdx, 64
+    jbe .width_clamped
+    mov edx, 64
+.width_clamped:
+    mov r11d, 1
+    shl r11, cl
+    dec r11
+    ; The digits are written backwards from the end of the buffer at r10.
+    lea r10, [rsp+80]
+    lea rsi, [.digits]
+.digit:
+    mov rax, r8
+    and rax, r11
+    movzx eax, byte [rsi+rax]
+    dec r10
+    mov [r10], al
+    shr r8, cl
+    lea rax, [rsp+80]
+    sub rax, r10
+    test r8, r8
+    jnz .digit
+    cmp rax, rdx
+    jb .digit
+    test r9d, r9d
+    jz .copy
+    dec r10
+    mov byte [r10], '-'
+.copy:
+    lea rbx, [rsp+80]
+    sub rbx, r10
+    mov r12, r10
+    mov rdi, rbx
+    ccall malloc
+    mov rdi, rax
+    mov rsi, r12
+    mov rdx, rbx
+    mov r12, rax
+    ccall memcpy
+    mov rax, r12
+    mov rdx, rbx
+    add rsp, 88
+    pop r12
+    pop rbx
+    ret
+.digits: db "0123456789abcdef"
@@ -0,0 +1,149 @@
+//! Ranges of the values of index expressions, so that indexing with a value
+//! that is known to be a whole number that isn't negative can pass it to the
+//! runtime as an integer instead of going through `double_to_usize`. The only
+//! variables with known ranges are the counters of `for` loops whose bodies
+//! don't assign to them.
+
+use super::Program;
+use crate::ir::{expr::Expr, statement::Statement};
+use cranelift::prelude::{types::*, *};
+use sb3_stuff::Value as Immediate;
+use std::ops::RangeInclusive;
+
+/// The largest count of a loop whose number of iterations isn't known. Loops
+/// don't run long enough to count past this, and every count up to it is
+/// exactly representable as a double.
+const MAX_UNKNOWN_COUNT: i64 = 1 << 53;
+
+/// A loop counter whose value is in a register while the body of its loop is
+/// generated.
+#[derive(Clone, Copy)]
+pub(super) struct Counter {
+    pub(super) count: Variable,
+    pub(super) max: i64,
+}
+
+impl Counter {
+    /// The largest value the counter of a loop with `times` iterations takes.
+    pub(super) fn max_for(times: &Expr) -> i64 {
+        match times {
+            Expr::Imm(Immediate::Num(n)) if *n < MAX_UNKNOWN_COUNT as f64 => {
+                n.max(0.0) as i64
+            }
+            _ => MAX_UNKNOWN_COUNT,
+        }
+    }
+}
+
+/// Whether running `stmt` can't change the value of the variable `name`. This
+/// is conservative, so anything that can run code that isn't visible here,
+/// like custom procedures and broadcasts, counts as changing it.
+pub(super) fn preserves(stmt: &Statement, name: &str) -> bool {
+    match stmt {
+        Statement::ProcCall {
+            proc_name, args, ..
+        } => match &**proc_name {
+            ":=" | "+=" => {
+                !matches!(args.first(), Some(Expr::Sym(var, _)) if var == name)
+            }
+            "print"
+            | "println"
+            | "print-fixed"
+            | "eprint"
+            | "append"
+            | "delete"
+            | "delete-all"
+            | "replace"
+            | "json-parse-into-lists"
+            | "stop-this-script"
+            | "stop-all"
+            | "speak"
+            | "wait" => true,
+            _ => false,
+        },
+        Statement::Do(stmts) => stmts.iter().all(|stmt| preserves(stmt, name)),
+        Statement::IfElse { then, else_, .. } => {
+            preserves(then, name) && preserves(else_, name)
+        }
+        Statement::For { counter, body, .. } => {
+            counter.0 != name && preserves(body, name)
+        }
+        Statement::Repeat { body, .. }
+        | Statement::Forever(body, _)
+        | Statement::Until { body, .. }
+        | Statement::While { body, .. } => preserves(body, name),
+    }
+}
+
+impl Program<'_> {
+    /// Generates an index as an integer if it is known to be a whole number
+    /// that isn't negative. Otherwise nothing is generated.
+    pub(super) fn generate_known_index(
+        &mut self,
+        expr: &Expr,
+        fb: &mut FunctionBuilder,
+    ) -> Option<Value> {
+        let range = self.integer_range(expr)?;
+        (*range.start() >= 0).then(|| self.generate_integer(expr, fb))
+    }
+
+    /// The range of an expression that is known to be an integer.
+    fn integer_range(&self, expr: &Expr) -> Option<RangeInclusive<i64>> {
+        match expr {
+            Expr::Imm(Immediate::Num(n))
+                if n.fract() == 0.0 && n.abs() <= MAX_UNKNOWN_COUNT as f64 =>
+            {
+                Some(*n as i64..=*n as i64)
+            }
+            Expr::Sym(sym, _) => {
+                let counter = self.counter(sym)?;
+                Some(1..=counter.max)
+            }
+            Expr::AddSub(positives, negatives, _) => {
+                let mut min = 0_i64;
+                let mut max = 0_i64;
+                for term in positives {
+                    let range = self.integer_range(term)?;
+                    min = min.checked_add(*range.start())?;
+                    max = max.checked_add(*range.end())?;
+                }
+                for term in negatives {
+                    let range = self.integer_range(term)?;
+                    min = min.checked_sub(*range.end())?;
+                    max = max.checked_sub(*range.start())?;
+                }
+                Some(min..=max)
+            }
+            _ => None,
+        }
+    }
+
+    /// Generates an expression that `integer_range` accepted.
+    fn generate_integer(&self, expr: &Expr, fb: &mut FunctionBuilder) -> Value {
+        match expr {
+            Expr::Imm(Immediate::Num(n)) => fb.ins().iconst(I64, *n as i64),
+            Expr::Sym(sym, _) => fb.use_var(self.counter(sym).unwrap().count),
+            Expr::AddSub(positives, negatives, _) => {
+                let mut sum = fb.ins().iconst(I64, 0);
+                for term in positives {
+                    let term = self.generate_integer(term, fb);
+                    sum = fb.ins().iadd(sum, term);
+                }
+                for term in negatives {
+                    let term = self.generate_integer(term, fb);
+                    sum = fb.ins().isub(sum, term);
+                }
+                sum
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn counter(&self, name: &str) -> Option<Counter> {
+        // `answer` and parameters shadow variables.
+        if name == "answer" || self.proc_params.contains_key(name) {
+            return None;
+        }
+        self.counters.get(name).copied()
+    }
+}
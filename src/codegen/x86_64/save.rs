@@ -0,0 +1,223 @@
+use super::Program;
+use cranelift::{
+    codegen::{
+        ir::{Function, UserFuncName},
+        Context,
+    },
+    prelude::{isa::CallConv, types::*, *},
+};
+use cranelift_module::{DataId, FuncId, Module};
+use std::{borrow::Cow, mem};
+
+/// A variable or list that is part of a save file.
+pub(super) struct Saved {
+    /// The key in the save file, qualified by the sprite name if it isn't
+    /// global.
+    pub key: String,
+    pub id: DataId,
+    /// Whether the variable holds an unboxed number.
+    pub is_num: bool,
+}
+
+impl<'a> Program<'a> {
+    /// The functions implementing `save-state` and `load-state`, which take
+    /// the path of the save file as a NUL-terminated string.
+    pub(super) fn state_functions(&mut self) -> (FuncId, FuncId) {
+        *self.state_functions.get_or_insert_with(|| {
+            let signature = Signature {
+                params: vec![AbiParam::new(I64)],
+                returns: Vec::new(),
+                call_conv: CallConv::SystemV,
+            };
+            let mut declare = || {
+                self.object_module
+                    .declare_anonymous_function(&signature)
+                    .unwrap()
+            };
+            (declare(), declare())
+        })
+    }
+
+    /// Generates the functions returned by [`Self::state_functions`] if they
+    /// were used.
+    pub(super) fn generate_state_functions(
+        &mut self,
+        ctx: &mut Context,
+        func_ctx: &mut FunctionBuilderContext,
+    ) {
+        let Some((save, load)) = self.state_functions else {
+            return;
+        };
+        // Sorting makes save files the same however the program is ordered.
+        let mut vars = mem::take(&mut self.saved_vars);
+        vars.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        let mut lists = mem::take(&mut self.saved_lists);
+        lists.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+
+        self.generate_state_function(
+            save,
+            "w",
+            ctx,
+            func_ctx,
+            |p, file, fb| {
+                p.write_literal("{\"variables\":{", file, fb);
+                for (i, var) in vars.iter().enumerate() {
+                    p.write_key(&var.key, i, file, fb);
+                    let var = p.data_address(var.id, fb);
+                    let mem_flags = MemFlags::trusted();
+                    let low = fb.ins().load(I64, mem_flags, var, 0);
+                    let high = fb.ins().load(I64, mem_flags, var, 8);
+                    p.call_extern("state_write_any", &[file, low, high], fb);
+                }
+                p.write_literal("},\"lists\":{", file, fb);
+                for (i, list) in lists.iter().enumerate() {
+                    p.write_key(&list.key, i, file, fb);
+                    let list = p.data_address(list.id, fb);
+                    p.call_extern("state_write_list", &[file, list], fb);
+                }
+                p.write_literal("}}\n", file, fb);
+            },
+        );
+
+        let var_table = self.saved_table(&vars);
+        let list_table = self.saved_table(&lists);
+        self.generate_state_function(
+            load,
+            "r",
+            ctx,
+            func_ctx,
+            |p, file, fb| {
+                let var_table = p.data_address(var_table, fb);
+                let var_count = fb.ins().iconst(I64, vars.len() as i64);
+                let list_table = p.data_address(list_table, fb);
+                let list_count = fb.ins().iconst(I64, lists.len() as i64);
+                p.call_extern(
+                    "state_load",
+                    &[file, var_table, var_count, list_table, list_count],
+                    fb,
+                );
+            },
+        );
+    }
+
+    /// Defines a table of saved variables or lists for `state_load`, laid
+    /// out like `SavedEntry` in the runtime.
+    fn saved_table(&mut self, entries: &[Saved]) -> DataId {
+        let id = self
+            .object_module
+            .declare_anonymous_data(false, false)
+            .unwrap();
+        let mut bytes = Vec::with_capacity(entries.len() * 32);
+        let mut relocs = Vec::new();
+        for entry in entries {
+            let key_id = self.static_str_id(Cow::Owned(entry.key.clone()));
+            // Static strings start 1 byte into their data.
+            relocs.push((bytes.len(), key_id, 1));
+            relocs.push((bytes.len() + 16, entry.id, 0));
+            bytes.extend(0_u64.to_le_bytes());
+            bytes.extend((entry.key.len() as u64).to_le_bytes());
+            bytes.extend(0_u64.to_le_bytes());
+            bytes.extend(u64::from(entry.is_num).to_le_bytes());
+        }
+        // Empty tables still need an address.
+        if bytes.is_empty() {
+            bytes.resize(8, 0);
+        }
+        self.data_ctx.clear();
+        self.data_ctx.set_align(8);
+        self.data_ctx.define(bytes.into_boxed_slice());
+        for (offset, data_id, addend) in relocs {
+            let data = self
+                .object_module
+                .declare_data_in_data(data_id, &mut self.data_ctx);
+            self.data_ctx.write_data_addr(offset as u32, data, addend);
+        }
+        self.object_module.define_data(id, &self.data_ctx).unwrap();
+        id
+    }
+
+    /// Generates a function that opens the save file with the `fopen` mode
+    /// `mode`, passes it to `body` and closes it. Nothing happens if the file
+    /// can't be opened.
+    fn generate_state_function(
+        &mut self,
+        func_id: FuncId,
+        mode: &'static str,
+        ctx: &mut Context,
+        func_ctx: &mut FunctionBuilderContext,
+        body: impl FnOnce(&mut Self, Value, &mut FunctionBuilder),
+    ) {
+        let signature = self
+            .object_module
+            .declarations()
+            .get_function_decl(func_id)
+            .signature
+            .clone();
+        ctx.clear();
+        ctx.func =
+            Function::with_name_signature(UserFuncName::default(), signature);
+        let mut fb = FunctionBuilder::new(&mut ctx.func, func_ctx);
+        let entry = fb.create_block();
+        let opened = fb.create_block();
+        let after = fb.create_block();
+        fb.switch_to_block(entry);
+        fb.seal_block(entry);
+        fb.append_block_params_for_function_params(entry);
+        let path = fb.block_params(entry)[0];
+
+        let mode = format!("{mode}\0");
+        let (mode, _) = self.allocate_static_str(Cow::Owned(mode), &mut fb);
+        let file = self.call_extern("fopen", &[path, mode], &mut fb);
+        let file = fb.inst_results(file)[0];
+        let is_null = fb.ins().icmp_imm(IntCC::Equal, file, 0);
+        fb.ins().brif(is_null, after, &[], opened, &[]);
+        fb.seal_block(opened);
+
+        fb.switch_to_block(opened);
+        body(self, file, &mut fb);
+        self.call_extern("fclose", &[file], &mut fb);
+        fb.ins().jump(after, &[]);
+
+        fb.switch_to_block(after);
+        fb.seal_block(after);
+        fb.ins().return_(&[]);
+        fb.finalize();
+        self.define_function(func_id, ctx);
+    }
+
+    fn write_literal(
+        &mut self,
+        s: &'static str,
+        file: Value,
+        fb: &mut FunctionBuilder,
+    ) {
+        let (ptr, len) = self.allocate_static_str(Cow::Borrowed(s), fb);
+        let one = fb.ins().iconst(I64, 1);
+        self.call_extern("fwrite", &[ptr, one, len, file], fb);
+    }
+
+    fn write_key(
+        &mut self,
+        key: &str,
+        index: usize,
+        file: Value,
+        fb: &mut FunctionBuilder,
+    ) {
+        if index != 0 {
+            self.write_literal(",", file, fb);
+        }
+        let (ptr, len) =
+            self.allocate_static_str(Cow::Owned(key.to_owned()), fb);
+        self.call_extern("state_write_str", &[file, ptr, len], fb);
+        self.write_literal(":", file, fb);
+    }
+
+    pub(super) fn data_address(
+        &mut self,
+        id: DataId,
+        fb: &mut FunctionBuilder,
+    ) -> Value {
+        let global_value = self.object_module.declare_data_in_func(id, fb.func);
+        fb.ins().global_value(I64, global_value)
+    }
+}
@@ -1,11 +1,19 @@
-use super::Program;
+use super::{
+    inline_asm_symbol,
+    range::{self, Counter},
+    typ::{MixedSizeValue, Typ},
+    Program,
+};
 use crate::{
     diagnostic::{Error, Result},
-    ir::{expr::Expr, statement::Statement},
+    ir::{expr::Expr, statement::Statement, typ::Type},
 };
 use codemap::Span;
-use cranelift::prelude::{types::*, *};
-use cranelift_module::Module;
+use cranelift::{
+    codegen::ir::SourceLoc,
+    prelude::{isa::CallConv, types::*, *},
+};
+use cranelift_module::{Linkage, Module};
 use sb3_stuff::Value as Immediate;
 use std::ops::ControlFlow;
 
@@ -17,13 +25,48 @@ impl<'a> Program<'a> {
         &mut self,
         stmt: &'a Statement,
         fb: &mut FunctionBuilder,
+    ) -> Result<ControlFlow<()>> {
+        // With `--annotate-asm`, the instructions of each statement are
+        // marked with where it is in the source code.
+        let Some(source_spans) = &mut self.source_spans else {
+            return self.generate_unmarked_statement(stmt, fb);
+        };
+        let (false, Some(span)) =
+            (matches!(stmt, Statement::Do(_)), stmt.span())
+        else {
+            return self.generate_unmarked_statement(stmt, fb);
+        };
+        let outer = self.srcloc;
+        self.srcloc = SourceLoc::new(source_spans.len() as u32);
+        source_spans.push(span);
+        fb.set_srcloc(self.srcloc);
+        let flow = self.generate_unmarked_statement(stmt, fb);
+        self.srcloc = outer;
+        fb.set_srcloc(outer);
+        flow
+    }
+
+    fn generate_unmarked_statement(
+        &mut self,
+        stmt: &'a Statement,
+        fb: &mut FunctionBuilder,
     ) -> Result<ControlFlow<()>> {
         match stmt {
             Statement::ProcCall {
                 proc_name,
                 args,
                 proc_span,
-            } => self.generate_proc_call(proc_name, args, *proc_span, fb),
+            } => {
+                let scope = self.temporary_scope();
+                let flow =
+                    self.generate_proc_call(proc_name, args, *proc_span, fb)?;
+                if flow.is_continue() {
+                    self.drop_temporaries(scope, fb);
+                } else {
+                    self.forget_temporaries(scope);
+                }
+                Ok(flow)
+            }
             Statement::Do(stmts) => {
                 match stmts.iter().try_for_each(|stmt| {
                     match self.generate_statement(stmt, fb) {
@@ -43,11 +86,13 @@ impl<'a> Program<'a> {
                 else_,
                 ..
             } => {
+                if let Some(switch) = self.as_switch(stmt) {
+                    return self.generate_switch(switch, fb);
+                }
                 let then_block = fb.create_block();
                 let else_block = fb.create_block();
                 let after = fb.create_block();
-                let condition = self.generate_bool_expr(condition, fb)?;
-                fb.ins().brif(condition, then_block, &[], else_block, &[]);
+                self.generate_branch(condition, then_block, else_block, fb)?;
                 fb.seal_block(else_block);
                 fb.seal_block(then_block);
                 fb.switch_to_block(then_block);
@@ -62,7 +107,7 @@ impl<'a> Program<'a> {
                 fb.seal_block(after);
                 Ok(CONTINUE)
             }
-            Statement::Repeat { times, body } => {
+            Statement::Repeat { times, body, .. } => {
                 let counter = self.new_variable();
                 fb.declare_var(counter, I64);
                 let times = self.generate_double_expr(times, fb)?;
@@ -87,7 +132,7 @@ impl<'a> Program<'a> {
                 fb.switch_to_block(after);
                 Ok(CONTINUE)
             }
-            Statement::Forever(body) => {
+            Statement::Forever(body, _) => {
                 let loop_start = fb.create_block();
                 fb.ins().jump(loop_start, &[]);
                 fb.switch_to_block(loop_start);
@@ -97,18 +142,21 @@ impl<'a> Program<'a> {
                 fb.seal_block(loop_start);
                 Ok(BREAK)
             }
-            Statement::Until { condition, body }
-            | Statement::While { condition, body } => {
+            Statement::Until {
+                condition, body, ..
+            }
+            | Statement::While {
+                condition, body, ..
+            } => {
                 let loop_start = fb.create_block();
                 let loop_body = fb.create_block();
                 let after = fb.create_block();
                 fb.ins().jump(loop_start, &[]);
                 fb.switch_to_block(loop_start);
-                let condition = self.generate_bool_expr(condition, fb)?;
                 if matches!(stmt, Statement::While { .. }) {
-                    fb.ins().brif(condition, loop_body, &[], after, &[]);
+                    self.generate_branch(condition, loop_body, after, fb)?;
                 } else {
-                    fb.ins().brif(condition, after, &[], loop_body, &[]);
+                    self.generate_branch(condition, after, loop_body, fb)?;
                 }
                 fb.seal_block(after);
                 fb.seal_block(loop_body);
@@ -135,22 +183,23 @@ impl<'a> Program<'a> {
                 let loop_start = fb.create_block();
                 let loop_body = fb.create_block();
                 let after = fb.create_block();
+                let max = Counter::max_for(times);
                 let times = self.generate_double_expr(times, fb)?;
                 let times = fb.ins().fcvt_to_uint_sat(I64, times);
-                let counter = self.new_variable();
-                fb.declare_var(counter, I64);
+                let count = self.new_variable();
+                fb.declare_var(count, I64);
                 let zero = fb.ins().iconst(I64, 0);
-                fb.def_var(counter, zero);
+                fb.def_var(count, zero);
                 fb.ins().jump(loop_start, &[]);
                 fb.switch_to_block(loop_start);
-                let old_count = fb.use_var(counter);
+                let old_count = fb.use_var(count);
                 let should_break =
                     fb.ins().icmp(IntCC::Equal, old_count, times);
                 fb.ins().brif(should_break, after, &[], loop_body, &[]);
                 fb.seal_block(loop_body);
                 fb.switch_to_block(loop_body);
                 let new_count = fb.ins().iadd_imm(old_count, 1);
-                fb.def_var(counter, new_count);
+                fb.def_var(count, new_count);
 
                 let new_count_as_f64 = fb.ins().fcvt_from_uint(F64, new_count);
                 let mem_flags = MemFlags::trusted();
@@ -160,7 +209,17 @@ impl<'a> Program<'a> {
                 fb.ins().store(mem_flags, number_type_tag, var, 0);
                 fb.ins().store(mem_flags, new_count_as_f64, var, 8);
 
-                if self.generate_statement(body, fb)?.is_continue() {
+                let outer = if range::preserves(body, &counter.0) {
+                    self.counters.insert(&counter.0, Counter { count, max })
+                } else {
+                    self.counters.remove(&*counter.0)
+                };
+                let flow = self.generate_statement(body, fb)?;
+                match outer {
+                    Some(outer) => self.counters.insert(&counter.0, outer),
+                    None => self.counters.remove(&*counter.0),
+                };
+                if flow.is_continue() {
                     fb.ins().jump(loop_start, &[]);
                 }
                 fb.seal_block(loop_start);
@@ -188,16 +247,51 @@ impl<'a> Program<'a> {
         };
 
         match proc_name {
-            "print" => match args {
+            "print" | "println" | "eprint" => match args {
                 [message] => {
-                    let (ptr, len) = self.generate_cow_expr(message, fb)?;
-                    let fd = fb.ins().iconst(I32, 1); // STDOUT_FILENO
-                    self.call_extern("write", &[fd, ptr, len], fb);
-                    self.call_extern("drop_cow", &[ptr], fb);
+                    let (print_any, print_str) = if proc_name == "eprint" {
+                        ("rt_eprint_any", "rt_eprint_str")
+                    } else {
+                        ("rt_print_any", "rt_print_str")
+                    };
+                    // The parts of a concatenation are written one after
+                    // another instead of allocating the whole string. They
+                    // are all evaluated first like they would be by `++`.
+                    let mut parts = Vec::new();
+                    concatenated_parts(message, &mut parts);
+                    let parts = parts
+                        .into_iter()
+                        .map(|part| {
+                            Ok(if let Typ::Any = self.expr_type(part) {
+                                let part =
+                                    self.generate_borrowed_any(part, fb)?;
+                                (print_any, part)
+                            } else {
+                                let part =
+                                    self.generate_temporary_cow(part, fb)?;
+                                (print_str, part.into())
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    for (print, part) in parts {
+                        self.call_extern(print, &part, fb);
+                    }
+                    if proc_name == "println" {
+                        self.call_extern("rt_print_newline", &[], fb);
+                    }
                     Ok(CONTINUE)
                 }
                 _ => wrong_arg_count(1),
             },
+            "print-fixed" => match args {
+                [n, digits] => {
+                    let n = self.generate_double_expr(n, fb)?;
+                    let digits = self.generate_double_expr(digits, fb)?;
+                    self.call_extern("rt_print_fixed", &[n, digits], fb);
+                    Ok(CONTINUE)
+                }
+                _ => wrong_arg_count(2),
+            },
             ":=" => match args {
                 [Expr::Sym(var_name, var_span), value] => {
                     let var =
@@ -207,8 +301,17 @@ impl<'a> Program<'a> {
                                 var_name: var_name.clone(),
                             }
                         })?;
-                    let new = self.generate_any_expr(value, fb)?;
                     let mem_flags = MemFlags::trusted();
+                    if self.unboxed_type(var_name) == Some(Type::Num) {
+                        // The old value is a number, so it doesn't need to be
+                        // dropped.
+                        let new = self.generate_double_expr(value, fb)?;
+                        let number_type_tag = fb.ins().iconst(I64, 2);
+                        fb.ins().store(mem_flags, number_type_tag, var, 0);
+                        fb.ins().store(mem_flags, new, var, 8);
+                        return Ok(CONTINUE);
+                    }
+                    let new = self.generate_any_expr(value, fb)?;
                     let old = fb.ins().load(I64, mem_flags, var, 0);
                     self.call_extern("drop_any", &[old], fb);
                     fb.ins().store(mem_flags, new.0, var, 0);
@@ -228,14 +331,19 @@ impl<'a> Program<'a> {
                         })?;
                     let amount = self.generate_double_expr(amount, fb)?;
                     let mem_flags = MemFlags::trusted();
-                    let old_low = fb.ins().load(I64, mem_flags, var, 0);
-                    let old_high = fb.ins().load(I64, mem_flags, var, 8);
-                    let old = self.call_extern(
-                        "any_to_double",
-                        &[old_low, old_high],
-                        fb,
-                    );
-                    let old = fb.inst_results(old)[0];
+                    let old = if self.unboxed_type(var_name) == Some(Type::Num)
+                    {
+                        fb.ins().load(F64, mem_flags, var, 8)
+                    } else {
+                        let old_low = fb.ins().load(I64, mem_flags, var, 0);
+                        let old_high = fb.ins().load(I64, mem_flags, var, 8);
+                        let old = self.call_extern(
+                            "any_to_double",
+                            &[old_low, old_high],
+                            fb,
+                        );
+                        fb.inst_results(old)[0]
+                    };
                     let new = fb.ins().fadd(old, amount);
                     let number_type_tag = fb.ins().iconst(I64, 2);
                     fb.ins().store(mem_flags, number_type_tag, var, 0);
@@ -292,6 +400,40 @@ impl<'a> Program<'a> {
                 }
                 _ => wrong_arg_count(3),
             },
+            "json-parse-into-lists" => match args {
+                [s, Expr::Sym(keys, key_span), Expr::Sym(vals, val_span)] => {
+                    let keys = self.lookup_list(keys, *key_span, fb)?;
+                    let values = self.lookup_list(vals, *val_span, fb)?;
+                    let (ptr, len) = self.generate_temporary_cow(s, fb)?;
+                    self.call_extern(
+                        "json_parse_into_lists",
+                        &[ptr, len, keys, values],
+                        fb,
+                    );
+                    Ok(CONTINUE)
+                }
+                _ => wrong_arg_count(3),
+            },
+            "save-state" | "load-state" => match args {
+                [path] => {
+                    let (save, load) = self.state_functions();
+                    let func_id = if proc_name == "save-state" {
+                        save
+                    } else {
+                        load
+                    };
+                    let (ptr, len) = self.generate_temporary_cow(path, fb)?;
+                    let path = self.call_extern("strndup", &[ptr, len], fb);
+                    let path = fb.inst_results(path)[0];
+                    self.add_temporary("free", path);
+                    let func_ref = self
+                        .object_module
+                        .declare_func_in_func(func_id, fb.func);
+                    fb.ins().call(func_ref, &[path]);
+                    Ok(CONTINUE)
+                }
+                _ => wrong_arg_count(1),
+            },
             "stop-this-script" => match args {
                 [] => {
                     if let Some(stop_block) = self.stop_block {
@@ -312,14 +454,47 @@ impl<'a> Program<'a> {
                 }
                 _ => wrong_arg_count(0),
             },
+            "asm" => match args {
+                [Expr::Imm(Immediate::String(code))] => {
+                    let index = self.inline_asm.len();
+                    self.inline_asm.push(code.to_string());
+                    let func_id = self
+                        .object_module
+                        .declare_function(
+                            &inline_asm_symbol(index),
+                            Linkage::Import,
+                            &Signature::new(CallConv::SystemV),
+                        )
+                        .unwrap();
+                    let func_ref = self
+                        .object_module
+                        .declare_func_in_func(func_id, fb.func);
+                    fb.ins().call(func_ref, &[]);
+                    Ok(CONTINUE)
+                }
+                [_] => Err(Box::new(Error::InvalidArgsForAsm { span })),
+                _ => wrong_arg_count(1),
+            },
+            "call-extern" => {
+                // A returned string is owned and has to be dropped.
+                if let Some(MixedSizeValue::Pair([ptr, _])) =
+                    self.generate_extern_call(args, span, false, fb)?
+                {
+                    self.add_temporary("drop_cow", ptr);
+                }
+                Ok(CONTINUE)
+            }
+            "call-extension" => Err(Box::new(Error::ExtensionNotSupported {
+                span,
+                target: "x86_64",
+            })),
             "ask" => match args {
                 [question] => {
-                    let question = self.generate_cow_expr(question, fb)?;
+                    let question = self.generate_temporary_cow(question, fb)?;
                     let new =
                         self.call_extern("ask", &<[_; 2]>::from(question), fb);
                     let new_ptr = fb.inst_results(new)[0];
                     let new_len = fb.inst_results(new)[1];
-                    self.call_extern("drop_cow", &[question.0], fb);
                     let answer = self.answer(fb);
                     let mem_flags = MemFlags::trusted();
                     let old = fb.ins().load(I64, mem_flags, answer, 0);
@@ -343,12 +518,20 @@ impl<'a> Program<'a> {
                     Ok(CONTINUE)
                 }
                 [name] => {
-                    let name = self.generate_cow_expr(name, fb)?;
+                    let name = self.generate_temporary_cow(name, fb)?;
                     let main_broadcast_handler =
                         self.main_broadcast_handler(fb);
                     fb.ins()
                         .call(main_broadcast_handler, &<[_; 2]>::from(name));
-                    self.call_extern("drop_cow", &[name.0], fb);
+                    Ok(CONTINUE)
+                }
+                _ => wrong_arg_count(1),
+            },
+            "speak" => match args {
+                [text] => {
+                    let text = self.generate_temporary_cow(text, fb)?;
+                    self.call_extern("rt_flush", &[], fb);
+                    self.call_extern("rt_speak", &<[_; 2]>::from(text), fb);
                     Ok(CONTINUE)
                 }
                 _ => wrong_arg_count(1),
@@ -356,6 +539,7 @@ impl<'a> Program<'a> {
             "wait" => match args {
                 [duration] => {
                     let duration = self.generate_double_expr(duration, fb)?;
+                    self.call_extern("rt_flush", &[], fb);
                     self.call_extern("wait_seconds", &[duration], fb);
                     Ok(CONTINUE)
                 }
@@ -394,15 +578,37 @@ impl<'a> Program<'a> {
             }));
         }
 
-        let args = args
-            .iter()
-            .map(|arg| self.generate_any_expr(arg, fb))
-            .collect::<Result<Vec<_>>>()?
-            .into_iter()
-            .flat_map(<[_; 2]>::from)
-            .collect::<Vec<_>>();
-        fb.ins().call(func_ref, &args);
+        let param_types = proc.param_types.clone();
+        let mut arg_values = Vec::with_capacity(args.len() * 2);
+        for (arg, typ) in args.iter().zip(param_types) {
+            match typ {
+                Some(Type::Num) => {
+                    arg_values.push(self.generate_double_expr(arg, fb)?);
+                }
+                Some(Type::Bool) => {
+                    arg_values.push(self.generate_bool_expr(arg, fb)?);
+                }
+                Some(Type::Str) | None => {
+                    let (low, high) = self.generate_any_expr(arg, fb)?;
+                    arg_values.extend([low, high]);
+                }
+            }
+        }
+        fb.ins().call(func_ref, &arg_values);
 
         Ok(())
     }
 }
+
+/// The expressions that are concatenated with `++` to make `expr`, which is
+/// just `expr` itself if it isn't a concatenation.
+fn concatenated_parts<'a>(expr: &'a Expr, parts: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::FuncCall("++", _, args) => {
+            for arg in args {
+                concatenated_parts(arg, parts);
+            }
+        }
+        _ => parts.push(expr),
+    }
+}
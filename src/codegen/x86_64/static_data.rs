@@ -0,0 +1,170 @@
+//! Arrays in read-only data that the generated code indexes directly, without
+//! going through a list at runtime. They hold doubles, like tables and lists
+//! of numbers, or `Any`s, whose strings point to static strings.
+
+use super::{typ::Typ, Program};
+use crate::{diagnostic::Result, ir::expr::Expr};
+use codemap::Span;
+use cranelift::prelude::{types::*, *};
+use cranelift_module::{DataDescription, DataId, Module};
+use cranelift_object::ObjectModule;
+use sb3_stuff::Value as Immediate;
+use std::borrow::Cow;
+
+/// A static array of the items of a list.
+#[derive(Clone, Copy)]
+pub(super) struct StaticList {
+    id: DataId,
+    len: usize,
+    /// Whether the items are all numbers, which are stored as doubles.
+    /// Otherwise they are stored as `Any`s.
+    doubles: bool,
+}
+
+impl<'a> Program<'a> {
+    /// Generates `(%static-get list index)`, which gets an item of a list
+    /// that the program never changes like `!!` does, but from a static
+    /// array of its initial items.
+    pub(super) fn generate_static_get(
+        &mut self,
+        list_name: &str,
+        list_span: Span,
+        index: &'a Expr,
+        fb: &mut FunctionBuilder,
+    ) -> Result<(Value, Value)> {
+        let list_id = self.list_id(list_name, list_span)?;
+        let Some(list) = self.static_list(list_id) else {
+            // There are no items to put in an array.
+            let list = self.lookup_list(list_name, list_span, fb)?;
+            let index = self.generate_any_expr(index, fb)?;
+            let got =
+                self.call_extern("list_get", &[index.0, index.1, list], fb);
+            return Ok((fb.inst_results(got)[0], fb.inst_results(got)[1]));
+        };
+
+        let len = fb.ins().iconst(I64, list.len as i64);
+        let index = if let Some(index) = self.generate_known_index(index, fb) {
+            index
+        } else if matches!(self.expr_type(index), Typ::Double) {
+            // Truncates and saturates like `double_to_usize`.
+            let index = self.generate_double_expr(index, fb)?;
+            fb.ins().fcvt_to_uint_sat(I64, index)
+        } else {
+            let index = self.generate_any_expr(index, fb)?;
+            let res =
+                self.call_extern("list_index", &[index.0, index.1, len], fb);
+            fb.inst_results(res)[0]
+        };
+        // Index 0 wraps around, so it is out of range too.
+        let offset = fb.ins().iadd_imm(index, -1);
+        let in_range = fb.ins().icmp(IntCC::UnsignedLessThan, offset, len);
+        let first = fb.ins().iconst(I64, 0);
+        let offset = fb.ins().select(in_range, offset, first);
+
+        let array = self.object_module.declare_data_in_func(list.id, fb.func);
+        let array = fb.ins().global_value(I64, array);
+        let (low, high) = if list.doubles {
+            let offset = fb.ins().imul_imm(offset, 8);
+            let address = fb.ins().iadd(array, offset);
+            let n = fb.ins().load(F64, MemFlags::trusted(), address, 0);
+            (
+                fb.ins().iconst(I64, 2),
+                fb.ins().bitcast(I64, MemFlags::new(), n),
+            )
+        } else {
+            let offset = fb.ins().imul_imm(offset, 16);
+            let address = fb.ins().iadd(array, offset);
+            (
+                fb.ins().load(I64, MemFlags::trusted(), address, 0),
+                fb.ins().load(I64, MemFlags::trusted(), address, 8),
+            )
+        };
+        let empty = self.allocate_static_str(Cow::Borrowed(""), fb);
+        Ok((
+            fb.ins().select(in_range, low, empty.0),
+            fb.ins().select(in_range, high, empty.1),
+        ))
+    }
+
+    /// The static array of the initial items of a list, which is defined the
+    /// first time it is used. Lists without items have none.
+    fn static_list(&mut self, list_id: DataId) -> Option<StaticList> {
+        if let Some(&list) = self.static_lists.get(&list_id) {
+            return Some(list);
+        }
+        let items = self
+            .initial_items
+            .iter()
+            .find(|(id, _)| *id == list_id)
+            .map(|(_, items)| *items)
+            .filter(|items| !items.is_empty())?;
+        let id = self
+            .object_module
+            .declare_anonymous_data(false, false)
+            .unwrap();
+        let doubles =
+            items.iter().all(|item| matches!(item, Immediate::Num(_)));
+        if doubles {
+            define_f64_array(
+                id,
+                items.iter().map(Immediate::to_num),
+                &mut self.data_ctx,
+                &mut self.object_module,
+            );
+        } else {
+            self.define_any_array(id, items);
+        }
+        let list = StaticList {
+            id,
+            len: items.len(),
+            doubles,
+        };
+        self.static_lists.insert(list_id, list);
+        Some(list)
+    }
+
+    /// Defines an array of `Any`s, laid out like `generate_any_imm` makes
+    /// them. Strings are static strings, so the items never have to be
+    /// cloned or freed.
+    fn define_any_array(&mut self, id: DataId, items: &'a [Immediate]) {
+        let mut bytes = Vec::with_capacity(items.len() * 16);
+        let mut strs = Vec::new();
+        for item in items {
+            let (low, high) = match item {
+                Immediate::Num(n) => (2, n.to_bits()),
+                Immediate::String(s) => {
+                    let str_id = self.static_str_id(Cow::Borrowed(s));
+                    strs.push((bytes.len(), str_id));
+                    (0, s.len() as u64)
+                }
+                Immediate::Bool(b) => (u64::from(*b), 0),
+            };
+            bytes.extend(low.to_le_bytes());
+            bytes.extend(high.to_le_bytes());
+        }
+        self.data_ctx.clear();
+        self.data_ctx.set_align(8);
+        self.data_ctx.define(bytes.into_boxed_slice());
+        for (offset, str_id) in strs {
+            let s = self
+                .object_module
+                .declare_data_in_data(str_id, &mut self.data_ctx);
+            // Static strings start 1 byte into their data.
+            self.data_ctx.write_data_addr(offset as u32, s, 1);
+        }
+        self.object_module.define_data(id, &self.data_ctx).unwrap();
+    }
+}
+
+/// Defines an array of doubles in read-only data.
+pub(super) fn define_f64_array(
+    id: DataId,
+    values: impl Iterator<Item = f64>,
+    data_ctx: &mut DataDescription,
+    object_module: &mut ObjectModule,
+) {
+    data_ctx.clear();
+    data_ctx.set_align(8);
+    data_ctx.define(values.flat_map(f64::to_le_bytes).collect());
+    object_module.define_data(id, data_ctx).unwrap();
+}
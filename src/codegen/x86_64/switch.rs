@@ -0,0 +1,151 @@
+use super::{typ::Typ, Program};
+use crate::{
+    diagnostic::Result,
+    ir::{
+        expr::Expr::{self, Imm, Sym},
+        statement::Statement,
+    },
+};
+use cranelift::prelude::{types::*, *};
+use sb3_stuff::Value as Immediate;
+use std::{collections::HashSet, ops::ControlFlow};
+
+/// Chains with fewer cases than this are left as comparisons.
+const MIN_CASES: usize = 4;
+/// The largest jump table that will be generated.
+const MAX_TABLE_LEN: i64 = 1024;
+
+/// An if-else chain that compares the same number against different integer
+/// constants, like those produced by `cond`.
+pub(super) struct Switch<'a> {
+    scrutinee: &'a Expr,
+    cases: Vec<(i64, &'a Statement)>,
+    default: &'a Statement,
+    min: i64,
+    max: i64,
+}
+
+impl<'a> Program<'a> {
+    /// Recognizes if-else chains that are worth lowering to a jump table.
+    pub(super) fn as_switch(&self, stmt: &'a Statement) -> Option<Switch<'a>> {
+        let mut scrutinee = None;
+        let mut seen = HashSet::new();
+        let mut cases = Vec::new();
+        let mut current = stmt;
+        while let Statement::IfElse {
+            condition: Expr::FuncCall("=", _, args),
+            then,
+            else_,
+            ..
+        } = current
+        {
+            let (value, name, case) = match &args[..] {
+                [value @ Sym(name, _), Imm(Immediate::Num(case))]
+                | [Imm(Immediate::Num(case)), value @ Sym(name, _)] => {
+                    (value, name, *case)
+                }
+                _ => break,
+            };
+            if case.fract() != 0.0
+                || case.abs() > f64::from(i32::MAX)
+                || scrutinee.is_some_and(|(_, prev)| prev != name)
+            {
+                break;
+            }
+            scrutinee = Some((value, name));
+            // Later cases for the same value are unreachable.
+            if seen.insert(case as i64) {
+                cases.push((case as i64, &**then));
+            }
+            current = &**else_;
+        }
+
+        let (scrutinee, _) = scrutinee?;
+        // Numbers compare differently than strings which look like numbers,
+        // so the scrutinee has to be known to be a number.
+        if cases.len() < MIN_CASES
+            || !matches!(self.expr_type(scrutinee), Typ::Double)
+        {
+            return None;
+        }
+        let min = cases.iter().map(|(case, _)| *case).min()?;
+        let max = cases.iter().map(|(case, _)| *case).max()?;
+        let len = max - min + 1;
+        if len > MAX_TABLE_LEN || len > 2 * cases.len() as i64 {
+            return None;
+        }
+
+        Some(Switch {
+            scrutinee,
+            cases,
+            default: current,
+            min,
+            max,
+        })
+    }
+
+    pub(super) fn generate_switch(
+        &mut self,
+        switch: Switch<'a>,
+        fb: &mut FunctionBuilder,
+    ) -> Result<ControlFlow<()>> {
+        let range_check = fb.create_block();
+        let dispatch = fb.create_block();
+        let default_block = fb.create_block();
+        let after = fb.create_block();
+
+        let value = self.generate_double_expr(switch.scrutinee, fb)?;
+        let int = fb.ins().fcvt_to_sint_sat(I64, value);
+        let round_trip = fb.ins().fcvt_from_sint(F64, int);
+        let is_int = fb.ins().fcmp(FloatCC::Equal, round_trip, value);
+        fb.ins().brif(is_int, range_check, &[], default_block, &[]);
+        fb.seal_block(range_check);
+
+        fb.switch_to_block(range_check);
+        let index = fb.ins().iadd_imm(int, -switch.min);
+        let in_range = fb.ins().icmp_imm(
+            IntCC::UnsignedLessThan,
+            index,
+            switch.max - switch.min + 1,
+        );
+        fb.ins().brif(in_range, dispatch, &[], default_block, &[]);
+        fb.seal_block(dispatch);
+
+        fb.switch_to_block(dispatch);
+        let case_blocks = switch
+            .cases
+            .iter()
+            .map(|_| fb.create_block())
+            .collect::<Vec<_>>();
+        let mut slots =
+            vec![default_block; (switch.max - switch.min + 1) as usize];
+        for (&(case, _), &block) in switch.cases.iter().zip(&case_blocks) {
+            slots[(case - switch.min) as usize] = block;
+        }
+        let default_call = fb.func.dfg.block_call(default_block, &[]);
+        let table = slots
+            .into_iter()
+            .map(|block| fb.func.dfg.block_call(block, &[]))
+            .collect::<Vec<_>>();
+        let jump_table =
+            fb.create_jump_table(JumpTableData::new(default_call, &table));
+        let index = fb.ins().ireduce(I32, index);
+        fb.ins().br_table(index, jump_table);
+        fb.seal_block(default_block);
+
+        for (block, (_, body)) in case_blocks.into_iter().zip(switch.cases) {
+            fb.seal_block(block);
+            fb.switch_to_block(block);
+            if self.generate_statement(body, fb)?.is_continue() {
+                fb.ins().jump(after, &[]);
+            }
+        }
+        fb.switch_to_block(default_block);
+        if self.generate_statement(switch.default, fb)?.is_continue() {
+            fb.ins().jump(after, &[]);
+        }
+        fb.switch_to_block(after);
+        fb.seal_block(after);
+        Ok(ControlFlow::Continue(()))
+    }
+}
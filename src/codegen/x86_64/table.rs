@@ -0,0 +1,71 @@
+use super::{static_data::define_f64_array, Program};
+use crate::{
+    diagnostic::{Error, Result},
+    ir::{expr::Expr, table::Table},
+};
+use codemap::Span;
+use cranelift::prelude::{types::*, *};
+use cranelift_module::{DataDescription, DataId, Module};
+use cranelift_object::ObjectModule;
+
+impl<'a> Program<'a> {
+    /// Generates `(table-get name i)`. Indices that are out of range, or NaN,
+    /// give 0. The first item is read instead of them so that there is no
+    /// branch.
+    pub(super) fn generate_table_get(
+        &mut self,
+        table_name: &str,
+        table_span: Span,
+        index: &'a Expr,
+        fb: &mut FunctionBuilder,
+    ) -> Result<Value> {
+        let (id, table) = self.lookup_table(table_name, table_span)?;
+        let index = self.generate_double_expr(index, fb)?;
+        let zero = fb.ins().f64const(0.0);
+        if table.items.is_empty() {
+            return Ok(zero);
+        }
+        let start = fb.ins().f64const(table.start);
+        let offset = fb.ins().fsub(index, start);
+        let offset = fb.ins().floor(offset);
+        let len = fb.ins().f64const(table.items.len() as f64);
+        let not_below =
+            fb.ins().fcmp(FloatCC::GreaterThanOrEqual, offset, zero);
+        let below_len = fb.ins().fcmp(FloatCC::LessThan, offset, len);
+        let in_range = fb.ins().band(not_below, below_len);
+        let offset = fb.ins().fcvt_to_sint_sat(I64, offset);
+        let first = fb.ins().iconst(I64, 0);
+        let offset = fb.ins().select(in_range, offset, first);
+        let offset = fb.ins().imul_imm(offset, 8);
+        let table = self.object_module.declare_data_in_func(id, fb.func);
+        let table = fb.ins().global_value(I64, table);
+        let address = fb.ins().iadd(table, offset);
+        let item = fb.ins().load(F64, MemFlags::trusted(), address, 0);
+        Ok(fb.ins().select(in_range, item, zero))
+    }
+
+    fn lookup_table(
+        &self,
+        name: &str,
+        span: Span,
+    ) -> Result<(DataId, &'a Table)> {
+        Ok(*self
+            .sprite_tables
+            .get(name)
+            .or_else(|| self.global_tables.get(name))
+            .ok_or_else(|| Error::UnknownTable {
+                span,
+                table_name: name.to_owned(),
+            })?)
+    }
+}
+
+/// Tables are arrays of doubles in read-only data.
+pub(super) fn define_table(
+    id: DataId,
+    table: &Table,
+    data_ctx: &mut DataDescription,
+    object_module: &mut ObjectModule,
+) {
+    define_f64_array(id, table.values(), data_ctx, object_module);
+}
@@ -0,0 +1,54 @@
+use super::Program;
+use crate::{diagnostic::Result, ir::expr::Expr};
+use cranelift::prelude::*;
+
+/// An owned value that is only needed until the builtin that generated it is
+/// done, together with the runtime function that drops it. Every builtin call
+/// is a scope that drops the temporaries registered while generating it, so
+/// builtins never have to drop their operands by hand.
+pub(super) type Temporary = (&'static str, Value);
+
+impl<'a> Program<'a> {
+    /// Generates a string that is dropped at the end of the current builtin.
+    pub(super) fn generate_temporary_cow(
+        &mut self,
+        expr: &'a Expr,
+        fb: &mut FunctionBuilder,
+    ) -> Result<(Value, Value)> {
+        let (ptr, len) = self.generate_cow_expr(expr, fb)?;
+        self.add_temporary("drop_cow", ptr);
+        Ok((ptr, len))
+    }
+
+    /// Registers a value to be passed to `destructor` at the end of the
+    /// current builtin.
+    pub(super) fn add_temporary(
+        &mut self,
+        destructor: &'static str,
+        value: Value,
+    ) {
+        self.temporaries.push((destructor, value));
+    }
+
+    /// Starts the scope of a builtin call. The returned marker is passed to
+    /// `drop_temporaries` once the call has been generated.
+    pub(super) fn temporary_scope(&self) -> usize {
+        self.temporaries.len()
+    }
+
+    pub(super) fn drop_temporaries(
+        &mut self,
+        scope: usize,
+        fb: &mut FunctionBuilder,
+    ) {
+        for (destructor, value) in self.temporaries.split_off(scope) {
+            self.call_extern(destructor, &[value], fb);
+        }
+    }
+
+    /// Ends the scope of a builtin call that doesn't return, like `stop-all`,
+    /// where there is no block left to drop its temporaries in.
+    pub(super) fn forget_temporaries(&mut self, scope: usize) {
+        self.temporaries.truncate(scope);
+    }
+}
@@ -1,7 +1,12 @@
-use crate::ir::expr::Expr;
-use cranelift::prelude::Value;
+use super::Program;
+use crate::{
+    diagnostic::Result,
+    ir::{expr::Expr, typ::Type},
+};
+use cranelift::prelude::{types::*, *};
 use sb3_stuff::Value as Immediate;
 
+#[derive(Clone, Copy)]
 pub enum Typ<'a> {
     Double,
     Bool,
@@ -10,30 +15,59 @@ pub enum Typ<'a> {
     Any,
 }
 
-pub fn expr_type(expr: &Expr) -> Typ {
-    match expr {
-        Expr::Imm(Immediate::String(s)) => Typ::StaticStr(s),
-        Expr::Imm(Immediate::Bool(_)) => Typ::Bool,
-        Expr::Imm(Immediate::Num(_)) | Expr::AddSub(..) | Expr::MulDiv(..) => {
-            Typ::Double
-        }
-        Expr::Sym(..) => {
-            // NOTE: This will need to be changed when we add support for converting
-            // lists to strings or start perform static type analysis on variables.
-            Typ::Any
+impl<'a> Program<'a> {
+    pub(super) fn generate_typed_expr(
+        &mut self,
+        expr: &'a Expr,
+        fb: &mut FunctionBuilder,
+    ) -> Result<TypedValue<'a>> {
+        let value = self.generate_expr(expr, fb)?;
+        Ok(TypedValue {
+            typ: self.expr_type(expr),
+            value,
+        })
+    }
+
+    pub(super) fn expr_type<'e>(&self, expr: &'e Expr) -> Typ<'e> {
+        match expr {
+            Expr::Imm(Immediate::String(s)) => Typ::StaticStr(s),
+            Expr::Imm(Immediate::Bool(_)) => Typ::Bool,
+            Expr::Imm(Immediate::Num(_))
+            | Expr::AddSub(..)
+            | Expr::MulDiv(..) => Typ::Double,
+            Expr::Sym(sym, _) => match self.unboxed_type(sym) {
+                Some(Type::Num) => Typ::Double,
+                Some(Type::Bool) => Typ::Bool,
+                Some(Type::Str) | None => Typ::Any,
+            },
+            Expr::FuncCall(func_name, _, args) => match *func_name {
+                "!!" | "%static-get" | "call-extension" => Typ::Any,
+                "call-extern" => match self
+                    .lookup_extern(args)
+                    .and_then(|function| function.returns)
+                {
+                    Some(Type::Num) => Typ::Double,
+                    Some(Type::Bool) => Typ::Bool,
+                    Some(Type::Str) => Typ::OwnedString,
+                    None => Typ::Any,
+                },
+                "not" | "and" | "or" | "<" | "=" | ">" | "to-bool"
+                | "str-match" => Typ::Bool,
+                "++" | "char-at" | "json-get" | "json-set"
+                | "format-decimal" | "to-hex" | "to-binary" => Typ::OwnedString,
+                "length" | "str-length" | "mod" | "abs" | "floor" | "ceil"
+                | "sqrt" | "ln" | "log" | "e^" | "ten^" | "sin" | "cos"
+                | "tan" | "asin" | "acos" | "atan" | "to-num" | "random"
+                | "round-to" | "bit-and" | "bit-or" | "bit-xor"
+                | "bit-shift-left" | "bit-shift-right" | "min" | "max"
+                | "clamp" | "dist" | "dir-to" | "table-get" => Typ::Double,
+                _ => todo!(),
+            },
         }
-        Expr::FuncCall(func_name, _, _args) => match *func_name {
-            "!!" => Typ::Any,
-            "not" | "and" | "or" | "<" | "=" | ">" => Typ::Bool,
-            "++" | "char-at" => Typ::OwnedString,
-            "length" | "str-length" | "mod" | "abs" | "floor" | "ceil"
-            | "sqrt" | "ln" | "log" | "e^" | "ten^" | "sin" | "cos" | "tan"
-            | "asin" | "acos" | "atan" | "to-num" | "random" => Typ::Double,
-            _ => todo!(),
-        },
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum MixedSizeValue {
     Single(Value),
     Pair([Value; 2]),
@@ -73,3 +107,152 @@ impl MixedSizeValue {
         }
     }
 }
+
+/// A generated value along with its type, which determines how it is
+/// represented: `Double` is an `F64`, `Bool` is an `I8`, strings are a pointer
+/// and a length and `Any` is the pair described in the runtime crate.
+#[derive(Clone, Copy)]
+pub struct TypedValue<'a> {
+    pub typ: Typ<'a>,
+    pub value: MixedSizeValue,
+}
+
+impl TypedValue<'_> {
+    /// Converts the value to a boolean, consuming it.
+    pub fn into_bool(
+        self,
+        p: &mut Program<'_>,
+        fb: &mut FunctionBuilder,
+    ) -> Value {
+        match self.typ {
+            Typ::Double => {
+                let zero = fb.ins().f64const(0.0);
+                fb.ins().fcmp(
+                    FloatCC::OrderedNotEqual,
+                    self.value.single(),
+                    zero,
+                )
+            }
+            Typ::Bool => self.value.single(),
+            Typ::StaticStr(_) | Typ::OwnedString => {
+                let inst =
+                    p.call_extern("any_to_bool", self.value.as_slice(), fb);
+                fb.inst_results(inst)[0]
+            }
+            Typ::Any => {
+                self.convert_any(p, fb, I8, "any_to_bool", |is_number, fb| {
+                    let (low, high) = self.value.pair();
+                    let is_true = fb.ins().icmp_imm(IntCC::Equal, low, 1);
+                    let n = fb.ins().bitcast(F64, MemFlags::new(), high);
+                    let zero = fb.ins().f64const(0.0);
+                    let nonzero =
+                        fb.ins().fcmp(FloatCC::OrderedNotEqual, n, zero);
+                    fb.ins().select(is_number, nonzero, is_true)
+                })
+            }
+        }
+    }
+
+    /// Converts the value to a number, consuming it.
+    pub fn into_double(
+        self,
+        p: &mut Program<'_>,
+        fb: &mut FunctionBuilder,
+    ) -> Value {
+        match self.typ {
+            Typ::Double => self.value.single(),
+            Typ::Bool => {
+                let zero = fb.ins().f64const(0.0);
+                let one = fb.ins().f64const(1.0);
+                fb.ins().select(self.value.single(), one, zero)
+            }
+            Typ::StaticStr(_) | Typ::OwnedString => {
+                let inst =
+                    p.call_extern("any_to_double", self.value.as_slice(), fb);
+                fb.inst_results(inst)[0]
+            }
+            Typ::Any => self.convert_any(
+                p,
+                fb,
+                F64,
+                "any_to_double",
+                |is_number, fb| {
+                    let (low, high) = self.value.pair();
+                    let n = fb.ins().bitcast(F64, MemFlags::new(), high);
+                    let b = fb.ins().fcvt_from_uint(F64, low);
+                    fb.ins().select(is_number, n, b)
+                },
+            ),
+        }
+    }
+
+    /// Converts an `Any` to a boolean or a number. Booleans and numbers are
+    /// converted inline by `inline`, which is given whether the value is a
+    /// number, and only strings are passed to the runtime function `slow`.
+    fn convert_any(
+        self,
+        p: &mut Program<'_>,
+        fb: &mut FunctionBuilder,
+        typ: types::Type,
+        slow: &str,
+        inline: impl FnOnce(Value, &mut FunctionBuilder) -> Value,
+    ) -> Value {
+        let (low, _) = self.value.pair();
+        let is_str = fb.ins().icmp_imm(IntCC::UnsignedGreaterThan, low, 2);
+        let is_number = fb.ins().icmp_imm(IntCC::Equal, low, 2);
+        let converted = inline(is_number, fb);
+        let str_block = fb.create_block();
+        let done_block = fb.create_block();
+        let result = fb.append_block_param(done_block, typ);
+        fb.ins()
+            .brif(is_str, str_block, &[], done_block, &[converted]);
+        fb.seal_block(str_block);
+
+        fb.switch_to_block(str_block);
+        let inst = p.call_extern(slow, self.value.as_slice(), fb);
+        let from_str = fb.inst_results(inst)[0];
+        fb.ins().jump(done_block, &[from_str]);
+        fb.seal_block(done_block);
+
+        fb.switch_to_block(done_block);
+        result
+    }
+
+    /// Converts the value to a string that may or may not be owned.
+    pub fn into_cow(
+        self,
+        p: &mut Program<'_>,
+        fb: &mut FunctionBuilder,
+    ) -> (Value, Value) {
+        let inst = match self.typ {
+            Typ::Double => {
+                p.call_extern("double_to_cow", &[self.value.single()], fb)
+            }
+            Typ::Bool => {
+                p.call_extern("bool_to_str", &[self.value.single()], fb)
+            }
+            Typ::StaticStr(_) | Typ::OwnedString => return self.value.pair(),
+            Typ::Any => p.call_extern("any_to_cow", self.value.as_slice(), fb),
+        };
+        let results = fb.inst_results(inst);
+        (results[0], results[1])
+    }
+
+    /// Converts the value to the representation of an `Any`.
+    pub fn into_any(self, fb: &mut FunctionBuilder) -> (Value, Value) {
+        match self.typ {
+            Typ::Double => {
+                let bits =
+                    fb.ins().bitcast(I64, MemFlags::new(), self.value.single());
+                (fb.ins().iconst(I64, 2), bits)
+            }
+            Typ::Bool => {
+                let extended = fb.ins().uextend(I64, self.value.single());
+                (extended, fb.ins().iconst(I64, 0))
+            }
+            Typ::StaticStr(_) | Typ::OwnedString | Typ::Any => {
+                self.value.pair()
+            }
+        }
+    }
+}
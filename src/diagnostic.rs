@@ -1,11 +1,20 @@
 mod error;
 pub use error::Error;
+mod explain;
+pub use explain::explain;
+mod remark;
+pub use remark::Remark;
+mod render;
 mod warning;
 pub use warning::Warning;
 
+use crate::opts::MessageFormat;
 use codemap::{CodeMap, Span};
-use codemap_diagnostic::{
-    ColorConfig, Diagnostic, Emitter, SpanLabel as Label, SpanStyle,
+use codemap_diagnostic::{Diagnostic, Level, SpanLabel as Label, SpanStyle};
+use serde_json::{json, Value as Json};
+use std::{
+    env,
+    io::{self, IsTerminal},
 };
 
 pub type Result<T> = std::result::Result<T, Box<Error>>;
@@ -18,6 +27,54 @@ const fn plural<'a>(count: usize, one: &'a str, many: &'a str) -> &'a str {
     }
 }
 
+/// What reading a variable or list that is never assigned gives.
+fn never_assigned_label(kind: &str) -> String {
+    if kind == "list" {
+        "nothing adds items to it, so it is always empty".to_owned()
+    } else {
+        "nothing assigns to it, so this is always 0".to_owned()
+    }
+}
+
+/// The candidate that `name` was most likely meant to be, if any is close
+/// enough to suggest.
+pub fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    if name.is_empty() {
+        return None;
+    }
+    let name = name.to_lowercase();
+    candidates
+        .iter()
+        .filter_map(|&candidate| {
+            let distance =
+                if name.contains(candidate) || candidate.contains(&*name) {
+                    0
+                } else {
+                    edit_distance(&name, candidate)
+                };
+            (distance <= candidate.len() / 3).then_some((distance, candidate))
+        })
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The number of characters that have to be inserted, removed or replaced to
+/// turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
 fn primary(span: Span, label: impl Into<Option<String>>) -> Label {
     Label {
         span,
@@ -34,7 +91,123 @@ fn secondary(span: Span, label: impl Into<Option<String>>) -> Label {
     }
 }
 
-fn emit_all(diagnostics: &[Diagnostic], code_map: &CodeMap) {
-    let mut emitter = Emitter::stderr(ColorConfig::Auto, Some(code_map));
-    emitter.emit(diagnostics);
+/// Prints errors ordered by the file and offset of their first primary label,
+/// so that the output doesn't depend on the order they were found in. Errors
+/// without a location come first. Only the first `max_errors` are printed,
+/// unless it is 0.
+pub fn emit_errors(
+    errors: &[Error],
+    code_map: &CodeMap,
+    format: MessageFormat,
+    max_errors: usize,
+) {
+    let mut groups = errors.iter().map(Error::diagnostics).collect::<Vec<_>>();
+    groups.sort_by_cached_key(|group| location(group, code_map));
+    let shown = match max_errors {
+        0 => groups.len(),
+        max => max.min(groups.len()),
+    };
+    for group in &groups[..shown] {
+        emit_all(group, code_map, format);
+    }
+    let hidden = groups.len() - shown;
+    if hidden != 0 {
+        let summary = Diagnostic {
+            level: Level::Note,
+            message: format!(
+                "{hidden} more {} not shown because of `--max-errors`",
+                plural(hidden, "error was", "errors were"),
+            ),
+            code: None,
+            spans: Vec::new(),
+        };
+        emit_all(&[summary], code_map, format);
+    }
+}
+
+/// The file name and offset of the first primary label.
+fn location(
+    diagnostics: &[Diagnostic],
+    code_map: &CodeMap,
+) -> Option<(String, u64)> {
+    let label = diagnostics
+        .iter()
+        .flat_map(|diagnostic| &diagnostic.spans)
+        .find(|label| matches!(label.style, SpanStyle::Primary))?;
+    let loc = code_map.look_up_span(label.span);
+    Some((
+        loc.file.name().to_owned(),
+        label.span.low() - loc.file.span.low(),
+    ))
+}
+
+fn emit_all(
+    diagnostics: &[Diagnostic],
+    code_map: &CodeMap,
+    format: MessageFormat,
+) {
+    match format {
+        MessageFormat::Human => {
+            eprint!("{}", render::render(diagnostics, code_map, use_color()));
+        }
+        MessageFormat::Json => {
+            let [diagnostic, children @ ..] = diagnostics else {
+                return;
+            };
+            let mut json = diagnostic_to_json(diagnostic, code_map);
+            json["children"] = children
+                .iter()
+                .map(|child| diagnostic_to_json(child, code_map))
+                .collect();
+            json["rendered"] =
+                render::render(diagnostics, code_map, false).into();
+            eprintln!("{json}");
+        }
+    }
+}
+
+/// Whether stderr is a terminal that can show colors. `NO_COLOR` turns them
+/// off, as described at <https://no-color.org>.
+fn use_color() -> bool {
+    io::stderr().is_terminal()
+        && env::var_os("NO_COLOR").is_none()
+        && env::var_os("TERM").is_some_and(|term| term != "dumb")
+}
+
+const fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Bug => "bug",
+        Level::Fatal => "fatal",
+        Level::Error => "error",
+        Level::Warning => "warning",
+        Level::Note => "note",
+        Level::Help => "help",
+    }
+}
+
+fn diagnostic_to_json(diagnostic: &Diagnostic, code_map: &CodeMap) -> Json {
+    let spans = diagnostic
+        .spans
+        .iter()
+        .map(|label| {
+            let loc = code_map.look_up_span(label.span);
+            json!({
+                "file_name": loc.file.name(),
+                "byte_start": label.span.low() - loc.file.span.low(),
+                "byte_end": label.span.high() - loc.file.span.low(),
+                "line_start": loc.begin.line + 1,
+                "line_end": loc.end.line + 1,
+                "column_start": loc.begin.column + 1,
+                "column_end": loc.end.column + 1,
+                "is_primary": matches!(label.style, SpanStyle::Primary),
+                "label": label.label,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "message": diagnostic.message,
+        "code": diagnostic.code,
+        "level": level_name(diagnostic.level),
+        "spans": spans,
+    })
 }
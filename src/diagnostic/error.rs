@@ -1,11 +1,15 @@
-use super::{emit_all, plural, primary, secondary, Diagnostic};
-use codemap::{CodeMap, Span};
+use super::{never_assigned_label, plural, primary, secondary, Diagnostic};
+use codemap::Span;
 use codemap_diagnostic::SpanLabel as Label;
 use ecow::EcoString;
-use std::io;
+use std::{fmt, io, path::PathBuf, process::ExitStatus};
 
 #[derive(Debug)]
 pub enum Error {
+    ArtifactNotSupportedByTarget {
+        artifact: &'static str,
+        target: &'static str,
+    },
     BuiltinProcWrongArgCount {
         span: Span,
         proc_name: String,
@@ -21,12 +25,67 @@ pub enum Error {
     CouldNotFinishZip {
         inner: zip::result::ZipError,
     },
+    CouldNotRunTool {
+        tool: String,
+        inner: io::Error,
+    },
+    CouldNotReadCostume {
+        span: Span,
+        path: PathBuf,
+        inner: io::Error,
+    },
+    CouldNotReadFile {
+        path: PathBuf,
+        inner: io::Error,
+    },
+    CouldNotWriteFile {
+        path: PathBuf,
+        inner: io::Error,
+    },
     CustomProcWrongArgCount {
         span: Span,
         proc_name: String,
         expected: usize,
         got: usize,
     },
+    DuplicateProc {
+        span: Span,
+        previous: Span,
+        proc_name: String,
+    },
+    EventNotSupported {
+        span: Span,
+        event: String,
+        target: &'static str,
+    },
+    ExtensionNotSupported {
+        span: Span,
+        target: &'static str,
+    },
+    ExtensionOpcodeMustBeString {
+        span: Span,
+    },
+    ExternFunctionWrongArgCount {
+        span: Span,
+        extern_name: String,
+        expected: usize,
+        got: usize,
+    },
+    ExternNameMustBeString {
+        span: Span,
+    },
+    ExternNotSupported {
+        span: Span,
+        target: &'static str,
+    },
+    ExternReturnsNothing {
+        span: Span,
+        extern_name: String,
+    },
+    ExternSignatureConflict {
+        span: Span,
+        extern_name: String,
+    },
     FunctionMacroMatchFailed {
         pattern: Span,
         provided: Span,
@@ -47,9 +106,55 @@ pub enum Error {
         expected: usize,
         got: usize,
     },
+    ImplicitConversion {
+        span: Span,
+        expected: &'static str,
+        conversion: &'static str,
+    },
+    InlineAsmNotSupported {
+        span: Span,
+        target: &'static str,
+    },
+    InvalidAllow {
+        span: Span,
+    },
+    InvalidArgsForAsm {
+        span: Span,
+    },
     InvalidArgsForInclude {
         span: Span,
     },
+    InvalidCfg {
+        span: Span,
+    },
+    InvalidColor {
+        span: Span,
+    },
+    InvalidCostume {
+        span: Span,
+        path: PathBuf,
+        problem: &'static str,
+    },
+    InvalidCostumeDeclaration {
+        span: Span,
+    },
+    InvalidDeclaration {
+        span: Span,
+        /// What is declared, like `variable`.
+        kind: &'static str,
+    },
+    InvalidEnumDefinition {
+        span: Span,
+    },
+    InvalidExtensionManifest {
+        span: Span,
+    },
+    InvalidExternDeclaration {
+        span: Span,
+    },
+    InvalidInitialValue {
+        span: Span,
+    },
     InvalidItemInSprite {
         span: Span,
     },
@@ -62,31 +167,137 @@ pub enum Error {
     InvalidParameterForCustomProcDef {
         span: Span,
     },
+    InvalidPattern {
+        span: Span,
+        reason: &'static str,
+    },
+    InvalidRecordDefinition {
+        span: Span,
+    },
+    InvalidPriority {
+        span: Span,
+    },
+    InvalidProcSignature {
+        span: Span,
+    },
+    InvalidRewriteRule {
+        span: Span,
+    },
+    InvalidStatement {
+        span: Span,
+    },
+    InvalidTableDefinition {
+        span: Span,
+    },
     InvalidTopLevelItem {
         span: Span,
     },
+    InvalidTypeAnnotation {
+        span: Span,
+    },
     MacroDefinitionMissingBody {
         span: Span,
     },
+    MacroDefaultNotTrailing {
+        span: Span,
+    },
     MacroDefinitionMissingSignature {
         span: Span,
     },
-    Parse(String),
+    NativeOnlyProc {
+        span: Span,
+        proc_name: &'static str,
+        target: &'static str,
+    },
+    NeverAssigned {
+        span: Span,
+        name: String,
+        kind: &'static str,
+    },
+    NonConstantCondition {
+        span: Span,
+        macro_name: String,
+    },
+    Parse {
+        span: Span,
+        /// What could have come next, like `` `)` `` or `an expression`.
+        expected: Vec<String>,
+    },
     ProgramMissingStage,
+    ProjectTooBig {
+        option: &'static str,
+        unit: &'static str,
+        size: usize,
+        limit: usize,
+        /// The biggest scripts and their sizes, biggest first.
+        biggest: Vec<(Span, usize)>,
+    },
+    RotatePointTargetNotVariable {
+        span: Span,
+    },
+    SourceNotUtf8 {
+        /// Where the file was included, if it was.
+        span: Option<Span>,
+        path: PathBuf,
+        offset: usize,
+        line: usize,
+    },
+    SourceTooBig {
+        span: Option<Span>,
+        path: PathBuf,
+        limit: u64,
+    },
     SpriteMissingName {
         span: Span,
         candidate_symbol: Option<Span>,
     },
+    StaticAssertFailed {
+        span: Span,
+        message: Option<String>,
+    },
+    StaticAssertNotConstant {
+        span: Span,
+    },
     SymbolMacroInInlinePosition {
         span: Span,
     },
     SymConcatEmptySymbol {
         span: Span,
     },
+    TableNotConstant {
+        span: Span,
+    },
+    TooDeeplyNested {
+        span: Span,
+        limit: usize,
+    },
+    ToolFailed {
+        tool: String,
+        status: ExitStatus,
+    },
+    TypeMismatch {
+        span: Span,
+        expected: &'static str,
+        found: &'static str,
+    },
+    UnknownExtensionBlock {
+        span: Span,
+        opcode: String,
+        kind: &'static str,
+    },
+    UnknownExtern {
+        span: Span,
+        extern_name: String,
+    },
     UnknownFunction {
         span: Span,
         func_name: String,
     },
+    UnknownKey {
+        span: Span,
+        key: String,
+        suggestion: Option<&'static str>,
+    },
     UnknownList {
         span: Span,
         list_name: EcoString,
@@ -99,6 +310,14 @@ pub enum Error {
         span: Span,
         proc_name: String,
     },
+    UnknownTable {
+        span: Span,
+        table_name: String,
+    },
+    UnknownType {
+        span: Span,
+        type_name: String,
+    },
     UnknownVar {
         span: Span,
         var_name: EcoString,
@@ -113,9 +332,110 @@ pub enum Error {
 }
 
 impl Error {
-    pub fn emit(&self, code_map: &CodeMap) {
+    /// A stable code identifying this kind of error, which can be looked up
+    /// with `scratch-compiler explain`.
+    pub const fn code(&self) -> &'static str {
+        use Error::*;
+        match self {
+            ArtifactNotSupportedByTarget { .. } => "E0001",
+            BuiltinProcWrongArgCount { .. } => "E0002",
+            CouldNotCreateSb3File { .. } => "E0003",
+            CouldNotCreateProjectJson { .. } => "E0004",
+            CouldNotFinishZip { .. } => "E0005",
+            CouldNotRunTool { .. } => "E0006",
+            CouldNotWriteFile { .. } => "E0007",
+            CustomProcWrongArgCount { .. } => "E0008",
+            FunctionMacroMatchFailed { .. } => "E0009",
+            FunctionMacroWrongArgCount { .. } => "E0010",
+            FunctionNameMustBeSymbol { .. } => "E0011",
+            FunctionWrongArgCount { .. } => "E0012",
+            InvalidArgsForInclude { .. } => "E0013",
+            InvalidItemInSprite { .. } => "E0014",
+            InvalidMacroParameter { .. } => "E0015",
+            InvalidMacroSignature { .. } => "E0016",
+            InvalidParameterForCustomProcDef { .. } => "E0017",
+            InvalidTopLevelItem { .. } => "E0018",
+            MacroDefinitionMissingBody { .. } => "E0019",
+            MacroDefinitionMissingSignature { .. } => "E0020",
+            Parse { .. } => "E0021",
+            ProgramMissingStage => "E0022",
+            SpriteMissingName { .. } => "E0023",
+            SymbolMacroInInlinePosition { .. } => "E0024",
+            SymConcatEmptySymbol { .. } => "E0025",
+            ToolFailed { .. } => "E0026",
+            UnknownFunction { .. } => "E0027",
+            UnknownList { .. } => "E0028",
+            UnknownMetavariable { .. } => "E0029",
+            UnknownProc { .. } => "E0030",
+            UnknownVar { .. } => "E0031",
+            UnknownVarOrList { .. } => "E0032",
+            UnquoteOutsideOfMacro { .. } => "E0033",
+            ImplicitConversion { .. } => "E0034",
+            InvalidTypeAnnotation { .. } => "E0035",
+            TypeMismatch { .. } => "E0036",
+            UnknownType { .. } => "E0037",
+            InvalidEnumDefinition { .. } => "E0038",
+            InvalidRecordDefinition { .. } => "E0039",
+            InlineAsmNotSupported { .. } => "E0040",
+            InvalidArgsForAsm { .. } => "E0041",
+            ExternFunctionWrongArgCount { .. } => "E0042",
+            ExternNotSupported { .. } => "E0043",
+            ExternReturnsNothing { .. } => "E0044",
+            InvalidExternDeclaration { .. } => "E0045",
+            UnknownExtern { .. } => "E0046",
+            ExternNameMustBeString { .. } => "E0047",
+            InvalidInitialValue { .. } => "E0048",
+            NativeOnlyProc { .. } => "E0049",
+            InvalidPattern { .. } => "E0050",
+            MacroDefaultNotTrailing { .. } => "E0051",
+            InvalidCfg { .. } => "E0052",
+            CouldNotReadFile { .. } => "E0053",
+            InvalidRewriteRule { .. } => "E0054",
+            InvalidPriority { .. } => "E0055",
+            CouldNotReadCostume { .. } => "E0056",
+            InvalidCostume { .. } => "E0057",
+            InvalidAllow { .. } => "E0058",
+            InvalidColor { .. } => "E0059",
+            UnknownKey { .. } => "E0060",
+            NeverAssigned { .. } => "E0061",
+            ProjectTooBig { .. } => "E0062",
+            SourceNotUtf8 { .. } => "E0063",
+            SourceTooBig { .. } => "E0064",
+            TooDeeplyNested { .. } => "E0065",
+            InvalidStatement { .. } => "E0066",
+            InvalidDeclaration { .. } => "E0067",
+            InvalidProcSignature { .. } => "E0068",
+            InvalidCostumeDeclaration { .. } => "E0069",
+            NonConstantCondition { .. } => "E0070",
+            DuplicateProc { .. } => "E0071",
+            EventNotSupported { .. } => "E0072",
+            InvalidExtensionManifest { .. } => "E0073",
+            UnknownExtensionBlock { .. } => "E0074",
+            ExtensionOpcodeMustBeString { .. } => "E0075",
+            ExtensionNotSupported { .. } => "E0076",
+            StaticAssertFailed { .. } => "E0077",
+            StaticAssertNotConstant { .. } => "E0078",
+            InvalidTableDefinition { .. } => "E0079",
+            TableNotConstant { .. } => "E0080",
+            UnknownTable { .. } => "E0081",
+            ExternSignatureConflict { .. } => "E0082",
+            RotatePointTargetNotVariable { .. } => "E0083",
+        }
+    }
+
+    /// The error followed by any notes about it.
+    pub(super) fn diagnostics(&self) -> Vec<Diagnostic> {
         use Error::*;
-        let diagnostics = match self {
+        let mut diagnostics = match self {
+            ArtifactNotSupportedByTarget { artifact, target } => {
+                vec![error(
+                    format!(
+                        "artifact `{artifact}` cannot be emitted for target \
+                        `{target}`"
+                    ),
+                    Vec::new(),
+                )]
+            }
             BuiltinProcWrongArgCount {
                 span,
                 proc_name,
@@ -140,6 +460,31 @@ impl Error {
                 error("could not finish zip archive", Vec::new()),
                 note(inner.to_string()),
             ],
+            CouldNotRunTool { tool, inner } => vec![
+                error(format!("could not run `{tool}`"), Vec::new()),
+                note(inner.to_string()),
+            ],
+            CouldNotReadCostume { span, path, inner } => vec![
+                error(
+                    format!("could not read costume `{}`", path.display()),
+                    vec![primary(*span, None)],
+                ),
+                note(inner.to_string()),
+            ],
+            CouldNotReadFile { path, inner } => vec![
+                error(
+                    format!("could not read `{}`", path.display()),
+                    Vec::new(),
+                ),
+                note(inner.to_string()),
+            ],
+            CouldNotWriteFile { path, inner } => vec![
+                error(
+                    format!("could not write `{}`", path.display()),
+                    Vec::new(),
+                ),
+                note(inner.to_string()),
+            ],
             CustomProcWrongArgCount {
                 span,
                 proc_name,
@@ -152,6 +497,90 @@ impl Error {
                 *got,
                 *span,
             )],
+            DuplicateProc {
+                span,
+                previous,
+                proc_name,
+            } => vec![error(
+                format!("custom procedure `{proc_name}` is defined twice"),
+                vec![
+                    primary(*span, "defined again here".to_owned()),
+                    secondary(*previous, "first defined here".to_owned()),
+                ],
+            )],
+            ExtensionNotSupported { span, target } => vec![
+                error(
+                    format!(
+                        "extension blocks are not supported by the {target} \
+                        target"
+                    ),
+                    vec![primary(*span, None)],
+                ),
+                note("`call-extension` can only be used when compiling to sb3"),
+            ],
+            ExtensionOpcodeMustBeString { span } => vec![error(
+                "the opcode of an extension block must be a string literal",
+                vec![primary(*span, None)],
+            )],
+            ExternFunctionWrongArgCount {
+                span,
+                extern_name,
+                expected,
+                got,
+            } => vec![wrong_arg_count(
+                "extern function",
+                extern_name,
+                *expected,
+                *got,
+                *span,
+            )],
+            ExternNameMustBeString { span } => vec![error(
+                "the name of an extern function must be a string literal",
+                vec![primary(*span, None)],
+            )],
+            EventNotSupported {
+                span,
+                event,
+                target,
+            } => vec![
+                error(
+                    format!(
+                        "`{event}` scripts are not supported by the {target} \
+                        target"
+                    ),
+                    vec![primary(*span, None)],
+                ),
+                note("native executables have no timer or microphone"),
+            ],
+            ExternNotSupported { span, target } => vec![
+                error(
+                    format!(
+                        "extern functions are not supported by the {target} \
+                        target"
+                    ),
+                    vec![primary(*span, None)],
+                ),
+                note("`call-extern` can only be used when compiling to x86_64"),
+            ],
+            ExternReturnsNothing { span, extern_name } => vec![error(
+                format!(
+                    "extern function `{extern_name}` does not return a value"
+                ),
+                vec![primary(*span, None)],
+            )],
+            ExternSignatureConflict { span, extern_name } => vec![
+                error(
+                    format!(
+                        "extern function `{extern_name}` conflicts with \
+                        another function of the same name"
+                    ),
+                    vec![primary(*span, None)],
+                ),
+                note(
+                    "the runtime already uses this name for a function with \
+                    a different signature",
+                ),
+            ],
             FunctionMacroMatchFailed {
                 pattern,
                 provided,
@@ -190,10 +619,119 @@ impl Error {
             } => vec![wrong_arg_count(
                 "function", func_name, *expected, *got, *span,
             )],
+            ImplicitConversion {
+                span,
+                expected,
+                conversion,
+            } => vec![
+                error(
+                    format!("implicit conversion to {expected}"),
+                    vec![primary(
+                        *span,
+                        format!("this is not known to be a {expected}"),
+                    )],
+                ),
+                help(format!(
+                    "use `({conversion} ...)` to convert it explicitly"
+                )),
+            ],
+            InlineAsmNotSupported { span, target } => vec![
+                error(
+                    format!(
+                        "inline assembly is not supported by the {target} \
+                        target"
+                    ),
+                    vec![primary(*span, None)],
+                ),
+                note("`asm` can only be used when compiling to x86_64"),
+            ],
+            InvalidAllow { span } => vec![error(
+                "invalid `allow`",
+                vec![primary(
+                    *span,
+                    "expected the name of a warning".to_owned(),
+                )],
+            )],
+            InvalidArgsForAsm { span } => vec![error(
+                "invalid arguments for `asm`",
+                vec![primary(
+                    *span,
+                    "expected a string literal containing assembly".to_owned(),
+                )],
+            )],
             InvalidArgsForInclude { span } => vec![error(
                 "invalid arguments for `include`",
                 vec![primary(*span, None)],
             )],
+            InvalidCfg { span } => vec![error(
+                "invalid `cfg`",
+                vec![primary(
+                    *span,
+                    "expected `(cfg key)` or `(cfg key \"value\")`".to_owned(),
+                )],
+            )],
+            InvalidColor { span } => vec![error(
+                "invalid color",
+                vec![primary(
+                    *span,
+                    "expected six hex digits, like `#ff8000`".to_owned(),
+                )],
+            )],
+            InvalidCostume {
+                span,
+                path,
+                problem,
+            } => vec![error(
+                format!("invalid costume `{}`", path.display()),
+                vec![primary(*span, (*problem).to_owned())],
+            )],
+            InvalidCostumeDeclaration { span } => vec![error(
+                "invalid costume declaration",
+                vec![primary(
+                    *span,
+                    "expected pairs of names and paths, like \
+                    `(costumes \"cat\" \"cat.svg\")`"
+                        .to_owned(),
+                )],
+            )],
+            InvalidDeclaration { span, kind } => vec![error(
+                format!("invalid {kind} declaration"),
+                vec![primary(
+                    *span,
+                    format!("expected the name of the {kind}"),
+                )],
+            )],
+            InvalidEnumDefinition { span } => vec![error(
+                "invalid enum definition",
+                vec![primary(
+                    *span,
+                    "expected `(enum Name variant...)`".to_owned(),
+                )],
+            )],
+            InvalidExtensionManifest { span } => vec![error(
+                "invalid extension manifest",
+                vec![primary(
+                    *span,
+                    "expected `(extension \"id\" \"url\" blocks...)`, with \
+                    blocks like `(reporter \"opcode\" INPUT...)`"
+                        .to_owned(),
+                )],
+            )],
+            InvalidExternDeclaration { span } => vec![error(
+                "invalid extern function declaration",
+                vec![primary(
+                    *span,
+                    "expected `(extern \"name\" (param : type)... -> type)`"
+                        .to_owned(),
+                )],
+            )],
+            InvalidInitialValue { span } => vec![error(
+                "invalid initial value",
+                vec![primary(
+                    *span,
+                    "expected a number, string or boolean literal".to_owned(),
+                )],
+            )],
             InvalidItemInSprite { span } => vec![error(
                 "invalid item in sprite",
                 vec![primary(*span, None)],
@@ -202,8 +740,8 @@ impl Error {
                 "invalid macro parameter",
                 vec![primary(
                     *span,
-                    "expected symbol or node consisiting of a symbol and \
-                        more parameters"
+                    "expected symbol, literal or node consisiting of a symbol \
+                        and more parameters"
                         .to_owned(),
                 )],
             )],
@@ -215,6 +753,55 @@ impl Error {
                 "invalid parameter for custom procedure definition",
                 vec![primary(*span, "expected symbol".to_owned())],
             )],
+            InvalidPattern { span, reason } => vec![error(
+                "invalid pattern",
+                vec![primary(*span, (*reason).to_owned())],
+            )],
+            InvalidRecordDefinition { span } => vec![error(
+                "invalid record definition",
+                vec![primary(
+                    *span,
+                    "expected `(record Name field...)`".to_owned(),
+                )],
+            )],
+            InvalidPriority { span } => vec![error(
+                "invalid priority",
+                vec![primary(
+                    *span,
+                    "expected `:priority n` at the end of the signature of \
+                    `when-flag-clicked` or `when-received`"
+                        .to_owned(),
+                )],
+            )],
+            InvalidProcSignature { span } => vec![error(
+                "invalid procedure signature",
+                vec![primary(
+                    *span,
+                    "expected the name and parameters, like `(greet name)`"
+                        .to_owned(),
+                )],
+            )],
+            InvalidRewriteRule { span } => vec![error(
+                "invalid rewrite rule",
+                vec![primary(
+                    *span,
+                    "expected `(rewrite pattern template)`".to_owned(),
+                )],
+            )],
+            InvalidStatement { span } => vec![
+                error("expected a statement", vec![primary(*span, None)]),
+                note(
+                    "a statement is a list that starts with the name of a \
+                    procedure, like `(say \"hi\")`",
+                ),
+            ],
+            InvalidTableDefinition { span } => vec![error(
+                "invalid table definition",
+                vec![primary(
+                    *span,
+                    "expected `(table! name expr start end)`".to_owned(),
+                )],
+            )],
             InvalidTopLevelItem { span } => vec![error(
                 "invalid top-level item",
                 vec![primary(
@@ -222,20 +809,119 @@ impl Error {
                     "expected macro or sprite definition".to_owned(),
                 )],
             )],
+            InvalidTypeAnnotation { span } => vec![error(
+                "invalid type annotation",
+                vec![primary(*span, "expected `(name : type)`".to_owned())],
+            )],
             MacroDefinitionMissingBody { span } => vec![error(
                 "macro definition is missing a body",
                 vec![primary(*span, None)],
             )],
+            MacroDefaultNotTrailing { span } => vec![error(
+                "macro parameter without a default follows one with a default",
+                vec![primary(*span, "expected a default value".to_owned())],
+            )],
             MacroDefinitionMissingSignature { span } => vec![error(
                 "macro definition is missing a signature",
                 vec![primary(*span, None)],
             )],
-            Parse(parse_error) => {
-                vec![error("syntax error", Vec::new()), note(parse_error)]
-            }
+            NativeOnlyProc {
+                span,
+                proc_name,
+                target,
+            } => vec![
+                error(
+                    format!(
+                        "`{proc_name}` is not supported by the {target} \
+                        target"
+                    ),
+                    vec![primary(*span, None)],
+                ),
+                note(format!(
+                    "`{proc_name}` can only be used when compiling to x86_64"
+                )),
+            ],
+            NeverAssigned { span, name, kind } => vec![error(
+                format!("{kind} `{name}` is read but never assigned"),
+                vec![primary(*span, never_assigned_label(kind))],
+            )],
+            NonConstantCondition { span, macro_name } => vec![
+                error(
+                    format!(
+                        "the condition of `{macro_name}` is not `true` or \
+                        `false`"
+                    ),
+                    vec![primary(*span, None)],
+                ),
+                note(
+                    "the condition must be a boolean once macros in it are \
+                    expanded, like `(str=! \"a\" \"b\")`",
+                ),
+            ],
+            Parse { span, expected } => vec![error(
+                "syntax error",
+                vec![primary(*span, expected_label(expected))],
+            )],
             ProgramMissingStage => {
                 vec![error("program is missing a stage", Vec::new())]
             }
+            ProjectTooBig {
+                option,
+                unit,
+                size,
+                limit,
+                biggest,
+            } => vec![
+                error(
+                    format!(
+                        "the project is {size} {unit}, which is more than \
+                        the {limit} allowed by `{option}`"
+                    ),
+                    biggest
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &(span, size))| {
+                            let label = format!("this script is {size} {unit}");
+                            if i == 0 {
+                                primary(span, label)
+                            } else {
+                                secondary(span, label)
+                            }
+                        })
+                        .collect(),
+                ),
+                note("the biggest scripts are shown"),
+            ],
+            RotatePointTargetNotVariable { span } => vec![
+                error(
+                    "`rotate-point` can only rotate a point in variables",
+                    vec![primary(*span, "this is not a variable".to_owned())],
+                ),
+                note("the new coordinates are assigned to the variables"),
+            ],
+            SourceNotUtf8 {
+                span,
+                path,
+                offset,
+                line,
+            } => vec![
+                error(
+                    format!("`{}` is not valid UTF-8", path.display()),
+                    included_at(*span),
+                ),
+                note(format!(
+                    "the first invalid byte is at offset {offset}, on line \
+                    {line}"
+                )),
+            ],
+            SourceTooBig { span, path, limit } => vec![error(
+                format!(
+                    "`{}` is bigger than the {limit} bytes allowed for a \
+                    source file",
+                    path.display()
+                ),
+                included_at(*span),
+            )],
             SpriteMissingName {
                 span,
                 candidate_symbol,
@@ -260,6 +946,25 @@ impl Error {
                     vec![diagnostic]
                 }
             }
+            StaticAssertFailed { span, message } => vec![error(
+                match message {
+                    Some(message) => {
+                        format!("static assertion failed: {message}")
+                    }
+                    None => "static assertion failed".to_owned(),
+                },
+                vec![primary(*span, None)],
+            )],
+            StaticAssertNotConstant { span } => vec![
+                error(
+                    "the arguments of `static-assert` are not constant",
+                    vec![primary(*span, None)],
+                ),
+                note(
+                    "`static-assert` is checked after constant folding, so \
+                    its arguments can only depend on constants",
+                ),
+            ],
             SymbolMacroInInlinePosition { span } => vec![error(
                 "symbol macro cannot be used in inline position",
                 vec![primary(*span, None)],
@@ -271,10 +976,70 @@ impl Error {
                 ),
                 note("at least one symbol must be provided as an argument"),
             ],
+            TableNotConstant { span } => vec![
+                error("table item is not constant", vec![primary(*span, None)]),
+                note(
+                    "tables are computed after constant folding, so their \
+                    items can only depend on constants",
+                ),
+            ],
+            TooDeeplyNested { span, limit } => vec![
+                error(
+                    format!("code is nested more than {limit} deep"),
+                    vec![primary(*span, None)],
+                ),
+                help("the limit can be raised with `--max-nesting`"),
+            ],
+            ToolFailed { tool, status } => {
+                vec![error(format!("`{tool}` failed: {status}"), Vec::new())]
+            }
+            TypeMismatch {
+                span,
+                expected,
+                found,
+            } => vec![error(
+                format!("mismatched types: expected {expected}, found {found}"),
+                vec![primary(*span, None)],
+            )],
+            UnknownExtensionBlock { span, opcode, kind } => vec![
+                error(
+                    format!("no extension has a {kind} `{opcode}`"),
+                    vec![primary(*span, None)],
+                ),
+                help("extensions are loaded with `--extension`"),
+            ],
+            UnknownExtern { span, extern_name } => vec![error(
+                format!("unknown extern function: `{extern_name}`"),
+                vec![primary(*span, None)],
+            )],
             UnknownFunction { span, func_name } => vec![error(
                 format!("unknown function: `{func_name}`"),
                 vec![primary(*span, None)],
             )],
+            UnknownKey {
+                span,
+                key,
+                suggestion,
+            } => vec![
+                error(
+                    format!("unknown key: `{key}`"),
+                    vec![primary(
+                        *span,
+                        "Scratch has no key with this name, so this is \
+                        always false"
+                            .to_owned(),
+                    )],
+                ),
+                suggestion.map_or_else(
+                    || {
+                        note(
+                            "keys are single characters, `space`, `enter`, \
+                            `any` and the arrow keys, like `left arrow`",
+                        )
+                    },
+                    |suggestion| help(format!("did you mean `{suggestion}`?")),
+                ),
+            ],
             UnknownList { span, list_name } => vec![error(
                 format!("unknown list: `{list_name}`"),
                 vec![primary(*span, None)],
@@ -287,6 +1052,17 @@ impl Error {
                 format!("unknown procedure: `{proc_name}`"),
                 vec![primary(*span, None)],
             )],
+            UnknownTable { span, table_name } => vec![error(
+                format!("unknown table: `{table_name}`"),
+                vec![primary(*span, None)],
+            )],
+            UnknownType { span, type_name } => vec![
+                error(
+                    format!("unknown type: `{type_name}`"),
+                    vec![primary(*span, None)],
+                ),
+                note("the available types are `num`, `bool` and `str`"),
+            ],
             UnknownVar { span, var_name } => vec![error(
                 format!("unknown variable: `{var_name}`"),
                 vec![primary(*span, None)],
@@ -301,7 +1077,39 @@ impl Error {
             )],
         };
 
-        emit_all(&diagnostics, code_map);
+        if let Some(diagnostic) = diagnostics.first_mut() {
+            diagnostic.code = Some(self.code().to_owned());
+        }
+        diagnostics
+    }
+}
+
+/// Just the code and message, for when there is no code map to show the
+/// source code with.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self
+            .diagnostics()
+            .into_iter()
+            .next()
+            .map_or_else(String::new, |diagnostic| diagnostic.message);
+        write!(f, "error[{}]: {message}", self.code())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use Error::*;
+        match self {
+            CouldNotCreateSb3File { inner }
+            | CouldNotRunTool { inner, .. }
+            | CouldNotReadCostume { inner, .. }
+            | CouldNotReadFile { inner, .. }
+            | CouldNotWriteFile { inner, .. } => Some(inner),
+            CouldNotCreateProjectJson { inner }
+            | CouldNotFinishZip { inner } => Some(inner),
+            _ => None,
+        }
     }
 }
 
@@ -321,6 +1129,17 @@ fn wrong_arg_count(
     )
 }
 
+/// Lists what a parser expected, like "expected `)` or an expression".
+fn expected_label(expected: &[String]) -> Option<String> {
+    match expected {
+        [] => None,
+        [only] => Some(format!("expected {only}")),
+        [rest @ .., last] => {
+            Some(format!("expected {} or {last}", rest.join(", ")))
+        }
+    }
+}
+
 fn error(message: impl Into<String>, labels: Vec<Label>) -> Diagnostic {
     Diagnostic {
         level: codemap_diagnostic::Level::Error,
@@ -330,6 +1149,13 @@ fn error(message: impl Into<String>, labels: Vec<Label>) -> Diagnostic {
     }
 }
 
+/// A label for where a file was included, if it was.
+fn included_at(span: Option<Span>) -> Vec<Label> {
+    span.map(|span| primary(span, "included here".to_owned()))
+        .into_iter()
+        .collect()
+}
+
 fn note(message: impl Into<String>) -> Diagnostic {
     Diagnostic {
         level: codemap_diagnostic::Level::Note,
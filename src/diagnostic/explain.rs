@@ -0,0 +1,1334 @@
+/// Returns the extended description of an error code, as printed by
+/// `scratch-compiler explain`.
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "E0001",
+        "\
+An artifact was requested with `--emit` that the selected target cannot
+produce.
+
+`sb3` can only be emitted for the `sb3` target, while `obj`, `exe` and `asm`
+require the `x86_64` target. `ir` works with every target.
+
+Erroneous invocation:
+
+    scratch-compiler --target sb3 --emit exe main.scratch
+
+Either change the target or ask for a different artifact:
+
+    scratch-compiler --target x86_64 --emit exe main.scratch
+",
+    ),
+    (
+        "E0002",
+        "\
+A builtin procedure was called with the wrong number of arguments.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (set-xy 0))
+
+`set-xy` takes an x and a y coordinate:
+
+    (proc when-flag-clicked
+      (set-xy 0 0))
+",
+    ),
+    (
+        "E0003",
+        "\
+The `.sb3` file could not be written to disk.
+
+This usually means that the output directory does not exist or is not
+writable. The note below the error contains the reason reported by the
+operating system.
+",
+    ),
+    (
+        "E0004",
+        "\
+The `project.json` entry could not be added to the `.sb3` archive.
+
+This is an internal problem with the zip library. The note below the error
+contains more details.
+",
+    ),
+    (
+        "E0005",
+        "\
+The `.sb3` archive could not be finalized.
+
+This is an internal problem with the zip library. The note below the error
+contains more details.
+",
+    ),
+    (
+        "E0006",
+        "\
+An external tool needed to build the requested artifacts could not be
+started.
+
+Building an executable with `--emit exe` requires `nasm` and a C compiler
+available as `cc` to be installed and in your `PATH`.
+",
+    ),
+    (
+        "E0007",
+        "\
+An output file or directory could not be written.
+
+Check that the directory given with `--out-dir` is writable. The note below
+the error contains the reason reported by the operating system.
+",
+    ),
+    (
+        "E0008",
+        "\
+A custom procedure was called with a different number of arguments than it
+was defined with.
+
+Erroneous code example:
+
+    (proc (greet name)
+      (say (++ \"Hello, \" name)))
+
+    (proc when-flag-clicked
+      (greet))
+
+Pass one argument for every parameter:
+
+    (proc when-flag-clicked
+      (greet \"Scratch Cat\"))
+",
+    ),
+    (
+        "E0009",
+        "\
+An argument passed to a function macro did not have the shape required by
+the macro's parameter pattern.
+
+Erroneous code example:
+
+    (macro (first (pair a b)) ,a)
+
+    (proc when-flag-clicked
+      (say (first 5)))
+
+The parameter `(pair a b)` only matches nodes like `(pair 1 2)`:
+
+    (proc when-flag-clicked
+      (say (first (pair 1 2))))
+",
+    ),
+    (
+        "E0010",
+        "\
+A function macro was used with a different number of arguments than it was
+defined with.
+
+Erroneous code example:
+
+    (macro (double x) (* 2 ,x))
+
+    (proc when-flag-clicked
+      (say (double 1 2)))
+
+Pass exactly one argument for every parameter, except for trailing parameters
+with a default value, which may be left out:
+
+    (proc when-flag-clicked
+      (say (double 1)))
+",
+    ),
+    (
+        "E0011",
+        "\
+The first item of a node, which names the function being called, must be a
+symbol.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (say (5 1 2)))
+
+Put the name of a function at the start of the node:
+
+    (proc when-flag-clicked
+      (say (+ 1 2)))
+",
+    ),
+    (
+        "E0012",
+        "\
+A builtin function was called with the wrong number of arguments.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (say (sqrt 4 9)))
+
+`sqrt` takes exactly one argument:
+
+    (proc when-flag-clicked
+      (say (sqrt 4)))
+",
+    ),
+    (
+        "E0013",
+        "\
+`include` expects exactly one string containing the path of the file to
+include.
+
+Erroneous code example:
+
+    (include utils.scratch)
+
+Wrap the path in double quotes:
+
+    (include \"utils.scratch\")
+",
+    ),
+    (
+        "E0014",
+        "\
+A sprite may only contain `variables`, `lists`, `costumes` and `proc`
+declarations.
+
+Erroneous code example:
+
+    (sprite \"Cat\"
+      (say \"Hello\"))
+
+Statements must be placed inside a procedure:
+
+    (sprite \"Cat\"
+      (proc when-flag-clicked
+        (say \"Hello\")))
+",
+    ),
+    (
+        "E0015",
+        "\
+A macro parameter must either be a symbol or a node consisting of a symbol
+followed by more parameters.
+
+Erroneous code example:
+
+    (macro (twice 5) (do ,5 ,5))
+
+Give the parameter a name:
+
+    (macro (twice x) (do ,x ,x))
+",
+    ),
+    (
+        "E0016",
+        "\
+The signature of a macro must either be a symbol (for a symbol macro) or a
+node starting with a symbol (for a function macro).
+
+Erroneous code example:
+
+    (macro \"pi\" 3.14159)
+
+Use a symbol as the name of the macro:
+
+    (macro pi 3.14159)
+",
+    ),
+    (
+        "E0017",
+        "\
+The parameters of a custom procedure must be symbols.
+
+Erroneous code example:
+
+    (proc (move-by 10)
+      (change-x 10))
+
+Give the parameter a name:
+
+    (proc (move-by steps)
+      (change-x steps))
+",
+    ),
+    (
+        "E0018",
+        "\
+Only macro definitions, `include`s and sprites may appear at the top level
+of a program.
+
+Erroneous code example:
+
+    (say \"Hello\")
+
+Statements must be placed inside a procedure of a sprite:
+
+    (sprite \"Stage\"
+      (proc when-flag-clicked
+        (say \"Hello\")))
+",
+    ),
+    (
+        "E0019",
+        "\
+A macro definition is missing the body that the macro expands to.
+
+Erroneous code example:
+
+    (macro pi)
+
+Add the code the macro should be replaced with:
+
+    (macro pi 3.14159)
+",
+    ),
+    (
+        "E0020",
+        "\
+A macro definition is missing its name and parameters.
+
+Erroneous code example:
+
+    (macro)
+
+Give the macro a signature and a body:
+
+    (macro (double x) (* 2 ,x))
+",
+    ),
+    (
+        "E0021",
+        "\
+The source code is not syntactically valid.
+
+Common causes are unbalanced parentheses, unterminated string literals and
+invalid escape sequences in strings.
+",
+    ),
+    (
+        "E0022",
+        "\
+Every program needs a sprite named `Stage`, which holds the global
+variables and lists.
+
+Add a stage to the program, even if it is empty:
+
+    (sprite \"Stage\")
+",
+    ),
+    (
+        "E0023",
+        "\
+The first item of a sprite definition must be a string containing the name
+of the sprite.
+
+Erroneous code example:
+
+    (sprite Cat
+      (costumes \"cat\" \"cat.svg\"))
+
+Wrap the name in double quotes:
+
+    (sprite \"Cat\"
+      (costumes \"cat\" \"cat.svg\"))
+",
+    ),
+    (
+        "E0024",
+        "\
+Only function macros can be defined and used inline; a symbol macro has no
+parameters to apply arguments to.
+
+Erroneous code example:
+
+    ((macro pi 3.14159) 1)
+
+Define the macro at the top level instead:
+
+    (macro pi 3.14159)
+",
+    ),
+    (
+        "E0025",
+        "\
+`sym-concat!` needs at least one symbol to create a new symbol from.
+
+Erroneous code example:
+
+    (sym-concat!)
+
+Pass one or more symbols:
+
+    (sym-concat! player- x)
+",
+    ),
+    (
+        "E0026",
+        "\
+An external tool needed to build the requested artifacts exited with an
+error.
+
+The output of the tool, printed above the error, usually explains what went
+wrong.
+",
+    ),
+    (
+        "E0027",
+        "\
+A function was called that is neither builtin nor defined by a macro.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (say (squareroot 4)))
+
+Check the spelling of the function name:
+
+    (proc when-flag-clicked
+      (say (sqrt 4)))
+",
+    ),
+    (
+        "E0028",
+        "\
+A list was used that has not been declared.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (proc when-flag-clicked
+        (append inventory \"sword\")))
+
+Declare the list in the sprite or procedure using it:
+
+    (sprite \"Stage\"
+      (lists inventory)
+      (proc when-flag-clicked
+        (append inventory \"sword\")))
+",
+    ),
+    (
+        "E0029",
+        "\
+A macro body unquotes a name that is not one of the macro's parameters.
+
+Erroneous code example:
+
+    (macro (double x) (* 2 ,y))
+
+Only parameters of the macro can be unquoted:
+
+    (macro (double x) (* 2 ,x))
+
+The same error is reported for a `?name` in the template of a rewrite rule
+given with `--rewrite-rules` that does not appear in its pattern.
+",
+    ),
+    (
+        "E0030",
+        "\
+A procedure was called that is neither builtin nor defined in the current
+sprite.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (proc when-flag-clicked
+        (jump)))
+
+Define the procedure in the same sprite:
+
+    (sprite \"Stage\"
+      (proc jump
+        (change-y 10))
+      (proc when-flag-clicked
+        (jump)))
+",
+    ),
+    (
+        "E0031",
+        "\
+A variable was used that has not been declared.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (proc when-flag-clicked
+        (:= score 0)))
+
+Declare the variable in the sprite or procedure using it:
+
+    (sprite \"Stage\"
+      (variables score)
+      (proc when-flag-clicked
+        (:= score 0)))
+",
+    ),
+    (
+        "E0032",
+        "\
+A symbol was used as a value, but there is no variable, list or procedure
+parameter with that name.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (proc when-flag-clicked
+        (say score)))
+
+Declare the variable or list before using it:
+
+    (sprite \"Stage\"
+      (variables score)
+      (proc when-flag-clicked
+        (say score)))
+",
+    ),
+    (
+        "E0033",
+        "\
+Unquoting with `,` is only meaningful inside the body of a macro, where it
+refers to one of the macro's parameters.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (say ,score))
+
+Remove the comma outside of macros:
+
+    (proc when-flag-clicked
+      (say score))
+",
+    ),
+    (
+        "E0034",
+        "\
+A value whose type is not known at compile time was used where a value of a
+specific type is required. This is checked for arguments to annotated
+parameters, assignments to annotated variables and, with `--strict-types`,
+for every use of a value as a number or boolean.
+
+Scratch converts such values silently, so adding a string that doesn't look
+like a number quietly produces 0. Strict mode requires these conversions to
+be written out.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (variables score)
+      (proc when-flag-clicked
+        (say (+ score 1))))
+
+Convert the value explicitly:
+
+    (sprite \"Stage\"
+      (variables score)
+      (proc when-flag-clicked
+        (say (+ (to-num score) 1))))
+",
+    ),
+    (
+        "E0035",
+        "\
+A type annotation must consist of a name, a colon and the name of a type.
+
+Erroneous code example:
+
+    (proc (move (x : (num)))
+      (change-x x))
+
+Write the type as a plain symbol:
+
+    (proc (move (x : num))
+      (change-x x))
+",
+    ),
+    (
+        "E0036",
+        "\
+A value of one type was used where a value of another type is required, for
+example as the argument for an annotated parameter or when assigning to an
+annotated variable.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (variables (score : num))
+      (proc when-flag-clicked
+        (:= score \"lots\")))
+
+Only assign numbers to a variable annotated with `num`:
+
+    (sprite \"Stage\"
+      (variables (score : num))
+      (proc when-flag-clicked
+        (:= score 100)))
+",
+    ),
+    (
+        "E0037",
+        "\
+A type annotation names a type that does not exist.
+
+Erroneous code example:
+
+    (variables (score : int))
+
+The available types are `num`, `bool` and `str`:
+
+    (variables (score : num))
+",
+    ),
+    (
+        "E0038",
+        "\
+An enum definition must consist of a name followed by symbols naming its
+variants. The name may be annotated with `str` to make the variants stand
+for strings instead of numbers.
+
+Erroneous code example:
+
+    (enum \"State\" idle running dead)
+
+Use symbols for the name and the variants:
+
+    (enum State idle running dead)
+    (enum (Direction : str) up down left right)
+",
+    ),
+    (
+        "E0039",
+        "\
+A record definition must consist of a name followed by at least one symbol
+naming a field.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (record Enemy))
+
+Give the record some fields:
+
+    (sprite \"Stage\"
+      (record Enemy x y hp))
+",
+    ),
+    (
+        "E0040",
+        "\
+Inline assembly was used with a target that does not run native code.
+
+`asm` injects x86_64 instructions into the compiled program, which has no
+equivalent in a Scratch project.
+
+Erroneous invocation:
+
+    scratch-compiler --target sb3 main.scratch
+
+Compile for x86_64 instead, or select the code with `when!`:
+
+    (when! (str=! COMPILER-OPTIONS.TARGET \"x86_64\")
+      (macro print-rdtsc (asm \"rdtsc\")))
+",
+    ),
+    (
+        "E0041",
+        "\
+`asm` expects exactly one string literal containing the assembly code.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (asm nop))
+
+Wrap the code in double quotes:
+
+    (proc when-flag-clicked
+      (asm \"nop\"))
+
+The code is assembled with NASM and called like a function, so it may
+clobber rax, rcx, rdx, rsi, rdi, r8-r11 and xmm0-xmm15, but has to preserve
+rbx, rbp, r12-r15 and rsp. It must not return by itself.
+",
+    ),
+    (
+        "E0042",
+        "\
+An extern function was called with the wrong number of arguments.
+
+Erroneous code example:
+
+    (extern \"atof\" (s : str) -> num)
+
+    (proc when-flag-clicked
+      (say (call-extern \"atof\" \"1.5\" \"2.5\")))
+
+Pass exactly one argument for every parameter in the declaration.
+",
+    ),
+    (
+        "E0043",
+        "\
+An extern function was called when compiling for a target that cannot call C
+code.
+
+Erroneous invocation:
+
+    scratch-compiler --target sb3 main.scratch
+
+`call-extern` calls functions from system libraries, which only exist for
+native executables. Compile for x86_64 instead.
+",
+    ),
+    (
+        "E0044",
+        "\
+An extern function without a return type was used as a value.
+
+Erroneous code example:
+
+    (extern \"puts\" (s : str))
+
+    (proc when-flag-clicked
+      (say (call-extern \"puts\" \"Hello\")))
+
+Either call it as a statement or declare its return type with `->`:
+
+    (extern \"getenv\" (name : str) -> str)
+",
+    ),
+    (
+        "E0045",
+        "\
+An extern function declaration is malformed.
+
+Erroneous code example:
+
+    (extern getenv name -> str)
+
+The name has to be a string and every parameter has to be annotated with its
+type. The return type is optional:
+
+    (extern \"getenv\" (name : str) -> str)
+
+Numbers are passed as `double`, booleans as `bool` and strings as
+NUL-terminated `const char *`.
+",
+    ),
+    (
+        "E0046",
+        "\
+`call-extern` was used with a function that has not been declared.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (call-extern \"puts\" \"Hello\"))
+
+Declare the function in the sprite or the stage first, so that the compiler
+knows how to pass the arguments:
+
+    (extern \"puts\" (s : str))
+",
+    ),
+    (
+        "E0047",
+        "\
+The first argument of `call-extern` was not a string literal.
+
+Erroneous code example:
+
+    (extern \"puts\" (s : str))
+
+    (proc when-flag-clicked
+      (variables name)
+      (:= name \"puts\")
+      (call-extern name \"Hello\"))
+
+The called function has to be known at compile time:
+
+    (call-extern \"puts\" \"Hello\")
+",
+    ),
+    (
+        "E0048",
+        "\
+The initial value of a variable or an item of a list was not a literal.
+
+Erroneous code example:
+
+    (sprite \"Player\"
+      (variables (health (* 10 max-health))))
+
+Initial values are stored in the compiled program as they are, so they have to
+be numbers, strings or booleans:
+
+    (sprite \"Player\"
+      (variables (health 100) (name \"Hiro\"))
+      (lists (inventory \"sword\" \"shield\")))
+",
+    ),
+    (
+        "E0049",
+        "\
+A procedure that needs an operating system was used when compiling to a target
+that doesn't have one.
+
+Erroneous invocation:
+
+    scratch-compiler --target sb3 main.scratch
+
+where `main.scratch` contains
+
+    (proc when-flag-clicked
+      (save-state \"game.json\"))
+
+Procedures like `save-state` and `load-state` access files, which Scratch
+projects cannot do. Compile for x86_64 instead.
+
+The same goes for functions implemented by the native runtime, like
+`str-match`, `format-decimal`, `to-hex` and `to-binary`.
+",
+    ),
+    (
+        "E0050",
+        "\
+The pattern given to `str-match` could not be parsed.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (ask \"Pick a color\")
+      (when (str-match answer \"[a-z\")
+        (say \"Nice\")))
+
+Patterns support `.`, character classes like `[a-z]` or `[^0-9]`, `*` and `?`
+after an item, `^` at the start, `$` at the end and `\\` to escape any of
+these. Every `[` needs a matching `]`:
+
+    (proc when-flag-clicked
+      (ask \"Pick a color\")
+      (when (str-match answer \"^[a-z]*$\")
+        (say \"Nice\")))
+",
+    ),
+    (
+        "E0051",
+        "\
+A macro parameter without a default value comes after one with a default.
+
+Erroneous code example:
+
+    (macro (greet (greeting = \"Hello\") name)
+      (say (join ,greeting ,name)))
+
+Only the trailing parameters of a macro can have default values, since
+arguments are matched to parameters from left to right. Move the parameters
+with defaults to the end:
+
+    (macro (greet name (greeting = \"Hello\"))
+      (say (join ,greeting ,name)))
+",
+    ),
+    (
+        "E0052",
+        "\
+A `cfg` was given something other than a key and an optional string value.
+
+Erroneous code example:
+
+    (proc when-flag-clicked
+      (if! (cfg (target \"x86_64\"))
+        (save-state \"save.txt\")
+        (say \"Saving is not supported\")))
+
+`(cfg key)` is true if `key` is set and `(cfg key \"value\")` is true if it is
+set to `value`. The `target` key is set to the target being compiled for and
+other keys can be set with `--define NAME` or `--define NAME=VALUE`:
+
+    (proc when-flag-clicked
+      (if! (cfg target \"x86_64\")
+        (save-state \"save.txt\")
+        (say \"Saving is not supported\")))
+",
+    ),
+    (
+        "E0053",
+        "\
+An input file could not be read.
+
+Check that the file given with `--rewrite-rules` exists and is readable. The
+note below the error contains the reason reported by the operating system.
+",
+    ),
+    (
+        "E0054",
+        "\
+A file given with `--rewrite-rules` contains something other than a rule.
+
+Erroneous code example:
+
+    (rewrite (* ?x 2))
+
+Every item in the file has the form `(rewrite pattern template)`. Symbols
+starting with `?` in the pattern match any expression, and an expression
+matching the pattern is replaced by the template with those symbols
+substituted:
+
+    (rewrite (* ?x 2) (+ ?x ?x))
+
+Rules are applied along with the builtin optimizations until none of them
+match, so a rule whose template matches its own pattern never finishes.
+",
+    ),
+    (
+        "E0055",
+        "\
+A `:priority` attribute is not followed by a number, is not at the end of the
+signature, or is on a procedure that isn't a script.
+
+Erroneous code example:
+
+    (proc (greet :priority 1)
+      (say \"Hello\"))
+
+Only scripts started by `when-flag-clicked` and `when-received` have a
+priority. Native executables start the scripts for the same event in order of
+increasing priority, which defaults to 0, and scripts with the same priority in
+source order:
+
+    (proc (when-flag-clicked :priority -1)
+      (:= score 0))
+
+Scratch decides the order of scripts itself, so projects compiled to sb3
+ignore the priority.
+",
+    ),
+    (
+        "E0056",
+        "\
+A costume file could not be read.
+
+Erroneous code example:
+
+    (sprite \"Cat\"
+      (costumes \"cat\" \"does-not-exist.svg\"))
+
+Costume paths are relative to the directory the compiler is run from. The
+costumes are only read when compiling to sb3.
+",
+    ),
+    (
+        "E0057",
+        "\
+A costume is not a PNG or SVG image.
+
+Erroneous code example:
+
+    (sprite \"Cat\"
+      (costumes \"cat\" \"cat.gif\"))
+
+Scratch projects can only contain `.png` and `.svg` costumes, and the contents
+of the file have to match its extension. Convert other images to one of these
+formats before using them.
+",
+    ),
+    (
+        "E0058",
+        "\
+An `allow` contains something other than the name of a warning.
+
+Erroneous code example:
+
+    (allow \"shadowed-global\")
+
+The names are symbols, like the one shown in brackets after `warning` when the
+warning is reported:
+
+    (allow shadowed-global)
+
+An `allow` turns the warnings off for everything in the node it is in, or for
+the whole file when it is at the top level.
+",
+    ),
+    (
+        "E0059",
+        "\
+A color literal does not have exactly six hex digits.
+
+Erroneous code example:
+
+    (set-pen-color #f80)
+
+Colors are written as `#RRGGBB`, with two hex digits each for the red, green
+and blue components:
+
+    (set-pen-color #ff8800)
+
+A color is the number `0xRRGGBB`, which is what Scratch makes of numbers used
+as colors, so colors can be computed with arithmetic. In sb3 projects, color
+literals passed to `set-pen-color` are shown as color pickers.
+",
+    ),
+    (
+        "E0060",
+        "\
+`pressing-key` is given a key that Scratch doesn't have.
+
+Erroneous code example:
+
+    (if (pressing-key \"spacebar\")
+      (change-y 10)
+      (do))
+
+Scratch never reports such a key as pressed, so `pressing-key` is always
+false, and a condition like `(not (pressing-key \"spacebar\"))` is always true.
+Keys are single characters like `\"a\"` or `\"7\"`, or one of `\"space\"`,
+`\"enter\"`, `\"any\"`, `\"up arrow\"`, `\"down arrow\"`, `\"left arrow\"` and
+`\"right arrow\"`:
+
+    (if (pressing-key \"space\")
+      (change-y 10)
+      (do))
+
+Only key names written as string literals are checked.
+",
+    ),
+    (
+        "E0061",
+        "\
+A variable or list is read, but nothing ever assigns to it.
+
+Erroneous code example:
+
+    (variables score)
+    (proc when-flag-clicked
+      (say score))
+
+Variables start out as 0 and lists start out empty, so reading one that is
+never given a value always gives that. This is usually a misspelled name or a
+forgotten assignment. Give the variable an initial value or assign to it:
+
+    (variables (score 10))
+
+Assignments are `:=`, `+=`, `for` loops and initial values for variables, and
+`append`, `json-parse-into-lists` and initial items for lists. This is a
+warning named `never-assigned`, which is only an error with
+`--strict-types`. Nothing is reported for programs that use `load-state` or
+`asm`, since those can change any variable.
+",
+    ),
+    (
+        "E0062",
+        "\
+An sb3 project is bigger than a limit given with `--max-blocks` or
+`--max-json-size`.
+
+Erroneous code example, compiled with `--max-blocks 3`:
+
+    (proc when-flag-clicked
+      (say \"Hello\")
+      (wait 1)
+      (say \"Goodbye\"))
+
+The Scratch website rejects projects whose `project.json` is bigger than about
+5 MB, and the editor gets slow with many blocks. The error points at the
+scripts with the most blocks or bytes, which are the best places to start
+making the project smaller. Moving repeated code into custom procedures
+helps, as does `--flatten-scripts` for custom blocks that are a single block.
+",
+    ),
+    (
+        "E0063",
+        "\
+A source file, or a file read with `include` or `include-str`, is not valid
+UTF-8.
+
+Source files must be UTF-8, which is what most editors save as by default.
+The error gives the byte offset and line of the first byte that isn't part of
+a valid UTF-8 sequence. Files saved as Latin-1 or UTF-16, or binary files
+passed by mistake, are the usual cause. Convert the file to UTF-8, for example
+with `iconv -f latin1 -t utf-8`.
+",
+    ),
+    (
+        "E0064",
+        "\
+A source file, or a file read with `include` or `include-str`, is bigger than
+64 MiB.
+
+Files are only read up to this size, so that passing a huge file by mistake,
+like a video instead of a source file, fails right away instead of using up
+all memory. Real programs are much smaller than this, so the path is most
+likely wrong.
+",
+    ),
+    (
+        "E0065",
+        "\
+Lists, quotes and unquotes are nested more deeply than `--max-nesting`
+allows, which is 256 by default, either in the source code or after expanding
+macros. Every `'` or `,` in front of an expression counts as a level, like the
+list around it would.
+
+The parser and the macro expander recurse once for every level of nesting, so
+without a limit, deeply nested code would overflow the stack and crash the
+compiler. This mostly happens with generated code, or with a recursive macro
+that never stops expanding. Pass a bigger `--max-nesting` if the nesting is
+intended, or break the code up into smaller pieces, like custom procedures.
+",
+    ),
+    (
+        "E0066",
+        "\
+Something that isn't a statement is used where a statement is expected, like
+in the body of a procedure or a loop.
+
+A statement is a list that starts with the name of a procedure or a control
+flow statement, like `(say \"hi\")` or `(repeat 10 (move 5))`. A bare value
+like `5` or `x` does nothing on its own, so it is an error.
+",
+    ),
+    (
+        "E0067",
+        "\
+A declaration of a variable, list or loop counter doesn't start with its name.
+
+Variables are declared with `(variables x (y 5) (z : num))`, lists with
+`(lists xs (ys 1 2 3))`, and the counter of a `for` loop with
+`(for i 10 ...)`. The name must be a symbol.
+",
+    ),
+    (
+        "E0068",
+        "\
+A procedure definition is missing its signature, or the signature isn't a
+list that starts with the name of the procedure.
+
+Procedures are defined with `(proc (name params...) body...)`, like
+`(proc (greet name) (say name))`. A procedure without parameters is still
+written with parentheses, like `(proc (jump) ...)`.
+",
+    ),
+    (
+        "E0069",
+        "\
+A `costumes` declaration isn't made of pairs of strings.
+
+Each costume is given a name and the path to its image, like
+`(costumes \"cat\" \"cat.svg\" \"dog\" \"dog.png\")`. Both must be strings,
+and every name must be followed by a path.
+",
+    ),
+    (
+        "E0070",
+        "\
+The condition of `when!` or `unless!` is not `true` or `false` after expanding
+macros.
+
+These are expanded before the program runs, so their condition must be known
+when compiling, like `(when! (cfg debug) ...)`. For a condition that is only
+known while the program runs, use `when` or `unless` in a procedure instead.
+",
+    ),
+    (
+        "E0071",
+        "\
+A custom procedure is defined more than once in the same sprite.
+
+Only event scripts like `when-flag-clicked` and `when-received` can be
+defined many times, since every one of them runs when the event happens. A
+call of a custom procedure would not know which definition to run, so rename
+one of them. Definitions in sprites with the same name are merged, so this
+also happens when two included files define the same procedure.
+",
+    ),
+    (
+        "E0072",
+        "\
+A script uses an event that the target can't detect.
+
+`when-timer-greater-than` and `when-loudness-greater-than` scripts are only
+supported when compiling to sb3, since native executables have no timer or
+microphone. Check the condition in a loop in a `when-flag-clicked` script
+instead, or compile with `--target sb3`.
+",
+    ),
+    (
+        "E0073",
+        "\
+A file given with `--extension` doesn't describe an extension.
+
+Each extension is written as `(extension \"id\" \"url\" blocks...)`. The URL
+is where the editor loads the extension from and can be left out for
+extensions that are built into it. Blocks are written as
+`(block \"opcode\" inputs...)` for statements and
+`(reporter \"opcode\" inputs...)` for reporters, with the opcode as the
+extension declares it, without the ID. Inputs are names, optionally with a
+type, like `(reporter \"fetch\" URL)` or `(block \"wait\" (SECONDS : num))`.
+",
+    ),
+    (
+        "E0074",
+        "\
+`call-extension` was used with an opcode that no extension has, or used a
+statement block as a reporter or the other way around.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (proc (when-flag-clicked)
+        (say (call-extension \"fetch_fetch\" \"https://example.com\"))))
+
+The opcode is the ID of the extension followed by an underscore and the
+opcode of the block, and the extension has to be loaded with
+`--extension fetch.scratch`, where `fetch.scratch` contains:
+
+    (extension \"fetch\" \"https://extensions.turbowarp.org/fetch.js\"
+      (reporter \"fetch\" URL))
+",
+    ),
+    (
+        "E0075",
+        "\
+The first argument of `call-extension` was not a string literal.
+
+The block is chosen while compiling, so its opcode must be known then, like
+`(call-extension \"fetch_fetch\" url)`.
+",
+    ),
+    (
+        "E0076",
+        "\
+`call-extension` was used when compiling to x86_64.
+
+Extension blocks run in the Scratch editor, so they only exist in sb3
+projects. Use `call-extern` to call native code instead.
+",
+    ),
+    (
+        "E0077",
+        "\
+The condition of a `static-assert` was false.
+
+Erroneous code example:
+
+    (macro board-size 7)
+
+    (sprite \"Stage\"
+      (proc (when-flag-clicked)
+        (static-assert (= (* board-size board-size) 64) \"board size\")))
+
+`static-assert` is checked while compiling, so that macro libraries can check
+the constants they are configured with. The message is shown when the
+condition is false.
+",
+    ),
+    (
+        "E0078",
+        "\
+The condition or message of a `static-assert` was not known while compiling.
+
+`static-assert` is checked after constant folding, so its arguments can use
+operators and macros but not variables, parameters or reporters like `timer`.
+To check something while the program runs, use an `if` instead.
+",
+    ),
+    (
+        "E0079",
+        "\
+A `table!` was not given a name, an expression and a range of numbers.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (table! sines (sin i) 0))
+
+`(table! name expr start end)` evaluates `expr` while compiling for every
+`i` from `start` up to but not including `end`, so the example should be:
+
+    (sprite \"Stage\"
+      (table! sines (sin i) 0 360))
+
+Tables are read with `(table-get name i)`. A table can have at most 200000
+items, like a Scratch list.
+",
+    ),
+    (
+        "E0080",
+        "\
+An item of a `table!` was not known while compiling.
+
+Tables are computed after constant folding, so their expression can use `i`,
+operators and macros but not variables, parameters or reporters like `timer`.
+Use a list that is filled while the program runs instead.
+",
+    ),
+    (
+        "E0081",
+        "\
+`table-get` was used with a table that the sprite and the stage don't define.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (proc (when-flag-clicked)
+        (say (table-get sines 90))))
+
+Tables are defined in a sprite with `(table! name expr start end)`, and the
+tables of the stage can be read from every sprite.
+",
+    ),
+    (
+        "E0082",
+        "\
+An extern function has the name of a function that the runtime already
+declares with a different signature.
+
+Erroneous code example:
+
+    (extern \"list_get\" (index : num) -> num)
+
+    (proc when-flag-clicked
+      (say (call-extern \"list_get\" 1)))
+
+Every extern function is linked by its name, so two declarations of the same
+name must agree on their parameters and return type. Extern functions can't
+call the functions of the runtime.
+",
+    ),
+    (
+        "E0083",
+        "\
+The coordinates given to `rotate-point` were not variables.
+
+Erroneous code example:
+
+    (sprite \"Stage\"
+      (variables x y)
+      (proc when-flag-clicked
+        (rotate-point (+ x 1) y 90)))
+
+`(rotate-point x y degrees)` rotates the point in the variables `x` and `y`
+around the origin by assigning the new coordinates to them, so both have to
+be variables. To rotate a point that is computed, store it in variables
+first:
+
+    (sprite \"Stage\"
+      (variables x y)
+      (proc when-flag-clicked
+        (:= x (+ x 1))
+        (rotate-point x y 90)))
+",
+    ),
+];
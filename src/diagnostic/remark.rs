@@ -0,0 +1,66 @@
+use super::{emit_all, primary, Diagnostic};
+use crate::opts::MessageFormat;
+use codemap::{CodeMap, Span};
+use codemap_diagnostic::SpanLabel as Label;
+
+/// Notes about how the program is compiled, printed with `--remarks`.
+pub enum Remark {
+    BoxedVariable {
+        span: Span,
+        name: String,
+        found: Option<&'static str>,
+    },
+    UnannotatedNumber {
+        span: Span,
+        name: String,
+    },
+}
+
+impl Remark {
+    pub const fn span(&self) -> Span {
+        match *self {
+            Self::BoxedVariable { span, .. }
+            | Self::UnannotatedNumber { span, .. } => span,
+        }
+    }
+
+    pub fn emit(&self, code_map: &CodeMap, format: MessageFormat) {
+        let diagnostic = match self {
+            Self::BoxedVariable { span, name, found } => remark(
+                format!("could not unbox variable `{name}`"),
+                vec![primary(
+                    *span,
+                    found.map_or_else(
+                        || {
+                            "because it is assigned a value whose type is \
+                            only known at runtime"
+                                .to_owned()
+                        },
+                        |found| format!("because it is assigned a {found}"),
+                    ),
+                )],
+            ),
+            Self::UnannotatedNumber { span, name } => remark(
+                format!("variable `{name}` is not unboxed"),
+                vec![primary(
+                    *span,
+                    format!(
+                        "it is only assigned numbers, annotate it as \
+                        `({name} : num)` to unbox it"
+                    ),
+                )],
+            ),
+        };
+
+        emit_all(&[diagnostic], code_map, format);
+    }
+}
+
+fn remark(message: impl Into<String>, labels: Vec<Label>) -> Diagnostic {
+    Diagnostic {
+        level: codemap_diagnostic::Level::Note,
+        message: message.into(),
+        code: None,
+        spans: labels,
+    }
+}
@@ -0,0 +1,453 @@
+//! Draws diagnostics with the lines of source code that they point at. Markers
+//! go in the display column of what they point at, so they still line up
+//! after characters like emoji that take up two columns in a terminal.
+
+use super::level_name;
+use crate::span::char_width;
+use codemap::{CodeMap, File, Pos, Span};
+use codemap_diagnostic::{Diagnostic, Level, SpanLabel, SpanStyle};
+use std::{collections::BTreeSet, sync::Arc};
+
+/// Tabs are shown as this many spaces so that markers line up whatever the
+/// tab stops of the terminal are.
+const TAB_WIDTH: usize = 4;
+
+const BOLD: &str = "1";
+const BLUE: &str = "1;94";
+
+/// Renders a diagnostic followed by its notes. They share one line number
+/// gutter so that their source lines line up.
+pub fn render(
+    diagnostics: &[Diagnostic],
+    code_map: &CodeMap,
+    color: bool,
+) -> String {
+    let gutter = diagnostics
+        .iter()
+        .flat_map(|diagnostic| &diagnostic.spans)
+        .map(|label| code_map.look_up_span(label.span).end.line + 1)
+        .max()
+        .map_or(0, |line| line.to_string().len());
+    let mut renderer = Renderer {
+        out: String::new(),
+        color,
+        gutter,
+    };
+    for diagnostic in diagnostics {
+        renderer.diagnostic(diagnostic, code_map);
+    }
+    renderer.out.push('\n');
+    renderer.out
+}
+
+struct Renderer {
+    out: String,
+    color: bool,
+    gutter: usize,
+}
+
+/// A label that has been looked up in its file, with columns counted in
+/// display columns. `end` is exclusive.
+struct Annotation<'a> {
+    start_line: usize,
+    start: usize,
+    end_line: usize,
+    end: usize,
+    label: Option<&'a str>,
+    primary: bool,
+}
+
+impl Annotation<'_> {
+    fn is_multiline(&self) -> bool {
+        self.start_line != self.end_line
+    }
+
+    fn marker(&self) -> char {
+        if self.primary {
+            '^'
+        } else {
+            '-'
+        }
+    }
+}
+
+/// One line of output as characters and the color of each, so that markers
+/// for several labels can be drawn over each other before it is written.
+#[derive(Default)]
+struct Row(Vec<(char, Option<&'static str>)>);
+
+impl Row {
+    fn put(&mut self, column: usize, text: &str, style: &'static str) {
+        for (i, c) in text.chars().enumerate() {
+            if self.0.len() <= column + i {
+                self.0.resize(column + i + 1, (' ', None));
+            }
+            self.0[column + i] = (c, Some(style));
+        }
+    }
+}
+
+impl Renderer {
+    fn paint(&mut self, style: &str, text: &str) {
+        if self.color {
+            self.out.push_str(&format!("\x1b[{style}m{text}\x1b[0m"));
+        } else {
+            self.out.push_str(text);
+        }
+    }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic, code_map: &CodeMap) {
+        let style = level_style(diagnostic.level);
+        self.paint(style, level_name(diagnostic.level));
+        if let Some(code) = &diagnostic.code {
+            self.paint(style, &format!("[{code}]"));
+        }
+        self.paint(BOLD, &format!(": {}", diagnostic.message));
+        self.out.push('\n');
+
+        // Files are shown in the order that their first label appears in,
+        // except that the primary label comes first.
+        let mut labels = diagnostic.spans.iter().collect::<Vec<_>>();
+        labels.sort_by_key(|label| !matches!(label.style, SpanStyle::Primary));
+        let mut files = Vec::<(&Arc<File>, Vec<&SpanLabel>)>::new();
+        for label in labels {
+            let file = code_map.find_file(label.span.low());
+            match files.iter_mut().find(|(seen, _)| Arc::ptr_eq(seen, file)) {
+                Some((_, labels)) => labels.push(label),
+                None => files.push((file, vec![label])),
+            }
+        }
+        for (i, (file, labels)) in files.into_iter().enumerate() {
+            let loc = file.find_line_col(labels[0].span.low());
+            self.out.push_str(&" ".repeat(self.gutter));
+            self.paint(BLUE, if i == 0 { "-->" } else { ":::" });
+            self.out.push_str(&format!(
+                " {}:{}:{}\n",
+                file.name(),
+                loc.line + 1,
+                loc.column + 1,
+            ));
+            self.snippet(file, &labels, style);
+        }
+    }
+
+    fn snippet(
+        &mut self,
+        file: &File,
+        labels: &[&SpanLabel],
+        style: &'static str,
+    ) {
+        let annotations = labels
+            .iter()
+            .map(|label| annotation(file, label))
+            .collect::<Vec<_>>();
+        let multiline = annotations
+            .iter()
+            .filter(|annotation| annotation.is_multiline())
+            .collect::<Vec<_>>();
+        // Multiline labels are drawn as a line down the left of the source,
+        // each in its own column.
+        let margin = if multiline.is_empty() {
+            0
+        } else {
+            multiline.len() + 1
+        };
+        let mut lines = BTreeSet::new();
+        for annotation in &annotations {
+            let (start, end) = (annotation.start_line, annotation.end_line);
+            lines.extend(
+                (start..=end)
+                    .filter(|&line| line <= start + 1 || line + 1 >= end),
+            );
+        }
+
+        self.gutter_row(None, Row::default());
+        let mut previous = None;
+        for line in lines {
+            match previous {
+                Some(previous) if line == previous + 2 => {
+                    self.source_row(file, line - 1, &multiline, margin);
+                }
+                Some(previous) if line > previous + 2 => {
+                    self.out.push_str("...\n");
+                }
+                _ => {}
+            }
+            previous = Some(line);
+            self.source_row(file, line, &multiline, margin);
+            self.marker_rows(line, &annotations, &multiline, margin, style);
+        }
+    }
+
+    fn source_row(
+        &mut self,
+        file: &File,
+        line: usize,
+        multiline: &[&Annotation],
+        margin: usize,
+    ) {
+        let mut row = Row::default();
+        for (column, annotation) in multiline.iter().enumerate() {
+            if annotation.start_line < line && line <= annotation.end_line {
+                row.put(column, "|", BLUE);
+            }
+        }
+        let text = file.source_line(line).replace('\t', &" ".repeat(TAB_WIDTH));
+        row.0.resize(margin, (' ', None));
+        row.0.extend(text.chars().map(|c| (c, None)));
+        self.gutter_row(Some(line), row);
+    }
+
+    fn marker_rows(
+        &mut self,
+        line: usize,
+        annotations: &[Annotation],
+        multiline: &[&Annotation],
+        margin: usize,
+        style: &'static str,
+    ) {
+        let marker_style = |annotation: &Annotation| {
+            if annotation.primary {
+                style
+            } else {
+                BLUE
+            }
+        };
+        let mut row = Row::default();
+        // The bars of multiline labels that carry on past this line.
+        let mut bars = Row::default();
+        let mut labelled = Vec::new();
+        for (column, annotation) in multiline.iter().enumerate() {
+            let style = marker_style(annotation);
+            if annotation.start_line == line {
+                let marker = margin + annotation.start;
+                row.put(column + 1, &"_".repeat(marker - column - 1), style);
+                row.put(marker, &annotation.marker().to_string(), style);
+            } else if annotation.end_line == line {
+                let marker = margin + annotation.end.max(1) - 1;
+                row.put(column, "|", style);
+                row.put(column + 1, &"_".repeat(marker - column - 1), style);
+                row.put(marker, &annotation.marker().to_string(), style);
+                labelled.push((marker, marker + 1, *annotation));
+            }
+            if annotation.start_line < line && line < annotation.end_line {
+                bars.put(column, "|", BLUE);
+            }
+        }
+        // Secondary markers are drawn first so that primary markers go over
+        // them where they overlap.
+        let mut single = annotations
+            .iter()
+            .filter(|annotation| {
+                !annotation.is_multiline() && annotation.start_line == line
+            })
+            .collect::<Vec<_>>();
+        single.sort_by_key(|annotation| annotation.primary);
+        for annotation in single {
+            let (start, end) =
+                (margin + annotation.start, margin + annotation.end);
+            let markers =
+                annotation.marker().to_string().repeat((end - start).max(1));
+            row.put(start, &markers, marker_style(annotation));
+            labelled.push((start, start + markers.len(), annotation));
+        }
+        if row.0.is_empty() {
+            return;
+        }
+        for (column, &bar) in bars.0.iter().enumerate() {
+            if bar.1.is_some() {
+                row.0[column] = bar;
+            }
+        }
+
+        // The label that ends furthest right goes on the same line as the
+        // markers. The rest go underneath, connected to their markers,
+        // starting with the rightmost one so that the connectors don't cross.
+        labelled.retain(|(_, _, annotation)| annotation.label.is_some());
+        labelled.sort_by_key(|&(start, end, _)| (end, start));
+        if let Some((_, end, annotation)) = labelled.pop() {
+            let text = annotation.label.unwrap();
+            row.put(end + 1, text, marker_style(annotation));
+        }
+        self.gutter_row(None, row);
+        labelled.sort_by_key(|&(start, _, _)| usize::MAX - start);
+        for i in 0..labelled.len() {
+            let mut connectors = Row(bars.0.clone());
+            let mut label = Row(bars.0.clone());
+            for (j, &(start, _, annotation)) in
+                labelled.iter().enumerate().skip(i)
+            {
+                let style = marker_style(annotation);
+                connectors.put(start, "|", style);
+                if j == i {
+                    label.put(start, annotation.label.unwrap(), style);
+                } else {
+                    label.put(start, "|", style);
+                }
+            }
+            self.gutter_row(None, connectors);
+            self.gutter_row(None, label);
+        }
+    }
+
+    /// Writes a row of a snippet after the line number gutter, which has the
+    /// line number if it is a line of source code.
+    fn gutter_row(&mut self, line: Option<usize>, mut row: Row) {
+        let number = line.map_or(String::new(), |line| (line + 1).to_string());
+        self.paint(BLUE, &format!("{number:>width$} |", width = self.gutter));
+        while row.0.last().is_some_and(|&(c, _)| c == ' ') {
+            row.0.pop();
+        }
+        if !row.0.is_empty() {
+            self.out.push(' ');
+        }
+        let mut cells = row.0.as_slice();
+        while let Some(&(_, style)) = cells.first() {
+            let len = cells
+                .iter()
+                .position(|&(_, other)| other != style)
+                .unwrap_or(cells.len());
+            let text = cells[..len].iter().map(|&(c, _)| c).collect::<String>();
+            match style {
+                Some(style) => self.paint(style, &text),
+                None => self.out.push_str(&text),
+            }
+            cells = &cells[len..];
+        }
+        self.out.push('\n');
+    }
+}
+
+fn annotation<'a>(file: &File, label: &'a SpanLabel) -> Annotation<'a> {
+    let start_line = file.find_line(label.span.low());
+    let mut end_line = file.find_line(label.span.high());
+    let mut end = column(file, end_line, label.span.high());
+    // A span that ends with a newline ends on the line before.
+    if end_line > start_line
+        && label.span.high() == file.line_span(end_line).low()
+    {
+        end_line -= 1;
+        end = column(file, end_line, file.line_span(end_line).high())
+            .min(width(file.source_line(end_line)));
+    }
+    Annotation {
+        start_line,
+        start: column(file, start_line, label.span.low()),
+        end_line,
+        end,
+        label: label.label.as_deref(),
+        primary: matches!(label.style, SpanStyle::Primary),
+    }
+}
+
+/// The display column of `pos` in `line`, with tabs shown as spaces.
+fn column(file: &File, line: usize, pos: Pos) -> usize {
+    let line = file.line_span(line);
+    width(file.source_slice(line.subspan(0, pos - line.low())))
+}
+
+fn width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { char_width(c) })
+        .sum()
+}
+
+const fn level_style(level: Level) -> &'static str {
+    match level {
+        Level::Bug | Level::Fatal | Level::Error => "1;91",
+        Level::Warning => "1;93",
+        Level::Note => "1;92",
+        Level::Help => "1;96",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(spans: Vec<SpanLabel>) -> Diagnostic {
+        Diagnostic {
+            level: Level::Error,
+            message: "oops".to_owned(),
+            code: Some("E0001".to_owned()),
+            spans,
+        }
+    }
+
+    fn label(span: Span, label: &str, style: SpanStyle) -> SpanLabel {
+        SpanLabel {
+            span,
+            label: Some(label.to_owned()),
+            style,
+        }
+    }
+
+    fn find(file: &File, text: &str) -> Span {
+        let start = file.source().find(text).unwrap() as u64;
+        file.span.subspan(start, start + text.len() as u64)
+    }
+
+    #[test]
+    fn markers_line_up_after_wide_characters() {
+        let mut code_map = CodeMap::new();
+        let file = code_map.add_file(
+            "main.scratch".to_owned(),
+            "(say \"界🙂\"\toops)\n".to_owned(),
+        );
+        let spans =
+            vec![label(find(&file, "oops"), "here", SpanStyle::Primary)];
+        assert_eq!(
+            render(&[diagnostic(spans)], &code_map, false),
+            "error[E0001]: oops\n \
+             --> main.scratch:1:11\n  \
+              |\n\
+             1 | (say \"界🙂\"    oops)\n  \
+              |                ^^^^ here\n\n",
+        );
+    }
+
+    #[test]
+    fn labels_that_do_not_fit_go_underneath() {
+        let mut code_map = CodeMap::new();
+        let file = code_map
+            .add_file("main.scratch".to_owned(), "(foo bar)\n".to_owned());
+        let spans = vec![
+            label(find(&file, "foo"), "first", SpanStyle::Primary),
+            label(find(&file, "bar"), "second", SpanStyle::Secondary),
+        ];
+        assert_eq!(
+            render(&[diagnostic(spans)], &code_map, false),
+            "error[E0001]: oops\n \
+             --> main.scratch:1:2\n  \
+              |\n\
+             1 | (foo bar)\n  \
+              |  ^^^ --- second\n  \
+              |  |\n  \
+              |  first\n\n",
+        );
+    }
+
+    #[test]
+    fn multiline_labels_are_drawn_down_the_left() {
+        let mut code_map = CodeMap::new();
+        let file = code_map.add_file(
+            "main.scratch".to_owned(),
+            "(proc (foo)\n  (bar))\n".to_owned(),
+        );
+        let spans = vec![label(
+            find(&file, "(proc (foo)\n  (bar))"),
+            "here",
+            SpanStyle::Primary,
+        )];
+        assert_eq!(
+            render(&[diagnostic(spans)], &code_map, false),
+            "error[E0001]: oops\n \
+             --> main.scratch:1:1\n  \
+              |\n\
+             1 |   (proc (foo)\n  \
+              |  _^\n\
+             2 | |   (bar))\n  \
+              | |________^ here\n\n",
+        );
+    }
+}
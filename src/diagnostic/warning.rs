@@ -1,6 +1,8 @@
-use super::{emit_all, primary, secondary, Diagnostic};
+use super::{emit_all, never_assigned_label, primary, secondary, Diagnostic};
+use crate::{allow::Allowed, opts::MessageFormat};
 use codemap::{CodeMap, Span};
 use codemap_diagnostic::SpanLabel as Label;
+use std::iter;
 
 pub enum Warning {
     ParenTooFarLeft {
@@ -12,12 +14,160 @@ pub enum Warning {
         good: Span,
         offender: Span,
     },
+    NonExhaustiveCond {
+        span: Span,
+        missing: Vec<String>,
+    },
+    ShadowedMacroParameter {
+        first: Span,
+        shadowing: Span,
+        name: String,
+    },
+    UnknownCfgKey {
+        span: Span,
+        key: String,
+    },
+    InexactRewriteSkipped {
+        span: Span,
+        reason: &'static str,
+    },
+    ShadowedGlobal {
+        span: Span,
+        global: Span,
+        name: String,
+        kind: &'static str,
+    },
+    UnknownWarning {
+        span: Span,
+        name: String,
+    },
+    NeverAssigned {
+        span: Span,
+        name: String,
+        kind: &'static str,
+    },
+    BusyLoop {
+        span: Span,
+    },
+    VideoSensingStubbed {
+        span: Span,
+        name: String,
+        /// What a reporter was replaced with, or `None` for a statement.
+        value: Option<f64>,
+    },
+    LongProc {
+        span: Span,
+        name: String,
+        statements: usize,
+        limit: usize,
+    },
+    DeepNesting {
+        span: Span,
+        limit: usize,
+    },
+    DuplicatedCode {
+        span: Span,
+        first: Span,
+        statements: usize,
+    },
+    RewriteLimit {
+        span: Span,
+        limit: usize,
+    },
+    MagicNumber {
+        span: Span,
+        others: Vec<Span>,
+        value: f64,
+    },
+    UnusedListItems {
+        span: Span,
+        name: String,
+    },
 }
 
 impl Warning {
-    pub fn emit(&self, code_map: &CodeMap) {
+    /// The names of the warnings, for `(allow name...)`.
+    pub const NAMES: &'static [&'static str] = &[
+        "misleading-formatting",
+        "inconsistent-indentation",
+        "non-exhaustive-cond",
+        "shadowed-macro-parameter",
+        "unknown-cfg-key",
+        "inexact-rewrite",
+        "shadowed-global",
+        "unknown-warning",
+        "never-assigned",
+        "busy-loop",
+        "video-sensing",
+        "long-proc",
+        "deep-nesting",
+        "duplicated-code",
+        "rewrite-limit",
+        "magic-number",
+        "unused-list-items",
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        use Warning::*;
+        match self {
+            ParenTooFarLeft { .. } => "misleading-formatting",
+            InconsistentIndentation { .. } => "inconsistent-indentation",
+            NonExhaustiveCond { .. } => "non-exhaustive-cond",
+            ShadowedMacroParameter { .. } => "shadowed-macro-parameter",
+            UnknownCfgKey { .. } => "unknown-cfg-key",
+            InexactRewriteSkipped { .. } => "inexact-rewrite",
+            ShadowedGlobal { .. } => "shadowed-global",
+            UnknownWarning { .. } => "unknown-warning",
+            NeverAssigned { .. } => "never-assigned",
+            BusyLoop { .. } => "busy-loop",
+            VideoSensingStubbed { .. } => "video-sensing",
+            LongProc { .. } => "long-proc",
+            DeepNesting { .. } => "deep-nesting",
+            DuplicatedCode { .. } => "duplicated-code",
+            RewriteLimit { .. } => "rewrite-limit",
+            MagicNumber { .. } => "magic-number",
+            UnusedListItems { .. } => "unused-list-items",
+        }
+    }
+
+    /// Where the warning is, which decides whether an `allow` covers it.
+    pub const fn span(&self) -> Span {
+        use Warning::*;
+        match *self {
+            ParenTooFarLeft { right: span, .. }
+            | InconsistentIndentation { node: span, .. }
+            | NonExhaustiveCond { span, .. }
+            | ShadowedMacroParameter {
+                shadowing: span, ..
+            }
+            | UnknownCfgKey { span, .. }
+            | InexactRewriteSkipped { span, .. }
+            | ShadowedGlobal { span, .. }
+            | UnknownWarning { span, .. }
+            | NeverAssigned { span, .. }
+            | BusyLoop { span }
+            | VideoSensingStubbed { span, .. }
+            | LongProc { span, .. }
+            | DeepNesting { span, .. }
+            | DuplicatedCode { span, .. }
+            | RewriteLimit { span, .. }
+            | MagicNumber { span, .. }
+            | UnusedListItems { span, .. } => span,
+        }
+    }
+
+    /// Prints the warning unless it was turned off with `allow`.
+    pub fn emit(
+        &self,
+        code_map: &CodeMap,
+        format: MessageFormat,
+        allowed: &Allowed,
+    ) {
         use Warning::*;
-        let diagnostic = match self {
+        if allowed.allows(self) {
+            return;
+        }
+        let mut diagnostic = match self {
             ParenTooFarLeft { left, right } => warning(
                 "misleading formatting",
                 vec![
@@ -38,9 +188,177 @@ impl Warning {
                 secondary(*good, "if this item is indented correctly...".to_owned()),
                 secondary(*offender, "...then this is not".to_owned()),
             ]),
+            NonExhaustiveCond { span, missing } => warning(
+                "non-exhaustive `cond`",
+                vec![primary(
+                    *span,
+                    format!(
+                        "missing {}",
+                        missing
+                            .iter()
+                            .map(|variant| format!("`{variant}`"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                )],
+            ),
+            ShadowedMacroParameter {
+                first,
+                shadowing,
+                name,
+            } => warning(
+                "macro parameter bound more than once",
+                vec![
+                    primary(
+                        *shadowing,
+                        format!("`{name}` is bound again here"),
+                    ),
+                    secondary(*first, "first bound here".to_owned()),
+                ],
+            ),
+            UnknownCfgKey { span, key } => warning(
+                "unknown `cfg` key",
+                vec![primary(
+                    *span,
+                    format!(
+                        "`{key}` is not `target` and was not set with \
+                        `--define`, so this is always false"
+                    ),
+                )],
+            ),
+            InexactRewriteSkipped { span, reason } => warning(
+                "expression not simplified",
+                vec![primary(
+                    *span,
+                    format!(
+                        "{reason}, pass `--fast-math` to simplify it anyway"
+                    ),
+                )],
+            ),
+            ShadowedGlobal {
+                span,
+                global,
+                name,
+                kind,
+            } => warning(
+                format!("{kind} `{name}` shadows a global {kind}"),
+                vec![
+                    primary(
+                        *span,
+                        format!(
+                            "`{name}` refers to this {kind} everywhere in \
+                            this sprite"
+                        ),
+                    ),
+                    secondary(*global, "global declared here".to_owned()),
+                ],
+            ),
+            UnknownWarning { span, name } => warning(
+                format!("unknown warning `{name}`"),
+                vec![primary(
+                    *span,
+                    format!("expected one of {}", Self::NAMES.join(", ")),
+                )],
+            ),
+            NeverAssigned { span, name, kind } => warning(
+                format!("{kind} `{name}` is read but never assigned"),
+                vec![primary(*span, never_assigned_label(kind))],
+            ),
+            BusyLoop { span } => warning(
+                "`forever` loop never waits",
+                vec![primary(
+                    *span,
+                    "this keeps a core busy in native code, add a `wait` if \
+                    it is waiting for something to change"
+                        .to_owned(),
+                )],
+            ),
+            VideoSensingStubbed { span, name, value } => warning(
+                format!("`{name}` needs a webcam"),
+                vec![primary(
+                    *span,
+                    match value {
+                        Some(value) => format!(
+                            "this is always {value} in native code, set it \
+                            with `--video-stub`"
+                        ),
+                        None => "this does nothing in native code".to_owned(),
+                    },
+                )],
+            ),
+            LongProc {
+                span,
+                name,
+                statements,
+                limit,
+            } => warning(
+                format!("procedure `{name}` has {statements} statements"),
+                vec![primary(
+                    *span,
+                    format!(
+                        "more than {limit}, split it into smaller procedures"
+                    ),
+                )],
+            ),
+            DeepNesting { span, limit } => warning(
+                format!("control flow is nested more than {limit} deep"),
+                vec![primary(
+                    *span,
+                    "move this into a procedure of its own".to_owned(),
+                )],
+            ),
+            DuplicatedCode {
+                span,
+                first,
+                statements,
+            } => warning(
+                format!("{statements} statements are written twice"),
+                vec![
+                    primary(
+                        *span,
+                        "this is the same as other code, a procedure could \
+                        be used for both"
+                            .to_owned(),
+                    ),
+                    secondary(*first, "first written here".to_owned()),
+                ],
+            ),
+            RewriteLimit { span, limit } => warning(
+                format!("rewrite rules were applied {limit} times"),
+                vec![primary(
+                    *span,
+                    "this rule may match its own result, so no more rules \
+                    were applied"
+                        .to_owned(),
+                )],
+            ),
+            MagicNumber {
+                span,
+                others,
+                value,
+            } => warning(
+                format!("{value} is written {} times", others.len() + 1),
+                iter::once(primary(
+                    *span,
+                    "a macro or variable could give this number a name"
+                        .to_owned(),
+                ))
+                .chain(others.iter().map(|&other| {
+                    secondary(other, "also written here".to_owned())
+                }))
+                .collect(),
+            ),
+            UnusedListItems { span, name } => warning(
+                format!("items are added to list `{name}` but never read"),
+                vec![primary(
+                    *span,
+                    "nothing in the program reads this list".to_owned(),
+                )],
+            ),
         };
+        diagnostic.code = Some(self.name().to_owned());
 
-        emit_all(&[diagnostic], code_map);
+        emit_all(&[diagnostic], code_map, format);
     }
 }
 
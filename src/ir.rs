@@ -1,24 +1,56 @@
+mod decl;
+mod dispatch;
+pub mod effect;
 pub mod expr;
+pub mod ffi;
+mod flatten;
+mod param_types;
+pub mod polyfill;
 pub mod proc;
+mod rotate_point;
 pub mod sprite;
 pub mod statement;
+mod static_assert;
+pub mod table;
+pub mod typ;
+pub mod verify;
+mod video;
 
 use crate::{
     ast::Ast,
-    diagnostic::{Error, Result},
-    ir::sprite::Sprite,
+    diagnostic::{Error, Result, Warning},
+    ir::{sprite::Sprite, statement::Statement},
+    optimize::Optimizer,
 };
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    iter,
+};
+
+/// Builtins that wait before continuing, or stop the script they are in.
+const WAITING_PROCS: &[&str] = &[
+    "wait",
+    "say-for-seconds",
+    "ask",
+    "send-broadcast-sync",
+    "speak",
+    "play-note",
+    "rest",
+    "stop-this-script",
+    "stop-all",
+];
 
 #[derive(Debug)]
 pub struct Program {
     pub stage: Sprite,
-    pub sprites: HashMap<String, Sprite>,
+    /// Ordered by name so that everything that goes over the sprites does so
+    /// in the same order every time.
+    pub sprites: BTreeMap<String, Sprite>,
 }
 
 impl Program {
     pub fn from_asts(asts: Vec<Ast>) -> Result<Self> {
-        let mut sprites = HashMap::<String, Sprite>::new();
+        let mut sprites = BTreeMap::<String, Sprite>::new();
 
         for ast in asts {
             let (name, sprite) = Sprite::from_ast(ast)?;
@@ -32,16 +64,82 @@ impl Program {
             }
         }
 
+        for sprite in sprites.values_mut() {
+            sprite.check_duplicate_procs()?;
+            dispatch::lower(sprite)?;
+            rotate_point::lower(sprite)?;
+        }
+
         let stage =
             sprites.remove("Stage").ok_or(Error::ProgramMissingStage)?;
 
         Ok(Self { stage, sprites })
     }
 
-    pub fn optimize(&mut self) {
-        self.stage.optimize();
+    /// Warnings for the variables and lists of sprites that have the same name
+    /// as a global one, which they hide from the sprite.
+    pub fn shadowing_warnings(&self) -> Vec<Warning> {
+        let stage = &self.stage;
+        let mut shadowed = Vec::new();
+        for sprite in self.sprites.values() {
+            for (spans, globals, kind) in [
+                (&sprite.variable_spans, &stage.variable_spans, "variable"),
+                (&sprite.list_spans, &stage.list_spans, "list"),
+            ] {
+                for (name, span) in spans {
+                    if let Some(global) = globals.get(name) {
+                        shadowed.push((*span, *global, name, kind));
+                    }
+                }
+            }
+        }
+        shadowed.sort_by_key(|&(span, ..)| span);
+        shadowed
+            .into_iter()
+            .map(|(span, global, name, kind)| Warning::ShadowedGlobal {
+                span,
+                global,
+                name: name.clone(),
+                kind,
+            })
+            .collect()
+    }
+
+    /// Warnings for `forever` loops that never wait, which keep a core busy in
+    /// native code. Calls to custom procedures are assumed to wait since they
+    /// might.
+    pub fn busy_loop_warnings(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for sprite in iter::once(&self.stage).chain(self.sprites.values()) {
+            let mut may_wait = |stmt: &Statement| {
+                matches!(
+                    stmt,
+                    Statement::ProcCall { proc_name, .. }
+                        if WAITING_PROCS.contains(&&**proc_name)
+                            || sprite.procedures.contains_key(proc_name)
+                )
+            };
+            for proc in sprite.procedures.values().flatten() {
+                proc.body.any(&mut |stmt| {
+                    if let Statement::Forever(body, span) = stmt {
+                        if !body.any(&mut may_wait) {
+                            warnings.push(Warning::BusyLoop { span: *span });
+                        }
+                    }
+                    false
+                });
+            }
+        }
+        warnings.sort_by_key(Warning::span);
+        warnings
+    }
+
+    /// Returns whether anything changed.
+    pub fn optimize(&mut self, optimizer: &mut Optimizer) -> bool {
+        let mut dirty = self.stage.optimize(optimizer);
         for sprite in self.sprites.values_mut() {
-            sprite.optimize();
+            dirty |= sprite.optimize(optimizer);
         }
+        dirty
     }
 }
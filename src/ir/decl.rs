@@ -0,0 +1,104 @@
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::{
+        expr::color,
+        typ::{split_annotation, Type},
+    },
+};
+use codemap::Span;
+use sb3_stuff::Value;
+use std::collections::HashMap;
+
+/// Parses the tail of a `variables` declaration. A variable can be given an
+/// initial value with `(name value)`, and a type with `(name : type)` or
+/// `((name : type) value)`. Returns the names along with their spans.
+pub fn parse_variable_decls(
+    decls: Vec<Ast>,
+    annotations: &mut HashMap<String, Type>,
+    initial_values: &mut HashMap<String, Value>,
+) -> Result<Vec<(String, Span)>> {
+    decls
+        .into_iter()
+        .map(|decl| {
+            let (decl, initial_value) = match decl {
+                Ast::Node(name, mut tail, _) if tail.len() == 1 => {
+                    (*name, tail.pop())
+                }
+                _ => (decl, None),
+            };
+            let (decl, typ) = split_annotation(decl)?;
+            let Ast::Sym(name, name_span) = decl else {
+                return Err(Box::new(Error::InvalidDeclaration {
+                    span: decl.span(),
+                    kind: "variable",
+                }));
+            };
+            if let Some(typ) = typ {
+                annotations.insert(name.clone(), typ);
+            }
+            if let Some(initial_value) = initial_value {
+                let span = initial_value.span();
+                let value = literal(initial_value)?;
+                let found = type_of(&value);
+                if let Some(expected) = typ.filter(|&typ| typ != found) {
+                    return Err(Box::new(Error::TypeMismatch {
+                        span,
+                        expected: expected.to_str(),
+                        found: found.to_str(),
+                    }));
+                }
+                initial_values.insert(name.clone(), value);
+            }
+            Ok((name, name_span))
+        })
+        .collect()
+}
+
+/// Parses the tail of a `lists` declaration. A list can be pre-filled with
+/// `(name items...)`. Returns the names along with their spans.
+pub fn parse_list_decls(
+    decls: Vec<Ast>,
+    initial_items: &mut HashMap<String, Vec<Value>>,
+) -> Result<Vec<(String, Span)>> {
+    decls
+        .into_iter()
+        .map(|decl| match decl {
+            Ast::Sym(name, span) => Ok((name, span)),
+            Ast::Node(head, items, node_span) => {
+                let Ast::Sym(name, span) = *head else {
+                    return Err(Box::new(Error::InvalidDeclaration {
+                        span: node_span,
+                        kind: "list",
+                    }));
+                };
+                let items =
+                    items.into_iter().map(literal).collect::<Result<_>>()?;
+                initial_items.insert(name.clone(), items);
+                Ok((name, span))
+            }
+            _ => Err(Box::new(Error::InvalidDeclaration {
+                span: decl.span(),
+                kind: "list",
+            })),
+        })
+        .collect()
+}
+
+fn literal(ast: Ast) -> Result<Value> {
+    match ast {
+        Ast::Num(n, _) => Ok(Value::Num(n)),
+        Ast::String(s, _) => Ok(Value::String(s.into())),
+        Ast::Bool(b, _) => Ok(Value::Bool(b)),
+        Ast::Color(digits, span) => Ok(Value::Num(color(&digits, span)?)),
+        _ => Err(Box::new(Error::InvalidInitialValue { span: ast.span() })),
+    }
+}
+
+const fn type_of(value: &Value) -> Type {
+    match value {
+        Value::Num(_) => Type::Num,
+        Value::String(_) => Type::Str,
+        Value::Bool(_) => Type::Bool,
+    }
+}
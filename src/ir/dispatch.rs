@@ -0,0 +1,178 @@
+use crate::{
+    diagnostic::{Error, Result},
+    ir::{expr::Expr, proc::is_event, sprite::Sprite, statement::Statement},
+};
+use codemap::Span;
+use sb3_stuff::Value;
+use std::{collections::HashMap, mem};
+
+/// Name of the hidden local variable that holds the target of a
+/// `call-indirect` while it is being dispatched.
+const TARGET_VAR: &str = "call-indirect target";
+
+/// Lowers procedure references and indirect calls to plain IR.
+///
+/// Every custom procedure of a sprite gets an index in the sprite's dispatch
+/// table, starting from 1 so that the default value of a variable (0) refers
+/// to no procedure. `(proc-ref name)` evaluates to that index and
+/// `(call-indirect target args...)` becomes a chain of comparisons against the
+/// indices of all procedures taking as many arguments, which calls the
+/// matching one.
+pub fn lower(sprite: &mut Sprite) -> Result<()> {
+    let mut table = sprite
+        .procedures
+        .iter()
+        .filter(|(name, _)| !is_event(name))
+        .map(|(name, procs)| (name.clone(), procs[0].params.len()))
+        .collect::<Vec<_>>();
+    table.sort();
+    let indices = table
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.clone(), i + 1))
+        .collect::<HashMap<_, _>>();
+
+    for proc in sprite.procedures.values_mut().flatten() {
+        let mut error = None;
+        let mut needs_target_var = false;
+        proc.body.traverse_postorder_mut(&mut |stmt| {
+            if error.is_some() {
+                return;
+            }
+            for expr in exprs_mut(stmt) {
+                expr.traverse_postorder_mut(&mut |expr| {
+                    if error.is_none() {
+                        if let Err(err) = lower_proc_ref(expr, &indices) {
+                            error = Some(err);
+                        }
+                    }
+                });
+            }
+            if let Statement::ProcCall {
+                proc_name,
+                proc_span,
+                args,
+            } = stmt
+            {
+                if proc_name == "call-indirect" {
+                    match lower_call_indirect(
+                        mem::take(args),
+                        *proc_span,
+                        &table,
+                    ) {
+                        Ok((lowered, uses_target_var)) => {
+                            *stmt = lowered;
+                            needs_target_var |= uses_target_var;
+                        }
+                        Err(err) => error = Some(err),
+                    }
+                }
+            }
+        });
+        if let Some(err) = error {
+            return Err(err);
+        }
+        if needs_target_var {
+            proc.variables.insert(TARGET_VAR.to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+fn lower_proc_ref(
+    expr: &mut Expr,
+    indices: &HashMap<String, usize>,
+) -> Result<()> {
+    let Expr::FuncCall("proc-ref", span, args) = expr else {
+        return Ok(());
+    };
+    let [Expr::Sym(proc_name, proc_span)] = &args[..] else {
+        return Err(Box::new(Error::FunctionWrongArgCount {
+            span: *span,
+            func_name: "proc-ref",
+            expected: 1,
+            got: args.len(),
+        }));
+    };
+    let Some(index) = indices.get(&**proc_name) else {
+        return Err(Box::new(Error::UnknownProc {
+            span: *proc_span,
+            proc_name: proc_name.to_string(),
+        }));
+    };
+    *expr = Expr::Imm(Value::Num(*index as f64));
+    Ok(())
+}
+
+/// Returns the lowered statement and whether it stores the target in
+/// [`TARGET_VAR`].
+fn lower_call_indirect(
+    args: Vec<Expr>,
+    span: Span,
+    table: &[(String, usize)],
+) -> Result<(Statement, bool)> {
+    let mut args = args.into_iter();
+    let Some(target) = args.next() else {
+        return Err(Box::new(Error::BuiltinProcWrongArgCount {
+            span,
+            proc_name: "call-indirect".to_owned(),
+            expected: 1,
+            got: 0,
+        }));
+    };
+    let args = args.collect::<Vec<_>>();
+
+    // Avoid evaluating the target more than once.
+    let (target, assignment) = match target {
+        Expr::Imm(_) | Expr::Sym(..) => (target, None),
+        _ => (
+            Expr::Sym(TARGET_VAR.into(), span),
+            Some(Statement::ProcCall {
+                proc_name: ":=".to_owned(),
+                proc_span: span,
+                args: vec![Expr::Sym(TARGET_VAR.into(), span), target],
+            }),
+        ),
+    };
+
+    let dispatch = table
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, param_count))| *param_count == args.len())
+        .rfold(Statement::default(), |else_, (i, (proc_name, _))| {
+            Statement::IfElse {
+                condition: Expr::FuncCall(
+                    "=",
+                    span,
+                    vec![target.clone(), Expr::Imm(Value::Num((i + 1) as f64))],
+                ),
+                then: Box::new(Statement::ProcCall {
+                    proc_name: proc_name.clone(),
+                    proc_span: span,
+                    args: args.clone(),
+                }),
+                else_: Box::new(else_),
+                span,
+            }
+        });
+
+    let uses_target_var = assignment.is_some();
+    Ok((
+        Statement::Do(assignment.into_iter().chain([dispatch]).collect()),
+        uses_target_var,
+    ))
+}
+
+pub(super) fn exprs_mut(stmt: &mut Statement) -> Vec<&mut Expr> {
+    match stmt {
+        Statement::ProcCall { args, .. } => args.iter_mut().collect(),
+        Statement::IfElse { condition, .. }
+        | Statement::Until { condition, .. }
+        | Statement::While { condition, .. } => vec![condition],
+        Statement::Repeat { times, .. } | Statement::For { times, .. } => {
+            vec![times]
+        }
+        Statement::Do(_) | Statement::Forever(..) => Vec::new(),
+    }
+}
@@ -0,0 +1,96 @@
+//! What evaluating expressions and running statements can do besides giving a
+//! value, which decides whether the optimizer may remove, duplicate or merge
+//! them.
+
+use crate::ir::{expr::Expr, statement::Statement};
+
+/// The effects of code, ordered from none to the most. Code has the largest
+/// effect of anything in it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Effect {
+    /// Always gives the same value.
+    Pure,
+    /// Depends on variables, lists or something else that can change between
+    /// evaluations, like `timer`, but doesn't change anything itself.
+    ReadsState,
+    /// Changes state of the program, like `random` which advances the random
+    /// number generator.
+    WritesState,
+    /// Does something that is visible outside of the program or might, like
+    /// printing, calling native code, or never finishing.
+    Io,
+}
+
+impl Effect {
+    /// Whether code with this effect can be removed when its value isn't
+    /// needed, evaluated more than once, or evaluated where it wasn't before,
+    /// since evaluating it doesn't change anything.
+    pub fn can_drop(self) -> bool {
+        self <= Self::ReadsState
+    }
+}
+
+impl Expr {
+    pub fn effect(&self) -> Effect {
+        match self {
+            Self::Imm(_) => Effect::Pure,
+            // Variables, parameters and reporters like `timer` and `answer`.
+            Self::Sym(..) => Effect::ReadsState,
+            Self::FuncCall(func_name, _, args) => {
+                let own = match *func_name {
+                    "!!" | "length" | "pressing-key" => Effect::ReadsState,
+                    "random" => Effect::WritesState,
+                    "call-extern" | "call-extension" => Effect::Io,
+                    _ => Effect::Pure,
+                };
+                args.iter().map(Self::effect).fold(own, Effect::max)
+            }
+            Self::AddSub(a, b, _) | Self::MulDiv(a, b, _) => a
+                .iter()
+                .chain(b)
+                .map(Self::effect)
+                .fold(Effect::Pure, Effect::max),
+        }
+    }
+}
+
+impl Statement {
+    pub fn effect(&self) -> Effect {
+        match self {
+            Self::ProcCall {
+                proc_name, args, ..
+            } => {
+                let own = match &**proc_name {
+                    ":="
+                    | "+="
+                    | "append"
+                    | "delete"
+                    | "delete-all"
+                    | "replace"
+                    | "json-parse-into-lists"
+                    | "reset-timer" => Effect::WritesState,
+                    // Everything else either has visible effects or is a
+                    // custom procedure, which could.
+                    _ => Effect::Io,
+                };
+                args.iter().map(Expr::effect).fold(own, Effect::max)
+            }
+            Self::Do(stmts) => stmts
+                .iter()
+                .map(Self::effect)
+                .fold(Effect::Pure, Effect::max),
+            Self::IfElse {
+                condition,
+                then,
+                else_,
+                ..
+            } => condition.effect().max(then.effect()).max(else_.effect()),
+            // Loops might never finish.
+            Self::Repeat { .. }
+            | Self::Forever(..)
+            | Self::Until { .. }
+            | Self::While { .. }
+            | Self::For { .. } => Effect::Io,
+        }
+    }
+}
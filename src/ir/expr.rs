@@ -11,8 +11,12 @@ pub enum Expr {
     Imm(Value),
     Sym(EcoString, Span),
     FuncCall(&'static str, Span, Vec<Self>),
-    AddSub(Vec<Self>, Vec<Self>),
-    MulDiv(Vec<Self>, Vec<Self>),
+    /// The terms that are added and those that are subtracted, and the span
+    /// of the operator.
+    AddSub(Vec<Self>, Vec<Self>, Span),
+    /// The factors that are multiplied and those that are divided by, and the
+    /// span of the operator.
+    MulDiv(Vec<Self>, Vec<Self>, Span),
 }
 
 impl Default for Expr {
@@ -30,6 +34,17 @@ macro_rules! known_func_name {
     }
 }
 
+/// The value of a color literal, which is the number `0xRRGGBB` since that is
+/// what Scratch makes of numbers used as colors.
+pub fn color(digits: &str, span: Span) -> Result<f64> {
+    if digits.len() != 6 {
+        return Err(Box::new(Error::InvalidColor { span }));
+    }
+    let rgb = u32::from_str_radix(digits, 16)
+        .map_err(|_| Error::InvalidColor { span })?;
+    Ok(f64::from(rgb))
+}
+
 impl Expr {
     pub fn from_ast(ast: Ast) -> Result<Self> {
         Ok(match ast {
@@ -37,23 +52,45 @@ impl Expr {
             Ast::Bool(b, ..) => Self::Imm(Value::Bool(b)),
             Ast::String(s, ..) => Self::Imm(Value::String(s.into())),
             Ast::Sym(sym, span) => Self::Sym(sym.into(), span),
-            Ast::Node(box Ast::Sym(func_name, span), args, ..) => {
+            Ast::Color(digits, span) => {
+                Self::Imm(Value::Num(color(&digits, span)?))
+            }
+            Ast::Node(head, args, ..) => {
+                let Ast::Sym(func_name, span) = *head else {
+                    return Err(Box::new(Error::FunctionNameMustBeSymbol {
+                        span: head.span(),
+                    }));
+                };
                 match &*func_name {
                     "+" => {
                         let positives = args
                             .into_iter()
                             .map(Self::from_ast)
                             .collect::<Result<_>>()?;
-                        Self::AddSub(positives, Vec::new())
+                        Self::AddSub(positives, Vec::new(), span)
                     }
                     "-" => {
                         let mut terms = args.into_iter().map(Self::from_ast);
-                        let positive_or_negated = terms.next().unwrap()?;
+                        let Some(positive_or_negated) = terms.next() else {
+                            return Err(Box::new(
+                                Error::FunctionWrongArgCount {
+                                    span,
+                                    func_name: "-",
+                                    expected: 1,
+                                    got: 0,
+                                },
+                            ));
+                        };
+                        let positive_or_negated = positive_or_negated?;
                         let terms = terms.collect::<Result<Vec<_>>>()?;
                         if terms.is_empty() {
-                            Self::AddSub(Vec::new(), vec![positive_or_negated])
+                            Self::AddSub(
+                                Vec::new(),
+                                vec![positive_or_negated],
+                                span,
+                            )
                         } else {
-                            Self::AddSub(vec![positive_or_negated], terms)
+                            Self::AddSub(vec![positive_or_negated], terms, span)
                         }
                     }
                     "*" => {
@@ -61,19 +98,34 @@ impl Expr {
                             .into_iter()
                             .map(Self::from_ast)
                             .collect::<Result<_>>()?;
-                        Self::MulDiv(numerators, Vec::new())
+                        Self::MulDiv(numerators, Vec::new(), span)
                     }
                     "/" => {
                         let mut terms = args.into_iter().map(Self::from_ast);
-                        let numerator_or_inverted = terms.next().unwrap()?;
+                        let Some(numerator_or_inverted) = terms.next() else {
+                            return Err(Box::new(
+                                Error::FunctionWrongArgCount {
+                                    span,
+                                    func_name: "/",
+                                    expected: 1,
+                                    got: 0,
+                                },
+                            ));
+                        };
+                        let numerator_or_inverted = numerator_or_inverted?;
                         let terms = terms.collect::<Result<Vec<_>>>()?;
                         if terms.is_empty() {
                             Self::MulDiv(
                                 Vec::new(),
                                 vec![numerator_or_inverted],
+                                span,
                             )
                         } else {
-                            Self::MulDiv(vec![numerator_or_inverted], terms)
+                            Self::MulDiv(
+                                vec![numerator_or_inverted],
+                                terms,
+                                span,
+                            )
                         }
                     }
                     _ => {
@@ -82,7 +134,10 @@ impl Expr {
                                 "*", "/", "!!", "++", "and", "or", "not", "=", "<", ">", "length",
                                 "str-length", "char-at", "mod", "abs", "floor", "ceil", "sqrt", "ln", "log",
                                 "e^", "ten^", "sin", "cos", "tan", "asin", "acos", "atan", "pressing-key",
-                                "to-num", "random",
+                                "to-num", "to-bool", "random", "proc-ref", "call-extern", "call-extension",
+                                "json-get", "json-set", "str-match", "round-to", "format-decimal",
+                                "to-hex", "to-binary", "bit-and", "bit-or", "bit-xor", "bit-shift-left",
+                                "bit-shift-right", "min", "max", "clamp", "dist", "dir-to", "table-get",
                             }.ok_or(
                                 Error::UnknownFunction { span, func_name },
                             )?;
@@ -96,14 +151,11 @@ impl Expr {
                     }
                 }
             }
-            Ast::Node(not_a_symbol, ..) => {
-                return Err(Box::new(Error::FunctionNameMustBeSymbol {
-                    span: not_a_symbol.span(),
-                }))
-            }
             Ast::Unquote(_, span) => {
                 return Err(Box::new(Error::UnquoteOutsideOfMacro { span }))
             }
+            // Quoted code is compiled without expanding macros in it.
+            Ast::Quote(quoted, _) => Self::from_ast(*quoted)?,
         })
     }
 
@@ -115,6 +167,18 @@ impl Expr {
         matches!(self, Self::Imm(..))
     }
 
+    /// The span of the expression, which is that of the operator or function
+    /// name for calls. Only literals don't have one.
+    pub const fn span(&self) -> Option<Span> {
+        match *self {
+            Self::Imm(_) => None,
+            Self::Sym(_, span)
+            | Self::FuncCall(_, span, _)
+            | Self::AddSub(_, _, span)
+            | Self::MulDiv(_, _, span) => Some(span),
+        }
+    }
+
     pub fn traverse_postorder_mut(&mut self, f: &mut impl FnMut(&mut Self)) {
         match self {
             Self::Imm(_) | Self::Sym(_, _) => {}
@@ -123,7 +187,7 @@ impl Expr {
                     expr.traverse_postorder_mut(f);
                 }
             }
-            Self::AddSub(a, b) | Self::MulDiv(a, b) => {
+            Self::AddSub(a, b, _) | Self::MulDiv(a, b, _) => {
                 for expr in a.iter_mut().chain(b) {
                     expr.traverse_postorder_mut(f);
                 }
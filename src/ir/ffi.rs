@@ -0,0 +1,50 @@
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::typ::{parse_type, split_annotation, Type},
+};
+use codemap::Span;
+
+/// A C function declared with
+/// `(extern "name" (param : type)... -> return-type)`.
+///
+/// Numbers are passed as `double`, booleans as `bool` and strings as
+/// NUL-terminated `const char *`. Strings returned by the function are copied,
+/// so the function keeps ownership of them. The return type can be omitted for
+/// functions whose result isn't needed.
+#[derive(Debug)]
+pub struct ExternFunction {
+    pub params: Vec<Type>,
+    pub returns: Option<Type>,
+}
+
+impl ExternFunction {
+    pub fn from_asts(args: Vec<Ast>, span: Span) -> Result<(String, Self)> {
+        let invalid = || Box::new(Error::InvalidExternDeclaration { span });
+        let mut args = args.into_iter();
+        let Some(Ast::String(name, _)) = args.next() else {
+            return Err(invalid());
+        };
+
+        let mut params = Vec::new();
+        let mut returns = None;
+        while let Some(arg) = args.next() {
+            if matches!(&arg, Ast::Sym(arrow, _) if arrow == "->") {
+                let Some(typ) = args.next() else {
+                    return Err(invalid());
+                };
+                returns = Some(parse_type(typ)?);
+                if args.next().is_some() {
+                    return Err(invalid());
+                }
+                break;
+            }
+            let (Ast::Sym(..), Some(typ)) = split_annotation(arg)? else {
+                return Err(invalid());
+            };
+            params.push(typ);
+        }
+
+        Ok((name, Self { params, returns }))
+    }
+}
@@ -0,0 +1,189 @@
+//! Inlining of custom procedures whose body is a single block, which makes
+//! Scratch projects smaller since every call, definition and prototype is a
+//! block of its own.
+
+use crate::ir::{
+    dispatch::exprs_mut,
+    expr::Expr,
+    proc::{is_event, Procedure},
+    sprite::Sprite,
+    statement::Statement,
+    Program,
+};
+use ecow::EcoString;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A procedure that can be inlined.
+struct Inlinable {
+    params: Vec<EcoString>,
+    body: Statement,
+    /// The variables, lists and reporters that the body uses, which must mean
+    /// the same in every caller.
+    names: HashSet<EcoString>,
+}
+
+impl Program {
+    /// Replaces calls of custom procedures that are a single block with that
+    /// block, and removes the procedures. A procedure is only inlined if
+    /// every call of it can be, so that its definition goes away.
+    pub fn flatten_scripts(&mut self) {
+        let global_lists = self.stage.lists.clone();
+        for sprite in
+            std::iter::once(&mut self.stage).chain(self.sprites.values_mut())
+        {
+            while flatten(sprite, &global_lists) {}
+        }
+    }
+}
+
+/// Returns whether anything changed.
+fn flatten(sprite: &mut Sprite, global_lists: &BTreeSet<String>) -> bool {
+    let mut inlinable = sprite
+        .procedures
+        .iter()
+        .filter_map(|(name, procs)| match &procs[..] {
+            [proc] => Some((name.clone(), as_inlinable(sprite, proc)?)),
+            _ => None,
+        })
+        .filter(|(name, _)| !is_event(name))
+        .filter(|(_, proc)| {
+            !proc.params.iter().any(|param| {
+                sprite.lists.contains(&**param)
+                    || global_lists.contains(&**param)
+            })
+        })
+        .collect::<HashMap<_, _>>();
+
+    for caller in sprite.procedures.values().flatten() {
+        caller.body.any(&mut |stmt| {
+            if let Statement::ProcCall {
+                proc_name, args, ..
+            } = stmt
+            {
+                if let Some(callee) = inlinable.get(proc_name) {
+                    if !can_inline(callee, caller, args) {
+                        inlinable.remove(proc_name);
+                    }
+                }
+            }
+            false
+        });
+    }
+    if inlinable.is_empty() {
+        return false;
+    }
+
+    for caller in sprite.procedures.values_mut().flatten() {
+        caller.body.traverse_postorder_mut(&mut |stmt| {
+            if let Statement::ProcCall {
+                proc_name, args, ..
+            } = stmt
+            {
+                if let Some(callee) = inlinable.get(&**proc_name) {
+                    *stmt = inline(callee, args);
+                }
+            }
+        });
+    }
+    for name in inlinable.keys() {
+        sprite.procedures.remove(name);
+    }
+    true
+}
+
+/// Checks that a procedure is a single block that doesn't depend on being in
+/// a procedure of its own, like `stop-this-script` does.
+fn as_inlinable(sprite: &Sprite, proc: &Procedure) -> Option<Inlinable> {
+    if !proc.variables.is_empty() || !proc.lists.is_empty() {
+        return None;
+    }
+    let params = proc
+        .params
+        .iter()
+        .map(|(param, _)| match param {
+            Expr::Sym(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if let Statement::ProcCall {
+        proc_name, args, ..
+    } = &proc.body
+    {
+        let assigns_param = matches!(&**proc_name, ":=" | "+=")
+            && matches!(
+                args.first(),
+                Some(Expr::Sym(var, _)) if params.contains(var)
+            );
+        if assigns_param
+            || proc_name == "stop-this-script"
+            || sprite.procedures.contains_key(proc_name)
+        {
+            return None;
+        }
+    } else if !proc.body.is_nop() {
+        return None;
+    }
+    let mut names = HashSet::new();
+    if let Statement::ProcCall { args, .. } = &proc.body {
+        for arg in args {
+            collect_names(arg, &mut names);
+        }
+    }
+    for param in &params {
+        names.remove(param);
+    }
+    Some(Inlinable {
+        params,
+        body: proc.body.clone(),
+        names,
+    })
+}
+
+/// Whether a call can be replaced by the body of the procedure. Arguments are
+/// evaluated where the parameters are used instead of before the call, so
+/// they must not change anything. The caller must not hide any names that
+/// the body uses with its own parameters or local variables and lists.
+fn can_inline(callee: &Inlinable, caller: &Procedure, args: &[Expr]) -> bool {
+    args.len() == callee.params.len()
+        && args.iter().all(|arg| arg.effect().can_drop())
+        && callee.names.iter().all(|name| {
+            !caller.variables.contains(&**name)
+                && !caller.lists.contains(&**name)
+                && !caller.params.iter().any(
+                    |(param, _)| matches!(param, Expr::Sym(p, _) if p == name),
+                )
+        })
+}
+
+fn inline(callee: &Inlinable, args: &[Expr]) -> Statement {
+    let mut body = callee.body.clone();
+    for expr in exprs_mut(&mut body) {
+        expr.traverse_postorder_mut(&mut |expr| {
+            if let Expr::Sym(sym, _) = expr {
+                if let Some(i) = callee.params.iter().position(|p| *p == *sym) {
+                    *expr = args[i].clone();
+                }
+            }
+        });
+    }
+    body
+}
+
+fn collect_names(expr: &Expr, names: &mut HashSet<EcoString>) {
+    match expr {
+        Expr::Imm(_) => {}
+        Expr::Sym(sym, _) => {
+            names.insert(sym.clone());
+        }
+        Expr::FuncCall(_, _, args) => {
+            for arg in args {
+                collect_names(arg, names);
+            }
+        }
+        Expr::AddSub(a, b, _) | Expr::MulDiv(a, b, _) => {
+            for term in a.iter().chain(b) {
+                collect_names(term, names);
+            }
+        }
+    }
+}
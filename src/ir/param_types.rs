@@ -0,0 +1,101 @@
+//! Types of parameters that aren't annotated but are only ever passed numbers,
+//! so that native code can pass them as doubles instead of boxing them.
+
+use crate::{
+    ir::{
+        expr::Expr, proc::is_event, statement::Statement, typ::Type, Program,
+    },
+    typecheck::Checker,
+};
+use std::iter;
+
+impl Program {
+    /// Annotates parameters of custom procedures as numbers when every call
+    /// passes a number. This runs after type checking, since the annotations
+    /// only hold for the calls that are in the program.
+    pub fn infer_param_types(&mut self) {
+        // A parameter that is passed on to another procedure only becomes a
+        // number once the parameter it is passed from has become one.
+        loop {
+            let numeric = self.numeric_params();
+            if numeric.is_empty() {
+                break;
+            }
+            for (sprite_name, proc_name, param) in numeric {
+                let sprite = match sprite_name {
+                    Some(name) => self.sprites.get_mut(&name).unwrap(),
+                    None => &mut self.stage,
+                };
+                sprite.procedures.get_mut(&proc_name).unwrap()[0]
+                    .annotations
+                    .insert(param, Type::Num);
+            }
+        }
+    }
+
+    /// The parameters that aren't annotated and that every call passes a
+    /// number to, by sprite, procedure and name. The sprite is `None` for the
+    /// stage.
+    fn numeric_params(&self) -> Vec<(Option<String>, String, String)> {
+        let mut numeric = Vec::new();
+        let sprites = iter::once((None, &self.stage)).chain(
+            self.sprites
+                .iter()
+                .map(|(name, sprite)| (Some(name), sprite)),
+        );
+        for (sprite_name, sprite) in sprites {
+            for (proc_name, procs) in &sprite.procedures {
+                let [proc] = &procs[..] else {
+                    continue;
+                };
+                if is_event(proc_name) {
+                    continue;
+                }
+                for (i, (param, _)) in proc.params.iter().enumerate() {
+                    let Expr::Sym(param_name, _) = param else {
+                        continue;
+                    };
+                    if proc.annotations.contains_key(&**param_name) {
+                        continue;
+                    }
+                    let mut called = false;
+                    let mut other = false;
+                    for caller in sprite.procedures.values().flatten() {
+                        let checker =
+                            Checker::new(false, &self.stage, sprite, caller);
+                        caller.body.any(&mut |stmt| {
+                            if let Statement::ProcCall {
+                                proc_name: callee,
+                                args,
+                                ..
+                            } = stmt
+                            {
+                                if callee == proc_name {
+                                    called = true;
+                                    other |= !args.get(i).is_some_and(|arg| {
+                                        is_number(&checker, arg)
+                                    });
+                                }
+                            }
+                            false
+                        });
+                    }
+                    if called && !other {
+                        numeric.push((
+                            sprite_name.cloned(),
+                            proc_name.clone(),
+                            param_name.to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+        numeric
+    }
+}
+
+fn is_number(checker: &Checker, expr: &Expr) -> bool {
+    // The type checker doesn't look at procedure references.
+    !matches!(expr, Expr::FuncCall("proc-ref", ..))
+        && checker.type_of(expr) == Some(Type::Num)
+}
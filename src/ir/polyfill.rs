@@ -0,0 +1,329 @@
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::{
+        expr::Expr, proc::Procedure, sprite::Sprite, statement::Statement,
+        Program,
+    },
+    parser,
+};
+use codemap::{CodeMap, Span};
+use std::{iter, mem};
+
+/// Builtins written in the language itself, as a sprite whose procedures get
+/// merged into every sprite that uses them.
+struct Polyfill {
+    name: &'static str,
+    source: &'static str,
+    /// The functions lowered to calls to procedures, with the number of
+    /// arguments they take.
+    functions: &'static [(&'static str, &'static str, usize)],
+    /// The variable the procedures store their result in.
+    result: &'static str,
+    /// Whether calls with only literal arguments are left for the optimizer
+    /// to fold.
+    foldable: bool,
+}
+
+const POLYFILLS: [Polyfill; 3] = [
+    Polyfill {
+        name: "json",
+        source: include_str!("json.scratch"),
+        functions: &[
+            ("json-get", "%json.get", 2),
+            ("json-set", "%json.set", 3),
+        ],
+        result: "%json.result",
+        foldable: false,
+    },
+    Polyfill {
+        name: "bitwise",
+        source: include_str!("bitwise.scratch"),
+        functions: &[
+            ("bit-and", "%bits.and", 2),
+            ("bit-or", "%bits.or", 2),
+            ("bit-xor", "%bits.xor", 2),
+            ("bit-shift-left", "%bits.shift-left", 2),
+            ("bit-shift-right", "%bits.shift-right", 2),
+        ],
+        result: "%bits.result",
+        foldable: true,
+    },
+    Polyfill {
+        name: "math",
+        source: include_str!("math.scratch"),
+        functions: &[
+            ("min", "%math.min", 2),
+            ("max", "%math.max", 2),
+            ("clamp", "%math.clamp", 3),
+            ("dist", "%math.dist", 4),
+            ("dir-to", "%math.dir-to", 4),
+        ],
+        result: "%math.result",
+        foldable: true,
+    },
+];
+
+/// Index of the JSON polyfill, which `json-parse-into-lists` also uses.
+const JSON: usize = 0;
+
+/// Name of the hidden local variable used as the counter when copying the
+/// results of `json-parse-into-lists`.
+const INDEX_VAR: &str = "%json.index";
+
+/// Lowers the builtins in [`POLYFILLS`] to calls to their procedures, for
+/// targets that don't have a runtime to implement them.
+///
+/// Procedures can't return values, so every call is hoisted into a procedure
+/// call before the statement that uses it, which stores the result in a
+/// hidden local variable. Loop conditions are recomputed at the end of every
+/// iteration.
+pub fn apply(program: &mut Program, code_map: &mut CodeMap) -> Result<()> {
+    let mut parsed: [Option<Ast>; POLYFILLS.len()] = Default::default();
+    for sprite in
+        iter::once(&mut program.stage).chain(program.sprites.values_mut())
+    {
+        let mut used = [false; POLYFILLS.len()];
+        for proc in sprite.procedures.values_mut().flatten() {
+            lower(proc, &mut used)?;
+        }
+        for (i, polyfill) in POLYFILLS.iter().enumerate() {
+            if !used[i] {
+                continue;
+            }
+            if parsed[i].is_none() {
+                let file = code_map.add_file(
+                    format!("<{} polyfill>", polyfill.name),
+                    polyfill.source.to_owned(),
+                );
+                // The polyfills are written by hand and never nested deeply.
+                let mut asts = parser::program(&file, usize::MAX)?;
+                parsed[i] = Some(asts.remove(0));
+            }
+            let (_, procs) = Sprite::from_ast(parsed[i].clone().unwrap())?;
+            sprite.merge(procs);
+        }
+    }
+    Ok(())
+}
+
+/// Marks the polyfills used by the procedure in `used`.
+fn lower(
+    proc: &mut Procedure,
+    used: &mut [bool; POLYFILLS.len()],
+) -> Result<()> {
+    let mut lowering = Lowering {
+        temp_count: 0,
+        used,
+        uses_index_var: false,
+    };
+    lowering.lower_stmt(&mut proc.body)?;
+    proc.variables
+        .extend((0..lowering.temp_count).map(temp_var));
+    if lowering.uses_index_var {
+        proc.variables.insert(INDEX_VAR.to_owned());
+    }
+    Ok(())
+}
+
+struct Lowering<'u> {
+    /// The largest number of temporary variables needed by any statement.
+    temp_count: usize,
+    used: &'u mut [bool; POLYFILLS.len()],
+    uses_index_var: bool,
+}
+
+impl Lowering<'_> {
+    fn lower_stmt(&mut self, stmt: &mut Statement) -> Result<()> {
+        let mut hoisted = Vec::new();
+        // Every hoisted call of the statement gets its own temporary
+        // variable, since they are all assigned before any of them is used.
+        let mut temp_count = 0;
+        match stmt {
+            Statement::ProcCall {
+                proc_name,
+                proc_span,
+                args,
+            } => {
+                for arg in &mut *args {
+                    self.hoist(arg, &mut hoisted, &mut temp_count)?;
+                }
+                if proc_name == "json-parse-into-lists" {
+                    let lowered =
+                        self.lower_parse(mem::take(args), *proc_span)?;
+                    *stmt = lowered;
+                }
+            }
+            Statement::Do(stmts) => {
+                for stmt in stmts {
+                    self.lower_stmt(stmt)?;
+                }
+            }
+            Statement::IfElse {
+                condition,
+                then,
+                else_,
+                ..
+            } => {
+                self.hoist(condition, &mut hoisted, &mut temp_count)?;
+                self.lower_stmt(then)?;
+                self.lower_stmt(else_)?;
+            }
+            Statement::Repeat { times, body, .. }
+            | Statement::For { times, body, .. } => {
+                self.hoist(times, &mut hoisted, &mut temp_count)?;
+                self.lower_stmt(body)?;
+            }
+            Statement::Forever(body, _) => self.lower_stmt(body)?,
+            Statement::Until {
+                condition, body, ..
+            }
+            | Statement::While {
+                condition, body, ..
+            } => {
+                self.hoist(condition, &mut hoisted, &mut temp_count)?;
+                self.lower_stmt(body)?;
+                if !hoisted.is_empty() {
+                    let recompute = Statement::Do(hoisted.clone());
+                    let body_stmt = mem::take(&mut **body);
+                    **body = Statement::Do(vec![body_stmt, recompute]);
+                }
+            }
+        }
+        if !hoisted.is_empty() {
+            hoisted.push(mem::take(stmt));
+            *stmt = Statement::Do(hoisted);
+        }
+        self.temp_count = self.temp_count.max(temp_count);
+        Ok(())
+    }
+
+    /// Replaces every call to a polyfilled function in `expr` with a temporary
+    /// variable, which the statements pushed to `hoisted` assign to.
+    /// `temp_count` is the number of temporary variables that the statement
+    /// already uses.
+    fn hoist(
+        &mut self,
+        expr: &mut Expr,
+        hoisted: &mut Vec<Statement>,
+        temp_count: &mut usize,
+    ) -> Result<()> {
+        let mut error = None;
+        expr.traverse_postorder_mut(&mut nest_min_max);
+        expr.traverse_postorder_mut(&mut |expr| {
+            let Expr::FuncCall(func_name, span, args) = expr else {
+                return;
+            };
+            let Some((index, &(_, proc_name, expected))) =
+                POLYFILLS.iter().enumerate().find_map(|(i, polyfill)| {
+                    polyfill
+                        .functions
+                        .iter()
+                        .find(|(name, ..)| name == func_name)
+                        .map(|function| (i, function))
+                })
+            else {
+                return;
+            };
+            let polyfill = &POLYFILLS[index];
+            if polyfill.foldable && args.iter().all(Expr::is_imm) {
+                return;
+            }
+            if args.len() != expected {
+                error.get_or_insert(Box::new(Error::FunctionWrongArgCount {
+                    span: *span,
+                    func_name: *func_name,
+                    expected,
+                    got: args.len(),
+                }));
+                return;
+            }
+            let span = *span;
+            let temp = Expr::Sym(temp_var(*temp_count).into(), span);
+            *temp_count += 1;
+            hoisted.push(Statement::ProcCall {
+                proc_name: proc_name.to_owned(),
+                proc_span: span,
+                args: mem::take(args),
+            });
+            hoisted.push(Statement::ProcCall {
+                proc_name: ":=".to_owned(),
+                proc_span: span,
+                args: vec![
+                    temp.clone(),
+                    Expr::Sym(polyfill.result.into(), span),
+                ],
+            });
+            *expr = temp;
+            self.used[index] = true;
+        });
+        if let Some(err) = error {
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Lowers `(json-parse-into-lists json keys values)` to a scan followed
+    /// by copying the scanned entries into the lists.
+    fn lower_parse(
+        &mut self,
+        args: Vec<Expr>,
+        span: Span,
+    ) -> Result<Statement> {
+        let [json, keys, values] =
+            <[_; 3]>::try_from(args).map_err(|args| {
+                Error::BuiltinProcWrongArgCount {
+                    span,
+                    proc_name: "json-parse-into-lists".to_owned(),
+                    expected: 3,
+                    got: args.len(),
+                }
+            })?;
+        self.used[JSON] = true;
+        self.uses_index_var = true;
+
+        let call = |proc_name: &str, args| Statement::ProcCall {
+            proc_name: proc_name.to_owned(),
+            proc_span: span,
+            args,
+        };
+        let sym = |name: &str| Expr::Sym(name.into(), span);
+        let item =
+            |list| Expr::FuncCall("!!", span, vec![sym(list), sym(INDEX_VAR)]);
+        Ok(Statement::Do(vec![
+            call("%json.scan", vec![json]),
+            call("delete-all", vec![keys.clone()]),
+            call("delete-all", vec![values.clone()]),
+            Statement::For {
+                counter: (INDEX_VAR.to_owned(), span),
+                times: Expr::FuncCall("length", span, vec![sym("%json.keys")]),
+                body: Box::new(Statement::Do(vec![
+                    call("append", vec![keys, item("%json.keys")]),
+                    call("append", vec![values, item("%json.values")]),
+                ])),
+            },
+        ]))
+    }
+}
+
+/// Nests calls of `min` and `max` with more than two arguments into calls
+/// with two, since their procedures take two. Calls with one argument become
+/// `to-num`.
+fn nest_min_max(expr: &mut Expr) {
+    let Expr::FuncCall(func_name @ ("min" | "max"), span, args) = expr else {
+        return;
+    };
+    let (func_name, span) = (*func_name, *span);
+    if args.len() == 1 {
+        *expr = Expr::FuncCall("to-num", span, mem::take(args));
+        return;
+    }
+    while args.len() > 2 {
+        let nested = args.drain(..2).collect();
+        args.insert(0, Expr::FuncCall(func_name, span, nested));
+    }
+}
+
+fn temp_var(index: usize) -> String {
+    format!("%polyfill.temp.{index}")
+}
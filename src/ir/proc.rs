@@ -1,38 +1,76 @@
 use crate::{
-    ast::{all_symbols, Ast},
-    diagnostic::Result,
-    ir::{expr::Expr, statement::Statement},
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::{
+        decl::{parse_list_decls, parse_variable_decls},
+        expr::Expr,
+        statement::Statement,
+        typ::{split_annotation, Type},
+    },
+    optimize::Optimizer,
     uid::Uid,
 };
 use codemap::Span;
 use ecow::EcoString;
-use std::collections::HashSet;
+use sb3_stuff::Value;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+};
 
 #[derive(Debug)]
 pub struct Procedure {
     pub params: Vec<(Expr, Span)>,
     pub body: Statement,
-    pub variables: HashSet<String>,
-    pub lists: HashSet<String>,
+    pub variables: BTreeSet<String>,
+    pub lists: BTreeSet<String>,
+    /// Types of annotated parameters and local variables.
+    pub annotations: HashMap<String, Type>,
+    pub initial_values: HashMap<String, Value>,
+    pub initial_items: HashMap<String, Vec<Value>>,
+    pub span: Span,
+    /// Set with `:priority n` at the end of the signature of a script that
+    /// runs when the flag is clicked or a broadcast is received.
+    pub priority: f64,
+    /// Set with `:refresh` in the signature of a custom procedure, so that
+    /// sb3 projects redraw the screen while it runs instead of running it
+    /// without screen refresh.
+    pub refresh: bool,
 }
 
 impl Procedure {
-    pub fn from_asts(args: Vec<Ast>) -> Result<(String, Self)> {
-        // TODO: Error handling
+    pub fn from_asts(args: Vec<Ast>, span: Span) -> Result<(String, Self)> {
         let mut args = args.into_iter();
-        let signature = args.next().unwrap();
-        let (name, params) = parse_signature(signature)?;
+        let signature =
+            args.next().ok_or(Error::InvalidProcSignature { span })?;
+        let mut annotations = HashMap::new();
+        let (name, params, priority, refresh) =
+            parse_signature(signature, &mut annotations)?;
         let mut body = Vec::new();
-        let mut variables = HashSet::new();
-        let mut lists = HashSet::new();
+        let mut variables = BTreeSet::new();
+        let mut lists = BTreeSet::new();
+        let mut initial_values = HashMap::new();
+        let mut initial_items = HashMap::new();
 
         for stmt_or_decl in args {
             match stmt_or_decl {
-                Ast::Node(box Ast::Sym("variables", ..), var_decls, ..) => {
-                    variables.extend(all_symbols(var_decls).unwrap());
+                Ast::Node(head, var_decls, ..) if head.is_sym("variables") => {
+                    variables.extend(
+                        parse_variable_decls(
+                            var_decls,
+                            &mut annotations,
+                            &mut initial_values,
+                        )?
+                        .into_iter()
+                        .map(|(name, _)| name),
+                    );
                 }
-                Ast::Node(box Ast::Sym("lists", ..), list_decls, ..) => {
-                    lists.extend(all_symbols(list_decls).unwrap());
+                Ast::Node(head, list_decls, ..) if head.is_sym("lists") => {
+                    lists.extend(
+                        parse_list_decls(list_decls, &mut initial_items)?
+                            .into_iter()
+                            .map(|(name, _)| name),
+                    );
                 }
                 _ => body.push(Statement::from_ast(stmt_or_decl)?),
             }
@@ -45,30 +83,111 @@ impl Procedure {
                 body: Statement::Do(body),
                 variables,
                 lists,
+                annotations,
+                initial_values,
+                initial_items,
+                span,
+                priority: priority.unwrap_or(0.0),
+                refresh,
             },
         ))
     }
 
-    pub fn optimize(&mut self) {
-        self.body.optimize();
+    /// The order in which scripts for the same event are started in native
+    /// code: lower priorities first, and scripts with the same priority in
+    /// the order they appear in the source. Scratch decides the order itself,
+    /// so the priority is ignored for sb3.
+    pub fn run_order(&self, other: &Self) -> Ordering {
+        self.priority
+            .total_cmp(&other.priority)
+            .then(self.span.cmp(&other.span))
+    }
+
+    pub fn optimize(&mut self, optimizer: &mut Optimizer) -> bool {
+        self.body.optimize(optimizer)
     }
 }
 
-fn parse_signature(ast: Ast) -> Result<(String, Vec<(Expr, Span)>)> {
-    // TODO: Error handling
-    let Ast::Node(box Ast::Sym(name, ..), params, ..) = ast else {
-        todo!();
+fn parse_signature(
+    ast: Ast,
+    annotations: &mut HashMap<String, Type>,
+) -> Result<(String, Vec<(Expr, Span)>, Option<f64>, bool)> {
+    let span = ast.span();
+    let Ast::Node(head, mut params, ..) = ast else {
+        return Err(Box::new(Error::InvalidProcSignature { span }));
     };
+    let Ast::Sym(name, _) = *head else {
+        return Err(Box::new(Error::InvalidProcSignature { span }));
+    };
+    // Event scripts always refresh the screen, so this is ignored for them.
+    let refresh = params
+        .extract_if(
+            ..,
+            |param| matches!(param, Ast::Sym(sym, _) if sym == ":refresh"),
+        )
+        .count()
+        != 0;
+    let priority = params
+        .iter()
+        .position(
+            |param| matches!(param, Ast::Sym(sym, _) if sym == ":priority"),
+        )
+        .map(|i| {
+            let span = params[i].span();
+            match &params.drain(i..).collect::<Vec<_>>()[..] {
+                [_, Ast::Num(priority, _)]
+                    if name == "when-flag-clicked"
+                        || name == "when-received" =>
+                {
+                    Ok(*priority)
+                }
+                _ => Err(Box::new(Error::InvalidPriority { span })),
+            }
+        })
+        .transpose()?;
+    // Event scripts take arguments instead, like the name of a broadcast.
+    let is_event = is_event(&name);
     let params = params
         .into_iter()
         .map(|param| {
             let span = param.span();
+            let (param, typ) = split_annotation(param)?;
+            match (&param, typ) {
+                (Ast::Sym(param_name, _), Some(typ)) => {
+                    annotations.insert(param_name.clone(), typ);
+                }
+                (Ast::Sym(..), None) => {}
+                _ if is_event => {}
+                _ => {
+                    return Err(Box::new(
+                        Error::InvalidParameterForCustomProcDef { span },
+                    ))
+                }
+            }
             Ok((Expr::from_ast(param)?, span))
         })
         .collect::<Result<_>>()?;
-    Ok((name, params))
+    Ok((name, params, priority, refresh))
+}
+
+/// Whether procedures with this name are scripts that run when something
+/// happens, rather than custom blocks. A sprite can have any number of each.
+pub fn is_event(name: &str) -> bool {
+    matches!(
+        name,
+        "when-flag-clicked"
+            | "when-cloned"
+            | "when-received"
+            | "when-timer-greater-than"
+            | "when-loudness-greater-than"
+    )
 }
 
 pub struct CustomProcedure {
+    /// The name shown on the block, which is only different from the name in
+    /// the source with `--minify`.
+    pub name: String,
     pub params: Vec<(EcoString, Uid)>,
+    /// Whether the procedure runs without screen refresh.
+    pub warp: bool,
 }
@@ -0,0 +1,136 @@
+use crate::{
+    diagnostic::{Error, Result},
+    ir::{expr::Expr, sprite::Sprite, statement::Statement},
+};
+use codemap::Span;
+use std::mem;
+
+/// Name of the hidden local variable that holds the new x coordinate while
+/// the y coordinate is computed from the old one.
+const X_VAR: &str = "%rotate.x";
+
+/// Names of the hidden local variables that hold the cosine and sine of an
+/// angle that isn't constant, so that it is only evaluated once.
+const COS_VAR: &str = "%rotate.cos";
+const SIN_VAR: &str = "%rotate.sin";
+
+/// Lowers `(rotate-point x y degrees)`, which rotates the point in the
+/// variables `x` and `y` clockwise around the origin like turning a sprite
+/// right, to assignments.
+pub fn lower(sprite: &mut Sprite) -> Result<()> {
+    for proc in sprite.procedures.values_mut().flatten() {
+        let mut error = None;
+        let mut needs_x_var = false;
+        let mut needs_trig_vars = false;
+        proc.body.traverse_postorder_mut(&mut |stmt| {
+            if error.is_some() {
+                return;
+            }
+            if let Statement::ProcCall {
+                proc_name,
+                proc_span,
+                args,
+            } = stmt
+            {
+                if proc_name == "rotate-point" {
+                    match lower_rotate_point(mem::take(args), *proc_span) {
+                        Ok((lowered, uses_trig_vars)) => {
+                            *stmt = lowered;
+                            needs_x_var = true;
+                            needs_trig_vars |= uses_trig_vars;
+                        }
+                        Err(err) => error = Some(err),
+                    }
+                }
+            }
+        });
+        if let Some(err) = error {
+            return Err(err);
+        }
+        if needs_x_var {
+            proc.variables.insert(X_VAR.to_owned());
+        }
+        if needs_trig_vars {
+            proc.variables.insert(COS_VAR.to_owned());
+            proc.variables.insert(SIN_VAR.to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the lowered statement and whether it stores the cosine and sine in
+/// [`COS_VAR`] and [`SIN_VAR`].
+fn lower_rotate_point(
+    args: Vec<Expr>,
+    span: Span,
+) -> Result<(Statement, bool)> {
+    let got = args.len();
+    let Ok([x, y, degrees]) = <[_; 3]>::try_from(args) else {
+        return Err(Box::new(Error::BuiltinProcWrongArgCount {
+            span,
+            proc_name: "rotate-point".to_owned(),
+            expected: 3,
+            got,
+        }));
+    };
+    if let Some(not_a_variable) = [&x, &y]
+        .into_iter()
+        .find(|arg| !matches!(arg, Expr::Sym(..)))
+    {
+        return Err(Box::new(Error::RotatePointTargetNotVariable {
+            span: not_a_variable.span().unwrap_or(span),
+        }));
+    }
+
+    let assign = |var, value| Statement::ProcCall {
+        proc_name: ":=".to_owned(),
+        proc_span: span,
+        args: vec![var, value],
+    };
+    let var = |name: &str| Expr::Sym(name.into(), span);
+    let call = |func_name, arg| Expr::FuncCall(func_name, span, vec![arg]);
+    let product = |a: &Expr, b: &Expr| {
+        Expr::MulDiv(vec![a.clone(), b.clone()], Vec::new(), span)
+    };
+
+    // A constant angle is left for the optimizer to fold.
+    let uses_trig_vars = !degrees.is_imm();
+    let (mut stmts, cos, sin) = if uses_trig_vars {
+        (
+            vec![
+                assign(var(SIN_VAR), degrees),
+                assign(var(COS_VAR), call("cos", var(SIN_VAR))),
+                assign(var(SIN_VAR), call("sin", var(SIN_VAR))),
+            ],
+            var(COS_VAR),
+            var(SIN_VAR),
+        )
+    } else {
+        (
+            Vec::new(),
+            call("cos", degrees.clone()),
+            call("sin", degrees),
+        )
+    };
+    stmts.extend([
+        assign(
+            var(X_VAR),
+            Expr::AddSub(
+                vec![product(&x, &cos), product(&y, &sin)],
+                Vec::new(),
+                span,
+            ),
+        ),
+        assign(
+            y.clone(),
+            Expr::AddSub(
+                vec![product(&y, &cos)],
+                vec![product(&x, &sin)],
+                span,
+            ),
+        ),
+        assign(x, var(X_VAR)),
+    ]);
+    Ok((Statement::Do(stmts), uses_trig_vars))
+}
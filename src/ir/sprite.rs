@@ -1,25 +1,47 @@
 use crate::{
-    ast::{all_symbols, Ast},
+    ast::Ast,
     diagnostic::{Error, Result},
-    ir::proc::Procedure,
+    ir::{
+        decl::{parse_list_decls, parse_variable_decls},
+        ffi::ExternFunction,
+        proc::{is_event, Procedure},
+        table::Table,
+        typ::Type,
+    },
+    optimize::{expr::optimize_expr, Optimizer},
 };
+use codemap::Span;
+use sb3_stuff::Value;
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap},
     path::PathBuf,
 };
 
 #[derive(Debug)]
 pub struct Sprite {
     pub costumes: HashMap<String, PathBuf>,
-    pub variables: HashSet<String>,
-    pub lists: HashSet<String>,
-    pub procedures: HashMap<String, Vec<Procedure>>,
+    // These are ordered so that the output is the same every time.
+    pub variables: BTreeSet<String>,
+    pub lists: BTreeSet<String>,
+    pub procedures: BTreeMap<String, Vec<Procedure>>,
+    /// Types of annotated variables.
+    pub annotations: HashMap<String, Type>,
+    pub externs: HashMap<String, ExternFunction>,
+    pub tables: BTreeMap<String, Table>,
+    pub initial_values: HashMap<String, Value>,
+    pub initial_items: HashMap<String, Vec<Value>>,
+    /// Where each variable was declared.
+    pub variable_spans: HashMap<String, Span>,
+    /// Where each list was declared.
+    pub list_spans: HashMap<String, Span>,
+    /// Where the path of each costume was given.
+    pub costume_spans: HashMap<String, Span>,
 }
 
 impl Sprite {
     pub fn from_ast(ast: Ast) -> Result<(String, Self)> {
         let (mut tail, span) = match ast {
-            Ast::Node(box Ast::Sym("sprite", ..), tail, span) => {
+            Ast::Node(head, tail, span) if head.is_sym("sprite") => {
                 Ok((tail.into_iter(), span))
             }
             _ => Err(Error::InvalidTopLevelItem { span: ast.span() }),
@@ -38,31 +60,78 @@ impl Sprite {
         }?;
 
         let mut costumes = HashMap::new();
-        let mut variables = HashSet::new();
-        let mut lists = HashSet::new();
-        let mut procedures = HashMap::new();
+        let mut variables = BTreeSet::new();
+        let mut lists = BTreeSet::new();
+        let mut procedures = BTreeMap::new();
+        let mut annotations = HashMap::new();
+        let mut externs = HashMap::new();
+        let mut tables = BTreeMap::new();
+        let mut initial_values = HashMap::new();
+        let mut initial_items = HashMap::new();
+        let mut variable_spans = HashMap::new();
+        let mut list_spans = HashMap::new();
+        let mut costume_spans = HashMap::new();
 
         for decl in tail {
             let span = decl.span();
             match decl {
-                Ast::Node(box Ast::Sym(sym, ..), tail, ..) => match &*sym {
-                    // TODO: Error handling
-                    "variables" => variables.extend(all_symbols(tail).unwrap()),
-                    "lists" => lists.extend(all_symbols(tail).unwrap()),
-                    "costumes" => parse_costume_decl(&mut costumes, tail),
-                    "proc" => {
-                        let (name, proc) = Procedure::from_asts(tail)?;
-                        procedures
-                            .entry(name)
-                            .or_insert_with(|| Vec::with_capacity(1))
-                            .push(proc);
-                    }
-                    _ => {
+                Ast::Node(head, tail, ..) => {
+                    let Ast::Sym(sym, _) = *head else {
                         return Err(Box::new(Error::InvalidItemInSprite {
                             span,
-                        }))
+                        }));
+                    };
+                    match &*sym {
+                        "variables" => {
+                            for (name, span) in parse_variable_decls(
+                                tail,
+                                &mut annotations,
+                                &mut initial_values,
+                            )? {
+                                variable_spans
+                                    .entry(name.clone())
+                                    .or_insert(span);
+                                variables.insert(name);
+                            }
+                        }
+                        "lists" => {
+                            for (name, span) in
+                                parse_list_decls(tail, &mut initial_items)?
+                            {
+                                list_spans.entry(name.clone()).or_insert(span);
+                                lists.insert(name);
+                            }
+                        }
+                        "costumes" => parse_costume_decl(
+                            &mut costumes,
+                            &mut costume_spans,
+                            tail,
+                            span,
+                        )?,
+                        "proc" => {
+                            let (name, proc) =
+                                Procedure::from_asts(tail, span)?;
+                            procedures
+                                .entry(name)
+                                .or_insert_with(|| Vec::with_capacity(1))
+                                .push(proc);
+                        }
+                        "extern" => {
+                            let (name, function) =
+                                ExternFunction::from_asts(tail, span)?;
+                            externs.insert(name, function);
+                        }
+                        "table" => {
+                            let (name, table) = Table::from_asts(tail, span)?;
+                            tables.insert(name, table);
+                        }
+                        _ => {
+                            return Err(Box::new(Error::InvalidItemInSprite {
+                                span,
+                            }))
+                        }
                     }
-                },
+                }
                 _ => return Err(Box::new(Error::InvalidItemInSprite { span })),
             }
         }
@@ -74,20 +143,66 @@ impl Sprite {
                 variables,
                 lists,
                 procedures,
+                annotations,
+                externs,
+                tables,
+                initial_values,
+                initial_items,
+                variable_spans,
+                list_spans,
+                costume_spans,
             },
         ))
     }
 
+    /// Custom procedures can only be defined once, unlike event scripts,
+    /// which run together. Sprites with the same name are merged first, so
+    /// definitions in different files are caught too.
+    pub(super) fn check_duplicate_procs(&self) -> Result<()> {
+        for (name, procs) in &self.procedures {
+            if let [first, second, ..] = &procs[..] {
+                if !is_event(name) {
+                    return Err(Box::new(Error::DuplicateProc {
+                        span: second.span,
+                        previous: first.span,
+                        proc_name: name.clone(),
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn merge(&mut self, other: Self) {
         let Self {
             costumes,
             variables,
             lists,
             procedures,
+            annotations,
+            externs,
+            tables,
+            initial_values,
+            initial_items,
+            variable_spans,
+            list_spans,
+            costume_spans,
         } = other;
         self.costumes.extend(costumes);
+        self.costume_spans.extend(costume_spans);
         self.variables.extend(variables);
         self.lists.extend(lists);
+        self.annotations.extend(annotations);
+        self.externs.extend(externs);
+        self.tables.extend(tables);
+        self.initial_values.extend(initial_values);
+        self.initial_items.extend(initial_items);
+        for (name, span) in variable_spans {
+            self.variable_spans.entry(name).or_insert(span);
+        }
+        for (name, span) in list_spans {
+            self.list_spans.entry(name).or_insert(span);
+        }
         for (name, procs) in procedures {
             match self.procedures.entry(name) {
                 Entry::Occupied(mut occupied) => {
@@ -100,24 +215,34 @@ impl Sprite {
         }
     }
 
-    pub fn optimize(&mut self) {
+    pub fn optimize(&mut self, optimizer: &mut Optimizer) -> bool {
+        let mut dirty = false;
         for proc in self.procedures.values_mut().flatten() {
-            proc.optimize();
+            dirty |= proc.optimize(optimizer);
+        }
+        for item in self.tables.values_mut().flat_map(|table| &mut table.items)
+        {
+            dirty |= optimize_expr(item, optimizer);
         }
+        dirty
     }
 }
 
-fn parse_costume_decl(costumes: &mut HashMap<String, PathBuf>, args: Vec<Ast>) {
-    // TODO: Error handling
+fn parse_costume_decl(
+    costumes: &mut HashMap<String, PathBuf>,
+    costume_spans: &mut HashMap<String, Span>,
+    args: Vec<Ast>,
+    span: Span,
+) -> Result<()> {
     let mut args = args.into_iter();
     while let Some(name) = args.next() {
-        let Ast::String(name, ..) = name else {
-            todo!();
-        };
-        let path = args.next().unwrap();
-        let Ast::String(path, ..) = path else {
-            todo!();
+        let (Ast::String(name, ..), Some(Ast::String(path, path_span))) =
+            (name, args.next())
+        else {
+            return Err(Box::new(Error::InvalidCostumeDeclaration { span }));
         };
+        costume_spans.insert(name.clone(), path_span);
         costumes.insert(name, path.into());
     }
+    Ok(())
 }
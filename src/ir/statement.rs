@@ -1,10 +1,12 @@
 use crate::{
-    ast::Ast, diagnostic::Result, ir::expr::Expr,
-    optimize::statement::optimize_stmt,
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::expr::Expr,
+    optimize::{statement::optimize_stmt, Optimizer},
 };
 use codemap::Span;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     ProcCall {
         proc_name: String,
@@ -21,15 +23,18 @@ pub enum Statement {
     Repeat {
         times: Expr,
         body: Box<Self>,
+        span: Span,
     },
-    Forever(Box<Self>),
+    Forever(Box<Self>, Span),
     Until {
         condition: Expr,
         body: Box<Self>,
+        span: Span,
     },
     While {
         condition: Expr,
         body: Box<Self>,
+        span: Span,
     },
     For {
         counter: (String, Span),
@@ -46,19 +51,30 @@ impl Default for Statement {
 
 impl Statement {
     pub fn from_ast(ast: Ast) -> Result<Self> {
-        // TODO: Error handling
+        if let Ast::Quote(quoted, _) = ast {
+            return Self::from_ast(*quoted);
+        }
         let full_span = ast.span();
-        let Ast::Node(box Ast::Sym(sym, sym_span), tail, ..) = ast else {
-            todo!();
+        let Ast::Node(head, tail, ..) = ast else {
+            return Err(Box::new(Error::InvalidStatement { span: full_span }));
         };
+        let Ast::Sym(sym, sym_span) = *head else {
+            return Err(Box::new(Error::InvalidStatement { span: full_span }));
+        };
+        let got = tail.len();
         let mut tail = tail.into_iter();
         Ok(match &*sym {
             "do" => Self::Do(tail.map(Self::from_ast).collect::<Result<_>>()?),
             "if" => {
-                let condition = tail.next().unwrap();
-                let then = tail.next().unwrap();
-                let else_ = tail.next().unwrap();
-                assert!(tail.next().is_none());
+                let [condition, then, else_] = <[Ast; 3]>::try_from(
+                    tail.collect::<Vec<_>>(),
+                )
+                .map_err(|args| Error::BuiltinProcWrongArgCount {
+                    span: full_span,
+                    proc_name: sym.clone(),
+                    expected: 3,
+                    got: args.len(),
+                })?;
                 Self::IfElse {
                     condition: Expr::from_ast(condition)?,
                     then: Box::new(Self::from_ast(then)?),
@@ -67,42 +83,61 @@ impl Statement {
                 }
             }
             "repeat" => {
-                let times = tail.next().unwrap();
+                let Some(times) = tail.next() else {
+                    return Err(too_few_args(sym, 1, got, full_span));
+                };
                 Self::Repeat {
                     times: Expr::from_ast(times)?,
                     body: Box::new(Self::Do(
                         tail.map(Self::from_ast).collect::<Result<_>>()?,
                     )),
+                    span: full_span,
                 }
             }
-            "forever" => Self::Forever(Box::new(Self::Do(
-                tail.map(Self::from_ast).collect::<Result<_>>()?,
-            ))),
+            "forever" => Self::Forever(
+                Box::new(Self::Do(
+                    tail.map(Self::from_ast).collect::<Result<_>>()?,
+                )),
+                full_span,
+            ),
             "until" => {
-                let condition = tail.next().unwrap();
+                let Some(condition) = tail.next() else {
+                    return Err(too_few_args(sym, 1, got, full_span));
+                };
                 Self::Until {
                     condition: Expr::from_ast(condition)?,
                     body: Box::new(Self::Do(
                         tail.map(Self::from_ast).collect::<Result<_>>()?,
                     )),
+                    span: full_span,
                 }
             }
             "while" => {
-                let condition = tail.next().unwrap();
+                let Some(condition) = tail.next() else {
+                    return Err(too_few_args(sym, 1, got, full_span));
+                };
                 Self::While {
                     condition: Expr::from_ast(condition)?,
                     body: Box::new(Self::Do(
                         tail.map(Self::from_ast).collect::<Result<_>>()?,
                     )),
+                    span: full_span,
                 }
             }
             "for" => {
-                let counter = tail.next().unwrap();
+                let (Some(counter), Some(times)) = (tail.next(), tail.next())
+                else {
+                    return Err(too_few_args(sym, 2, got, full_span));
+                };
                 let counter = match counter {
                     Ast::Sym(sym, span) => (sym, span),
-                    _ => todo!(),
+                    _ => {
+                        return Err(Box::new(Error::InvalidDeclaration {
+                            span: counter.span(),
+                            kind: "loop counter",
+                        }))
+                    }
                 };
-                let times = tail.next().unwrap();
                 Self::For {
                     counter,
                     times: Expr::from_ast(times)?,
@@ -112,7 +147,9 @@ impl Statement {
                 }
             }
             "when" => {
-                let condition = tail.next().unwrap();
+                let Some(condition) = tail.next() else {
+                    return Err(too_few_args(sym, 1, got, full_span));
+                };
                 Self::IfElse {
                     condition: Expr::from_ast(condition)?,
                     then: Box::new(Self::Do(
@@ -123,7 +160,9 @@ impl Statement {
                 }
             }
             "unless" => {
-                let condition = tail.next().unwrap();
+                let Some(condition) = tail.next() else {
+                    return Err(too_few_args(sym, 1, got, full_span));
+                };
                 Self::IfElse {
                     condition: Expr::from_ast(condition)?,
                     then: Box::new(Self::Do(Vec::new())),
@@ -163,8 +202,8 @@ impl Statement {
         })
     }
 
-    pub fn optimize(&mut self) {
-        optimize_stmt(self);
+    pub fn optimize(&mut self, optimizer: &mut Optimizer) -> bool {
+        optimize_stmt(self, optimizer)
     }
 
     pub fn traverse_postorder_mut(&mut self, f: &mut impl FnMut(&mut Self)) {
@@ -188,10 +227,10 @@ impl Statement {
                 then.traverse_postorder_mut(f);
                 else_.traverse_postorder_mut(f);
             }
-            Self::Repeat { times: _, body }
-            | Self::Forever(body)
-            | Self::Until { condition: _, body }
-            | Self::While { condition: _, body }
+            Self::Repeat { body, .. }
+            | Self::Forever(body, _)
+            | Self::Until { body, .. }
+            | Self::While { body, .. }
             | Self::For {
                 counter: _,
                 times: _,
@@ -201,7 +240,54 @@ impl Statement {
         f(self);
     }
 
+    /// Whether `f` is true for this statement or any statement in it.
+    pub fn any(&self, f: &mut impl FnMut(&Self) -> bool) -> bool {
+        f(self)
+            || match self {
+                Self::ProcCall { .. } => false,
+                Self::Do(stmts) => stmts.iter().any(|stmt| stmt.any(f)),
+                Self::IfElse { then, else_, .. } => then.any(f) || else_.any(f),
+                Self::Repeat { body, .. }
+                | Self::Forever(body, _)
+                | Self::Until { body, .. }
+                | Self::While { body, .. }
+                | Self::For { body, .. } => body.any(f),
+            }
+    }
+
     pub fn is_nop(&self) -> bool {
         matches!(self, Self::Do(stmts) if stmts.is_empty())
     }
+
+    /// Where the statement starts. For a block, that is its first statement.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::ProcCall { proc_span, .. } => Some(*proc_span),
+            Self::Do(stmts) => stmts.iter().find_map(Self::span),
+            Self::IfElse { span, .. }
+            | Self::Repeat { span, .. }
+            | Self::Forever(_, span)
+            | Self::Until { span, .. }
+            | Self::While { span, .. }
+            | Self::For {
+                counter: (_, span), ..
+            } => Some(*span),
+        }
+    }
+}
+
+/// The error for a control flow statement that needs at least `expected`
+/// arguments.
+fn too_few_args(
+    name: String,
+    expected: usize,
+    got: usize,
+    span: Span,
+) -> Box<Error> {
+    Box::new(Error::BuiltinProcWrongArgCount {
+        span,
+        proc_name: name,
+        expected,
+        got,
+    })
 }
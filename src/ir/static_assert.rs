@@ -0,0 +1,75 @@
+//! `(static-assert condition "message")`, which fails compilation unless its
+//! condition is true once it has been constant folded, so that macro
+//! libraries can check the constants they are configured with.
+
+use crate::{
+    diagnostic::{Error, Result},
+    ir::{expr::Expr, statement::Statement, Program},
+};
+use codemap::Span;
+use std::iter;
+
+impl Program {
+    /// Checks every `static-assert` and removes them, since they do nothing
+    /// while the program runs. This runs after optimization so that their
+    /// arguments have been folded into constants.
+    pub fn check_static_asserts(&mut self) -> Result<()> {
+        for sprite in
+            iter::once(&mut self.stage).chain(self.sprites.values_mut())
+        {
+            for proc in sprite.procedures.values_mut().flatten() {
+                let mut result = Ok(());
+                proc.body.traverse_postorder_mut(&mut |stmt| {
+                    if let Statement::ProcCall {
+                        proc_name,
+                        proc_span,
+                        args,
+                    } = stmt
+                    {
+                        if proc_name == "static-assert" {
+                            if result.is_ok() {
+                                result = check(args, *proc_span);
+                            }
+                            *stmt = Statement::default();
+                        }
+                    }
+                });
+                result?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check(args: &[Expr], span: Span) -> Result<()> {
+    let (condition, message) = match args {
+        [condition] => (condition, None),
+        [condition, message] => (condition, Some(message)),
+        _ => {
+            return Err(Box::new(Error::BuiltinProcWrongArgCount {
+                span,
+                proc_name: "static-assert".to_owned(),
+                expected: 2,
+                got: args.len(),
+            }))
+        }
+    };
+    let not_constant = |expr: &Expr| {
+        Box::new(Error::StaticAssertNotConstant {
+            span: expr.span().unwrap_or(span),
+        })
+    };
+    let message = match message {
+        Some(Expr::Imm(message)) => Some(message.to_cow_str().into_owned()),
+        Some(message) => return Err(not_constant(message)),
+        None => None,
+    };
+    let Expr::Imm(condition) = condition else {
+        return Err(not_constant(condition));
+    };
+    if condition.to_bool() {
+        Ok(())
+    } else {
+        Err(Box::new(Error::StaticAssertFailed { span, message }))
+    }
+}
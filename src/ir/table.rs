@@ -0,0 +1,165 @@
+//! Tables of numbers that are computed while compiling, defined in a sprite
+//! with `(table! name expr start end)` and read with `(table-get name i)`,
+//! which is the item that was computed for `i`. Native code keeps them in
+//! read-only data, and Scratch projects get a list for each of them.
+
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::{dispatch::exprs_mut, expr::Expr, Program},
+};
+use codemap::Span;
+use sb3_stuff::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    iter,
+};
+
+#[derive(Debug)]
+pub struct Table {
+    /// The `i` of the first item. The following items are for every number
+    /// after it.
+    pub start: f64,
+    /// These are only numbers once the program has been constant folded.
+    pub items: Vec<Expr>,
+    pub span: Span,
+}
+
+impl Table {
+    /// Parses the tail of `(table name start items...)`, which is what
+    /// `table!` expands to.
+    pub fn from_asts(args: Vec<Ast>, span: Span) -> Result<(String, Self)> {
+        let mut args = args.into_iter();
+        let (Some(Ast::Sym(name, _)), Some(Ast::Num(start, _))) =
+            (args.next(), args.next())
+        else {
+            return Err(Box::new(Error::InvalidTableDefinition { span }));
+        };
+        let items = args.map(Expr::from_ast).collect::<Result<_>>()?;
+        Ok((name, Self { start, items, span }))
+    }
+
+    /// The items, which have been checked by [`Program::check_tables`].
+    pub fn values(&self) -> impl Iterator<Item = f64> + '_ {
+        self.items.iter().map(|item| match item {
+            Expr::Imm(value) => value.to_num(),
+            _ => unreachable!("tables are checked to be constant"),
+        })
+    }
+}
+
+impl Program {
+    /// Checks that every item of every table has been folded into a
+    /// constant. This runs after optimization, like `check_static_asserts`.
+    pub fn check_tables(&self) -> Result<()> {
+        for sprite in iter::once(&self.stage).chain(self.sprites.values()) {
+            for table in sprite.tables.values() {
+                if let Some(item) =
+                    table.items.iter().find(|item| !item.is_imm())
+                {
+                    return Err(Box::new(Error::TableNotConstant {
+                        span: item.span().unwrap_or(table.span),
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns every table into a list for Scratch, named `%table.name` so that
+    /// it doesn't clash with the lists of the program, and every `table-get`
+    /// into getting an item of it. Tables of the stage are global, like its
+    /// lists.
+    pub fn tables_to_lists(&mut self) -> Result<()> {
+        let global_starts = starts(&self.stage.tables);
+        for (sprite, is_stage) in iter::once((&mut self.stage, true))
+            .chain(self.sprites.values_mut().map(|sprite| (sprite, false)))
+        {
+            let mut starts = starts(&sprite.tables);
+            if !is_stage {
+                for (name, start) in &global_starts {
+                    starts.entry(name.clone()).or_insert(*start);
+                }
+            }
+            for (name, table) in &sprite.tables {
+                let list_name = list_name(name);
+                sprite.list_spans.insert(list_name.clone(), table.span);
+                sprite.initial_items.insert(
+                    list_name.clone(),
+                    table.values().map(Value::Num).collect(),
+                );
+                sprite.lists.insert(list_name);
+            }
+            for proc in sprite.procedures.values_mut().flatten() {
+                let mut error = None;
+                proc.body.traverse_postorder_mut(&mut |stmt| {
+                    for expr in exprs_mut(stmt) {
+                        expr.traverse_postorder_mut(&mut |expr| {
+                            if error.is_none() {
+                                if let Err(err) = lower_table_get(expr, &starts)
+                                {
+                                    error = Some(err);
+                                }
+                            }
+                        });
+                    }
+                });
+                if let Some(err) = error {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn starts(tables: &BTreeMap<String, Table>) -> HashMap<String, f64> {
+    tables
+        .iter()
+        .map(|(name, table)| (name.clone(), table.start))
+        .collect()
+}
+
+fn list_name(table_name: &str) -> String {
+    format!("%table.{table_name}")
+}
+
+/// Lowers `(table-get name i)` to `(to-num (!! %table.name n))`, where `n` is
+/// the 1-based index of the item for `i`. Scratch rounds the index down and
+/// gives an empty string for indices that are out of range, which `to-num`
+/// turns into 0 like native code gives.
+fn lower_table_get(
+    expr: &mut Expr,
+    starts: &HashMap<String, f64>,
+) -> Result<()> {
+    let Expr::FuncCall("table-get", span, args) = expr else {
+        return Ok(());
+    };
+    let span = *span;
+    let [Expr::Sym(table_name, table_span), index] = &args[..] else {
+        return Err(Box::new(Error::FunctionWrongArgCount {
+            span,
+            func_name: "table-get",
+            expected: 2,
+            got: args.len(),
+        }));
+    };
+    let Some(start) = starts.get(&**table_name) else {
+        return Err(Box::new(Error::UnknownTable {
+            span: *table_span,
+            table_name: table_name.to_string(),
+        }));
+    };
+    let list = Expr::Sym(list_name(table_name).into(), *table_span);
+    let index = Expr::AddSub(
+        vec![index.clone(), Expr::Imm(Value::Num(1.0 - start))],
+        Vec::new(),
+        span,
+    );
+    *expr = Expr::FuncCall(
+        "to-num",
+        span,
+        vec![Expr::FuncCall("!!", span, vec![list, index])],
+    );
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+};
+
+/// The type given to a parameter or variable by an annotation like
+/// `(x : num)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Num,
+    Bool,
+    Str,
+}
+
+impl Type {
+    pub const fn to_str(self) -> &'static str {
+        match self {
+            Self::Num => "number",
+            Self::Bool => "boolean",
+            Self::Str => "string",
+        }
+    }
+
+    /// The builtin that converts any value to this type.
+    pub const fn conversion(self) -> &'static str {
+        match self {
+            Self::Num => "to-num",
+            Self::Bool => "to-bool",
+            Self::Str => "++",
+        }
+    }
+}
+
+/// Splits `(name : type)` into `name` and its type. Anything else is returned
+/// unchanged.
+pub fn split_annotation(ast: Ast) -> Result<(Ast, Option<Type>)> {
+    let Ast::Node(head, tail, _) = &ast else {
+        return Ok((ast, None));
+    };
+    if !matches!(**head, Ast::Sym(..))
+        || !matches!(&tail[..], [Ast::Sym(colon, _), _] if colon == ":")
+    {
+        return Ok((ast, None));
+    }
+    let Ast::Node(name, tail, span) = ast else {
+        unreachable!();
+    };
+    let [_, typ] = <[Ast; 2]>::try_from(tail).unwrap();
+    let typ = match typ {
+        Ast::Sym(..) => parse_type(typ)?,
+        _ => return Err(Box::new(Error::InvalidTypeAnnotation { span })),
+    };
+    Ok((*name, Some(typ)))
+}
+
+/// Parses the name of a type, like the `num` in `(x : num)`.
+pub fn parse_type(ast: Ast) -> Result<Type> {
+    match ast {
+        Ast::Sym(typ, span) => match &*typ {
+            "num" => Ok(Type::Num),
+            "bool" => Ok(Type::Bool),
+            "str" => Ok(Type::Str),
+            _ => Err(Box::new(Error::UnknownType {
+                span,
+                type_name: typ,
+            })),
+        },
+        _ => Err(Box::new(Error::InvalidTypeAnnotation { span: ast.span() })),
+    }
+}
@@ -0,0 +1,188 @@
+use crate::ir::{
+    expr::Expr, proc::Procedure, sprite::Sprite, statement::Statement, Program,
+};
+use codemap::{CodeMap, Span};
+use std::{fmt::Write, iter};
+
+/// A broken invariant of the IR, found by `verify`.
+#[derive(PartialEq, Eq)]
+pub struct Violation {
+    span: Span,
+    message: String,
+}
+
+impl Violation {
+    pub fn describe(&self, code_map: &CodeMap) -> String {
+        let loc = code_map.look_up_span(self.span);
+        format!(
+            "{}:{}:{}: {}",
+            loc.file.name(),
+            loc.begin.line + 1,
+            loc.begin.column + 1,
+            self.message,
+        )
+    }
+}
+
+/// Checks the invariants that the optimizer has to preserve: parameters are
+/// unique and every symbol refers to a declared variable, list, table or
+/// parameter. Symbols that don't, like builtins such as `timer` and misspelled
+/// names that are reported by the backends, are only a problem if the
+/// optimizer introduced them, so callers compare the result to the violations
+/// found before optimizing. Sums and products without terms are valid, they
+/// are 0 and 1.
+pub fn verify(program: &Program) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for sprite in iter::once(&program.stage).chain(program.sprites.values()) {
+        for (proc_name, procs) in &sprite.procedures {
+            for proc in procs {
+                Verifier {
+                    stage: &program.stage,
+                    sprite,
+                    proc,
+                    violations: &mut violations,
+                }
+                .verify_proc(proc_name);
+            }
+        }
+    }
+    violations
+}
+
+/// Panics with the violations in `after` that aren't in `before`.
+pub fn assert_no_new_violations(
+    before: &[Violation],
+    after: &[Violation],
+    pass: &str,
+    code_map: &CodeMap,
+) {
+    let mut report = String::new();
+    for violation in after.iter().filter(|v| !before.contains(v)) {
+        writeln!(report, "  {}", violation.describe(code_map)).unwrap();
+    }
+    assert!(
+        report.is_empty(),
+        "IR verification failed after the `{pass}` pass:\n{report}"
+    );
+}
+
+struct Verifier<'a> {
+    stage: &'a Sprite,
+    sprite: &'a Sprite,
+    proc: &'a Procedure,
+    violations: &'a mut Vec<Violation>,
+}
+
+impl Verifier<'_> {
+    fn report(&mut self, span: Span, message: String) {
+        self.violations.push(Violation { span, message });
+    }
+
+    fn verify_proc(&mut self, proc_name: &str) {
+        let proc = self.proc;
+        for (i, (param, span)) in proc.params.iter().enumerate() {
+            if let Expr::Sym(name, _) = param {
+                if proc.params[..i].iter().any(
+                    |(prev, _)| matches!(prev, Expr::Sym(p, _) if p == name),
+                ) {
+                    self.report(
+                        *span,
+                        format!(
+                            "parameter `{name}` of `{proc_name}` is declared \
+                            more than once"
+                        ),
+                    );
+                }
+            }
+        }
+        self.verify_stmt(&proc.body);
+    }
+
+    fn is_declared(&self, name: &str) -> bool {
+        self.proc
+            .params
+            .iter()
+            .any(|(param, _)| matches!(param, Expr::Sym(p, _) if p == name))
+            || [&self.proc.variables, &self.proc.lists]
+                .into_iter()
+                .chain([&self.sprite.variables, &self.sprite.lists])
+                .chain([&self.stage.variables, &self.stage.lists])
+                .any(|names| names.contains(name))
+            || self.sprite.tables.contains_key(name)
+            || self.stage.tables.contains_key(name)
+    }
+
+    fn verify_stmt(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::ProcCall { args, .. } => {
+                for arg in args {
+                    self.verify_expr(arg);
+                }
+            }
+            Statement::Do(stmts) => {
+                for stmt in stmts {
+                    self.verify_stmt(stmt);
+                }
+            }
+            Statement::IfElse {
+                condition,
+                then,
+                else_,
+                ..
+            } => {
+                self.verify_expr(condition);
+                self.verify_stmt(then);
+                self.verify_stmt(else_);
+            }
+            Statement::For {
+                counter: (name, span),
+                times,
+                body,
+            } => {
+                if !self.is_declared(name) {
+                    self.report(*span, format!("`{name}` is not declared"));
+                }
+                self.verify_expr(times);
+                self.verify_stmt(body);
+            }
+            Statement::Repeat {
+                times: expr, body, ..
+            }
+            | Statement::Until {
+                condition: expr,
+                body,
+                ..
+            }
+            | Statement::While {
+                condition: expr,
+                body,
+                ..
+            } => {
+                self.verify_expr(expr);
+                self.verify_stmt(body);
+            }
+            Statement::Forever(body, _) => self.verify_stmt(body),
+        }
+    }
+
+    fn verify_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Imm(_) => {}
+            Expr::Sym(name, span) => {
+                if !self.is_declared(name) {
+                    self.report(*span, format!("`{name}` is not declared"));
+                }
+            }
+            Expr::FuncCall(_, _, args) => {
+                for arg in args {
+                    self.verify_expr(arg);
+                }
+            }
+            Expr::AddSub(a, b, _) | Expr::MulDiv(a, b, _) => {
+                for term in a.iter().chain(b) {
+                    self.verify_expr(term);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Video sensing in native code. Native executables have no webcam, so
+//! rather than failing to compile projects that use it, its reporters become
+//! a fixed number and its statements do nothing.
+
+use crate::{
+    diagnostic::Warning,
+    ir::{dispatch::exprs_mut, expr::Expr, statement::Statement, Program},
+};
+use sb3_stuff::Value;
+use std::iter;
+
+const REPORTERS: &[&str] = &["video-motion", "video-direction"];
+
+const STATEMENTS: &[&str] = &["set-video", "set-video-transparency"];
+
+impl Program {
+    /// Replaces video sensing reporters with `value` and removes video
+    /// sensing statements, with a warning for each.
+    pub fn stub_video_sensing(&mut self, value: f64) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for sprite in
+            iter::once(&mut self.stage).chain(self.sprites.values_mut())
+        {
+            for proc in sprite.procedures.values_mut().flatten() {
+                proc.body.traverse_postorder_mut(&mut |stmt| {
+                    if let Statement::ProcCall {
+                        proc_name,
+                        proc_span,
+                        ..
+                    } = stmt
+                    {
+                        if STATEMENTS.contains(&&**proc_name) {
+                            warnings.push(Warning::VideoSensingStubbed {
+                                span: *proc_span,
+                                name: proc_name.clone(),
+                                value: None,
+                            });
+                            *stmt = Statement::default();
+                            return;
+                        }
+                    }
+                    for expr in exprs_mut(stmt) {
+                        expr.traverse_postorder_mut(&mut |expr| {
+                            if let Expr::Sym(sym, span) = expr {
+                                if REPORTERS.contains(&&**sym) {
+                                    warnings.push(
+                                        Warning::VideoSensingStubbed {
+                                            span: *span,
+                                            name: sym.to_string(),
+                                            value: Some(value),
+                                        },
+                                    );
+                                    *expr = Expr::Imm(Value::Num(value));
+                                }
+                            }
+                        });
+                    }
+                });
+            }
+        }
+        warnings.sort_by_key(Warning::span);
+        warnings
+    }
+}
@@ -0,0 +1,302 @@
+//! Splits source code into tokens for the parser. Symbols, numbers, booleans
+//! and colors are made of the same characters, so the longest run of them is
+//! read first and then classified as a whole. That way `1+` is neither a
+//! number followed by a symbol nor a symbol.
+
+use crate::diagnostic::{Error, Result};
+use codemap::{File, Span};
+
+/// What is expected where any expression could go, for syntax errors.
+pub const EXPRESSION: &str = "an expression";
+pub const CLOSE: &str = "`)`";
+const QUOTE: &str = "`\"`";
+const ESCAPE_SEQUENCE: &str = "an escape sequence";
+const DIGIT: &str = "a digit";
+
+#[derive(Debug, PartialEq)]
+pub enum TokenKind<'a> {
+    Open,
+    Close,
+    Quote,
+    Unquote,
+    /// The name and opening parenthesis of reader syntax like `#rgb(`.
+    ReaderMacro(&'a str),
+    Num(f64),
+    Bool(bool),
+    /// A string literal with its escape sequences replaced.
+    String(String),
+    Sym(&'a str),
+    /// The hex digits of a color like `#ff8000`. The number of digits is
+    /// checked later so that it gets a better error than a syntax error.
+    Color(&'a str),
+    /// A character that can't start a token, or a run of symbol characters
+    /// that starts with a digit but isn't a number. It is up to the parser to
+    /// say what was expected instead.
+    Invalid,
+    Eof,
+}
+
+#[derive(Debug)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    pub span: Span,
+}
+
+/// Reads one token at a time, so the parser never holds more than the token
+/// it is looking at.
+pub struct Lexer<'a> {
+    file: &'a File,
+    source: &'a str,
+    offset: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(file: &'a File) -> Self {
+        Self {
+            file,
+            source: file.source(),
+            offset: 0,
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Token<'a>> {
+        self.skip_whitespace();
+        let start = self.offset;
+        let kind = match self.source[start..].chars().next() {
+            None => TokenKind::Eof,
+            Some('(') => self.punctuation(TokenKind::Open),
+            Some(')') => self.punctuation(TokenKind::Close),
+            Some('\'') => self.punctuation(TokenKind::Quote),
+            Some(',') => self.punctuation(TokenKind::Unquote),
+            Some('"') => {
+                self.offset += 1;
+                TokenKind::String(self.string()?)
+            }
+            Some(c) if is_sym_char(c) => self.atom()?,
+            Some(c) => {
+                self.offset += c.len_utf8();
+                TokenKind::Invalid
+            }
+        };
+        Ok(Token {
+            kind,
+            span: self.span(start, self.offset),
+        })
+    }
+
+    fn punctuation(&mut self, kind: TokenKind<'a>) -> TokenKind<'a> {
+        self.offset += 1;
+        kind
+    }
+
+    /// Skips whitespace and comments, which go until the end of the line.
+    fn skip_whitespace(&mut self) {
+        let bytes = self.source.as_bytes();
+        while let Some(&byte) = bytes.get(self.offset) {
+            match byte {
+                b' ' | b'\t' | b'\r' | b'\n' => self.offset += 1,
+                b';' => {
+                    self.offset = bytes[self.offset..]
+                        .iter()
+                        .position(|&byte| byte == b'\n')
+                        .map_or(bytes.len(), |len| self.offset + len);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Reads a run of symbol characters and works out what it is.
+    fn atom(&mut self) -> Result<TokenKind<'a>> {
+        let start = self.offset;
+        self.offset = self.source[start..]
+            .find(|c| !is_sym_char(c))
+            .map_or(self.source.len(), |len| start + len);
+        let text = &self.source[start..self.offset];
+        match number(text) {
+            Ok(Some(n)) => return Ok(TokenKind::Num(n)),
+            Ok(None) => {}
+            Err(len) => return Err(self.error(start + len, &[DIGIT])),
+        }
+        Ok(match text {
+            "true" => TokenKind::Bool(true),
+            "false" => TokenKind::Bool(false),
+            _ if text.starts_with('#') => {
+                let rest = &text[1..];
+                if rest.starts_with(is_sym_first_char)
+                    && self.source[self.offset..].starts_with('(')
+                {
+                    self.offset += 1;
+                    TokenKind::ReaderMacro(text)
+                } else if !rest.is_empty()
+                    && rest.bytes().all(|byte| byte.is_ascii_hexdigit())
+                {
+                    TokenKind::Color(rest)
+                } else {
+                    TokenKind::Sym(text)
+                }
+            }
+            _ if text.starts_with(is_sym_first_char) => TokenKind::Sym(text),
+            _ => TokenKind::Invalid,
+        })
+    }
+
+    /// Reads the rest of a string literal after its opening quote. Strings
+    /// can't span lines, so a missing closing quote is reported at the end of
+    /// the line instead of at the end of the file.
+    fn string(&mut self) -> Result<String> {
+        let mut string = String::new();
+        loop {
+            let rest = &self.source[self.offset..];
+            let len = rest.find(['"', '\\', '\n']).unwrap_or(rest.len());
+            string.push_str(&rest[..len]);
+            self.offset += len;
+            match rest.as_bytes().get(len) {
+                Some(b'"') => {
+                    self.offset += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.offset += 1;
+                    let (c, len) = escape_sequence(&self.source[self.offset..])
+                        .ok_or_else(|| {
+                            self.error(self.offset, &[ESCAPE_SEQUENCE])
+                        })?;
+                    string.push(c);
+                    self.offset += len;
+                }
+                _ => return Err(self.error(self.offset, &[QUOTE])),
+            }
+        }
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        self.file.span.subspan(start as u64, end as u64)
+    }
+
+    fn error(&self, offset: usize, expected: &[&str]) -> Box<Error> {
+        syntax_error(self.span(offset, offset), expected)
+    }
+}
+
+/// A syntax error at the start of `span`.
+pub fn syntax_error(span: Span, expected: &[&str]) -> Box<Error> {
+    Box::new(Error::Parse {
+        span: span.subspan(0, 0),
+        expected: expected.iter().map(|&it| it.to_owned()).collect(),
+    })
+}
+
+fn is_sym_first_char(c: char) -> bool {
+    match c {
+        'a'..='z' | 'A'..='Z' => true,
+        '!' | '#' | '$' | '%' | '&' | '*' | '+' | '-' | '.' | '/' | ':'
+        | '<' | '=' | '>' | '?' | '@' | '^' | '_' | '~' | '[' | ']' => true,
+        _ => !c.is_ascii() && c.is_alphabetic(),
+    }
+}
+
+fn is_sym_char(c: char) -> bool {
+    is_sym_first_char(c) || c.is_ascii_digit()
+}
+
+/// Reads a whole run of symbol characters as a number, which can be written
+/// in hex, binary or octal with a prefix like `0x`. Only an exponent without
+/// digits is an error, whose offset is returned, since something like `1e` is
+/// clearly meant to be a number.
+fn number(text: &str) -> std::result::Result<Option<f64>, usize> {
+    let (sign, unsigned) = match text.as_bytes().first() {
+        Some(b'-') => (-1.0, &text[1..]),
+        Some(b'+') => (1.0, &text[1..]),
+        _ => (1.0, text),
+    };
+    for (base, prefix) in [(16, ['x', 'X']), (2, ['b', 'B']), (8, ['o', 'O'])] {
+        let Some(digits) = unsigned
+            .strip_prefix('0')
+            .and_then(|rest| rest.strip_prefix(prefix))
+        else {
+            continue;
+        };
+        if !digits.is_empty() && digits.chars().all(|c| c.is_digit(base)) {
+            if let Ok(n) = i64::from_str_radix(digits, base) {
+                return Ok(Some(n as f64 * sign));
+            }
+        }
+    }
+
+    let bytes = text.as_bytes();
+    let digits = |from: usize| {
+        bytes[from..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count()
+    };
+    let mut len = text.len() - unsigned.len();
+    let whole = digits(len);
+    len += whole;
+    if bytes.get(len) == Some(&b'.') {
+        let fraction = digits(len + 1);
+        if whole != 0 || fraction != 0 {
+            len += 1 + fraction;
+        }
+    }
+    if len == text.len() - unsigned.len() {
+        let special = ["nan", "inf"]
+            .iter()
+            .any(|special| text.eq_ignore_ascii_case(special));
+        return Ok(special.then(|| text.parse().unwrap()));
+    }
+    if let Some(b'e' | b'E') = bytes.get(len) {
+        len += 1;
+        if let Some(b'+' | b'-') = bytes.get(len) {
+            len += 1;
+        }
+        match digits(len) {
+            0 => return Err(len),
+            exponent => len += exponent,
+        }
+    }
+    Ok((len == text.len()).then(|| text.parse().unwrap()))
+}
+
+/// Reads an escape sequence after its backslash, giving the character and how
+/// many bytes it took.
+fn escape_sequence(rest: &str) -> Option<(char, usize)> {
+    let c = match rest.chars().next()? {
+        '"' => '"',
+        '\'' => '\'',
+        '\\' => '\\',
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        'b' => '\x08',
+        'f' => '\x0c',
+        'v' => '\x11',
+        '0' if !rest[1..].starts_with(|c: char| c.is_ascii_digit()) => '\0',
+        'x' => return hex_char(&rest[1..], 2).map(|c| (c, 3)),
+        'u' if rest[1..].starts_with('{') => {
+            let digits = rest[2..]
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(rest.len() - 2);
+            if !(1..=6).contains(&digits)
+                || !rest[2 + digits..].starts_with('}')
+            {
+                return None;
+            }
+            return hex_char(&rest[2..], digits).map(|c| (c, digits + 3));
+        }
+        'u' => return hex_char(&rest[1..], 4).map(|c| (c, 5)),
+        _ => return None,
+    };
+    Some((c, 1))
+}
+
+/// The character with the code point given by the first `len` bytes of
+/// `digits`, which must all be hex digits.
+fn hex_char(digits: &str, len: usize) -> Option<char> {
+    let digits = digits.get(..len)?;
+    if digits.len() != len || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(digits, 16).ok()?)
+}
@@ -1,59 +1,468 @@
-use crate::{ast::Ast, diagnostic::Warning};
+use crate::{
+    allow::Allowed,
+    assigned::unused_lists,
+    ast::{walk, Ast, Visit},
+    diagnostic::Warning,
+    ir::{expr::Expr, statement::Statement, Program},
+    opts::MessageFormat,
+    span::display_column,
+};
 use codemap::{CodeMap, Span};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    iter, ptr,
+};
 
-pub fn lint_ast(ast: &Ast, code_map: &CodeMap) {
-    match ast {
-        Ast::Node(head, tail, span) => {
-            paren_too_far_left(*span, code_map);
-            inconsistent_indentation(tail, *span, code_map);
-            lint_ast(head, code_map);
-            for ast in tail {
-                lint_ast(ast, code_map);
-            }
+/// The fewest statements that a block must have for `lint` to report copies
+/// of it, so that short blocks like a `say` and a `wait` aren't.
+const MIN_DUPLICATED_STATEMENTS: usize = 4;
+
+/// How many times `lint` lets a number be written before suggesting a name
+/// for it.
+const MAX_MAGIC_NUMBER_USES: usize = 2;
+
+pub fn lint_ast(
+    ast: &Ast,
+    code_map: &CodeMap,
+    format: MessageFormat,
+    allowed: &Allowed,
+) {
+    Linter {
+        code_map,
+        format,
+        allowed,
+    }
+    .visit(ast);
+}
+
+struct Linter<'a> {
+    code_map: &'a CodeMap,
+    format: MessageFormat,
+    allowed: &'a Allowed,
+}
+
+impl Visit for Linter<'_> {
+    fn visit(&mut self, ast: &Ast) {
+        if let Ast::Node(_, tail, span) = ast {
+            paren_too_far_left(*span, self.code_map, self.format, self.allowed);
+            inconsistent_indentation(
+                tail,
+                *span,
+                self.code_map,
+                self.format,
+                self.allowed,
+            );
         }
-        Ast::Unquote(unquoted, _) => lint_ast(unquoted, code_map),
-        _ => {}
+        walk(self, ast);
     }
 }
 
-fn paren_too_far_left(span: Span, code_map: &CodeMap) {
+fn paren_too_far_left(
+    span: Span,
+    code_map: &CodeMap,
+    format: MessageFormat,
+    allowed: &Allowed,
+) {
     let left = span.low();
     let right = left + (span.high() - left - 1);
-    let left_column = code_map.look_up_pos(left).position.column;
-    let right_column = code_map.look_up_pos(right).position.column;
+    let left_column = display_column(code_map, left);
+    let right_column = display_column(code_map, right);
     if right_column < left_column {
         Warning::ParenTooFarLeft {
             left: span.subspan(0, 1),
             right: span.subspan(span.len() - 1, span.len()),
         }
-        .emit(code_map);
+        .emit(code_map, format, allowed);
     }
 }
 
-fn inconsistent_indentation(tail: &[Ast], span: Span, code_map: &CodeMap) {
+fn inconsistent_indentation(
+    tail: &[Ast],
+    span: Span,
+    code_map: &CodeMap,
+    format: MessageFormat,
+    allowed: &Allowed,
+) {
     let mut already_handled_line =
         code_map.look_up_pos(span.low()).position.line;
     let mut prev_column = None;
     let mut good = None;
     for ast in tail {
         let subspan = ast.span();
-        let loc = code_map.look_up_pos(subspan.low()).position;
-        if loc.line != already_handled_line {
+        let line = code_map.look_up_pos(subspan.low()).position.line;
+        let column = display_column(code_map, subspan.low());
+        if line != already_handled_line {
             if let Some(prev_column) = prev_column {
-                if loc.column != prev_column {
+                if column != prev_column {
                     Warning::InconsistentIndentation {
                         node: span,
                         good: good.unwrap(),
                         offender: subspan,
                     }
-                    .emit(code_map);
+                    .emit(code_map, format, allowed);
                     return;
                 }
             } else {
-                prev_column = Some(loc.column);
+                prev_column = Some(column);
                 good = Some(subspan);
             }
         }
-        already_handled_line = loc.line;
+        already_handled_line = line;
+    }
+}
+
+/// Numbers other than 0, 1 and -1 that are written more than
+/// [`MAX_MAGIC_NUMBER_USES`] times, for the `lint` subcommand. Numbers in
+/// declarations, macros and enums already have a name, so they don't count.
+pub fn magic_numbers(asts: &[Ast]) -> Vec<Warning> {
+    let mut numbers = MagicNumbers::default();
+    for ast in asts {
+        numbers.visit(ast);
+    }
+    let mut warnings = numbers
+        .uses
+        .into_values()
+        .filter(|(_, spans)| spans.len() > MAX_MAGIC_NUMBER_USES)
+        .map(|(value, mut spans)| {
+            spans.sort();
+            Warning::MagicNumber {
+                span: spans[0],
+                others: spans.split_off(1),
+                value,
+            }
+        })
+        .collect::<Vec<_>>();
+    warnings.sort_by_key(Warning::span);
+    warnings
+}
+
+/// Where every number is written, by its bits.
+#[derive(Default)]
+struct MagicNumbers {
+    uses: HashMap<u64, (f64, Vec<Span>)>,
+}
+
+impl Visit for MagicNumbers {
+    fn visit(&mut self, ast: &Ast) {
+        match ast {
+            Ast::Num(value, span) if ![0.0, 1.0, -1.0].contains(value) => {
+                self.uses
+                    .entry(value.to_bits())
+                    .or_insert_with(|| (*value, Vec::new()))
+                    .1
+                    .push(*span);
+            }
+            _ if ["variables", "lists", "macro", "enum", "table"]
+                .iter()
+                .any(|name| ast.is_the_function_call(name)) => {}
+            _ => walk(self, ast),
+        }
+    }
+}
+
+/// A block of code that might be written more than once.
+struct Occurrence<'a> {
+    stmt: &'a Statement,
+    span: Span,
+    statements: usize,
+}
+
+/// Lints of the whole program for the `lint` subcommand, which point out code
+/// that works but is hard to read: procedures with more than `max_statements`
+/// statements, control flow nested more than `max_depth` deep, blocks of
+/// code that are written more than once and lists that are never read.
+pub fn lint_program(
+    program: &Program,
+    max_statements: usize,
+    max_depth: usize,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut occurrences = HashMap::new();
+    for sprite in iter::once(&program.stage).chain(program.sprites.values()) {
+        for (name, procs) in &sprite.procedures {
+            for proc in procs {
+                let statements = count_statements(&proc.body);
+                if statements > max_statements {
+                    warnings.push(Warning::LongProc {
+                        span: proc.span,
+                        name: name.clone(),
+                        statements,
+                        limit: max_statements,
+                    });
+                }
+                if let Some(stmt) = too_deep(&proc.body, max_depth, 0) {
+                    warnings.push(Warning::DeepNesting {
+                        span: stmt.span().unwrap_or(proc.span),
+                        limit: max_depth,
+                    });
+                }
+                collect_occurrences(&proc.body, &mut occurrences);
+            }
+        }
+    }
+    warnings.extend(duplicated_code(occurrences));
+    warnings.extend(unused_lists(program));
+    warnings.sort_by_key(Warning::span);
+    warnings
+}
+
+fn children(stmt: &Statement) -> Vec<&Statement> {
+    match stmt {
+        Statement::ProcCall { .. } => Vec::new(),
+        Statement::Do(stmts) => stmts.iter().collect(),
+        Statement::IfElse { then, else_, .. } => vec![&**then, &**else_],
+        Statement::Repeat { body, .. }
+        | Statement::Forever(body, _)
+        | Statement::Until { body, .. }
+        | Statement::While { body, .. }
+        | Statement::For { body, .. } => vec![&**body],
+    }
+}
+
+/// Statements other than `do` blocks in `stmt`, including itself.
+fn count_statements(stmt: &Statement) -> usize {
+    let mut count = 0;
+    stmt.any(&mut |stmt| {
+        count += usize::from(!matches!(stmt, Statement::Do(_)));
+        false
+    });
+    count
+}
+
+/// The first loop or `if` in `stmt` that is nested more than `limit` deep,
+/// where `stmt` is nested `depth` deep. An `if` in the `else` branch of
+/// another continues the same chain of conditions, like `cond` expands to,
+/// so it doesn't count as nested.
+fn too_deep(
+    stmt: &Statement,
+    limit: usize,
+    depth: usize,
+) -> Option<&Statement> {
+    match stmt {
+        Statement::ProcCall { .. } => None,
+        Statement::Do(stmts) => {
+            stmts.iter().find_map(|stmt| too_deep(stmt, limit, depth))
+        }
+        _ if depth == limit => Some(stmt),
+        Statement::IfElse { then, else_, .. } => {
+            let else_depth = if matches!(**else_, Statement::IfElse { .. }) {
+                depth
+            } else {
+                depth + 1
+            };
+            too_deep(then, limit, depth + 1)
+                .or_else(|| too_deep(else_, limit, else_depth))
+        }
+        Statement::Repeat { body, .. }
+        | Statement::Forever(body, _)
+        | Statement::Until { body, .. }
+        | Statement::While { body, .. }
+        | Statement::For { body, .. } => too_deep(body, limit, depth + 1),
+    }
+}
+
+/// Collects the blocks in `stmt` that are big enough to report copies of, by
+/// their fingerprint. A `do` block with one statement is left out since that
+/// statement is collected on its own.
+fn collect_occurrences<'a>(
+    stmt: &'a Statement,
+    occurrences: &mut HashMap<String, Vec<Occurrence<'a>>>,
+) {
+    let statements = count_statements(stmt);
+    if statements < MIN_DUPLICATED_STATEMENTS {
+        return;
+    }
+    if !matches!(stmt, Statement::Do(stmts) if stmts.len() == 1) {
+        if let Some(span) = stmt.span() {
+            let mut key = String::new();
+            fingerprint(stmt, &mut key);
+            occurrences.entry(key).or_default().push(Occurrence {
+                stmt,
+                span,
+                statements,
+            });
+        }
+    }
+    for child in children(stmt) {
+        collect_occurrences(child, occurrences);
+    }
+}
+
+/// Writes the structure of a statement without its spans, so that copies of
+/// the same code get the same key.
+fn fingerprint(stmt: &Statement, key: &mut String) {
+    let (name, exprs): (String, Vec<&Expr>) = match stmt {
+        Statement::ProcCall {
+            proc_name, args, ..
+        } => (proc_name.clone(), args.iter().collect()),
+        Statement::Do(_) => ("do".to_owned(), Vec::new()),
+        Statement::IfElse { condition, .. } => {
+            ("if".to_owned(), vec![condition])
+        }
+        Statement::Repeat { times, .. } => ("repeat".to_owned(), vec![times]),
+        Statement::Forever(..) => ("forever".to_owned(), Vec::new()),
+        Statement::Until { condition, .. } => {
+            ("until".to_owned(), vec![condition])
+        }
+        Statement::While { condition, .. } => {
+            ("while".to_owned(), vec![condition])
+        }
+        Statement::For {
+            counter: (counter, _),
+            times,
+            ..
+        } => (format!("for {counter}"), vec![times]),
+    };
+    key.push('(');
+    key.push_str(&name);
+    for expr in exprs {
+        key.push(' ');
+        expr_fingerprint(expr, key);
+    }
+    for child in children(stmt) {
+        key.push(' ');
+        fingerprint(child, key);
+    }
+    key.push(')');
+}
+
+fn expr_fingerprint(expr: &Expr, key: &mut String) {
+    let (name, terms): (&str, Vec<&Expr>) = match expr {
+        Expr::Imm(value) => {
+            key.push_str(&format!("{value:?}"));
+            return;
+        }
+        Expr::Sym(sym, _) => {
+            key.push_str(sym);
+            return;
+        }
+        Expr::FuncCall(func_name, _, args) => {
+            (*func_name, args.iter().collect())
+        }
+        Expr::AddSub(positives, negatives, _) => {
+            key.push_str("(-");
+            for term in negatives {
+                key.push(' ');
+                expr_fingerprint(term, key);
+            }
+            key.push(')');
+            ("+", positives.iter().collect())
+        }
+        Expr::MulDiv(numerators, denominators, _) => {
+            key.push_str("(/");
+            for term in denominators {
+                key.push(' ');
+                expr_fingerprint(term, key);
+            }
+            key.push(')');
+            ("*", numerators.iter().collect())
+        }
+    };
+    key.push('(');
+    key.push_str(name);
+    for term in terms {
+        key.push(' ');
+        expr_fingerprint(term, key);
+    }
+    key.push(')');
+}
+
+/// A warning for every copy of a block after the first. Copies inside a
+/// bigger block that was reported already aren't reported again.
+fn duplicated_code(
+    occurrences: HashMap<String, Vec<Occurrence>>,
+) -> Vec<Warning> {
+    let mut groups = occurrences
+        .into_values()
+        .map(|mut copies| {
+            copies.sort_by_key(|copy| copy.span);
+            // Expansions of a macro keep the spans of its body, so they are
+            // written only once.
+            copies.dedup_by_key(|copy| copy.span);
+            copies
+        })
+        .filter(|copies| copies.len() > 1)
+        .collect::<Vec<_>>();
+    groups
+        .sort_by_key(|copies| (Reverse(copies[0].statements), copies[0].span));
+
+    let mut reported = HashSet::new();
+    let mut warnings = Vec::new();
+    for copies in groups {
+        let copies = copies
+            .into_iter()
+            .filter(|copy| !reported.contains(&ptr::from_ref(copy.stmt)))
+            .collect::<Vec<_>>();
+        let [first, rest @ ..] = &copies[..] else {
+            continue;
+        };
+        if rest.is_empty() {
+            continue;
+        }
+        for copy in &copies {
+            copy.stmt.any(&mut |stmt| {
+                reported.insert(ptr::from_ref(stmt));
+                false
+            });
+        }
+        warnings.extend(rest.iter().map(|copy| Warning::DuplicatedCode {
+            span: copy.span,
+            first: first.span,
+            statements: copy.statements,
+        }));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{macros::expand, opts::Opts, parser};
+    use gumdrop::Options;
+
+    fn duplicates(source: &str) -> usize {
+        let mut code_map = CodeMap::new();
+        let file =
+            code_map.add_file("main.scratch".to_owned(), source.to_owned());
+        let asts = parser::program(&file, 256).unwrap();
+        let opts = Opts::parse_args_default(&["main.scratch"]).unwrap();
+        let expanded =
+            expand(asts, &opts, &mut code_map, &mut Allowed::default())
+                .unwrap();
+        let program = Program::from_asts(expanded).unwrap();
+        lint_program(&program, usize::MAX, usize::MAX)
+            .iter()
+            .filter(|warning| matches!(warning, Warning::DuplicatedCode { .. }))
+            .count()
+    }
+
+    #[test]
+    fn macro_expansions_are_not_duplicated_code() {
+        let source = r#"
+(macro (reset-game!)
+  (do (:= a 0) (:= b 0) (:= c 0) (:= d 0)))
+
+(sprite "Stage"
+  (variables a b c d)
+  (proc when-flag-clicked
+    (reset-game!))
+  (proc when-cloned
+    (reset-game!)))
+"#;
+        assert_eq!(duplicates(source), 0);
+    }
+
+    #[test]
+    fn copies_in_the_source_are_duplicated_code() {
+        let source = r#"
+(sprite "Stage"
+  (variables a b c d)
+  (proc when-flag-clicked
+    (:= a 0) (:= b 0) (:= c 0) (:= d 0))
+  (proc when-cloned
+    (:= a 0) (:= b 0) (:= c 0) (:= d 0)))
+"#;
+        assert_eq!(duplicates(source), 1);
     }
 }
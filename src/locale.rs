@@ -0,0 +1,257 @@
+use crate::{
+    ast::{walk, Ast, Visit},
+    opts::Lang,
+};
+use std::{collections::HashSet, convert::Infallible, iter};
+
+/// Rewrites localized names of builtins into their canonical spelling. The
+/// canonical names keep working, so localized and English code can be mixed.
+/// Names that the program defines itself are left alone, even if they are
+/// also the localized name of a builtin. They are added to `user_names`,
+/// which carries them over to the files that are included later.
+pub fn localize(
+    asts: &mut [Ast],
+    lang: Lang,
+    user_names: &mut HashSet<String>,
+) {
+    let (functions, symbols) = match lang {
+        Lang::De => (GERMAN_FUNCTIONS, GERMAN_SYMBOLS),
+        Lang::Fr => (FRENCH_FUNCTIONS, FRENCH_SYMBOLS),
+    };
+    let mut definitions = Definitions {
+        functions,
+        user_names: &mut *user_names,
+    };
+    for ast in &*asts {
+        definitions.visit(ast);
+    }
+    let translate = |table, sym: &mut String| {
+        if !user_names.contains(&**sym) {
+            if let Some(canonical) = lookup(table, sym) {
+                *sym = canonical.to_owned();
+            }
+        }
+    };
+    for ast in asts {
+        ast.traverse_postorder_mut(&mut |node| {
+            match node {
+                Ast::Sym(sym, _) => translate(symbols, sym),
+                Ast::Node(head, ..) => {
+                    if let Ast::Sym(sym, _) = &mut **head {
+                        translate(functions, sym);
+                    }
+                }
+                _ => {}
+            }
+            Ok::<(), Infallible>(())
+        })
+        .unwrap_or_else(|never| match never {});
+    }
+}
+
+/// Collects the names of procedures, macros, variables, lists, parameters
+/// and loop counters.
+struct Definitions<'a> {
+    functions: Aliases,
+    user_names: &'a mut HashSet<String>,
+}
+
+impl Visit for Definitions<'_> {
+    fn visit(&mut self, ast: &Ast) {
+        if let Ast::Node(head, tail, _) = ast {
+            if let Ast::Sym(head, _) = &**head {
+                let head = lookup(self.functions, head).unwrap_or(head);
+                let names = match (head, &tail[..]) {
+                    ("proc" | "macro", [Ast::Node(name, params, _), ..])
+                        if matches!(**name, Ast::Sym(..)) =>
+                    {
+                        iter::once(&**name).chain(params).collect()
+                    }
+                    ("macro" | "for", [name, ..]) => vec![name],
+                    ("variables" | "lists", names) => names.iter().collect(),
+                    _ => Vec::new(),
+                };
+                for name in names {
+                    if let Ast::Sym(name, _) = name {
+                        self.user_names.insert(name.clone());
+                    }
+                }
+            }
+        }
+        walk(self, ast);
+    }
+}
+
+fn lookup(table: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(localized, _)| *localized == name)
+        .map(|(_, canonical)| *canonical)
+}
+
+type Aliases = &'static [(&'static str, &'static str)];
+
+/// Names that are only translated when they are called as a function.
+const GERMAN_FUNCTIONS: Aliases = &[
+    ("figur", "sprite"),
+    ("prozedur", "proc"),
+    ("variablen", "variables"),
+    ("listen", "lists"),
+    ("kostüme", "costumes"),
+    ("makro", "macro"),
+    ("einbinden", "include"),
+    ("mache", "do"),
+    ("falls", "if"),
+    ("wenn", "when"),
+    ("außer-wenn", "unless"),
+    ("fallunterscheidung", "cond"),
+    ("wiederhole", "repeat"),
+    ("wiederhole-fortlaufend", "forever"),
+    ("wiederhole-bis", "until"),
+    ("solange", "while"),
+    ("für", "for"),
+    ("warte", "wait"),
+    ("stoppe-alles", "stop-all"),
+    ("stoppe-dieses-skript", "stop-this-script"),
+    ("stoppe-andere-skripte", "stop-other-scripts"),
+    ("sage", "say"),
+    ("sage-für-sekunden", "say-for-seconds"),
+    ("zeige-dich", "show"),
+    ("verstecke-dich", "hide"),
+    ("wechsle-zu-kostüm", "set-costume"),
+    ("setze-größe", "set-size"),
+    ("gehe-zu", "set-xy"),
+    ("ändere-x", "change-x"),
+    ("ändere-y", "change-y"),
+    ("setze-x", "set-x"),
+    ("setze-y", "set-y"),
+    ("lösche-alles", "erase-all"),
+    ("hinterlasse-abdruck", "stamp"),
+    ("schalte-stift-ein", "pen-down"),
+    ("schalte-stift-aus", "pen-up"),
+    ("setze-stiftdicke", "set-pen-size"),
+    ("setze-stiftfarbe", "set-pen-color"),
+    ("spiele-ton", "play-note"),
+    ("pausiere", "rest"),
+    ("setze-instrument", "set-instrument"),
+    ("setze-tempo", "set-tempo"),
+    ("schalte-video", "set-video"),
+    ("setze-video-transparenz", "set-video-transparency"),
+    ("frage", "ask"),
+    ("sprich", "speak"),
+    ("setze-stoppuhr-zurück", "reset-timer"),
+    ("sende-und-warte", "send-broadcast-sync"),
+    ("füge-hinzu", "append"),
+    ("lösche", "delete"),
+    ("lösche-alle", "delete-all"),
+    ("ersetze", "replace"),
+    ("element", "!!"),
+    ("länge", "length"),
+    ("länge-von", "str-length"),
+    ("zeichen", "char-at"),
+    ("verbinde", "++"),
+    ("und", "and"),
+    ("oder", "or"),
+    ("nicht", "not"),
+    ("zufallszahl", "random"),
+    ("betrag", "abs"),
+    ("abrunden", "floor"),
+    ("aufrunden", "ceil"),
+    ("wurzel", "sqrt"),
+    ("taste-gedrückt", "pressing-key"),
+    ("zu-zahl", "to-num"),
+    ("zu-wahrheitswert", "to-bool"),
+];
+
+/// Names that are translated wherever they appear as a symbol.
+const GERMAN_SYMBOLS: Aliases = &[
+    ("wenn-fahne-angeklickt", "when-flag-clicked"),
+    ("wenn-ich-als-klon-entstehe", "when-cloned"),
+    ("wenn-ich-empfange", "when-received"),
+    ("antwort", "answer"),
+    ("stoppuhr", "timer"),
+    ("x-position", "x-pos"),
+    ("y-position", "y-pos"),
+    ("video-bewegung", "video-motion"),
+    ("video-richtung", "video-direction"),
+];
+
+const FRENCH_FUNCTIONS: Aliases = &[
+    ("lutin", "sprite"),
+    ("procédure", "proc"),
+    ("listes", "lists"),
+    ("inclure", "include"),
+    ("faire", "do"),
+    ("si", "if"),
+    ("quand", "when"),
+    ("sauf-si", "unless"),
+    ("selon", "cond"),
+    ("répéter", "repeat"),
+    ("répéter-indéfiniment", "forever"),
+    ("répéter-jusqu-à", "until"),
+    ("tant-que", "while"),
+    ("pour", "for"),
+    ("attendre", "wait"),
+    ("tout-arrêter", "stop-all"),
+    ("arrêter-ce-script", "stop-this-script"),
+    ("arrêter-les-autres-scripts", "stop-other-scripts"),
+    ("dire", "say"),
+    ("dire-pendant", "say-for-seconds"),
+    ("montrer", "show"),
+    ("cacher", "hide"),
+    ("basculer-sur-le-costume", "set-costume"),
+    ("mettre-la-taille-à", "set-size"),
+    ("aller-à", "set-xy"),
+    ("ajouter-à-x", "change-x"),
+    ("ajouter-à-y", "change-y"),
+    ("mettre-x-à", "set-x"),
+    ("mettre-y-à", "set-y"),
+    ("effacer-tout", "erase-all"),
+    ("estampiller", "stamp"),
+    ("stylo-en-position-d-écriture", "pen-down"),
+    ("relever-le-stylo", "pen-up"),
+    ("mettre-la-taille-du-stylo-à", "set-pen-size"),
+    ("mettre-la-couleur-du-stylo-à", "set-pen-color"),
+    ("jouer-la-note", "play-note"),
+    ("faire-une-pause", "rest"),
+    ("choisir-l-instrument", "set-instrument"),
+    ("mettre-le-tempo-à", "set-tempo"),
+    ("activer-la-vidéo", "set-video"),
+    ("mettre-la-transparence-vidéo-à", "set-video-transparency"),
+    ("demander", "ask"),
+    ("prononcer", "speak"),
+    ("réinitialiser-le-chronomètre", "reset-timer"),
+    ("envoyer-à-tous-et-attendre", "send-broadcast-sync"),
+    ("ajouter", "append"),
+    ("supprimer", "delete"),
+    ("supprimer-tout", "delete-all"),
+    ("remplacer", "replace"),
+    ("élément", "!!"),
+    ("longueur", "length"),
+    ("longueur-de", "str-length"),
+    ("lettre", "char-at"),
+    ("regrouper", "++"),
+    ("et", "and"),
+    ("ou", "or"),
+    ("non", "not"),
+    ("nombre-aléatoire", "random"),
+    ("valeur-absolue", "abs"),
+    ("plancher", "floor"),
+    ("plafond", "ceil"),
+    ("racine", "sqrt"),
+    ("touche-pressée", "pressing-key"),
+    ("en-nombre", "to-num"),
+    ("en-booléen", "to-bool"),
+];
+
+const FRENCH_SYMBOLS: Aliases = &[
+    ("quand-le-drapeau-est-cliqué", "when-flag-clicked"),
+    ("quand-je-commence-comme-un-clone", "when-cloned"),
+    ("quand-je-reçois", "when-received"),
+    ("réponse", "answer"),
+    ("chronomètre", "timer"),
+    ("abscisse-x", "x-pos"),
+    ("ordonnée-y", "y-pos"),
+    ("mouvement-vidéo", "video-motion"),
+    ("direction-vidéo", "video-direction"),
+];
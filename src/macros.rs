@@ -1,25 +1,43 @@
+mod template;
+
 use crate::{
-    ast::Ast,
-    diagnostic::{Error, Result},
+    allow::Allowed,
+    ast::{fold_children, Ast, Fold},
+    diagnostic::{Error, Result, Warning},
+    ir::typ::{split_annotation, Type},
     lint::lint_ast,
-    parser::{program, Input},
-    Opts,
+    locale::localize,
+    parser::program,
+    source, Opts,
 };
 use codemap::{CodeMap, Span};
-use std::{collections::HashMap, fs, mem};
-use winnow::stream::Located;
+use std::{
+    collections::{HashMap, HashSet},
+    iter, mem,
+    path::Path,
+    rc::Rc,
+};
+use template::Template;
 
 pub fn expand(
-    program: Vec<Ast>,
+    mut program: Vec<Ast>,
     opts: &Opts,
     code_map: &mut CodeMap,
+    allowed: &mut Allowed,
 ) -> Result<Vec<Ast>> {
+    let mut user_names = HashSet::new();
+    if let Some(lang) = opts.lang {
+        localize(&mut program, lang, &mut user_names);
+    }
     let mut ctx = MacroContext {
         opts,
         code_map,
+        allowed,
         asts: Vec::new(),
         symbols: HashMap::new(),
         functions: HashMap::new(),
+        enums: HashMap::new(),
+        user_names,
     };
     for ast in program {
         ctx.transform_top_level(ast)?;
@@ -46,16 +64,41 @@ impl Macro {
                 assert!(args.next().is_none());
                 Ok((macro_name, Self::Symbol(body)))
             }
-            Ast::Node(box Ast::Sym(macro_name, ..), params, ..) => {
+            Ast::Node(head, params, signature_span) => {
+                let Ast::Sym(macro_name, _) = *head else {
+                    return Err(Box::new(Error::InvalidMacroSignature {
+                        span: signature_span,
+                    }));
+                };
+                let mut defaults = Vec::new();
                 let params = params
                     .into_iter()
-                    .map(Parameter::from_ast)
+                    .map(|param| {
+                        let (param, default) = split_default(param);
+                        if let Some(default) = default {
+                            defaults.push(default);
+                        } else if !defaults.is_empty() {
+                            return Err(Box::new(
+                                Error::MacroDefaultNotTrailing {
+                                    span: param.span(),
+                                },
+                            ));
+                        }
+                        Parameter::from_ast(param)
+                    })
                     .collect::<Result<_>>()?;
                 let body = args
                     .next()
                     .ok_or(Error::MacroDefinitionMissingBody { span })?;
                 assert!(args.next().is_none());
-                Ok((macro_name, Self::Function(FunctionMacro { params, body })))
+                Ok((
+                    macro_name,
+                    Self::Function(FunctionMacro {
+                        params,
+                        defaults,
+                        body: Template::new(body),
+                    }),
+                ))
             }
             invalid_signature => Err(Box::new(Error::InvalidMacroSignature {
                 span: invalid_signature.span(),
@@ -67,9 +110,16 @@ impl Macro {
 struct MacroContext<'a> {
     opts: &'a Opts,
     code_map: &'a mut CodeMap,
+    allowed: &'a mut Allowed,
     asts: Vec<Ast>,
     symbols: HashMap<String, Ast>,
-    functions: HashMap<String, FunctionMacro>,
+    /// Shared so that expanding a macro doesn't have to copy its definition to
+    /// get around borrowing `self`.
+    functions: HashMap<String, Rc<FunctionMacro>>,
+    /// The variants of every enum, in order of definition.
+    enums: HashMap<String, Vec<String>>,
+    /// Names that the program defines, which aren't localized.
+    user_names: HashSet<String>,
 }
 
 impl MacroContext<'_> {
@@ -79,18 +129,201 @@ impl MacroContext<'_> {
                 self.symbols.insert(name, body);
             }
             (name, Macro::Function(func)) => {
-                self.functions.insert(name, func);
+                self.check_duplicate_params(&func.params);
+                self.functions.insert(name, Rc::new(func));
             }
         }
         Ok(())
     }
 
+    /// Warns about parameters that are bound more than once, since only the
+    /// last binding can be used in the body.
+    fn check_duplicate_params(&self, params: &[Parameter]) {
+        fn walk<'p>(
+            params: &'p [Parameter],
+            seen: &mut HashMap<&'p str, Span>,
+            duplicates: &mut Vec<Warning>,
+        ) {
+            for param in params {
+                match param {
+                    Parameter::Var(name, span) => {
+                        if let Some(first) = seen.insert(name, *span) {
+                            duplicates.push(Warning::ShadowedMacroParameter {
+                                first,
+                                shadowing: *span,
+                                name: name.clone(),
+                            });
+                        }
+                    }
+                    Parameter::Ignore | Parameter::Literal(_) => {}
+                    Parameter::Constructor(_, subparams, _) => {
+                        walk(subparams, seen, duplicates);
+                    }
+                }
+            }
+        }
+
+        let mut duplicates = Vec::new();
+        walk(params, &mut HashMap::new(), &mut duplicates);
+        for warning in duplicates {
+            warning.emit(self.code_map, self.opts.message_format, self.allowed);
+        }
+    }
+
+    /// Defines a symbol macro `Name.variant` for every variant of
+    /// `(enum Name variant...)`. Variants are numbered from 0, unless the enum
+    /// is declared as `(enum (Name : str) ...)`, in which case every variant
+    /// stands for its own name as a string.
+    fn define_enum(&mut self, args: Vec<Ast>, span: Span) -> Result<()> {
+        let mut args = args.into_iter();
+        let (name, typ) = split_annotation(
+            args.next().ok_or(Error::InvalidEnumDefinition { span })?,
+        )?;
+        let Ast::Sym(name, _) = name else {
+            return Err(Box::new(Error::InvalidEnumDefinition {
+                span: name.span(),
+            }));
+        };
+        let as_strings = match typ {
+            None | Some(Type::Num) => false,
+            Some(Type::Str) => true,
+            Some(Type::Bool) => {
+                return Err(Box::new(Error::InvalidEnumDefinition { span }))
+            }
+        };
+        let variants = args
+            .enumerate()
+            .map(|(i, variant)| match variant {
+                Ast::Sym(variant, span) => {
+                    let value = if as_strings {
+                        Ast::String(variant.clone(), span)
+                    } else {
+                        Ast::Num(i as f64, span)
+                    };
+                    self.symbols.insert(format!("{name}.{variant}"), value);
+                    Ok(variant)
+                }
+                _ => Err(Box::new(Error::InvalidEnumDefinition {
+                    span: variant.span(),
+                })),
+            })
+            .collect::<Result<_>>()?;
+        self.enums.insert(name, variants);
+        Ok(())
+    }
+
+    /// Warns about `cond`s that compare a value against some, but not all,
+    /// variants of an enum and have no else branch.
+    fn check_exhaustiveness(&self, ast: &Ast) {
+        let Ast::Node(head, tail, span) = ast else {
+            return;
+        };
+        for branch in iter::once(&**head).chain(tail) {
+            self.check_exhaustiveness(branch);
+        }
+        if !ast.is_the_function_call("cond") || tail.len() % 2 == 1 {
+            return;
+        }
+
+        let mut scrutinee = None;
+        let mut enum_name = None;
+        let mut covered = HashSet::new();
+        for condition in tail.iter().step_by(2) {
+            let Ast::Node(head, operands, _) = condition else {
+                return;
+            };
+            let Ast::Sym(eq, _) = &**head else {
+                return;
+            };
+            let [Ast::Sym(lhs, _), Ast::Sym(rhs, _)] = &operands[..] else {
+                return;
+            };
+            let Some((value, (enum_, variant))) = self
+                .enum_variant(rhs)
+                .map(|variant| (lhs, variant))
+                .or_else(|| {
+                    self.enum_variant(lhs).map(|variant| (rhs, variant))
+                })
+            else {
+                return;
+            };
+            if eq != "="
+                || *scrutinee.get_or_insert(value) != value
+                || *enum_name.get_or_insert(enum_) != enum_
+            {
+                return;
+            }
+            covered.insert(variant);
+        }
+
+        let Some(enum_name) = enum_name else {
+            return;
+        };
+        let missing = self.enums[enum_name]
+            .iter()
+            .filter(|variant| !covered.contains(&***variant))
+            .map(|variant| format!("{enum_name}.{variant}"))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            Warning::NonExhaustiveCond {
+                span: *span,
+                missing,
+            }
+            .emit(
+                self.code_map,
+                self.opts.message_format,
+                self.allowed,
+            );
+        }
+    }
+
+    /// Splits `Name.variant` into its parts if `Name` is a known enum.
+    fn enum_variant<'s>(&self, sym: &'s str) -> Option<(&'s str, &'s str)> {
+        let (enum_name, variant) = sym.rsplit_once('.')?;
+        self.enums
+            .get(enum_name)?
+            .iter()
+            .any(|v| v == variant)
+            .then_some((enum_name, variant))
+    }
+
     fn transform_shallow(&mut self, ast: &mut Ast) -> Result<bool> {
-        Ok(Self::use_builtin_function_macros(ast)?
+        let before = self.opts.trace_macros.then(|| ast.clone());
+        let dirty = Self::use_builtin_function_macros(ast)?
             | self.use_builtin_symbol_macros(ast)
+            | self.use_cfg(ast)?
             | self.use_user_defined_macros(ast)?
             | self.use_inline_include(ast)?
-            | self.use_inline_macros(ast)?)
+            | self.use_inline_macros(ast)?
+            | self.use_record_definitions(ast)?;
+        if dirty {
+            if let Some(before) = before {
+                self.trace(&before, ast);
+            }
+        }
+        Ok(dirty)
+    }
+
+    /// Prints a rewrite performed by `transform_shallow` for
+    /// `--trace-macros`.
+    fn trace(&self, before: &Ast, after: &Ast) {
+        let head = match before {
+            Ast::Node(head, ..) => &**head,
+            _ => before,
+        };
+        let name = match head {
+            Ast::Sym(sym, _) => sym.as_str(),
+            _ => "?",
+        };
+        let loc = self.code_map.look_up_span(before.span());
+        eprintln!(
+            "{}:{}:{}: expanded `{name}`\n  before: {}\n  after:  {}",
+            loc.file.name(),
+            loc.begin.line + 1,
+            loc.begin.column + 1,
+            snippet(before),
+            snippet(after),
+        );
     }
 
     fn transform_deep(&mut self, ast: &mut Ast) -> Result<bool> {
@@ -101,6 +334,12 @@ impl MacroContext<'_> {
                 this_step_dirty |= self.transform_shallow(branch)?;
                 Ok::<(), Box<Error>>(())
             })?;
+            if let Some(span) = ast.too_deep(self.opts.max_nesting) {
+                return Err(Box::new(Error::TooDeeplyNested {
+                    span,
+                    limit: self.opts.max_nesting,
+                }));
+            }
             this_step_dirty
         } {
             dirty = true;
@@ -109,29 +348,39 @@ impl MacroContext<'_> {
     }
 
     fn transform_top_level(&mut self, mut ast: Ast) -> Result<()> {
-        // HACK: Prevents early expansion of macro body, while still allowing
-        // macros to define other macros.
-        if !ast.is_the_function_call("macro") {
+        // HACK: Prevents early expansion of macro bodies and enum variants,
+        // while still allowing macros to define other macros.
+        if !ast.is_the_function_call("macro")
+            && !ast.is_the_function_call("enum")
+        {
+            // Has to happen before enum variants are expanded to their values.
+            self.check_exhaustiveness(&ast);
             self.transform_deep(&mut ast)?;
         };
 
         match ast {
-            Ast::Node(box Ast::Sym("macro", ..), args, span) => {
+            Ast::Node(head, args, span) if head.is_sym("macro") => {
                 self.define(args, span)
             }
-            Ast::Node(box Ast::Sym("include", ..), args, span) => {
+            Ast::Node(head, args, span) if head.is_sym("enum") => {
+                self.define_enum(args, span)
+            }
+            Ast::Node(head, args, span) if head.is_sym("include") => {
                 for item in self.include(&args, span)? {
                     self.transform_top_level(item)?;
                 }
                 Ok(())
             }
-            Ast::Node(box Ast::Sym(sym, ..), mut args, _)
-                if sym == "when!" || sym == "unless!" =>
+            Ast::Node(head, mut args, span)
+                if head.is_sym("when!") || head.is_sym("unless!") =>
             {
                 let Some(Ast::Bool(condition, _)) = args.get(0) else {
-                    todo!();
+                    return Err(Box::new(Error::NonConstantCondition {
+                        span: args.get(0).map_or(span, Ast::span),
+                        macro_name: head.to_string(),
+                    }));
                 };
-                if *condition ^ (sym == "unless!") {
+                if *condition ^ head.is_sym("unless!") {
                     for item in args.drain(1..) {
                         self.transform_top_level(item)?;
                     }
@@ -154,28 +403,40 @@ impl MacroContext<'_> {
                 *ast = symbol_macro.clone();
                 true
             }
-            Ast::Node(box Ast::Sym(sym, ..), args, span) => {
-                let Some(func_macro) = self.functions.get(sym) else {
+            Ast::Node(head, args, span) => {
+                let Ast::Sym(sym, _) = &**head else {
+                    return Ok(false);
+                };
+                let Some(func_macro) = self.functions.get(sym).cloned() else {
                     return Ok(false);
                 };
-                let params = &func_macro.params.clone();
+                let params = &func_macro.params;
                 let num_args = args.len();
                 let num_params = params.len();
-                if num_args != num_params {
+                let num_required = num_params - func_macro.defaults.len();
+                if !(num_required..=num_params).contains(&num_args) {
                     return Err(Box::new(Error::FunctionMacroWrongArgCount {
                         span: *span,
                         macro_name: sym.clone(),
-                        expected: num_params,
+                        expected: if num_args < num_required {
+                            num_required
+                        } else {
+                            num_params
+                        },
                         got: num_args,
                     }));
                 }
-                let body = func_macro.body.clone();
+                let defaults =
+                    func_macro.defaults[num_args - num_required..].to_vec();
                 let mut bindings = HashMap::new();
-                for (param, mut arg) in params.iter().zip(mem::take(args)) {
+                for (param, mut arg) in params
+                    .iter()
+                    .zip(mem::take(args).into_iter().chain(defaults))
+                {
                     self.transform_deep(&mut arg)?;
                     param.pattern_match(sym, arg, &mut bindings)?;
                 }
-                *ast = interpolate(body, &bindings)?;
+                *ast = func_macro.body.instantiate(&mut bindings)?;
                 true
             }
             _ => false,
@@ -195,11 +456,51 @@ impl MacroContext<'_> {
         }
     }
 
+    /// Evaluates `(cfg key)` and `(cfg key "value")` to whether the key is set
+    /// (to that value). `target` is always set, other keys come from
+    /// `--define`.
+    fn use_cfg(&self, ast: &mut Ast) -> Result<bool> {
+        let Ast::Node(head, args, span) = ast else {
+            return Ok(false);
+        };
+        if !head.is_sym("cfg") {
+            return Ok(false);
+        }
+        let (key, key_span, value) = match &args[..] {
+            [Ast::Sym(key, key_span)] => (key, *key_span, None),
+            [Ast::Sym(key, key_span), Ast::String(value, _)] => {
+                (key, *key_span, Some(&**value))
+            }
+            _ => return Err(Box::new(Error::InvalidCfg { span: *span })),
+        };
+        let enabled = if key == "target" {
+            value.map_or(true, |value| value == self.opts.target.to_str())
+        } else if let Some(defined) = self.opts.defined(key) {
+            value.map_or(true, |value| defined == Some(value))
+        } else {
+            Warning::UnknownCfgKey {
+                span: key_span,
+                key: key.clone(),
+            }
+            .emit(
+                self.code_map,
+                self.opts.message_format,
+                self.allowed,
+            );
+            false
+        };
+        *ast = Ast::Bool(enabled, *span);
+        Ok(true)
+    }
+
     fn use_builtin_function_macros(ast: &mut Ast) -> Result<bool> {
-        let Ast::Node(box Ast::Sym(sym, ..), args, span) = ast else {
+        let Ast::Node(head, args, span) = ast else {
             return Ok(false);
         };
-        Ok(match &**sym {
+        let Ast::Sym(sym, _) = &**head else {
+            return Ok(false);
+        };
+        Ok(match sym.as_str() {
             "str-concat!" => {
                 let Some(s) = args
                     .iter()
@@ -250,10 +551,14 @@ impl MacroContext<'_> {
                 }
                 _ => false,
             },
+            "table!" => {
+                *ast = expand_table(mem::take(args), *span)?;
+                true
+            }
             "include-str" => match &args[..] {
                 [Ast::String(path, ..)] => {
-                    *ast =
-                        Ast::String(fs::read_to_string(path).unwrap(), *span);
+                    let contents = source::read(Path::new(path), Some(*span))?;
+                    *ast = Ast::String(contents, *span);
                     true
                 }
                 _ => false,
@@ -274,7 +579,7 @@ impl MacroContext<'_> {
         *tail = mem::take(tail)
             .into_iter()
             .map(|item| match &item {
-                Ast::Node(box Ast::Sym("include", ..), args, span) => {
+                Ast::Node(head, args, span) if head.is_sym("include") => {
                     self.include(args, *span)
                 }
                 _ => Ok(vec![item]),
@@ -288,14 +593,16 @@ impl MacroContext<'_> {
     }
 
     fn use_inline_macros(&mut self, ast: &mut Ast) -> Result<bool> {
-        let Ast::Node(
-            box Ast::Node(box Ast::Sym("macro", _), macro_definition, def_span),
-            args,
-            span,
-        ) = ast
+        let Ast::Node(head, args, span) = ast else {
+            return Ok(false);
+        };
+        let Ast::Node(macro_head, macro_definition, def_span) = &mut **head
         else {
             return Ok(false);
         };
+        if !macro_head.is_sym("macro") {
+            return Ok(false);
+        }
         let (macro_name, Macro::Function(func_macro)) =
             Macro::parse(mem::take(macro_definition), *def_span)?
         else {
@@ -320,22 +627,162 @@ impl MacroContext<'_> {
             self.transform_deep(&mut arg)?;
             param.pattern_match(&macro_name, arg, &mut bindings)?;
         }
-        *ast = interpolate(func_macro.body, &bindings)?;
+        *ast = func_macro.body.instantiate(&mut bindings)?;
         Ok(true)
     }
 
+    /// Replaces `(record Name field...)` in a sprite with a declaration of one
+    /// list per field and defines macros to access the records stored in
+    /// them:
+    ///
+    /// - `(new-name! field...)` appends a record,
+    /// - `(delete-name! index)` deletes a record,
+    /// - `(name-count)` is the number of records,
+    /// - `(name-field index)` gets a field of a record,
+    /// - `(set-name-field! index value)` sets a field of a record.
+    fn use_record_definitions(&mut self, ast: &mut Ast) -> Result<bool> {
+        let Ast::Node(_, tail, _) = ast else {
+            return Ok(false);
+        };
+
+        if !tail.iter().any(|item| item.is_the_function_call("record")) {
+            return Ok(false);
+        }
+
+        *tail = mem::take(tail)
+            .into_iter()
+            .map(|item| match &item {
+                Ast::Node(head, args, span) if head.is_sym("record") => {
+                    self.define_record(args, *span)
+                }
+                _ => Ok(vec![item]),
+            })
+            .collect::<Result<Vec<Vec<Ast>>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Ok(true)
+    }
+
+    /// Defines the macros of a record and returns the declarations that
+    /// replace it. Besides the lists, there is a hidden variable that
+    /// `delete-name!` stores its index in, so that it is only evaluated once.
+    fn define_record(&mut self, args: &[Ast], span: Span) -> Result<Vec<Ast>> {
+        let Some((Ast::Sym(name, _), fields)) = args.split_first() else {
+            return Err(Box::new(Error::InvalidRecordDefinition { span }));
+        };
+        let fields = fields
+            .iter()
+            .map(|field| match field {
+                Ast::Sym(field, _) => Ok(&**field),
+                _ => Err(Box::new(Error::InvalidRecordDefinition {
+                    span: field.span(),
+                })),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if fields.is_empty() {
+            return Err(Box::new(Error::InvalidRecordDefinition { span }));
+        }
+
+        let sym = |sym: &str| Ast::Sym(sym.to_owned(), span);
+        let node = |head: &str, tail: Vec<Ast>| {
+            Ast::Node(Box::new(sym(head)), tail, span)
+        };
+        let metavar = |var: &str| Ast::Unquote(Box::new(sym(var)), span);
+        let list = |field: &str| sym(&format!("{name}.{field}"));
+        let index_var = format!("%{name}.index");
+        let prefix = name.to_lowercase();
+        let mut define = |macro_name: String, params: &[&str], body: Ast| {
+            self.functions.insert(
+                macro_name,
+                Rc::new(FunctionMacro {
+                    params: params
+                        .iter()
+                        .map(|param| Parameter::Var((*param).to_owned(), span))
+                        .collect(),
+                    defaults: Vec::new(),
+                    body: Template::new(body),
+                }),
+            );
+        };
+
+        define(
+            format!("new-{prefix}!"),
+            &fields,
+            node(
+                "do",
+                fields
+                    .iter()
+                    .map(|field| {
+                        node("append", vec![list(field), metavar(field)])
+                    })
+                    .collect(),
+            ),
+        );
+        define(
+            format!("delete-{prefix}!"),
+            &["index"],
+            node(
+                "do",
+                iter::once(node(":=", vec![sym(&index_var), metavar("index")]))
+                    .chain(fields.iter().map(|field| {
+                        node("delete", vec![list(field), sym(&index_var)])
+                    }))
+                    .collect(),
+            ),
+        );
+        define(
+            format!("{prefix}-count"),
+            &[],
+            node("length", vec![list(fields[0])]),
+        );
+        for field in &fields {
+            define(
+                format!("{prefix}-{field}"),
+                &["index"],
+                node("!!", vec![list(field), metavar("index")]),
+            );
+            define(
+                format!("set-{prefix}-{field}!"),
+                &["index", "value"],
+                node(
+                    "replace",
+                    vec![list(field), metavar("index"), metavar("value")],
+                ),
+            );
+        }
+
+        Ok(vec![
+            node("variables", vec![sym(&index_var)]),
+            node("lists", fields.iter().map(|field| list(field)).collect()),
+        ])
+    }
+
     fn include(&mut self, args: &[Ast], span: Span) -> Result<Vec<Ast>> {
         match args {
             [Ast::String(path, ..)] => {
-                let source = fs::read_to_string(path).unwrap();
-                let file = self.code_map.add_file(path.clone(), source.clone());
-                let asts = program(Input {
-                    input: Located::new(&source),
-                    state: &file,
-                })?;
+                let source = source::read(Path::new(path), Some(span))?;
+                let file = self.code_map.add_file(path.clone(), source);
+                let mut asts = program(&file, self.opts.max_nesting)?;
+                if let Some(lang) = self.opts.lang {
+                    localize(&mut asts, lang, &mut self.user_names);
+                }
+                for warning in self.allowed.collect(file.span, &mut asts)? {
+                    warning.emit(
+                        self.code_map,
+                        self.opts.message_format,
+                        self.allowed,
+                    );
+                }
                 if self.opts.lint {
                     for ast in &asts {
-                        lint_ast(ast, self.code_map);
+                        lint_ast(
+                            ast,
+                            self.code_map,
+                            self.opts.message_format,
+                            self.allowed,
+                        );
                     }
                 }
                 Ok(asts)
@@ -345,52 +792,127 @@ impl MacroContext<'_> {
     }
 }
 
-fn interpolate(body: Ast, bindings: &HashMap<&str, Ast>) -> Result<Ast> {
-    Ok(match body {
-        Ast::Unquote(box Ast::Sym(var_name, span), ..) => bindings
-            .get(&*var_name)
-            .ok_or(Error::UnknownMetavariable { span, var_name })?
-            .clone(),
-        Ast::Unquote(unquoted, ..) => *unquoted,
-        Ast::Num(..) | Ast::Bool(..) | Ast::String(..) | Ast::Sym(..) => body,
-        Ast::Node(mut head, tail, span) => {
-            *head = interpolate(*head, bindings)?;
-            Ast::Node(
-                head,
-                tail.into_iter()
-                    .map(|branch| interpolate(branch, bindings))
-                    .collect::<Result<_>>()?,
-                span,
-            )
+/// The most items a table can have, which is the most that a Scratch list
+/// can hold.
+const MAX_TABLE_LEN: f64 = 200_000.0;
+
+/// Expands `(table! name expr start end)` to `(table name start items...)`,
+/// where the items are `expr` with `i` replaced by every number from `start`
+/// up to but not including `end`. They become numbers when the program is
+/// constant folded.
+fn expand_table(args: Vec<Ast>, span: Span) -> Result<Ast> {
+    let invalid = || Box::new(Error::InvalidTableDefinition { span });
+    let Ok([name @ Ast::Sym(..), expr, start, end]) = <[_; 4]>::try_from(args)
+    else {
+        return Err(invalid());
+    };
+    let (Ast::Num(start, start_span), Ast::Num(end, _)) = (start, end) else {
+        return Err(invalid());
+    };
+    let len = (end - start).ceil();
+    if !(0.0..=MAX_TABLE_LEN).contains(&len) {
+        return Err(invalid());
+    }
+    let items = (0..len as usize)
+        .map(|k| {
+            Substitute {
+                var: "i",
+                value: start + k as f64,
+            }
+            .fold(expr.clone())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Ast::Node(
+        Box::new(Ast::Sym("table".to_owned(), span)),
+        [name, Ast::Num(start, start_span)]
+            .into_iter()
+            .chain(items)
+            .collect(),
+        span,
+    ))
+}
+
+/// Replaces a symbol with a number everywhere in an AST.
+struct Substitute<'a> {
+    var: &'a str,
+    value: f64,
+}
+
+impl Fold for Substitute<'_> {
+    type Error = Box<Error>;
+
+    fn fold(&mut self, ast: Ast) -> Result<Ast> {
+        match ast {
+            Ast::Sym(sym, span) if sym == self.var => {
+                Ok(Ast::Num(self.value, span))
+            }
+            _ => fold_children(self, ast),
         }
-    })
+    }
+}
+
+/// Renders an AST on one line, cutting it off if it is too long to read.
+fn snippet(ast: &Ast) -> String {
+    const MAX_LEN: usize = 72;
+    let rendered = ast.to_string().replace('\n', " ");
+    if rendered.chars().count() <= MAX_LEN {
+        return rendered;
+    }
+    let mut cut = rendered.chars().take(MAX_LEN - 3).collect::<String>();
+    cut.push_str("...");
+    cut
 }
 
 struct FunctionMacro {
     params: Vec<Parameter>,
-    body: Ast,
+    /// The default values of the trailing parameters that have one.
+    defaults: Vec<Ast>,
+    body: Template,
+}
+
+/// Splits a parameter written as `(param = default)` into its parts.
+fn split_default(ast: Ast) -> (Ast, Option<Ast>) {
+    match ast {
+        Ast::Node(param, tail, span) => match <[Ast; 2]>::try_from(tail) {
+            Ok([Ast::Sym(eq, _), default]) if eq == "=" => {
+                (*param, Some(default))
+            }
+            Ok(tail) => (Ast::Node(param, tail.into(), span), None),
+            Err(tail) => (Ast::Node(param, tail, span), None),
+        },
+        _ => (ast, None),
+    }
 }
 
 #[derive(Clone)]
 enum Parameter {
-    Var(String),
+    Var(String, Span),
+    /// `_`, which matches anything without binding it.
+    Ignore,
+    /// A number, string or boolean, which only matches the same literal.
+    Literal(Ast),
     Constructor(String, Vec<Parameter>, Span),
 }
 
 impl Parameter {
     fn from_ast(ast: Ast) -> Result<Self> {
         match ast {
-            Ast::Sym(var, _) => Ok(Self::Var(var)),
-            Ast::Node(box Ast::Sym(name, _), subparams, span) => {
-                Ok(Self::Constructor(
+            Ast::Sym(var, _) if var == "_" => Ok(Self::Ignore),
+            Ast::Sym(var, span) => Ok(Self::Var(var, span)),
+            Ast::Num(..) | Ast::String(..) | Ast::Bool(..) | Ast::Color(..) => {
+                Ok(Self::Literal(ast))
+            }
+            Ast::Node(head, subparams, span) => match *head {
+                Ast::Sym(name, _) => Ok(Self::Constructor(
                     name,
                     subparams
                         .into_iter()
                         .map(Self::from_ast)
                         .collect::<Result<_>>()?,
                     span,
-                ))
-            }
+                )),
+                _ => Err(Box::new(Error::InvalidMacroParameter { span })),
+            },
             _ => {
                 Err(Box::new(Error::InvalidMacroParameter { span: ast.span() }))
             }
@@ -403,14 +925,45 @@ impl Parameter {
         ast: Ast,
         bindings: &mut HashMap<&'a str, Ast>,
     ) -> Result<()> {
+        // Quoted arguments are bound as they are but destructured by their
+        // contents.
+        if !matches!(self, Self::Var(..) | Self::Ignore) {
+            if let Ast::Quote(quoted, _) = ast {
+                return self.pattern_match(macro_name, *quoted, bindings);
+            }
+        }
         match self {
-            Self::Var(var) => {
-                assert!(bindings.insert(var, ast).is_none());
+            Self::Var(var, _) => {
+                // Parameters bound more than once were warned about when the
+                // macro was defined. The last binding wins.
+                bindings.insert(var, ast);
                 Ok(())
             }
+            Self::Ignore => Ok(()),
+            Self::Literal(literal) => {
+                let matches = match (literal, &ast) {
+                    (Ast::Num(a, _), Ast::Num(b, _)) => a == b,
+                    (Ast::String(a, _), Ast::String(b, _)) => a == b,
+                    (Ast::Bool(a, _), Ast::Bool(b, _)) => a == b,
+                    (Ast::Color(a, _), Ast::Color(b, _)) => {
+                        a.eq_ignore_ascii_case(b)
+                    }
+                    _ => false,
+                };
+                if matches {
+                    Ok(())
+                } else {
+                    Err(Box::new(Error::FunctionMacroMatchFailed {
+                        pattern: literal.span(),
+                        provided: ast.span(),
+                        macro_name: macro_name.to_owned(),
+                    }))
+                }
+            }
             Self::Constructor(name, subparams, span) => match ast {
-                Ast::Node(box Ast::Sym(sym, _), subtrees, _)
-                    if sym == *name && subparams.len() == subtrees.len() =>
+                Ast::Node(head, subtrees, _)
+                    if head.is_sym(name)
+                        && subparams.len() == subtrees.len() =>
                 {
                     for (p, t) in subparams.iter().zip(subtrees) {
                         p.pattern_match(macro_name, t, bindings)?;
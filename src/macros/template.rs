@@ -0,0 +1,206 @@
+//! Bodies of function macros, kept in an arena of nodes that refer to each
+//! other by index. Parts of a body without metavariables are stored as they
+//! are, so expanding a macro copies them and only rebuilds the nodes that
+//! arguments are substituted into, instead of copying the whole body and
+//! then substituting into the copy.
+
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+};
+use codemap::Span;
+use std::{collections::HashMap, iter};
+
+pub struct Template {
+    nodes: Vec<Node>,
+    root: NodeId,
+}
+
+#[derive(Clone, Copy)]
+struct NodeId(u32);
+
+enum Node {
+    /// Copied as it is.
+    Verbatim(Ast),
+    /// A metavariable. Its value is moved out of the bindings where it is
+    /// used for the last time and copied everywhere else.
+    Var {
+        name: String,
+        span: Span,
+        last_use: bool,
+    },
+    List(NodeId, Vec<NodeId>, Span),
+    Unquote(NodeId, Span),
+    Quote(NodeId, Span),
+}
+
+/// Either a node in the arena, or a part of the body that doesn't need one
+/// since it has no metavariables.
+enum Built {
+    Node(NodeId),
+    Verbatim(Ast),
+}
+
+impl Template {
+    pub fn new(body: Ast) -> Self {
+        let mut template = Self {
+            nodes: Vec::new(),
+            root: NodeId(0),
+        };
+        let mut last_uses = HashMap::new();
+        template.root = match template.build(body, 0, &mut last_uses) {
+            Built::Node(id) => id,
+            Built::Verbatim(ast) => template.push(Node::Verbatim(ast)),
+        };
+        for id in last_uses.into_values() {
+            if let Node::Var { last_use, .. } =
+                &mut template.nodes[id.0 as usize]
+            {
+                *last_use = true;
+            }
+        }
+        template
+    }
+
+    /// Adds the nodes of `ast`, which is inside `depth` more quotes than
+    /// unquotes. Only unquotes that aren't inside more quotes than unquotes
+    /// are substituted: in `'(+ ,x ,,y)`, only `y` is a metavariable.
+    /// Metavariables are added in the order that they are expanded in, so
+    /// the last one added for each name is its last use.
+    fn build(
+        &mut self,
+        ast: Ast,
+        depth: usize,
+        last_uses: &mut HashMap<String, NodeId>,
+    ) -> Built {
+        match ast {
+            Ast::Unquote(unquoted, _) if depth == 0 => match *unquoted {
+                Ast::Sym(name, span) => {
+                    let id = self.push(Node::Var {
+                        name: name.clone(),
+                        span,
+                        last_use: false,
+                    });
+                    last_uses.insert(name, id);
+                    Built::Node(id)
+                }
+                unquoted => Built::Verbatim(unquoted),
+            },
+            Ast::Unquote(inner, span) => {
+                match self.build(*inner, depth - 1, last_uses) {
+                    Built::Node(inner) => {
+                        Built::Node(self.push(Node::Unquote(inner, span)))
+                    }
+                    Built::Verbatim(inner) => {
+                        Built::Verbatim(Ast::Unquote(Box::new(inner), span))
+                    }
+                }
+            }
+            Ast::Quote(inner, span) => {
+                match self.build(*inner, depth + 1, last_uses) {
+                    Built::Node(inner) => {
+                        Built::Node(self.push(Node::Quote(inner, span)))
+                    }
+                    Built::Verbatim(inner) => {
+                        Built::Verbatim(Ast::Quote(Box::new(inner), span))
+                    }
+                }
+            }
+            Ast::Node(head, tail, span) => {
+                let head = self.build(*head, depth, last_uses);
+                let tail = tail
+                    .into_iter()
+                    .map(|branch| self.build(branch, depth, last_uses))
+                    .collect::<Vec<_>>();
+                let all_verbatim = matches!(head, Built::Verbatim(_))
+                    && tail
+                        .iter()
+                        .all(|branch| matches!(branch, Built::Verbatim(_)));
+                if all_verbatim {
+                    let mut verbatim = iter_verbatim(head, tail);
+                    let head = verbatim.next().unwrap();
+                    return Built::Verbatim(Ast::Node(
+                        Box::new(head),
+                        verbatim.collect(),
+                        span,
+                    ));
+                }
+                let head = self.node(head);
+                let tail =
+                    tail.into_iter().map(|branch| self.node(branch)).collect();
+                Built::Node(self.push(Node::List(head, tail, span)))
+            }
+            _ => Built::Verbatim(ast),
+        }
+    }
+
+    fn node(&mut self, built: Built) -> NodeId {
+        match built {
+            Built::Node(id) => id,
+            Built::Verbatim(ast) => self.push(Node::Verbatim(ast)),
+        }
+    }
+
+    fn push(&mut self, node: Node) -> NodeId {
+        let id = NodeId(self.nodes.len().try_into().unwrap());
+        self.nodes.push(node);
+        id
+    }
+
+    /// Builds the expansion of the macro, taking the values of metavariables
+    /// from `bindings`.
+    pub fn instantiate(
+        &self,
+        bindings: &mut HashMap<&str, Ast>,
+    ) -> Result<Ast> {
+        self.instantiate_node(self.root, bindings)
+    }
+
+    fn instantiate_node(
+        &self,
+        id: NodeId,
+        bindings: &mut HashMap<&str, Ast>,
+    ) -> Result<Ast> {
+        Ok(match &self.nodes[id.0 as usize] {
+            Node::Verbatim(ast) => ast.clone(),
+            Node::Var {
+                name,
+                span,
+                last_use,
+            } => {
+                let value = if *last_use {
+                    bindings.remove(name.as_str())
+                } else {
+                    bindings.get(name.as_str()).cloned()
+                };
+                value.ok_or_else(|| Error::UnknownMetavariable {
+                    span: *span,
+                    var_name: name.clone(),
+                })?
+            }
+            Node::List(head, tail, span) => Ast::Node(
+                Box::new(self.instantiate_node(*head, bindings)?),
+                tail.iter()
+                    .map(|&branch| self.instantiate_node(branch, bindings))
+                    .collect::<Result<_>>()?,
+                *span,
+            ),
+            Node::Unquote(inner, span) => Ast::Unquote(
+                Box::new(self.instantiate_node(*inner, bindings)?),
+                *span,
+            ),
+            Node::Quote(inner, span) => Ast::Quote(
+                Box::new(self.instantiate_node(*inner, bindings)?),
+                *span,
+            ),
+        })
+    }
+}
+
+/// The ASTs of a head and tail that are all verbatim.
+fn iter_verbatim(head: Built, tail: Vec<Built>) -> impl Iterator<Item = Ast> {
+    iter::once(head).chain(tail).map(|built| match built {
+        Built::Verbatim(ast) => ast,
+        Built::Node(_) => unreachable!(),
+    })
+}
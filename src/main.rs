@@ -1,36 +1,77 @@
 #![forbid(unsafe_code)]
-#![feature(box_patterns)]
-#![feature(string_deref_patterns)]
-#![feature(extract_if)]
-#![feature(let_chains)]
 
+mod allow;
 mod asset;
+mod assigned;
 mod ast;
 mod codegen;
 mod diagnostic;
 mod ir;
+mod lexer;
 mod lint;
+mod locale;
 mod macros;
 mod optimize;
 mod opts;
 mod parser;
+mod pattern;
+mod remarks;
+mod source;
+mod span;
+mod stats;
+mod typecheck;
 mod uid;
 
 use crate::{
-    codegen::write_program, ir::Program, lint::lint_ast, macros::expand,
-    opts::Opts, parser::Input,
+    allow::Allowed,
+    codegen::{load_extensions, write_program, Stamp},
+    ir::{polyfill, Program},
+    lint::{lint_ast, lint_program, magic_numbers},
+    macros::expand,
+    optimize::{rewrite::load_rules, Optimizer},
+    opts::{Opts, Target},
+    remarks::remarks,
+    stats::Stats,
 };
 use codemap::CodeMap;
 use gumdrop::Options;
-use std::{fs, process::ExitCode};
-use winnow::stream::Located;
+use std::{env, process::ExitCode};
 
 fn main() -> ExitCode {
-    let opts = Opts::parse_args_default_or_exit();
-    let input = match fs::read_to_string(&opts.file) {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if let [subcommand, code] = &args[..] {
+        if subcommand == "explain" {
+            return explain(code);
+        }
+    }
+
+    // `lint FILE [OPTIONS]` reviews the whole project instead of compiling it.
+    let lint_project = args.first().is_some_and(|arg| arg == "lint");
+    let opts = if lint_project {
+        match Opts::parse_args_default(&args[1..]) {
+            Ok(opts) if opts.help_requested() => {
+                println!("Usage: scratch-compiler lint FILE [OPTIONS]\n");
+                println!("{}", Opts::usage());
+                return ExitCode::SUCCESS;
+            }
+            Ok(opts) => opts,
+            Err(err) => {
+                eprintln!("scratch-compiler: {err}");
+                return ExitCode::from(2);
+            }
+        }
+    } else {
+        Opts::parse_args_default_or_exit()
+    };
+    let input = match source::read(&opts.file, None) {
         Ok(input) => input,
         Err(err) => {
-            eprintln!("IO error: {err}");
+            diagnostic::emit_errors(
+                &[*err],
+                &CodeMap::new(),
+                opts.message_format,
+                opts.max_errors,
+            );
             return ExitCode::FAILURE;
         }
     };
@@ -39,24 +80,123 @@ fn main() -> ExitCode {
     let main_file =
         code_map.add_file(opts.file.display().to_string(), input.clone());
 
-    if let Err(err) = parser::program(Input {
-        input: Located::new(&input),
-        state: &main_file,
-    })
-    .and_then(|asts| {
-        if opts.lint {
-            for ast in &asts {
-                lint_ast(ast, &code_map);
+    if let Err(err) =
+        parser::program(&main_file, opts.max_nesting).and_then(|mut asts| {
+            let mut allowed = Allowed::default();
+            for warning in allowed.collect(main_file.span, &mut asts)? {
+                warning.emit(&code_map, opts.message_format, &allowed);
             }
-        }
-        let expanded = expand(asts, &opts, &mut code_map)?;
-        let mut program = Program::from_asts(expanded)?;
-        program.optimize();
-        write_program(&program, &opts)
-    }) {
-        err.emit(&code_map);
+            if opts.lint || lint_project {
+                for ast in &asts {
+                    lint_ast(ast, &code_map, opts.message_format, &allowed);
+                }
+            }
+            if lint_project {
+                for warning in magic_numbers(&asts) {
+                    warning.emit(&code_map, opts.message_format, &allowed);
+                }
+            }
+            let expanded = expand(asts, &opts, &mut code_map, &mut allowed)?;
+            let mut program = Program::from_asts(expanded)?;
+            typecheck::check(&program, opts.strict_types)?;
+            for warning in assigned::check(&program, opts.strict_types)? {
+                warning.emit(&code_map, opts.message_format, &allowed);
+            }
+            for warning in program
+                .shadowing_warnings()
+                .into_iter()
+                .chain(program.busy_loop_warnings())
+            {
+                warning.emit(&code_map, opts.message_format, &allowed);
+            }
+            if lint_project {
+                let warnings =
+                    lint_program(&program, opts.max_statements, opts.max_depth);
+                for warning in warnings {
+                    warning.emit(&code_map, opts.message_format, &allowed);
+                }
+                return Ok(());
+            }
+            match opts.target {
+                Target::SB3 => polyfill::apply(&mut program, &mut code_map)?,
+                Target::X86_64 => {
+                    for warning in program.stub_video_sensing(opts.video_stub) {
+                        warning.emit(&code_map, opts.message_format, &allowed);
+                    }
+                }
+            }
+            // Even debug builds are optimized since some builtins can only be
+            // compiled for Scratch once their arguments are constant folded.
+            let parsed = opts.stats.then(|| Stats::of(&program));
+            let rules = match &opts.rewrite_rules {
+                Some(path) => {
+                    load_rules(path, &mut code_map, opts.max_nesting)?
+                }
+                None => Vec::new(),
+            };
+            let extensions = load_extensions(
+                &opts.extension,
+                &mut code_map,
+                opts.max_nesting,
+            )?;
+            let mut optimizer =
+                Optimizer::new(opts.fast_math, opts.target, rules);
+            optimizer.run(
+                &mut program,
+                &opts.passes(),
+                opts.print_after_all,
+                &code_map,
+            );
+            for warning in optimizer.warnings() {
+                warning.emit(&code_map, opts.message_format, &allowed);
+            }
+            program.check_static_asserts()?;
+            program.check_tables()?;
+            if matches!(opts.target, Target::X86_64) {
+                program.infer_param_types();
+            } else {
+                program.tables_to_lists()?;
+                if opts.flatten_scripts {
+                    program.flatten_scripts();
+                }
+            }
+            if opts.remarks {
+                for remark in remarks(&program) {
+                    remark.emit(&code_map, opts.message_format);
+                }
+            }
+            let stamp = opts.stamp.then(|| Stamp::new(&input));
+            write_program(
+                &program,
+                &opts,
+                &code_map,
+                stamp.as_ref(),
+                &extensions,
+            )?;
+            if let Some(parsed) = parsed {
+                Stats::report(&parsed, &Stats::of(&program), &opts);
+            }
+            Ok(())
+        })
+    {
+        diagnostic::emit_errors(
+            &[*err],
+            &code_map,
+            opts.message_format,
+            opts.max_errors,
+        );
         return ExitCode::FAILURE;
     }
 
     ExitCode::SUCCESS
 }
+
+fn explain(code: &str) -> ExitCode {
+    if let Some(explanation) = diagnostic::explain(code) {
+        print!("{explanation}");
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("error: no extended information for `{code}`");
+        ExitCode::FAILURE
+    }
+}
@@ -1,2 +1,151 @@
+pub mod arguments;
 pub mod expr;
+pub mod rewrite;
 pub mod statement;
+pub mod static_lists;
+
+use crate::{
+    diagnostic::Warning,
+    ir::{
+        expr::Expr,
+        verify::{assert_no_new_violations, verify},
+        Program,
+    },
+    opts::{Pass, Target},
+};
+use codemap::CodeMap;
+use codemap::Span;
+use rewrite::Rule;
+
+/// The most times that rules given with `--rewrite-rules` are applied.
+const MAX_REWRITES: usize = 100_000;
+
+/// State shared by the optimization passes.
+pub struct Optimizer {
+    /// Whether to make rewrites that aren't exact for every value.
+    fast_math: bool,
+    /// Some rewrites are only inexact for some targets.
+    target: Target,
+    /// Inexact rewrites that were skipped, along with why they aren't exact.
+    skipped: Vec<(Span, &'static str)>,
+    /// Extra rules given with `--rewrite-rules`.
+    rules: Vec<Rule>,
+    /// How many more times rules may be applied. Rules can match their own
+    /// result, which would otherwise never stop.
+    rewrites_left: usize,
+    /// The rule that was applied last before running out of rewrites.
+    looping_rule: Option<Span>,
+    /// The pass that is running, which decides the rules that are applied.
+    pass: Pass,
+}
+
+impl Optimizer {
+    pub const fn new(
+        fast_math: bool,
+        target: Target,
+        rules: Vec<Rule>,
+    ) -> Self {
+        Self {
+            fast_math,
+            target,
+            skipped: Vec::new(),
+            rules,
+            rewrites_left: MAX_REWRITES,
+            looping_rule: None,
+            pass: Pass::Fold,
+        }
+    }
+
+    /// Runs the passes in order until none of them change the program,
+    /// optionally printing the IR after each one. Debug builds of the
+    /// compiler verify the IR after every pass.
+    pub fn run(
+        &mut self,
+        program: &mut Program,
+        passes: &[Pass],
+        print_after_all: bool,
+        code_map: &CodeMap,
+    ) {
+        let baseline = if cfg!(debug_assertions) {
+            verify(program)
+        } else {
+            Vec::new()
+        };
+        while {
+            let mut dirty = false;
+            for &pass in passes {
+                self.pass = pass;
+                dirty |= match pass {
+                    Pass::ConstArgs => arguments::propagate(program),
+                    Pass::StaticLists => static_lists::rewrite(program),
+                    _ => program.optimize(self),
+                };
+                if cfg!(debug_assertions) {
+                    assert_no_new_violations(
+                        &baseline,
+                        &verify(program),
+                        pass.to_str(),
+                        code_map,
+                    );
+                }
+                if print_after_all {
+                    eprintln!("; IR after {}\n{program:#?}", pass.to_str());
+                }
+            }
+            dirty
+        } {}
+    }
+
+    /// Applies every matching rule given with `--rewrite-rules`, until
+    /// [`MAX_REWRITES`] have been made.
+    fn rewrite(&mut self, expr: &mut Expr) -> bool {
+        let mut dirty = false;
+        for rule in &self.rules {
+            if self.rewrites_left == 0 {
+                break;
+            }
+            if rule.apply(expr) {
+                dirty = true;
+                self.rewrites_left -= 1;
+                if self.rewrites_left == 0 {
+                    self.looping_rule = Some(rule.span);
+                }
+            }
+        }
+        dirty
+    }
+
+    /// Decides whether to make a rewrite, which is always allowed if it is
+    /// `exact` and otherwise only with `--fast-math`. Skipped rewrites are
+    /// remembered so that they can be reported.
+    fn allow(
+        &mut self,
+        exact: bool,
+        span: Option<Span>,
+        reason: &'static str,
+    ) -> bool {
+        if exact || self.fast_math {
+            return true;
+        }
+        if let Some(span) = span {
+            if !self.skipped.contains(&(span, reason)) {
+                self.skipped.push((span, reason));
+            }
+        }
+        false
+    }
+
+    /// Warnings for the rewrites that were skipped.
+    pub fn warnings(self) -> impl Iterator<Item = Warning> {
+        self.skipped
+            .into_iter()
+            .map(|(span, reason)| Warning::InexactRewriteSkipped {
+                span,
+                reason,
+            })
+            .chain(self.looping_rule.map(|span| Warning::RewriteLimit {
+                span,
+                limit: MAX_REWRITES,
+            }))
+    }
+}
@@ -0,0 +1,165 @@
+//! Constant propagation into custom procedures. A parameter that every call
+//! passes the same literal to is replaced by that literal in the body of the
+//! procedure, so that the other passes can fold it. Wrappers generated by
+//! macros are often only called like this.
+
+use crate::ir::{
+    expr::Expr,
+    proc::{is_event, Procedure},
+    sprite::Sprite,
+    statement::Statement,
+    Program,
+};
+use sb3_stuff::Value;
+use std::{
+    collections::{BTreeSet, HashMap},
+    mem, slice,
+};
+
+/// What the calls of a procedure pass for one of its parameters.
+#[derive(Clone)]
+enum Passed {
+    Nothing,
+    Always(Value),
+    Varies,
+}
+
+impl Passed {
+    fn add(&mut self, arg: &Expr) {
+        *self = match (mem::replace(self, Self::Varies), arg) {
+            (Self::Nothing, Expr::Imm(value)) => Self::Always(value.clone()),
+            (Self::Always(old), Expr::Imm(value)) if same(&old, value) => {
+                Self::Always(old)
+            }
+            _ => Self::Varies,
+        };
+    }
+}
+
+/// Returns whether anything changed.
+pub fn propagate(program: &mut Program) -> bool {
+    let mut dirty = propagate_in(&mut program.stage, None);
+    for sprite in program.sprites.values_mut() {
+        dirty |= propagate_in(sprite, Some(&program.stage.lists));
+    }
+    dirty
+}
+
+fn propagate_in(
+    sprite: &mut Sprite,
+    global_lists: Option<&BTreeSet<String>>,
+) -> bool {
+    let mut passed = sprite
+        .procedures
+        .iter()
+        .filter(|(name, _)| !is_event(name))
+        .filter_map(|(name, procs)| match &procs[..] {
+            [proc] => {
+                Some((name.clone(), vec![Passed::Nothing; proc.params.len()]))
+            }
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    for proc in sprite.procedures.values().flatten() {
+        proc.body.any(&mut |stmt| {
+            if let Statement::ProcCall {
+                proc_name, args, ..
+            } = stmt
+            {
+                if let Some(params) = passed.get_mut(&**proc_name) {
+                    if args.len() == params.len() {
+                        for (param, arg) in params.iter_mut().zip(args) {
+                            param.add(arg);
+                        }
+                    } else {
+                        params.fill(Passed::Varies);
+                    }
+                }
+            }
+            false
+        });
+    }
+
+    let mut dirty = false;
+    for (name, params) in passed {
+        let proc = &mut sprite.procedures.get_mut(&name).unwrap()[0];
+        for (i, passed) in params.into_iter().enumerate() {
+            let (Passed::Always(value), (Expr::Sym(param, _), _)) =
+                (passed, &proc.params[i])
+            else {
+                continue;
+            };
+            let param = param.clone();
+            // Parameters only shadow variables, not lists.
+            let is_list = proc.lists.contains(&*param)
+                || sprite.lists.contains(&*param)
+                || global_lists.is_some_and(|lists| lists.contains(&*param));
+            if !is_list && !assigns(proc, &param) {
+                dirty |= substitute(&mut proc.body, &param, &value);
+            }
+        }
+    }
+    dirty
+}
+
+/// Whether the body of a procedure uses a name as the target of an
+/// assignment, where it can't be replaced by a value.
+fn assigns(proc: &Procedure, name: &str) -> bool {
+    proc.body.any(&mut |stmt| match stmt {
+        Statement::ProcCall {
+            proc_name, args, ..
+        } => {
+            let target = args.first();
+            matches!(&**proc_name, ":=" | "+=")
+                && matches!(target, Some(Expr::Sym(var, _)) if var == name)
+        }
+        Statement::For { counter, .. } => counter.0 == name,
+        _ => false,
+    })
+}
+
+/// Replaces every use of `param` in `body` with `value`.
+fn substitute(body: &mut Statement, param: &str, value: &Value) -> bool {
+    let mut dirty = false;
+    body.traverse_postorder_mut(&mut |stmt| {
+        for expr in own_exprs(stmt) {
+            expr.traverse_postorder_mut(&mut |expr| {
+                if matches!(expr, Expr::Sym(sym, _) if sym == param) {
+                    *expr = Expr::Imm(value.clone());
+                    dirty = true;
+                }
+            });
+        }
+    });
+    dirty
+}
+
+/// The expressions of a statement, not counting those of nested statements.
+pub(super) fn own_exprs(stmt: &mut Statement) -> &mut [Expr] {
+    match stmt {
+        Statement::ProcCall { args, .. } => args,
+        Statement::IfElse {
+            condition: expr, ..
+        }
+        | Statement::Repeat { times: expr, .. }
+        | Statement::Until {
+            condition: expr, ..
+        }
+        | Statement::While {
+            condition: expr, ..
+        }
+        | Statement::For { times: expr, .. } => slice::from_mut(expr),
+        Statement::Do(_) | Statement::Forever(..) => &mut [],
+    }
+}
+
+/// Whether two literals are the same, which for numbers means the same bits
+/// so that `0` and `-0` stay apart.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Num(a), Value::Num(b)) => a.to_bits() == b.to_bits(),
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        _ => false,
+    }
+}
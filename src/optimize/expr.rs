@@ -1,15 +1,29 @@
-use crate::ir::expr::Expr::{self, *};
+use crate::{
+    ir::expr::Expr::{self, *},
+    optimize::Optimizer,
+    opts::{Pass, Target},
+    pattern::Pattern,
+};
 use sb3_stuff::Value;
 use std::mem;
 
-pub fn optimize_expr(expr: &mut Expr) -> bool {
+pub fn optimize_expr(expr: &mut Expr, optimizer: &mut Optimizer) -> bool {
     let mut dirty = false;
     while {
         let mut this_step_dirty = false;
-        expr.traverse_postorder_mut(&mut |e| {
-            for f in EXPR_OPTIMIZATIONS {
-                this_step_dirty |= f(e);
+        expr.traverse_postorder_mut(&mut |e| match optimizer.pass {
+            Pass::Fold => {
+                for f in EXPR_OPTIMIZATIONS {
+                    this_step_dirty |= f(e);
+                }
+            }
+            Pass::Inexact => {
+                for f in INEXACT_OPTIMIZATIONS {
+                    this_step_dirty |= f(e, optimizer);
+                }
             }
+            Pass::Rewrite => this_step_dirty |= optimizer.rewrite(e),
+            Pass::ControlFlow | Pass::ConstArgs | Pass::StaticLists => {}
         });
         this_step_dirty
     } {
@@ -21,57 +35,86 @@ pub fn optimize_expr(expr: &mut Expr) -> bool {
 const EXPR_OPTIMIZATIONS: &[fn(&mut Expr) -> bool] = &[
     const_add_sub,
     const_mul_div,
-    add_sub_zero,
-    mul_zero,
+    sub_zero,
     mul_div_one,
     trigonometry,
     flatten_add_sub,
     flatten_mul_div,
+    div_by_power_of_two,
     mul_div_negation,
     distribute_mul_into_sum,
     redundant_to_num,
     const_mathops,
+    const_mod,
+    const_str_match,
+    const_formatting,
+    const_bitwise,
+    const_min_max,
+    const_geometry,
+    const_char_at,
     empty_call,
     flatten_unary_call,
 ];
 
+/// Rewrites that change the result for some values, which are only made when
+/// those values can be ruled out or with `--fast-math`.
+const INEXACT_OPTIMIZATIONS: &[fn(&mut Expr, &mut Optimizer) -> bool] = &[
+    mul_zero,
+    add_zero,
+    cancel_add_sub,
+    single_term,
+    imms_last,
+];
+
 /// Constant folding for addition and subtraction.
 fn const_add_sub(expr: &mut Expr) -> bool {
-    if let AddSub(positives, negatives) = expr
-      && positives.iter().chain(&*negatives).filter(|term| term.is_imm()).take(2).count() == 2
-    {
-        let positive_sum: f64 = drain_imms(positives).map(|term| term.to_num()).sum();
-        let negative_sum: f64 = drain_imms(negatives).map(|term| term.to_num()).sum();
-        let sum = positive_sum - negative_sum;
-        positives.push(Imm(Value::Num(sum)));
-        true
-    } else {
-        false
+    let AddSub(positives, negatives, _) = expr else {
+        return false;
+    };
+    if positives.iter().chain(&*negatives).filter(|term| term.is_imm()).take(2).count() < 2 {
+        return false;
     }
+    let positive_sum: f64 = drain_imms(positives).map(|term| term.to_num()).sum();
+    let negative_sum: f64 = drain_imms(negatives).map(|term| term.to_num()).sum();
+    let sum = positive_sum - negative_sum;
+    positives.push(Imm(Value::Num(sum)));
+    true
 }
 
 /// Constant folding for multiplication and division.
 fn const_mul_div(expr: &mut Expr) -> bool {
-    if let MulDiv(numerators, denominators) = expr
-      && numerators.iter().chain(&*denominators).filter(|term| term.is_imm()).take(2).count() == 2
-    {
-        let numerator: f64 = drain_imms(numerators).map(|term| term.to_num()).product();
-        let denominator: f64 = drain_imms(denominators).map(|term| term.to_num()).product();
-        let product = numerator / denominator;
-        numerators.push(Imm(Value::Num(product)));
-        true
-    } else {
-        false
+    let MulDiv(numerators, denominators, _) = expr else {
+        return false;
+    };
+    if numerators.iter().chain(&*denominators).filter(|term| term.is_imm()).take(2).count() < 2 {
+        return false;
     }
+    let numerator: f64 = drain_imms(numerators).map(|term| term.to_num()).product();
+    let denominator: f64 = drain_imms(denominators).map(|term| term.to_num()).product();
+    let product = numerator / denominator;
+    numerators.push(Imm(Value::Num(product)));
+    true
 }
 
-/// Multiplication by 0.
-fn mul_zero(expr: &mut Expr) -> bool {
-    if let MulDiv(numerators, _) = expr
-      && numerators.iter().any(
-             |arg| matches!(arg, Imm(Value::Num(num)) if *num == 0.0),
-         )
-    {
+/// Multiplication by 0, which would give NaN for infinities and negative zero
+/// for negative numbers. The other factors aren't evaluated anymore, so they
+/// must not have effects.
+fn mul_zero(expr: &mut Expr, optimizer: &mut Optimizer) -> bool {
+    let span = expr.span();
+    if !expr.effect().can_drop() {
+        return false;
+    }
+    let MulDiv(numerators, _, _) = expr else {
+        return false;
+    };
+    if numerators.iter().any(
+        |arg| matches!(arg, Imm(Value::Num(num)) if *num == 0.0),
+    ) && optimizer.allow(
+        false,
+        span,
+        "multiplying by zero doesn't give zero for infinities, NaN or \
+        negative numbers",
+    ) {
         *expr = Expr::Imm(Value::Num(0.0));
         true
     } else {
@@ -81,7 +124,7 @@ fn mul_zero(expr: &mut Expr) -> bool {
 
 /// Multiplication and division by 1.
 fn mul_div_one(expr: &mut Expr) -> bool {
-    if let MulDiv(numerators, denominators) = expr {
+    if let MulDiv(numerators, denominators, _) = expr {
         for terms in &mut [numerators, denominators] {
             if let Some(index) = terms.iter().position(
                 |arg| matches!(arg, Imm(Value::Num(num)) if *num == 1.0),
@@ -94,19 +137,42 @@ fn mul_div_one(expr: &mut Expr) -> bool {
     false
 }
 
-/// Addition and subtraction with 0.
-fn add_sub_zero(expr: &mut Expr) -> bool {
-    if let AddSub(positives, negatives) = expr {
-        for terms in &mut [positives, negatives] {
-            if let Some(index) = terms.iter().position(
-                |arg| matches!(arg, Imm(Value::Num(num)) if *num == 0.0),
-            ) {
-                terms.swap_remove(index);
-                return true;
-            }
-        }
+/// Subtraction of 0.
+fn sub_zero(expr: &mut Expr) -> bool {
+    let AddSub(_, negatives, _) = expr else {
+        return false;
+    };
+    if let Some(index) = negatives.iter().position(
+        |arg| matches!(arg, Imm(Value::Num(num)) if *num == 0.0),
+    ) {
+        negatives.swap_remove(index);
+        true
+    } else {
+        false
+    }
+}
+
+/// Addition of 0, which would turn negative zero into zero.
+fn add_zero(expr: &mut Expr, optimizer: &mut Optimizer) -> bool {
+    let span = expr.span();
+    let AddSub(positives, _, _) = expr else {
+        return false;
+    };
+    let Some(index) = positives.iter().position(
+        |arg| matches!(arg, Imm(Value::Num(num)) if *num == 0.0),
+    ) else {
+        return false;
+    };
+    if optimizer.allow(
+        false,
+        span,
+        "adding zero turns negative zero into zero",
+    ) {
+        positives.swap_remove(index);
+        true
+    } else {
+        false
     }
-    false
 }
 
 /// Trigonometric identities
@@ -114,39 +180,35 @@ fn add_sub_zero(expr: &mut Expr) -> bool {
 /// - `(sin (- n))` => `(- (sin n))`
 /// - `(cos (- n))` => `(cos n)`
 fn trigonometry(expr: &mut Expr) -> bool {
-    if let FuncCall("sin", span, args) = expr
-      && let [AddSub(positives, negatives)] = &mut args[..]
-      && positives.is_empty()
-      && negatives.len() == 1
-    {
-        *expr = AddSub(Vec::new(), vec![FuncCall("sin", *span, mem::take(negatives))]);
-        true
-    } else if let FuncCall("cos", _, args) = expr
-      && let [AddSub(positives, negatives)] = &mut args[..]
-      && positives.is_empty()
-      && negatives.len() == 1
-    {
-        *args = mem::take(negatives);
-        true
-    } else {
-        false
+    if let FuncCall("sin", span, args) = expr {
+        if let [AddSub(positives, negatives, minus)] = &mut args[..] {
+            if positives.is_empty() && negatives.len() == 1 {
+                *expr = AddSub(Vec::new(), vec![FuncCall("sin", *span, mem::take(negatives))], *minus);
+                return true;
+            }
+        }
+    } else if let FuncCall("cos", _, args) = expr {
+        if let [AddSub(positives, negatives, _)] = &mut args[..] {
+            if positives.is_empty() && negatives.len() == 1 {
+                *args = mem::take(negatives);
+                return true;
+            }
+        }
     }
+    false
 }
 
 /// Flattens nested addition and subtraction.
 fn flatten_add_sub(expr: &mut Expr) -> bool {
-    let AddSub(positives, negatives) = expr else {
+    let AddSub(positives, negatives, _) = expr else {
         return false;
     };
-    if positives.len() <= 1 && negatives.is_empty() {
-        *expr = positives.pop().unwrap_or(Expr::Imm(Value::Num(0.0)));
-        true
-    } else if positives.iter().any(|term| matches!(term, AddSub(..))) {
+    if positives.iter().any(|term| matches!(term, AddSub(..))) {
         let (flat_positives, flat_negatives): (Vec<Vec<Expr>>, Vec<Vec<Expr>>) =
             positives
-                .extract_if(|term| matches!(term, AddSub(..)))
+                .extract_if(.., |term| matches!(term, AddSub(..)))
                 .map(|term| match term {
-                    AddSub(flat_positives, flat_negatives) => {
+                    AddSub(flat_positives, flat_negatives, _) => {
                         (flat_positives, flat_negatives)
                     }
                     _ => unreachable!(),
@@ -158,9 +220,9 @@ fn flatten_add_sub(expr: &mut Expr) -> bool {
     } else if negatives.iter().any(|term| matches!(term, AddSub(..))) {
         let (flat_negatives, flat_positives): (Vec<Vec<Expr>>, Vec<Vec<Expr>>) =
             negatives
-                .extract_if(|term| matches!(term, AddSub(..)))
+                .extract_if(.., |term| matches!(term, AddSub(..)))
                 .map(|term| match term {
-                    AddSub(flat_negatives, flat_positives) => {
+                    AddSub(flat_negatives, flat_positives, _) => {
                         (flat_negatives, flat_positives)
                     }
                     _ => unreachable!(),
@@ -176,20 +238,17 @@ fn flatten_add_sub(expr: &mut Expr) -> bool {
 
 /// Flattens nested multiplication and division.
 fn flatten_mul_div(expr: &mut Expr) -> bool {
-    let MulDiv(numerators, denominators) = expr else {
+    let MulDiv(numerators, denominators, _) = expr else {
         return false;
     };
-    if numerators.len() <= 1 && denominators.is_empty() {
-        *expr = numerators.pop().unwrap_or(Expr::Imm(Value::Num(1.0)));
-        true
-    } else if numerators.iter().any(|term| matches!(term, MulDiv(..))) {
+    if numerators.iter().any(|term| matches!(term, MulDiv(..))) {
         let (flat_numerators, flat_denominators): (
             Vec<Vec<Expr>>,
             Vec<Vec<Expr>>,
         ) = numerators
-            .extract_if(|term| matches!(term, MulDiv(..)))
+            .extract_if(.., |term| matches!(term, MulDiv(..)))
             .map(|term| match term {
-                MulDiv(flat_numerators, flat_denominators) => {
+                MulDiv(flat_numerators, flat_denominators, _) => {
                     (flat_numerators, flat_denominators)
                 }
                 _ => unreachable!(),
@@ -203,9 +262,9 @@ fn flatten_mul_div(expr: &mut Expr) -> bool {
             Vec<Vec<Expr>>,
             Vec<Vec<Expr>>,
         ) = denominators
-            .extract_if(|term| matches!(term, MulDiv(..)))
+            .extract_if(.., |term| matches!(term, MulDiv(..)))
             .map(|term| match term {
-                MulDiv(flat_denominators, flat_numerators) => {
+                MulDiv(flat_denominators, flat_numerators, _) => {
                     (flat_denominators, flat_numerators)
                 }
                 _ => unreachable!(),
@@ -219,19 +278,140 @@ fn flatten_mul_div(expr: &mut Expr) -> bool {
     }
 }
 
+/// Cancels a variable that is both added and subtracted, which would give 0
+/// instead of NaN for infinities.
+fn cancel_add_sub(expr: &mut Expr, optimizer: &mut Optimizer) -> bool {
+    let span = expr.span();
+    let AddSub(positives, negatives, _) = expr else {
+        return false;
+    };
+    let Some((i, j)) = positives.iter().enumerate().find_map(|(i, term)| {
+        let Sym(name, _) = term else { return None };
+        let j = negatives.iter().position(
+            |other| matches!(other, Sym(other, _) if other == name),
+        )?;
+        Some((i, j))
+    }) else {
+        return false;
+    };
+    if optimizer.allow(
+        false,
+        span,
+        "subtracting a variable from itself doesn't give zero for infinities",
+    ) {
+        positives.swap_remove(i);
+        negatives.swap_remove(j);
+        true
+    } else {
+        false
+    }
+}
+
+/// Replaces a sum or product of a single term with the term itself, which
+/// also undoes double negation. This would skip converting the term to a
+/// number in native code, while Scratch never converts it since such a sum
+/// is compiled to the term alone.
+fn single_term(expr: &mut Expr, optimizer: &mut Optimizer) -> bool {
+    let (AddSub(terms, others, _) | MulDiv(terms, others, _)) = expr else {
+        return false;
+    };
+    if !others.is_empty() || terms.len() > 1 {
+        return false;
+    }
+    let Some(term) = terms.pop() else {
+        *expr = Imm(Value::Num(if let AddSub(..) = expr { 0.0 } else { 1.0 }));
+        return true;
+    };
+    if optimizer.allow(
+        is_number(&term) || matches!(optimizer.target, Target::SB3),
+        term.span(),
+        "this converts its operand to a number",
+    ) {
+        *expr = term;
+        true
+    } else {
+        terms.push(term);
+        false
+    }
+}
+
+/// Moves the constant term of a sum or product to the end of the positive
+/// terms, so that equivalent expressions end up in the same shape. A
+/// subtracted constant is added negated instead. With more than two terms,
+/// this changes the order in which they are rounded.
+fn imms_last(expr: &mut Expr, optimizer: &mut Optimizer) -> bool {
+    let span = expr.span();
+    let reason = "moving a constant changes the order in which the terms are \
+                  rounded";
+    if let AddSub(positives, negatives, _) = expr {
+        if let Some(index) = negatives.iter().position(Expr::is_imm) {
+            if optimizer.allow(negatives.len() == 1, span, reason) {
+                let Imm(imm) = negatives.swap_remove(index) else {
+                    unreachable!();
+                };
+                positives.push(Imm(Value::Num(-imm.to_num())));
+                return true;
+            }
+        }
+    }
+    let (AddSub(terms, _, _) | MulDiv(terms, _, _)) = expr else {
+        return false;
+    };
+    let Some(index) = terms.iter().position(Expr::is_imm) else {
+        return false;
+    };
+    if index != terms.len() - 1
+      && optimizer.allow(terms.len() == 2, span, reason)
+    {
+        let imm = terms.remove(index);
+        terms.push(imm);
+        true
+    } else {
+        false
+    }
+}
+
+/// Turns division by a power of two into multiplication by its reciprocal,
+/// which is exact, unlike for other constants.
+fn div_by_power_of_two(expr: &mut Expr) -> bool {
+    let MulDiv(numerators, denominators, _) = expr else {
+        return false;
+    };
+    let Some(index) = denominators.iter().position(|term| {
+        matches!(term, Imm(imm) if is_power_of_two(imm.to_num()))
+    }) else {
+        return false;
+    };
+    let Imm(imm) = denominators.swap_remove(index) else {
+        unreachable!();
+    };
+    numerators.push(Imm(Value::Num(imm.to_num().recip())));
+    true
+}
+
+/// Whether the magnitude of `n` is a power of two with a reciprocal that is
+/// also a normal number.
+fn is_power_of_two(n: f64) -> bool {
+    const MANTISSA: u64 = (1 << 52) - 1;
+    n.is_normal() && n.to_bits() & MANTISSA == 0 && n.recip().is_normal()
+}
+
 /// Floats negation in a multiplication or division outward.
 fn mul_div_negation(expr: &mut Expr) -> bool {
-    if let MulDiv(numerators, denominators) = expr
-      && [numerators, denominators].into_iter().flatten().any(|factor|
-        if let AddSub(positives, negatives) = factor && positives.is_empty() {
-            mem::swap(positives, negatives);
-            true
-        } else {
-            false
+    let MulDiv(numerators, denominators, span) = expr else {
+        return false;
+    };
+    if [numerators, denominators].into_iter().flatten().any(|factor|
+        match factor {
+            AddSub(positives, negatives, _) if positives.is_empty() => {
+                mem::swap(positives, negatives);
+                true
+            }
+            _ => false,
         }
-    )
-    {
-        *expr = AddSub(Vec::new(), vec![mem::take(expr)]);
+    ) {
+        let span = *span;
+        *expr = AddSub(Vec::new(), vec![mem::take(expr)], span);
         true
     } else {
         false
@@ -244,38 +424,42 @@ fn distribute_mul_into_sum(expr: &mut Expr) -> bool {
     let contains_an_imm =
         |v: &[Expr]| v.iter().filter(|arg| arg.is_imm()).take(1).count() == 1;
 
-    if let MulDiv(args, _) = expr
-      && let Some(sum_index) = args.iter().position(|arg| {
-             matches!(arg, AddSub(positives, negatives) if contains_an_imm(positives) && negatives.is_empty())
-         })
-      && contains_an_imm(args)
-    {
-        let mut sum = args.swap_remove(sum_index);
-        let factor = drain_imms(args).next().unwrap();
-        let AddSub(terms, _) = &mut sum else {
-            unreachable!();
-        };
-        let known_term = drain_imms(terms).next().unwrap();
-        args.push(
-            AddSub(vec![
-                MulDiv(vec![
-                    Expr::Imm(factor.clone()), Expr::Imm(known_term),
-                ], Vec::new()),
-                MulDiv(vec![Expr::Imm(factor), sum], Vec::new()),
-            ], Vec::new()),
-        );
-        true
-    } else {
-        false
+    let MulDiv(args, _, span) = expr else {
+        return false;
+    };
+    let Some(sum_index) = args.iter().position(|arg| {
+        matches!(arg, AddSub(positives, negatives, _) if contains_an_imm(positives) && negatives.is_empty())
+    }) else {
+        return false;
+    };
+    if !contains_an_imm(args) {
+        return false;
     }
+    let span = *span;
+    let mut sum = args.swap_remove(sum_index);
+    let factor = drain_imms(args).next().unwrap();
+    let AddSub(terms, _, sum_span) = &mut sum else {
+        unreachable!();
+    };
+    let sum_span = *sum_span;
+    let known_term = drain_imms(terms).next().unwrap();
+    args.push(
+        AddSub(vec![
+            MulDiv(vec![
+                Expr::Imm(factor.clone()), Expr::Imm(known_term),
+            ], Vec::new(), span),
+            MulDiv(vec![Expr::Imm(factor), sum], Vec::new(), span),
+        ], Vec::new(), sum_span),
+    );
+    true
 }
 
 /// (Sometimes) removes `to-num` if the argument is already a number.
 fn redundant_to_num(expr: &mut Expr) -> bool {
-    if let FuncCall("to-num", _, ref mut args) = expr
-      && let [arg] = &args[..]
-      && is_guaranteed_number(arg)
-    {
+    let FuncCall("to-num", _, args) = expr else {
+        return false;
+    };
+    if matches!(&args[..], [arg] if is_guaranteed_number(arg)) {
         *expr = args.pop().unwrap();
         true
     } else {
@@ -285,32 +469,207 @@ fn redundant_to_num(expr: &mut Expr) -> bool {
 
 /// Constant folding for math operations.
 fn const_mathops(expr: &mut Expr) -> bool {
-    if let FuncCall(op, _, args) = expr
-      && let [Expr::Imm(arg)] = &args[..]
-    {
-        let n = arg.to_num();
-        *expr =
-        Expr::Imm(Value::Num(match *op {
-            "abs" => n.abs(),
-            "floor" => n.floor(),
-            "ceil" => n.ceil(),
-            "sqrt" => n.sqrt(),
-            "ln" => n.ln(),
-            "log" => n.log10(),
-            "e^" => n.exp(),
-            "ten^" => 10.0f64.powf(n),
-            "sin" => n.to_radians().sin(),
-            "cos" => n.to_radians().cos(),
-            "tan" => n.to_radians().tan(),
-            "asin" => n.asin().to_degrees(),
-            "acos" => n.acos().to_degrees(),
-            "atan" => n.atan().to_degrees(),
-            _ => return false,
-        }));
-        true
-    } else {
-        false
+    let FuncCall(op, _, args) = expr else {
+        return false;
+    };
+    let [Expr::Imm(arg)] = &args[..] else {
+        return false;
+    };
+    let n = arg.to_num();
+    *expr =
+    Expr::Imm(Value::Num(match *op {
+        "abs" => n.abs(),
+        "floor" => n.floor(),
+        "ceil" => n.ceil(),
+        "sqrt" => n.sqrt(),
+        "ln" => n.ln(),
+        "log" => n.log10(),
+        "e^" => n.exp(),
+        "ten^" => 10.0f64.powf(n),
+        "sin" => n.to_radians().sin(),
+        "cos" => n.to_radians().cos(),
+        "tan" => n.to_radians().tan(),
+        "asin" => n.asin().to_degrees(),
+        "acos" => n.acos().to_degrees(),
+        "atan" => n.atan().to_degrees(),
+        _ => return false,
+    }));
+    true
+}
+
+/// Constant folding for `mod`, which must agree with `scratch_mod` in the
+/// runtime.
+fn const_mod(expr: &mut Expr) -> bool {
+    let FuncCall("mod", _, args) = expr else {
+        return false;
+    };
+    let [Imm(n), Imm(modulus)] = &args[..] else {
+        return false;
+    };
+    let modulus = modulus.to_num();
+    let remainder = n.to_num() % modulus;
+    let adjustment = if remainder / modulus < 0.0 { modulus } else { 0.0 };
+    *expr = Imm(Value::Num(remainder + adjustment));
+    true
+}
+
+/// Constant folding for `str-match`. Invalid patterns are left for the code
+/// generator to report.
+fn const_str_match(expr: &mut Expr) -> bool {
+    let FuncCall("str-match", _, args) = expr else {
+        return false;
+    };
+    let [Imm(Value::String(s)), Imm(Value::String(pattern))] = &args[..] else {
+        return false;
+    };
+    let Ok(pattern) = Pattern::parse(pattern) else {
+        return false;
+    };
+    *expr = Imm(Value::Bool(pattern.is_match(s)));
+    true
+}
+
+/// Constant folding for the number formatting functions, which must agree
+/// with `format_decimal` and `format_radix` in the runtime.
+fn const_formatting(expr: &mut Expr) -> bool {
+    let FuncCall(func_name, _, args) = expr else {
+        return false;
+    };
+    if !args.iter().all(Expr::is_imm) {
+        return false;
+    }
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            Imm(imm) => imm.to_num(),
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    *expr = Imm(match (*func_name, &args[..]) {
+        ("round-to", &[n, places]) => {
+            let scale = 10.0f64.powf(places);
+            Value::Num((n * scale + 0.5).floor() / scale)
+        }
+        ("format-decimal", &[n, _]) if !n.is_finite() => {
+            Value::String(Value::Num(n).to_string().into())
+        }
+        ("format-decimal", &[n, places]) => {
+            let places = (places as usize).min(100);
+            Value::String(format!("{n:.places$}").into())
+        }
+        ("to-hex" | "to-binary", &[n] | &[n, _]) => {
+            let width = args.get(1).map_or(0, |&width| width as usize).min(64);
+            let magnitude = (n as i64).unsigned_abs();
+            let digits = if *func_name == "to-hex" {
+                format!("{magnitude:0width$x}")
+            } else {
+                format!("{magnitude:0width$b}")
+            };
+            let sign = if (n as i64) < 0 { "-" } else { "" };
+            Value::String(format!("{sign}{digits}").into())
+        }
+        _ => return false,
+    });
+    true
+}
+
+/// Constant folding for the bitwise functions.
+fn const_bitwise(expr: &mut Expr) -> bool {
+    let FuncCall(func_name, _, args) = expr else {
+        return false;
+    };
+    let [Imm(a), Imm(b)] = &args[..] else {
+        return false;
+    };
+    let a = to_int32(a.to_num());
+    let b = to_int32(b.to_num());
+    *expr = Imm(Value::Num(f64::from(match *func_name {
+        "bit-and" => a & b,
+        "bit-or" => a | b,
+        "bit-xor" => a ^ b,
+        "bit-shift-left" => a.wrapping_shl(b as u32),
+        "bit-shift-right" => a.wrapping_shr(b as u32),
+        _ => return false,
+    })));
+    true
+}
+
+/// Constant folding for `min`, `max` and `clamp`, which must agree with the
+/// `minsd` and `maxsd` instructions that native code uses: a later argument
+/// only replaces the result so far if it is less (or greater).
+fn const_min_max(expr: &mut Expr) -> bool {
+    let FuncCall(func_name @ ("min" | "max" | "clamp"), _, args) = expr else {
+        return false;
+    };
+    if !args.iter().all(Expr::is_imm) {
+        return false;
     }
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            Imm(imm) => imm.to_num(),
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    *expr = Imm(Value::Num(match (*func_name, &args[..]) {
+        ("min", [first, rest @ ..]) => rest.iter().copied().fold(*first, min),
+        ("max", [first, rest @ ..]) => rest.iter().copied().fold(*first, max),
+        ("clamp", &[n, low, high]) => min(max(n, low), high),
+        _ => return false,
+    }));
+    true
+}
+
+/// Like `fmin_pseudo` in Cranelift.
+fn min(a: f64, b: f64) -> f64 {
+    if b < a { b } else { a }
+}
+
+/// Like `fmax_pseudo` in Cranelift.
+fn max(a: f64, b: f64) -> f64 {
+    if a < b { b } else { a }
+}
+
+/// Constant folding for `dist` and `dir-to`, which must agree with the native
+/// code.
+fn const_geometry(expr: &mut Expr) -> bool {
+    let FuncCall(func_name @ ("dist" | "dir-to"), _, args) = expr else {
+        return false;
+    };
+    let [Imm(x1), Imm(y1), Imm(x2), Imm(y2)] = &args[..] else {
+        return false;
+    };
+    let dx = x2.to_num() - x1.to_num();
+    let dy = y2.to_num() - y1.to_num();
+    *expr = Imm(Value::Num(if *func_name == "dist" {
+        (dx * dx + dy * dy).sqrt()
+    } else {
+        dx.atan2(dy).to_degrees()
+    }));
+    true
+}
+
+/// Converts a number to a 32-bit integer like the native code does, which
+/// only differs from JavaScript for numbers too large for an `i64`.
+fn to_int32(n: f64) -> i32 {
+    if n.is_finite() { n as i64 as i32 } else { 0 }
+}
+
+/// Constant folding for `char-at`, converting the index like
+/// `double_to_char_index` in the runtime.
+fn const_char_at(expr: &mut Expr) -> bool {
+    let FuncCall("char-at", _, args) = expr else {
+        return false;
+    };
+    let [Imm(s), Imm(index)] = &args[..] else {
+        return false;
+    };
+    let letter = (index.to_num() as usize)
+        .checked_sub(1)
+        .and_then(|index| s.to_string().chars().nth(index));
+    let letter = letter.map_or_else(String::new, String::from);
+    *expr = Imm(Value::String(letter.into()));
+    true
 }
 
 /// Some functions return known constants when applied to zero arguments.
@@ -342,6 +701,12 @@ fn flatten_unary_call(expr: &mut Expr) -> bool {
     true
 }
 
+/// Whether an expression always evaluates to a number.
+fn is_number(expr: &Expr) -> bool {
+    matches!(expr, Imm(Value::Num(_)) | AddSub(..) | MulDiv(..))
+        || is_guaranteed_number(expr)
+}
+
 fn is_guaranteed_number(expr: &Expr) -> bool {
     matches!(
         expr,
@@ -363,7 +728,19 @@ fn is_guaranteed_number(expr: &Expr) -> bool {
                 | "asin"
                 | "acos"
                 | "atan"
-                | "to-num",
+                | "to-num"
+                | "round-to"
+                | "bit-and"
+                | "bit-or"
+                | "bit-xor"
+                | "bit-shift-left"
+                | "bit-shift-right"
+                | "min"
+                | "max"
+                | "clamp"
+                | "dist"
+                | "dir-to"
+                | "table-get",
             _,
             _
         )
@@ -372,7 +749,7 @@ fn is_guaranteed_number(expr: &Expr) -> bool {
 
 fn drain_imms(exprs: &mut Vec<Expr>) -> impl Iterator<Item = Value> + '_ {
     exprs
-        .extract_if(|expr| expr.is_imm())
+        .extract_if(.., |expr| expr.is_imm())
         .map(|expr| match expr {
             Imm(imm) => imm,
             _ => unreachable!(),
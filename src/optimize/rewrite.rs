@@ -0,0 +1,216 @@
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+    ir::expr::Expr::{self, *},
+    parser, source,
+};
+use codemap::{CodeMap, Span};
+use sb3_stuff::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// A rewrite rule given with `--rewrite-rules`, like
+/// `(rewrite (* ?x 2) (+ ?x ?x))`. Symbols starting with `?` in the pattern
+/// match any expression, and the matched expression is replaced by the
+/// template with those symbols substituted.
+pub struct Rule {
+    pattern: Expr,
+    template: Expr,
+    pub(super) span: Span,
+}
+
+/// Reads the rules from a file.
+pub fn load_rules(
+    path: &Path,
+    code_map: &mut CodeMap,
+    max_nesting: usize,
+) -> Result<Vec<Rule>> {
+    let source = source::read(path, None)?;
+    let file = code_map.add_file(path.display().to_string(), source);
+    parser::program(&file, max_nesting)?
+        .into_iter()
+        .map(Rule::from_ast)
+        .collect()
+}
+
+impl Rule {
+    fn from_ast(ast: Ast) -> Result<Self> {
+        let span = ast.span();
+        let Ast::Node(head, tail, _) = ast else {
+            return Err(Box::new(Error::InvalidRewriteRule { span }));
+        };
+        if !head.is_sym("rewrite") {
+            return Err(Box::new(Error::InvalidRewriteRule { span }));
+        }
+        let [pattern, template] = <[Ast; 2]>::try_from(tail)
+            .map_err(|_| Error::InvalidRewriteRule { span })?;
+        let pattern = Expr::from_ast(pattern)?;
+        let template = Expr::from_ast(template)?;
+
+        let mut bound = HashSet::new();
+        metavariables(&pattern, &mut |name, _| {
+            bound.insert(name.to_owned());
+        });
+        let mut unbound = None;
+        metavariables(&template, &mut |name, span| {
+            if !bound.contains(name) && unbound.is_none() {
+                unbound = Some(Error::UnknownMetavariable {
+                    span,
+                    var_name: name.to_owned(),
+                });
+            }
+        });
+        match unbound {
+            Some(err) => Err(Box::new(err)),
+            None => Ok(Self {
+                pattern,
+                template,
+                span,
+            }),
+        }
+    }
+
+    /// Replaces `expr` if it matches the pattern, unless that would remove,
+    /// duplicate or reorder matched expressions that have effects.
+    pub fn apply(&self, expr: &mut Expr) -> bool {
+        let mut bindings = HashMap::new();
+        if !match_pattern(&self.pattern, expr, &mut bindings)
+            || !self.keeps_effects(&bindings)
+        {
+            return false;
+        }
+        *expr = instantiate(&self.template, &bindings);
+        true
+    }
+
+    /// Whether the template uses at most one matched expression that can't be
+    /// dropped, and uses it exactly once.
+    fn keeps_effects(&self, bindings: &HashMap<String, &Expr>) -> bool {
+        let mut uses = HashMap::<String, usize>::new();
+        metavariables(&self.template, &mut |name, _| {
+            *uses.entry(name.to_owned()).or_default() += 1;
+        });
+        let mut effectful = bindings
+            .iter()
+            .filter(|(_, expr)| !expr.effect().can_drop());
+        match (effectful.next(), effectful.next()) {
+            (None, _) => true,
+            (Some((name, _)), None) => uses.get(&**name) == Some(&1),
+            (Some(_), Some(_)) => false,
+        }
+    }
+}
+
+fn is_metavariable(sym: &str) -> bool {
+    sym.len() > 1 && sym.starts_with('?')
+}
+
+fn metavariables(expr: &Expr, f: &mut impl FnMut(&str, Span)) {
+    match expr {
+        Imm(_) => {}
+        Sym(sym, span) => {
+            if is_metavariable(sym) {
+                f(sym, *span);
+            }
+        }
+        FuncCall(_, _, args) => {
+            for arg in args {
+                metavariables(arg, f);
+            }
+        }
+        AddSub(a, b, _) | MulDiv(a, b, _) => {
+            for term in a.iter().chain(b) {
+                metavariables(term, f);
+            }
+        }
+    }
+}
+
+fn match_pattern<'e>(
+    pattern: &Expr,
+    expr: &'e Expr,
+    bindings: &mut HashMap<String, &'e Expr>,
+) -> bool {
+    match (pattern, expr) {
+        (Sym(sym, _), _) if is_metavariable(sym) => {
+            match bindings.get(&**sym) {
+                Some(bound) => same(bound, expr),
+                None => {
+                    bindings.insert(sym.to_string(), expr);
+                    true
+                }
+            }
+        }
+        (Imm(a), Imm(b)) => same_value(a, b),
+        (Sym(a, _), Sym(b, _)) => a == b,
+        (FuncCall(a, _, a_args), FuncCall(b, _, b_args)) => {
+            a == b && all_match(a_args, b_args, bindings)
+        }
+        (AddSub(a_pos, a_neg, _), AddSub(b_pos, b_neg, _))
+        | (MulDiv(a_pos, a_neg, _), MulDiv(b_pos, b_neg, _)) => {
+            all_match(a_pos, b_pos, bindings)
+                && all_match(a_neg, b_neg, bindings)
+        }
+        _ => false,
+    }
+}
+
+fn all_match<'e>(
+    patterns: &[Expr],
+    exprs: &'e [Expr],
+    bindings: &mut HashMap<String, &'e Expr>,
+) -> bool {
+    patterns.len() == exprs.len()
+        && patterns
+            .iter()
+            .zip(exprs)
+            .all(|(pattern, expr)| match_pattern(pattern, expr, bindings))
+}
+
+/// Compares expressions while ignoring their spans.
+fn same(a: &Expr, b: &Expr) -> bool {
+    let same_terms = |a: &[Expr], b: &[Expr]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+    };
+    match (a, b) {
+        (Imm(a), Imm(b)) => same_value(a, b),
+        (Sym(a, _), Sym(b, _)) => a == b,
+        (FuncCall(a, _, a_args), FuncCall(b, _, b_args)) => {
+            a == b && same_terms(a_args, b_args)
+        }
+        (AddSub(a_pos, a_neg, _), AddSub(b_pos, b_neg, _))
+        | (MulDiv(a_pos, a_neg, _), MulDiv(b_pos, b_neg, _)) => {
+            same_terms(a_pos, b_pos) && same_terms(a_neg, b_neg)
+        }
+        _ => false,
+    }
+}
+
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Num(a), Value::Num(b)) => a.to_bits() == b.to_bits(),
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn instantiate(template: &Expr, bindings: &HashMap<String, &Expr>) -> Expr {
+    let all = |terms: &[Expr]| -> Vec<Expr> {
+        terms
+            .iter()
+            .map(|term| instantiate(term, bindings))
+            .collect()
+    };
+    match template {
+        Sym(sym, _) if is_metavariable(sym) => bindings[&**sym].clone(),
+        Imm(_) | Sym(..) => template.clone(),
+        FuncCall(func_name, span, args) => {
+            FuncCall(*func_name, *span, all(args))
+        }
+        AddSub(a, b, span) => AddSub(all(a), all(b), *span),
+        MulDiv(a, b, span) => MulDiv(all(a), all(b), *span),
+    }
+}
@@ -3,34 +3,51 @@ use crate::{
         expr::Expr::{self, Imm},
         statement::Statement::{self, *},
     },
-    optimize::expr::optimize_expr,
+    optimize::{expr::optimize_expr, Optimizer},
+    opts::Pass,
 };
 use std::mem;
 
-pub fn optimize_stmt(stmt: &mut Statement) {
+pub fn optimize_stmt(
+    stmt: &mut Statement,
+    optimizer: &mut Optimizer,
+) -> bool {
+    let mut dirty = false;
     while {
         let mut this_step_dirty = false;
         stmt.traverse_postorder_mut(&mut |s| {
-            for f in STMT_OPTIMIZATIONS {
-                this_step_dirty |= f(s);
+            if optimizer.pass == Pass::ControlFlow {
+                for f in STMT_OPTIMIZATIONS {
+                    this_step_dirty |= f(s);
+                }
+            } else {
+                this_step_dirty |= optimize_stmt_exprs(s, optimizer);
             }
         });
         this_step_dirty
-    } {}
+    } {
+        dirty = true;
+    }
+    dirty
 }
 
 const STMT_OPTIMIZATIONS: &[fn(&mut Statement) -> bool] = &[
-    optimize_stmt_exprs,
     flatten_do,
     const_conditions,
     nested_ifs,
+    no_effect,
 ];
 
 /// Optimizes all expressions contained in a statement.
-fn optimize_stmt_exprs(stmt: &mut Statement) -> bool {
+fn optimize_stmt_exprs(
+    stmt: &mut Statement,
+    optimizer: &mut Optimizer,
+) -> bool {
     match stmt {
-        Do(_) | Forever(_) => false,
-        ProcCall { args, .. } => args.iter_mut().any(optimize_expr),
+        Do(_) | Forever(..) => false,
+        ProcCall { args, .. } => {
+            args.iter_mut().any(|arg| optimize_expr(arg, optimizer))
+        }
         IfElse {
             condition: expr, ..
         }
@@ -41,7 +58,7 @@ fn optimize_stmt_exprs(stmt: &mut Statement) -> bool {
         | While {
             condition: expr, ..
         }
-        | For { times: expr, .. } => optimize_expr(expr),
+        | For { times: expr, .. } => optimize_expr(expr, optimizer),
     }
 }
 
@@ -85,20 +102,22 @@ fn const_conditions(stmt: &mut Statement) -> bool {
         Until {
             condition: Imm(condition),
             body,
+            span,
         } => {
             *stmt = if condition.to_bool() {
                 Do(Vec::new())
             } else {
-                Forever(mem::take(body))
+                Forever(mem::take(body), *span)
             };
             true
         }
         While {
             condition: Imm(condition),
             body,
+            span,
         } => {
             *stmt = if condition.to_bool() {
-                Forever(mem::take(body))
+                Forever(mem::take(body), *span)
             } else {
                 Do(Vec::new())
             };
@@ -109,33 +128,51 @@ fn const_conditions(stmt: &mut Statement) -> bool {
 }
 
 /// Turns two nested `if`s into a single `if` with the conjunction of both
-/// conditions.
+/// conditions. This evaluates the inner condition even when the outer one is
+/// false, so the inner one must not have effects.
 fn nested_ifs(stmt: &mut Statement) -> bool {
-    if let Statement::IfElse {
+    let Statement::IfElse {
         condition: outer_condition,
-        then:
-            box Statement::IfElse {
-                condition: inner_condition,
-                then: inner_then,
-                else_: inner_else,
-                ..
-            },
+        then: outer_then,
         else_: outer_else,
         span,
     } = stmt
-      && outer_else.is_nop()
-      && inner_else.is_nop()
+    else {
+        return false;
+    };
+    let Statement::IfElse {
+        condition: inner_condition,
+        then: inner_then,
+        else_: inner_else,
+        ..
+    } = &mut **outer_then
+    else {
+        return false;
+    };
+    if !outer_else.is_nop()
+        || !inner_else.is_nop()
+        || !inner_condition.effect().can_drop()
     {
-        *stmt = Statement::IfElse {
-            condition: Expr::FuncCall(
-                "and",
-                *span,
-                vec![mem::take(outer_condition), mem::take(inner_condition)],
-            ),
-            then: mem::take(inner_then),
-            else_: Box::default(),
-            span: *span,
-        };
+        return false;
+    }
+    *stmt = Statement::IfElse {
+        condition: Expr::FuncCall(
+            "and",
+            *span,
+            vec![mem::take(outer_condition), mem::take(inner_condition)],
+        ),
+        then: mem::take(inner_then),
+        else_: Box::default(),
+        span: *span,
+    };
+    true
+}
+
+/// Removes statements that don't do anything, like an `if` with empty
+/// branches.
+fn no_effect(stmt: &mut Statement) -> bool {
+    if !stmt.is_nop() && stmt.effect().can_drop() {
+        *stmt = Do(Vec::new());
         true
     } else {
         false
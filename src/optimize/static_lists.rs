@@ -0,0 +1,165 @@
+//! Reading lists that the program never changes from static data. Such a list
+//! always holds its initial items, so `(!! list i)` becomes
+//! `(%static-get list i)`, which native code reads from an array in read-only
+//! data instead of from the list, and `(length list)` becomes a literal.
+//! Nothing is rewritten in programs that use `load-state` or `asm`, since
+//! those can change any list.
+
+use super::arguments::own_exprs;
+use crate::ir::{expr::Expr, sprite::Sprite, statement::Statement, Program};
+use sb3_stuff::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+};
+
+/// Returns whether anything changed.
+pub fn rewrite(program: &mut Program) -> bool {
+    let is_opaque = iter::once(&program.stage)
+        .chain(program.sprites.values())
+        .flat_map(|sprite| sprite.procedures.values().flatten())
+        .any(|proc| {
+            proc.body.any(&mut |stmt| {
+                matches!(
+                    stmt,
+                    Statement::ProcCall { proc_name, .. }
+                        if proc_name == "load-state" || proc_name == "asm"
+                )
+            })
+        });
+    if is_opaque {
+        return false;
+    }
+
+    let mut used_global = HashSet::new();
+    let used = program
+        .sprites
+        .values_mut()
+        .map(|sprite| mark_used(sprite, &mut used_global))
+        .collect::<Vec<_>>();
+    let used_by_stage = mark_used(&mut program.stage, &mut HashSet::new());
+    used_global.extend(used_by_stage);
+
+    let global = unchanged(&program.stage, &used_global);
+    let mut dirty = rewrite_in(&mut program.stage, &global, &HashMap::new());
+    for (sprite, used) in program.sprites.values_mut().zip(used) {
+        let own = unchanged(sprite, &used);
+        dirty |= rewrite_in(sprite, &global, &own);
+    }
+    dirty
+}
+
+/// Finds the lists that a sprite uses in any other way than reading them.
+/// Those of the sprite are returned and those of the stage are added to
+/// `used_global`. For the stage itself, they are all returned.
+fn mark_used(
+    sprite: &mut Sprite,
+    used_global: &mut HashSet<String>,
+) -> HashSet<String> {
+    let mut used = HashSet::new();
+    for proc in sprite.procedures.values_mut().flatten() {
+        proc.body.traverse_postorder_mut(&mut |stmt| {
+            for expr in own_exprs(stmt) {
+                other_uses(expr, &mut |name| {
+                    if proc.lists.contains(name) {
+                        // Local lists are never rewritten.
+                    } else if sprite.lists.contains(name) {
+                        used.insert(name.to_owned());
+                    } else {
+                        used_global.insert(name.to_owned());
+                    }
+                });
+            }
+        });
+    }
+    used
+}
+
+/// Calls `f` with every name in an expression that isn't only read as a list
+/// by `!!` or `length`. Some of them are variables, which is fine since
+/// leaving a list alone is always correct.
+fn other_uses(expr: &Expr, f: &mut impl FnMut(&str)) {
+    match expr {
+        Expr::Imm(_) => {}
+        Expr::Sym(name, _) => f(name),
+        Expr::FuncCall(func_name, _, args) => {
+            let args = match (*func_name, &args[..]) {
+                (
+                    "!!" | "%static-get" | "length",
+                    [Expr::Sym(..), rest @ ..],
+                ) => rest,
+                _ => args,
+            };
+            for arg in args {
+                other_uses(arg, f);
+            }
+        }
+        Expr::AddSub(a, b, _) | Expr::MulDiv(a, b, _) => {
+            for term in a.iter().chain(b) {
+                other_uses(term, f);
+            }
+        }
+    }
+}
+
+/// The lengths of the lists of a sprite that are never changed, which only
+/// counts lists with initial items since reading an empty list isn't worth
+/// an array.
+fn unchanged(
+    sprite: &Sprite,
+    used: &HashSet<String>,
+) -> HashMap<String, usize> {
+    sprite
+        .initial_items
+        .iter()
+        .filter(|(name, items)| {
+            !items.is_empty()
+                && sprite.lists.contains(*name)
+                && !used.contains(*name)
+        })
+        .map(|(name, items)| (name.clone(), items.len()))
+        .collect()
+}
+
+fn rewrite_in(
+    sprite: &mut Sprite,
+    global: &HashMap<String, usize>,
+    own: &HashMap<String, usize>,
+) -> bool {
+    let mut dirty = false;
+    for proc in sprite.procedures.values_mut().flatten() {
+        proc.body.traverse_postorder_mut(&mut |stmt| {
+            for expr in own_exprs(stmt) {
+                expr.traverse_postorder_mut(&mut |expr| {
+                    let Expr::FuncCall(func_name @ ("!!" | "length"), _, args) =
+                        expr
+                    else {
+                        return;
+                    };
+                    let Some(Expr::Sym(list_name, _)) = args.first() else {
+                        return;
+                    };
+                    let len = if proc.lists.contains(&**list_name) {
+                        None
+                    } else if sprite.lists.contains(&**list_name) {
+                        own.get(&**list_name)
+                    } else {
+                        global.get(&**list_name)
+                    };
+                    let Some(&len) = len else {
+                        return;
+                    };
+                    match (*func_name, args.len()) {
+                        ("!!", 2) => *func_name = "%static-get",
+                        ("length", 1) => {
+                            *expr = Expr::Imm(Value::Num(len as f64));
+                        }
+                        _ => return,
+                    }
+                    dirty = true;
+                });
+            }
+        });
+    }
+    dirty
+}
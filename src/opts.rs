@@ -15,8 +15,169 @@ pub struct Opts {
     #[options(no_short)]
     pub lint: bool,
 
+    /// Reject implicit conversions to numbers and booleans
+    #[options(no_short)]
+    pub strict_types: bool,
+
+    /// Simplify arithmetic even where that changes the result for some
+    /// values, like NaN, infinities, negative zero or strings
+    #[options(no_short)]
+    pub fast_math: bool,
+
+    /// Comma-separated optimization passes to run in order until none of
+    /// them change anything: fold, inexact, rewrite, control-flow,
+    /// const-args, static-lists (default all). Leaving out fold makes some
+    /// builtins fail to compile for sb3
+    #[options(no_short, meta = "PASSES")]
+    pub passes: Option<Passes>,
+
+    /// Print the IR after every optimization pass
+    #[options(no_short)]
+    pub print_after_all: bool,
+
+    /// File of extra optimizer rules like `(rewrite (* ?x 2) (+ ?x ?x))`,
+    /// which are applied until none match
+    #[options(no_short, meta = "FILE")]
+    pub rewrite_rules: Option<PathBuf>,
+
+    /// File describing the blocks of a Scratch extension, like a TurboWarp
+    /// custom extension, for `call-extension`. Can be given more than once
+    #[options(no_short, meta = "FILE")]
+    pub extension: Vec<PathBuf>,
+
     /// Type of code to compile to: sb3 (default) or x86_64
     pub target: Target,
+
+    /// Build profile: release (default) or debug
+    #[options(no_short)]
+    pub profile: Profile,
+
+    /// Stack size of native executables, like 64M
+    #[options(no_short, default = "8M")]
+    pub stack_size: ByteSize,
+
+    /// Make native executables print right away instead of buffering output
+    #[options(no_short)]
+    pub unbuffered: bool,
+
+    /// Comma-separated artifacts to produce: sb3, obj, exe, asm, ir, symbols,
+    /// cfg
+    pub emit: Option<Artifacts>,
+
+    /// Disassemble the asm artifact with objdump and put the source code of
+    /// each statement above its instructions
+    #[options(no_short)]
+    pub annotate_asm: bool,
+
+    /// Directory to write the artifacts to
+    #[options(default = ".")]
+    pub out_dir: PathBuf,
+
+    /// Format of diagnostics: human (default) or json
+    #[options(no_short)]
+    pub message_format: MessageFormat,
+
+    /// Stop reporting errors after this many, or never if it is 0
+    #[options(no_short, default = "0", meta = "N")]
+    pub max_errors: usize,
+
+    /// Also accept builtin names in this language: de or fr
+    #[options(no_short)]
+    pub lang: Option<Lang>,
+
+    /// Fail if lists and quotes are nested more than this deep, in the source
+    /// code or after expanding macros
+    #[options(no_short, default = "256", meta = "N")]
+    pub max_nesting: usize,
+
+    /// Make `lint` warn about procedures with more statements than this
+    #[options(no_short, default = "50", meta = "N")]
+    pub max_statements: usize,
+
+    /// Make `lint` warn about loops and conditions nested more than this
+    /// deep
+    #[options(no_short, default = "4", meta = "N")]
+    pub max_depth: usize,
+
+    /// Print every macro expansion as it happens
+    #[options(no_short)]
+    pub trace_macros: bool,
+
+    /// Explain which variables could not be unboxed in native code
+    #[options(no_short)]
+    pub remarks: bool,
+
+    /// The number that video motion and direction are in native executables,
+    /// which have no webcam
+    #[options(no_short, default = "0", meta = "N")]
+    pub video_stub: f64,
+
+    /// Inline custom blocks whose body is a single block into their callers
+    /// when compiling to sb3, which makes the project smaller
+    #[options(no_short)]
+    pub flatten_scripts: bool,
+
+    /// Give variables, lists, custom blocks and their parameters in sb3
+    /// projects short names that don't tell what they are for
+    #[options(no_short)]
+    pub minify: bool,
+
+    /// Fail if the sb3 project has more blocks than this
+    #[options(no_short, meta = "N")]
+    pub max_blocks: Option<usize>,
+
+    /// Fail if `project.json` in the sb3 project is bigger than this many
+    /// bytes. The Scratch website accepts up to 5000000
+    #[options(no_short, meta = "BYTES")]
+    pub max_json_size: Option<usize>,
+
+    /// Embed the compiler version, a hash of the source code and the time of
+    /// the build in the output
+    #[options(no_short)]
+    pub stamp: bool,
+
+    /// Print the size of the program before and after optimizing it
+    #[options(no_short)]
+    pub stats: bool,
+
+    /// Set a key for `cfg`, optionally with a value: NAME or NAME=VALUE
+    #[options(no_short, meta = "NAME[=VALUE]")]
+    pub define: Vec<String>,
+}
+
+impl Opts {
+    /// The artifacts to produce, defaulting to the natural output of the
+    /// target when `--emit` wasn't given.
+    pub fn artifacts(&self) -> Vec<Artifact> {
+        self.emit.as_ref().map_or_else(
+            || {
+                vec![match self.target {
+                    Target::SB3 => Artifact::Sb3,
+                    Target::X86_64 => Artifact::Obj,
+                }]
+            },
+            |emit| emit.0.clone(),
+        )
+    }
+
+    /// The optimization passes to run, defaulting to all of them.
+    pub fn passes(&self) -> Vec<Pass> {
+        self.passes
+            .as_ref()
+            .map_or_else(|| Pass::ALL.to_vec(), |passes| passes.0.clone())
+    }
+
+    /// Looks up a key set with `--define`, along with its value if it was
+    /// given one. Later definitions override earlier ones.
+    pub fn defined(&self, key: &str) -> Option<Option<&str>> {
+        self.define
+            .iter()
+            .rev()
+            .find_map(|def| match def.split_once('=') {
+                Some((name, value)) => (name == key).then_some(Some(value)),
+                None => (def == key).then_some(None),
+            })
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -54,3 +215,281 @@ impl fmt::Display for InvalidTarget {
         write!(f, "invalid target: {}", self.0)
     }
 }
+
+/// Debug builds skip optimizations and add runtime checks to native code,
+/// release builds optimize and leave the checks out.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Debug,
+    #[default]
+    Release,
+}
+
+impl FromStr for Profile {
+    type Err = InvalidProfile;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debug" => Ok(Self::Debug),
+            "release" => Ok(Self::Release),
+            _ => Err(InvalidProfile(s.to_owned())),
+        }
+    }
+}
+
+pub struct InvalidProfile(String);
+
+impl fmt::Display for InvalidProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid profile: {}", self.0)
+    }
+}
+
+/// A number of bytes, optionally followed by `K`, `M` or `G`.
+#[derive(Clone, Copy)]
+pub struct ByteSize(pub usize);
+
+impl FromStr for ByteSize {
+    type Err = InvalidByteSize;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, unit) = match s.strip_suffix(['K', 'M', 'G']) {
+            Some(digits) => (digits, s.as_bytes()[s.len() - 1]),
+            None => (s, b'B'),
+        };
+        let shift = match unit {
+            b'K' => 10,
+            b'M' => 20,
+            b'G' => 30,
+            _ => 0,
+        };
+        digits
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_mul(1 << shift))
+            .map(Self)
+            .ok_or_else(|| InvalidByteSize(s.to_owned()))
+    }
+}
+
+pub struct InvalidByteSize(String);
+
+impl fmt::Display for InvalidByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid size: {}", self.0)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Lang {
+    De,
+    Fr,
+}
+
+impl FromStr for Lang {
+    type Err = InvalidLang;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "de" => Ok(Self::De),
+            "fr" => Ok(Self::Fr),
+            _ => Err(InvalidLang(s.to_owned())),
+        }
+    }
+}
+
+pub struct InvalidLang(String);
+
+impl fmt::Display for InvalidLang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported language: {}", self.0)
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = InvalidMessageFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(InvalidMessageFormat(s.to_owned())),
+        }
+    }
+}
+
+pub struct InvalidMessageFormat(String);
+
+impl fmt::Display for InvalidMessageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid message format: {}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    Sb3,
+    Obj,
+    Exe,
+    Asm,
+    Ir,
+    Symbols,
+    Cfg,
+}
+
+impl Artifact {
+    pub const fn to_str(self) -> &'static str {
+        match self {
+            Self::Sb3 => "sb3",
+            Self::Obj => "obj",
+            Self::Exe => "exe",
+            Self::Asm => "asm",
+            Self::Ir => "ir",
+            Self::Symbols => "symbols",
+            Self::Cfg => "cfg",
+        }
+    }
+
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Self::Sb3 => "project.sb3",
+            Self::Obj => "project.o",
+            Self::Exe => "project",
+            Self::Asm => "project.s",
+            Self::Ir => "project.ir",
+            Self::Symbols => "project.symbols",
+            Self::Cfg => "project.dot",
+        }
+    }
+
+    pub const fn is_supported_by(self, target: Target) -> bool {
+        match self {
+            Self::Sb3 => matches!(target, Target::SB3),
+            Self::Obj | Self::Exe | Self::Asm | Self::Symbols => {
+                matches!(target, Target::X86_64)
+            }
+            Self::Ir | Self::Cfg => true,
+        }
+    }
+}
+
+impl FromStr for Artifact {
+    type Err = InvalidArtifact;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sb3" => Ok(Self::Sb3),
+            "obj" => Ok(Self::Obj),
+            "exe" => Ok(Self::Exe),
+            "asm" => Ok(Self::Asm),
+            "ir" => Ok(Self::Ir),
+            "symbols" => Ok(Self::Symbols),
+            "cfg" => Ok(Self::Cfg),
+            _ => Err(InvalidArtifact(s.to_owned())),
+        }
+    }
+}
+
+pub struct InvalidArtifact(String);
+
+impl fmt::Display for InvalidArtifact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid artifact: {}", self.0)
+    }
+}
+
+pub struct Artifacts(Vec<Artifact>);
+
+impl FromStr for Artifacts {
+    type Err = InvalidArtifact;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// A group of optimizations that can be named in `--passes`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Exact arithmetic simplifications and constant folding.
+    Fold,
+    /// Arithmetic simplifications that are only made when they are exact for
+    /// the values involved or with `--fast-math`.
+    Inexact,
+    /// The rules given with `--rewrite-rules`.
+    Rewrite,
+    /// Flattening blocks and removing branches with constant conditions.
+    ControlFlow,
+    /// Replacing parameters of custom procedures with the literal that every
+    /// call passes for them.
+    ConstArgs,
+    /// Reading lists that are never changed from static data.
+    StaticLists,
+}
+
+impl Pass {
+    pub const ALL: [Self; 6] = [
+        Self::Fold,
+        Self::Inexact,
+        Self::Rewrite,
+        Self::ControlFlow,
+        Self::ConstArgs,
+        Self::StaticLists,
+    ];
+
+    pub const fn to_str(self) -> &'static str {
+        match self {
+            Self::Fold => "fold",
+            Self::Inexact => "inexact",
+            Self::Rewrite => "rewrite",
+            Self::ControlFlow => "control-flow",
+            Self::ConstArgs => "const-args",
+            Self::StaticLists => "static-lists",
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = InvalidPass;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|pass| pass.to_str() == s)
+            .ok_or_else(|| InvalidPass(s.to_owned()))
+    }
+}
+
+pub struct InvalidPass(String);
+
+impl fmt::Display for InvalidPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pass: {}", self.0)
+    }
+}
+
+pub struct Passes(Vec<Pass>);
+
+impl FromStr for Passes {
+    type Err = InvalidPass;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self(Vec::new()));
+        }
+        s.split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
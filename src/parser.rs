@@ -1,186 +1,216 @@
-use crate::ast::Ast;
-use codemap::{File, Span};
-use std::borrow::Cow;
-use winnow::{
-    ascii::{digit1, float, hex_digit1, multispace1, oct_digit1},
-    combinator::{
-        alt, delimited, fail, not, opt, preceded, repeat, separated_pair,
-        success, terminated,
-    },
-    dispatch,
-    error::{ContextError as Error, ParserError},
-    token::{any, one_of, take_till0, take_till1, take_while},
-    Located, PResult, Parser, Stateful,
+use crate::{
+    ast::Ast,
+    diagnostic::{Error, Result},
+    lexer::{syntax_error, Lexer, Token, TokenKind, CLOSE, EXPRESSION},
 };
+use codemap::{File, Span};
 
-pub type Input<'a> = Stateful<Located<&'a str>, &'a File>;
-
-pub fn program(input: Input) -> crate::diagnostic::Result<Vec<Ast>> {
-    Ok(preceded(ws, repeat(0.., terminated(expr, ws)))
-        .parse(input)
-        .map_err(|err| crate::diagnostic::Error::Parse(format!("{err:?}")))?)
-}
-
-fn expr(input: &mut Input) -> PResult<Ast> {
-    alt((number, boolean, string, sym, node, unquote)).parse_next(input)
-}
-
-fn number(input: &mut Input) -> PResult<Ast> {
-    let hex = based(16, &['x', 'X'], hex_digit1);
-    let binary = based(2, &['b', 'B'], take_while(1.., ['0', '1']));
-    let octal = based(8, &['o', 'O'], oct_digit1);
-
-    spanned(terminated(
-        alt((hex, binary, octal, float)),
-        not(sym_non_first_char),
-    ))
-    .map(|(span, num)| Ast::Num(num, span))
-    .parse_next(input)
-}
-
-fn based<'a>(
-    base: u32,
-    prefix: &'static [char],
-    digitp: impl Parser<Input<'a>, &'a str, Error>,
-) -> impl Parser<Input<'a>, f64, Error> {
-    separated_pair(sign, ('0', one_of(prefix)), digitp).try_map(
-        move |(sign, digits)| {
-            i64::from_str_radix(digits, base)
-                .map(|n| n as f64 * if sign == Some('-') { -1.0 } else { 1.0 })
-        },
-    )
-}
-
-fn sign(input: &mut Input) -> PResult<Option<char>> {
-    opt(one_of(['+', '-'])).parse_next(input)
-}
-
-fn hex_digit(input: &mut Input) -> PResult<char> {
-    one_of(|c: char| c.is_ascii_hexdigit()).parse_next(input)
-}
-
-fn boolean(input: &mut Input) -> PResult<Ast> {
-    terminated(
-        spanned(alt((
-            Parser::<_, _, Error>::value("true", true),
-            Parser::<_, _, Error>::value("false", false),
-        ))),
-        not(sym_non_first_char),
-    )
-    .map(|(span, b)| Ast::Bool(b, span))
-    .parse_next(input)
-}
-
-fn string(input: &mut Input) -> PResult<Ast> {
-    let normal = take_till1(['\"', '\\', '\n']).map(Cow::Borrowed);
-    let null = terminated('0', not(digit1)).value(Cow::Borrowed("\0"));
-    let character_escape_sequence = dispatch! {any;
-        '"' => success("\""),
-        '\'' => success("'"),
-        '\\' => success("\\"),
-        'n' => success("\n"),
-        't' => success("\t"),
-        'r' => success("\r"),
-        'b' => success("\x08"),
-        'f' => success("\x0c"),
-        'v' => success("\x11"),
-        _ => fail,
+/// Parses the tokens from the lexer by recursive descent. Each token decides
+/// what is parsed next, so a syntax error is reported at the first token that
+/// doesn't fit, along with what could have gone there.
+pub fn program(file: &File, max_nesting: usize) -> Result<Vec<Ast>> {
+    check_nesting(file, max_nesting)?;
+    let mut lexer = Lexer::new(file);
+    let mut asts = Vec::new();
+    loop {
+        let token = lexer.next_token()?;
+        if token.kind == TokenKind::Eof {
+            return Ok(asts);
+        }
+        asts.push(expr(&mut lexer, token, &[EXPRESSION])?);
     }
-    .map(Cow::Borrowed);
-
-    let hex_escape_sequence =
-        preceded('x', repeat::<_, _, (), _, _>(2, hex_digit).recognize());
-    let hex4digits = repeat::<_, _, (), _, _>(4, hex_digit).recognize();
-    let bracketed_unicode =
-        delimited('{', take_while(1..=6, |c: char| c.is_ascii_hexdigit()), '}');
-    let unicode_escape_sequence =
-        preceded('u', alt((hex4digits, bracketed_unicode)));
-    let escape_sequence = preceded(
-        '\\',
-        alt((
-            character_escape_sequence,
-            null,
-            alt((hex_escape_sequence, unicode_escape_sequence))
-                .try_map(|digits| u32::from_str_radix(digits, 16))
-                .verify_map(|c| {
-                    char::from_u32(c).map(String::from).map(Cow::Owned)
-                }),
-        )),
-    );
-    let string_char = alt((normal, escape_sequence));
-
-    spanned(delimited('"', repeat(0.., string_char), '"'))
-        .map(|(span, strs): (_, Vec<_>)| Ast::String(strs.concat(), span))
-        .parse_next(input)
 }
 
-fn sym_first_char(input: &mut Input) -> PResult<char> {
-    one_of((
-        char::is_alphabetic,
-        [
-            '!', '$', '%', '&', '*', '+', '-', '.', '/', ':', '<', '=', '>',
-            '?', '@', '^', '_', '~', '[', ']',
-        ],
-    ))
-    .parse_next(input)
+/// Checks that lists, quotes and unquotes aren't nested more than `limit`
+/// deep, since the parser recurses for every level and would overflow the
+/// stack. This runs before parsing and only looks at the bytes, so it doesn't
+/// recurse itself.
+fn check_nesting(file: &File, limit: usize) -> Result<()> {
+    // Quotes and unquotes nest the expression after them, so the ones in
+    // front of a list stay open until it is closed.
+    let mut open = Vec::new();
+    let mut depth = 0_usize;
+    let mut prefixes = 0_usize;
+    let too_deep = |i: usize| {
+        let start = i as u64;
+        Box::new(Error::TooDeeplyNested {
+            span: file.span.subspan(start, start + 1),
+            limit,
+        })
+    };
+    let mut bytes = file.source().bytes().enumerate();
+    while let Some((i, byte)) = bytes.next() {
+        match byte {
+            b'\'' | b',' => {
+                prefixes += 1;
+                if depth + prefixes > limit {
+                    return Err(too_deep(i));
+                }
+            }
+            b'(' => {
+                open.push(prefixes + 1);
+                depth += prefixes + 1;
+                prefixes = 0;
+                if depth > limit {
+                    return Err(too_deep(i));
+                }
+            }
+            b')' => {
+                depth -= open.pop().unwrap_or(0);
+                prefixes = 0;
+            }
+            b'"' => {
+                prefixes = 0;
+                let mut escaped = false;
+                for (_, byte) in bytes.by_ref() {
+                    match byte {
+                        _ if escaped => escaped = false,
+                        b'\\' => escaped = true,
+                        b'"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            b';' => {
+                bytes.by_ref().find(|&(_, byte)| byte == b'\n');
+            }
+            _ if byte.is_ascii_whitespace() => {}
+            _ => prefixes = 0,
+        }
+    }
+    Ok(())
+}
+
+/// Parses the expression that starts with `token`, or reports that one of
+/// `expected` should have been there instead.
+fn expr(lexer: &mut Lexer, token: Token, expected: &[&str]) -> Result<Ast> {
+    let span = token.span;
+    Ok(match token.kind {
+        TokenKind::Num(n) => Ast::Num(n, span),
+        TokenKind::Bool(b) => Ast::Bool(b, span),
+        TokenKind::String(s) => Ast::String(s, span),
+        TokenKind::Sym(sym) => Ast::Sym(sym.to_owned(), span),
+        TokenKind::Color(digits) => Ast::Color(digits.to_owned(), span),
+        TokenKind::Open => {
+            let token = lexer.next_token()?;
+            let first = expr(lexer, token, &[EXPRESSION])?;
+            let (rest, close) = list(lexer)?;
+            Ast::Node(Box::new(first), rest, span.merge(close))
+        }
+        // Reader syntax like `#rgb(255 0 0)` is read as `(#rgb 255 0 0)` so
+        // that it can be given a meaning with `(macro (#rgb ,r ,g ,b) ...)`.
+        TokenKind::ReaderMacro(name) => {
+            let name_span = span.subspan(0, name.len() as u64);
+            let (args, close) = list(lexer)?;
+            Ast::Node(
+                Box::new(Ast::Sym(name.to_owned(), name_span)),
+                args,
+                span.merge(close),
+            )
+        }
+        TokenKind::Quote => {
+            let token = lexer.next_token()?;
+            let quoted = expr(lexer, token, &[EXPRESSION])?;
+            let span = span.merge(quoted.span());
+            Ast::Quote(Box::new(quoted), span)
+        }
+        TokenKind::Unquote => {
+            let token = lexer.next_token()?;
+            let unquoted = expr(lexer, token, &[EXPRESSION])?;
+            let span = span.merge(unquoted.span());
+            Ast::Unquote(Box::new(unquoted), span)
+        }
+        TokenKind::Close | TokenKind::Invalid | TokenKind::Eof => {
+            return Err(syntax_error(span, expected));
+        }
+    })
+}
+
+/// Parses the rest of a list up to its closing parenthesis, whose span is
+/// returned too.
+fn list(lexer: &mut Lexer) -> Result<(Vec<Ast>, Span)> {
+    let mut items = Vec::new();
+    loop {
+        let token = lexer.next_token()?;
+        if token.kind == TokenKind::Close {
+            return Ok((items, token.span));
+        }
+        items.push(expr(lexer, token, &[EXPRESSION, CLOSE])?);
+    }
 }
 
-fn sym_non_first_char(input: &mut Input) -> PResult<()> {
-    alt((sym_first_char.void(), digit1.void())).parse_next(input)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codemap::CodeMap;
 
-fn sym(input: &mut Input) -> PResult<Ast> {
-    spanned(
-        (
-            sym_first_char,
-            repeat::<_, _, (), _, _>(0.., sym_non_first_char),
-        )
-            .recognize(),
-    )
-    .map(|(span, sym)| Ast::Sym(sym.to_owned(), span))
-    .parse_next(input)
-}
+    fn nesting_error(source: &str, limit: usize) -> Option<u64> {
+        let mut code_map = CodeMap::new();
+        let file = code_map.add_file("main.scratch".to_owned(), source.into());
+        check_nesting(&file, limit).err().map(|err| match *err {
+            Error::TooDeeplyNested { span, .. } => span.low() - file.span.low(),
+            _ => unreachable!(),
+        })
+    }
 
-fn node(input: &mut Input) -> PResult<Ast> {
-    let content = (expr, repeat(0.., preceded(ws, expr)));
-    spanned(delimited(('(', ws), content, (ws, ')')))
-        .map(|(span, (first, rest))| Ast::Node(Box::new(first), rest, span))
-        .parse_next(input)
-}
+    fn parse(source: &str) -> std::result::Result<String, (u64, Vec<String>)> {
+        let mut code_map = CodeMap::new();
+        let file = code_map.add_file("main.scratch".to_owned(), source.into());
+        match program(&file, 256) {
+            Ok(asts) => Ok(asts.iter().map(Ast::to_string).collect()),
+            Err(err) => match *err {
+                Error::Parse { span, expected } => {
+                    Err((span.low() - file.span.low(), expected))
+                }
+                _ => unreachable!(),
+            },
+        }
+    }
 
-fn unquote(input: &mut Input) -> PResult<Ast> {
-    spanned(preceded((',', ws), expr))
-        .map(|(span, ast)| Ast::Unquote(Box::new(ast), span))
-        .parse_next(input)
-}
+    #[test]
+    fn atoms_are_read_as_a_whole() {
+        let ok = |source: &str, parsed: &str| {
+            assert_eq!(parse(source), Ok(parsed.to_owned()));
+        };
+        ok(
+            "(0x1F -0b11 +0o17 1. .5 2e3 inf)",
+            "(31 -3 15 1 0.5 2000 inf)",
+        );
+        ok("(infinity -1a - ...)", "(infinity -1a - ...)");
+        ok("#ff8000 #rgb(1 2) #ffz", "#ff8000(#rgb 1 2)#ffz");
+        ok("'a ,b true\"\\x41\\u{1F600}\"", "'a,btrue\"A😀\"");
+    }
 
-fn eol_comment(input: &mut Input) -> PResult<()> {
-    (';', take_till0('\n')).void().parse_next(input)
-}
+    #[test]
+    fn syntax_errors_say_what_was_expected() {
+        let err = |source: &str, offset: u64, expected: &[&str]| {
+            assert_eq!(
+                parse(source),
+                Err((offset, expected.iter().map(|&it| it.into()).collect())),
+            );
+        };
+        err("(a 1+)", 3, &[EXPRESSION, CLOSE]);
+        err("(a", 2, &[EXPRESSION, CLOSE]);
+        err("()", 1, &[EXPRESSION]);
+        err("a)", 1, &[EXPRESSION]);
+        err("'", 1, &[EXPRESSION]);
+        err("\"ab\ncd\"", 3, &["`\"`"]);
+        err("\"\\q\"", 2, &["an escape sequence"]);
+        err("(1e)", 3, &["a digit"]);
+    }
 
-fn ws(input: &mut Input) -> PResult<()> {
-    repeat(0.., alt((multispace1.void(), eol_comment))).parse_next(input)
-}
+    #[test]
+    fn quote_chains_count_as_nesting() {
+        let chain = format!("{}x", "'".repeat(300));
+        assert_eq!(nesting_error(&chain, 256), Some(256));
+        assert_eq!(nesting_error(&chain, 300), None);
+        assert_eq!(nesting_error(",',',x", 3), Some(3));
+    }
 
-fn spanned<'a, O, E: ParserError<Input<'a>>, F>(
-    mut parser: F,
-) -> impl Parser<Input<'a>, (Span, O), E>
-where
-    F: Parser<Input<'a>, O, E>,
-{
-    move |input: &mut Input<'a>| {
-        parser
-            .by_ref()
-            .with_span()
-            .map(|(parsed, range)| {
-                (
-                    input.state.span.subspan(
-                        range.start.try_into().unwrap(),
-                        range.end.try_into().unwrap(),
-                    ),
-                    parsed,
-                )
-            })
-            .parse_next(input)
+    #[test]
+    fn quotes_stay_open_until_their_list_is_closed() {
+        assert_eq!(nesting_error("''(a (b))", 3), Some(5));
+        assert_eq!(nesting_error("''(a) (b)", 3), None);
+        assert_eq!(nesting_error("''a (b (c))", 2), None);
     }
 }
@@ -0,0 +1,205 @@
+//! The pattern language of `str-match`, a small subset of regular expressions
+//! that works on bytes:
+//!
+//! - `.` matches any byte and `\c` matches `c` itself.
+//! - `[abc]`, `[a-z]` and `[^abc]` match a byte from (or not from) a set.
+//! - `*` and `?` make the previous item repeatable or optional.
+//! - `^` at the start and `$` at the end anchor the match. Anywhere else they
+//!   match themselves.
+//!
+//! Patterns known at compile time are turned into tables by [`Pattern::table`]
+//! and matched by `str_match` in the runtime. Other patterns are compiled by
+//! the runtime in the same way.
+
+/// Patterns are matched with one bit of state per item.
+pub const MAX_ITEMS: usize = 63;
+
+pub struct Pattern {
+    anchored_start: bool,
+    anchored_end: bool,
+    items: Vec<Item>,
+}
+
+struct Item {
+    bytes: [u64; 4],
+    optional: bool,
+    repeated: bool,
+}
+
+impl Item {
+    const fn new(bytes: [u64; 4]) -> Self {
+        Self {
+            bytes,
+            optional: false,
+            repeated: false,
+        }
+    }
+
+    const fn matches(&self, byte: u8) -> bool {
+        self.bytes[byte as usize / 64] & 1 << (byte % 64) != 0
+    }
+}
+
+fn insert(bytes: &mut [u64; 4], byte: u8) {
+    bytes[byte as usize / 64] |= 1 << (byte % 64);
+}
+
+impl Pattern {
+    /// Returns a description of the problem if the pattern is invalid.
+    pub fn parse(pattern: &str) -> Result<Self, &'static str> {
+        let mut bytes = pattern.as_bytes();
+        let anchored_start = if let [b'^', rest @ ..] = bytes {
+            bytes = rest;
+            true
+        } else {
+            false
+        };
+        let mut anchored_end = false;
+        let mut items = Vec::<Item>::new();
+        let mut quantified = false;
+
+        while let [byte, rest @ ..] = bytes {
+            bytes = rest;
+            let item = match byte {
+                b'$' if rest.is_empty() => {
+                    anchored_end = true;
+                    break;
+                }
+                b'*' | b'?' => {
+                    let Some(last) = items.last_mut().filter(|_| !quantified)
+                    else {
+                        return Err("`*` and `?` must follow an item");
+                    };
+                    last.optional = true;
+                    last.repeated = *byte == b'*';
+                    quantified = true;
+                    continue;
+                }
+                b'.' => Item::new([u64::MAX; 4]),
+                b'\\' => {
+                    let [escaped, rest @ ..] = bytes else {
+                        return Err("the pattern ends with a `\\`");
+                    };
+                    bytes = rest;
+                    let mut set = [0; 4];
+                    insert(&mut set, *escaped);
+                    Item::new(set)
+                }
+                b'[' => Item::new(parse_class(&mut bytes)?),
+                _ => {
+                    let mut set = [0; 4];
+                    insert(&mut set, *byte);
+                    Item::new(set)
+                }
+            };
+            if items.len() == MAX_ITEMS {
+                return Err("the pattern has too many items");
+            }
+            items.push(item);
+            quantified = false;
+        }
+
+        Ok(Self {
+            anchored_start,
+            anchored_end,
+            items,
+        })
+    }
+
+    /// The layout read by `str_match`: the number of items, the anchor flags,
+    /// a mask of the optional items and one of the repeated items, all as
+    /// 64-bit integers, followed by a 256-bit set of bytes for every item.
+    pub fn table(&self) -> Vec<u8> {
+        let mask = |f: fn(&Item) -> bool| {
+            self.items
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| f(item))
+                .fold(0_u64, |mask, (i, _)| mask | 1 << i)
+        };
+        let flags =
+            u64::from(self.anchored_start) | u64::from(self.anchored_end) << 1;
+        [
+            self.items.len() as u64,
+            flags,
+            mask(|item| item.optional),
+            mask(|item| item.repeated),
+        ]
+        .into_iter()
+        .chain(self.items.iter().flat_map(|item| item.bytes))
+        .flat_map(u64::to_le_bytes)
+        .collect()
+    }
+
+    /// Whether the pattern matches any part of `s`, which is what the runtime
+    /// computes.
+    pub fn is_match(&self, s: &str) -> bool {
+        let accept = 1 << self.items.len();
+        let mut states = 1_u64;
+        let mut rest = s.as_bytes();
+        loop {
+            for (i, item) in self.items.iter().enumerate() {
+                if states & 1 << i != 0 && item.optional {
+                    states |= 1 << (i + 1);
+                }
+            }
+            if states & accept != 0 && (!self.anchored_end || rest.is_empty()) {
+                return true;
+            }
+            let [byte, tail @ ..] = rest else {
+                return false;
+            };
+            rest = tail;
+            let mut next = 0;
+            for (i, item) in self.items.iter().enumerate() {
+                if states & 1 << i != 0 && item.matches(*byte) {
+                    next |= 1 << (i + usize::from(!item.repeated));
+                }
+            }
+            states = next | u64::from(!self.anchored_start);
+        }
+    }
+}
+
+/// Parses the rest of a `[...]` class.
+fn parse_class(bytes: &mut &[u8]) -> Result<[u64; 4], &'static str> {
+    const UNTERMINATED: &str = "the pattern has a `[` without a `]`";
+
+    let negated = if let [b'^', rest @ ..] = *bytes {
+        *bytes = rest;
+        true
+    } else {
+        false
+    };
+    let mut set = [0; 4];
+    loop {
+        let low = match *bytes {
+            [] | [b'\\'] => return Err(UNTERMINATED),
+            [b']', rest @ ..] => {
+                *bytes = rest;
+                break;
+            }
+            [b'\\', low, rest @ ..] | [low, rest @ ..] => {
+                *bytes = rest;
+                *low
+            }
+        };
+        let high = match *bytes {
+            [b'-', high, rest @ ..] if *high != b']' => {
+                *bytes = rest;
+                *high
+            }
+            _ => low,
+        };
+        if high < low {
+            return Err("the pattern has a range that is out of order");
+        }
+        for byte in low..=high {
+            insert(&mut set, byte);
+        }
+    }
+    if negated {
+        set = set.map(|bits| !bits);
+    }
+    Ok(set)
+}
@@ -0,0 +1,184 @@
+use crate::{
+    diagnostic::Remark,
+    ir::{
+        expr::Expr, proc::Procedure, sprite::Sprite, statement::Statement,
+        typ::Type, Program,
+    },
+    typecheck::Checker,
+};
+use codemap::Span;
+use std::{collections::HashMap, iter, ptr};
+
+/// Where a variable was declared, which tells apart variables that share a
+/// name.
+#[derive(PartialEq, Eq, Hash)]
+enum Owner<'a> {
+    Stage,
+    Sprite(&'a str),
+    Proc(&'a str, &'a str, usize),
+}
+
+/// The values assigned to a variable, keeping the earliest span of each kind.
+#[derive(Default)]
+struct Assignments {
+    number: Option<Span>,
+    other: Option<(Span, Option<Type>)>,
+}
+
+/// Explains why variables are stored boxed in native code, where only
+/// variables annotated as numbers are unboxed.
+pub fn remarks(program: &Program) -> Vec<Remark> {
+    let mut variables = HashMap::<_, Assignments>::new();
+    let sprites = iter::once(("", &program.stage)).chain(
+        program
+            .sprites
+            .iter()
+            .map(|(name, sprite)| (&**name, sprite)),
+    );
+    for (sprite_name, sprite) in sprites {
+        for (proc_name, procs) in &sprite.procedures {
+            for (i, proc) in procs.iter().enumerate() {
+                let scope = Scope {
+                    checker: Checker::new(false, &program.stage, sprite, proc),
+                    stage: &program.stage,
+                    sprite_name,
+                    sprite,
+                    proc_name,
+                    proc_index: i,
+                    proc,
+                };
+                scope.visit(&proc.body, &mut |owner, name, span, typ| {
+                    let assignments =
+                        variables.entry((owner, name)).or_default();
+                    if typ == Some(Type::Num) {
+                        let first = assignments.number.get_or_insert(span);
+                        *first = span.min(*first);
+                    } else if assignments
+                        .other
+                        .map_or(true, |(first, _)| span < first)
+                    {
+                        assignments.other = Some((span, typ));
+                    }
+                });
+            }
+        }
+    }
+
+    let mut remarks = variables
+        .into_iter()
+        .filter_map(|((_, name), assignments)| match assignments {
+            Assignments {
+                other: Some((span, typ)),
+                ..
+            } => Some(Remark::BoxedVariable {
+                span,
+                name: name.to_owned(),
+                found: typ.map(Type::to_str),
+            }),
+            Assignments {
+                number: Some(span),
+                other: None,
+            } => Some(Remark::UnannotatedNumber {
+                span,
+                name: name.to_owned(),
+            }),
+            Assignments { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    remarks.sort_by_key(Remark::span);
+    remarks
+}
+
+struct Scope<'a> {
+    checker: Checker<'a>,
+    stage: &'a Sprite,
+    sprite_name: &'a str,
+    sprite: &'a Sprite,
+    proc_name: &'a str,
+    proc_index: usize,
+    proc: &'a Procedure,
+}
+
+impl<'a> Scope<'a> {
+    /// Finds the declaration of an unannotated variable. Parameters are left
+    /// out since their representation depends on the callers.
+    fn owner(&self, name: &str) -> Option<Owner<'a>> {
+        if self.checker.annotation(name).is_some()
+            || self
+                .proc
+                .params
+                .iter()
+                .any(|(param, _)| matches!(param, Expr::Sym(p, _) if p == name))
+        {
+            None
+        } else if self.proc.variables.contains(name) {
+            Some(Owner::Proc(
+                self.sprite_name,
+                self.proc_name,
+                self.proc_index,
+            ))
+        } else if self.sprite.variables.contains(name)
+            && !ptr::eq(self.sprite, self.stage)
+        {
+            Some(Owner::Sprite(self.sprite_name))
+        } else if self.stage.variables.contains(name) {
+            Some(Owner::Stage)
+        } else {
+            None
+        }
+    }
+
+    /// Calls `f` with every assignment to an unannotated variable, along with
+    /// the type of the assigned value if it is known.
+    fn visit<'s>(
+        &self,
+        stmt: &'s Statement,
+        f: &mut impl FnMut(Owner<'a>, &'s str, Span, Option<Type>),
+    ) {
+        match stmt {
+            Statement::ProcCall {
+                proc_name,
+                proc_span,
+                args,
+            } => {
+                let (name, typ, span) = match (&**proc_name, &args[..]) {
+                    (":=", [Expr::Sym(name, _), value]) => (
+                        name,
+                        self.checker.type_of(value),
+                        value.span().unwrap_or(*proc_span),
+                    ),
+                    ("+=", [Expr::Sym(name, span), _]) => {
+                        (name, Some(Type::Num), *span)
+                    }
+                    _ => return,
+                };
+                if let Some(owner) = self.owner(name) {
+                    f(owner, name, span, typ);
+                }
+            }
+            Statement::Do(stmts) => {
+                for stmt in stmts {
+                    self.visit(stmt, f);
+                }
+            }
+            Statement::IfElse { then, else_, .. } => {
+                self.visit(then, f);
+                self.visit(else_, f);
+            }
+            Statement::For {
+                counter: (name, span),
+                body,
+                ..
+            } => {
+                if let Some(owner) = self.owner(name) {
+                    f(owner, name, *span, Some(Type::Num));
+                }
+                self.visit(body, f);
+            }
+            Statement::Repeat { body, .. }
+            | Statement::Forever(body, _)
+            | Statement::Until { body, .. }
+            | Statement::While { body, .. } => self.visit(body, f),
+        }
+    }
+}
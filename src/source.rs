@@ -0,0 +1,41 @@
+//! Reading of source files. Files that aren't UTF-8 are reported with where
+//! the first invalid byte is, and files are only read up to a limit, so that
+//! passing a huge file by mistake fails right away.
+
+use crate::diagnostic::{Error, Result};
+use codemap::Span;
+use std::{fs::File, io::Read, path::Path};
+
+/// The biggest source file that is read, in bytes.
+pub const MAX_SIZE: u64 = 64 << 20;
+
+/// Reads a source file. `span` is where it was included, if it was.
+pub fn read(path: &Path, span: Option<Span>) -> Result<String> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(MAX_SIZE + 1).read_to_end(&mut bytes))
+        .map_err(|inner| Error::CouldNotReadFile {
+            path: path.to_owned(),
+            inner,
+        })?;
+    if bytes.len() as u64 > MAX_SIZE {
+        return Err(Box::new(Error::SourceTooBig {
+            span,
+            path: path.to_owned(),
+            limit: MAX_SIZE,
+        }));
+    }
+    String::from_utf8(bytes).map_err(|err| {
+        let offset = err.utf8_error().valid_up_to();
+        let line = 1 + err.as_bytes()[..offset]
+            .iter()
+            .filter(|&&byte| byte == b'\n')
+            .count();
+        Box::new(Error::SourceNotUtf8 {
+            span,
+            path: path.to_owned(),
+            offset,
+            line,
+        })
+    })
+}
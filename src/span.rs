@@ -0,0 +1,75 @@
+//! Columns of positions in source code as they are shown in a terminal.
+//! Spans are byte offsets and `codemap` counts columns in characters, but
+//! characters like emoji and CJK ideographs take up two columns and
+//! combining marks take up none.
+
+use codemap::{CodeMap, Pos};
+
+/// The column that the character at `pos` is shown in, counting from 0.
+pub fn display_column(code_map: &CodeMap, pos: Pos) -> usize {
+    let loc = code_map.look_up_pos(pos);
+    let line = loc.file.line_span(loc.position.line);
+    loc.file
+        .source_slice(line.subspan(0, pos - line.low()))
+        .chars()
+        .map(char_width)
+        .sum()
+}
+
+/// How many columns a character takes up. This covers the ranges of wide and
+/// zero width characters that show up in practice rather than all of Unicode.
+pub const fn char_width(c: char) -> usize {
+    match c {
+        // Combining marks, zero width spaces and joiners, and variation
+        // selectors.
+        '\u{300}'..='\u{36f}'
+        | '\u{200b}'..='\u{200f}'
+        | '\u{20d0}'..='\u{20ff}'
+        | '\u{fe00}'..='\u{fe0f}'
+        | '\u{fe20}'..='\u{fe2f}' => 0,
+        // Hangul Jamo, CJK, Hangul syllables, fullwidth forms and emoji.
+        '\u{1100}'..='\u{115f}'
+        | '\u{2e80}'..='\u{303e}'
+        | '\u{3041}'..='\u{a4cf}'
+        | '\u{ac00}'..='\u{d7a3}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{fe30}'..='\u{fe4f}'
+        | '\u{ff00}'..='\u{ff60}'
+        | '\u{ffe0}'..='\u{ffe6}'
+        | '\u{1f300}'..='\u{1f64f}'
+        | '\u{1f900}'..='\u{1f9ff}'
+        | '\u{20000}'..='\u{3fffd}' => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn char_width_of_wide_characters_is_two() {
+        for c in ['界', '한', '🙂', '🦀', 'Ａ'] {
+            assert_eq!(char_width(c), 2, "{c}");
+        }
+    }
+
+    #[test]
+    fn char_width_of_combining_characters_is_zero() {
+        for c in ['\u{301}', '\u{20d7}', '\u{200d}', '\u{fe0f}'] {
+            assert_eq!(char_width(c), 0, "{c:?}");
+        }
+        assert_eq!("e\u{301}".chars().map(char_width).sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn display_column_counts_columns() {
+        let mut code_map = CodeMap::new();
+        let file = code_map.add_file(
+            "main.scratch".to_owned(),
+            "(say \"🙂 e\u{301}\" x)".to_owned(),
+        );
+        let x = file.source().find('x').unwrap() as u64;
+        assert_eq!(display_column(&code_map, file.span.low() + x), 12);
+    }
+}
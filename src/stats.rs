@@ -0,0 +1,151 @@
+use crate::{
+    ir::{expr::Expr, sprite::Sprite, statement::Statement, Program},
+    opts::Opts,
+};
+use sb3_stuff::Value;
+use std::fs;
+
+/// Counts of the nodes in a program, for `--stats`.
+#[derive(Default)]
+pub struct Stats {
+    procedures: usize,
+    statements: usize,
+    immediates: usize,
+    symbols: usize,
+    function_calls: usize,
+    sums: usize,
+    products: usize,
+    /// Bytes in string literals, which end up in the output.
+    string_bytes: usize,
+}
+
+impl Stats {
+    pub fn of(program: &Program) -> Self {
+        let mut stats = Self::default();
+        for sprite in
+            std::iter::once(&program.stage).chain(program.sprites.values())
+        {
+            stats.count_sprite(sprite);
+        }
+        stats
+    }
+
+    fn count_sprite(&mut self, sprite: &Sprite) {
+        for proc in sprite.procedures.values().flatten() {
+            self.procedures += 1;
+            self.count_statement(&proc.body);
+        }
+    }
+
+    fn count_statement(&mut self, stmt: &Statement) {
+        self.statements += 1;
+        match stmt {
+            Statement::ProcCall { args, .. } => {
+                for arg in args {
+                    self.count_expr(arg);
+                }
+            }
+            Statement::Do(stmts) => {
+                for stmt in stmts {
+                    self.count_statement(stmt);
+                }
+            }
+            Statement::IfElse {
+                condition,
+                then,
+                else_,
+                ..
+            } => {
+                self.count_expr(condition);
+                self.count_statement(then);
+                self.count_statement(else_);
+            }
+            Statement::Repeat {
+                times: expr, body, ..
+            }
+            | Statement::Until {
+                condition: expr,
+                body,
+                ..
+            }
+            | Statement::While {
+                condition: expr,
+                body,
+                ..
+            }
+            | Statement::For {
+                times: expr, body, ..
+            } => {
+                self.count_expr(expr);
+                self.count_statement(body);
+            }
+            Statement::Forever(body, _) => self.count_statement(body),
+        }
+    }
+
+    fn count_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Imm(imm) => {
+                self.immediates += 1;
+                if let Value::String(s) = imm {
+                    self.string_bytes += s.len();
+                }
+            }
+            Expr::Sym(..) => self.symbols += 1,
+            Expr::FuncCall(_, _, args) => {
+                self.function_calls += 1;
+                for arg in args {
+                    self.count_expr(arg);
+                }
+            }
+            Expr::AddSub(a, b, _) | Expr::MulDiv(a, b, _) => {
+                if let Expr::AddSub(..) = expr {
+                    self.sums += 1;
+                } else {
+                    self.products += 1;
+                }
+                for term in a.iter().chain(b) {
+                    self.count_expr(term);
+                }
+            }
+        }
+    }
+
+    const fn expressions(&self) -> usize {
+        self.immediates
+            + self.symbols
+            + self.function_calls
+            + self.sums
+            + self.products
+    }
+
+    /// Prints the counts from before and after optimizing the program, along
+    /// with the sizes of the artifacts that were written.
+    pub fn report(before: &Self, after: &Self, opts: &Opts) {
+        let rows = [
+            ("procedures", before.procedures, after.procedures),
+            ("statements", before.statements, after.statements),
+            ("expressions", before.expressions(), after.expressions()),
+            ("  literals", before.immediates, after.immediates),
+            ("  variables", before.symbols, after.symbols),
+            (
+                "  function calls",
+                before.function_calls,
+                after.function_calls,
+            ),
+            ("  sums", before.sums, after.sums),
+            ("  products", before.products, after.products),
+            ("string bytes", before.string_bytes, after.string_bytes),
+        ];
+        eprintln!("{:<20}{:>12}{:>12}", "", "parsed", "optimized");
+        for (name, before, after) in rows {
+            eprintln!("{name:<20}{before:>12}{after:>12}");
+        }
+        for artifact in opts.artifacts() {
+            let path = opts.out_dir.join(artifact.file_name());
+            if let Ok(metadata) = fs::metadata(&path) {
+                eprintln!("{:<20}{:>12} bytes", path.display(), metadata.len());
+            }
+        }
+    }
+}
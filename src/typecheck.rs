@@ -0,0 +1,373 @@
+use crate::{
+    diagnostic::{closest, Error, Result},
+    ir::{
+        expr::Expr, ffi::ExternFunction, proc::Procedure, sprite::Sprite,
+        statement::Statement, typ::Type, Program,
+    },
+    pattern::Pattern,
+};
+use codemap::Span;
+use sb3_stuff::Value;
+use std::iter;
+
+/// Checks that annotated parameters and variables only ever receive values of
+/// their declared type. In strict mode, it also reports every value that is
+/// implicitly converted to a number or a boolean without an explicit
+/// `to-num`/`to-bool`. Scratch performs such conversions silently, which hides
+/// bugs like adding a string to a number.
+pub fn check(program: &Program, strict: bool) -> Result<()> {
+    for sprite in iter::once(&program.stage).chain(program.sprites.values()) {
+        for proc in sprite.procedures.values().flatten() {
+            Checker::new(strict, &program.stage, sprite, proc)
+                .check_stmt(&proc.body)?;
+        }
+    }
+    Ok(())
+}
+
+/// Looks up the types of expressions in a procedure.
+pub struct Checker<'a> {
+    strict: bool,
+    stage: &'a Sprite,
+    sprite: &'a Sprite,
+    proc: &'a Procedure,
+}
+
+impl<'a> Checker<'a> {
+    pub const fn new(
+        strict: bool,
+        stage: &'a Sprite,
+        sprite: &'a Sprite,
+        proc: &'a Procedure,
+    ) -> Self {
+        Self {
+            strict,
+            stage,
+            sprite,
+            proc,
+        }
+    }
+
+    /// Returns `None` if the type of the expression is only known at runtime.
+    pub fn type_of(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Imm(Value::Num(_)) | Expr::AddSub(..) | Expr::MulDiv(..) => {
+                Some(Type::Num)
+            }
+            Expr::Imm(Value::Bool(_)) => Some(Type::Bool),
+            Expr::Imm(Value::String(_)) => Some(Type::Str),
+            Expr::Sym(sym, _) => match &**sym {
+                "x-pos" | "y-pos" | "timer" | "video-motion"
+                | "video-direction" => Some(Type::Num),
+                _ => self.annotation(sym),
+            },
+            Expr::FuncCall(func_name, _, args) => match *func_name {
+                "not" | "and" | "or" | "<" | "=" | ">" | "pressing-key"
+                | "to-bool" | "str-match" => Some(Type::Bool),
+                "++" | "char-at" | "json-get" | "json-set"
+                | "format-decimal" | "to-hex" | "to-binary" => Some(Type::Str),
+                "!!" | "%static-get" | "call-extension" => None,
+                "call-extern" => {
+                    self.extern_function(args).and_then(|f| f.returns)
+                }
+                _ => Some(Type::Num),
+            },
+        }
+    }
+
+    pub fn annotation(&self, name: &str) -> Option<Type> {
+        let is_param =
+            self.proc.params.iter().any(
+                |(param, _)| matches!(param, Expr::Sym(p, _) if p == name),
+            );
+        if is_param || self.proc.variables.contains(name) {
+            self.proc.annotations.get(name).copied()
+        } else if self.sprite.variables.contains(name) {
+            self.sprite.annotations.get(name).copied()
+        } else {
+            self.stage.annotations.get(name).copied()
+        }
+    }
+
+    fn extern_function(&self, args: &[Expr]) -> Option<&ExternFunction> {
+        let [Expr::Imm(Value::String(name)), ..] = args else {
+            return None;
+        };
+        self.sprite
+            .externs
+            .get(&**name)
+            .or_else(|| self.stage.externs.get(&**name))
+    }
+
+    /// Checks the arguments of a `call-extern` against the declared parameter
+    /// types.
+    fn check_extern_call(&self, args: &[Expr], span: Span) -> Result<()> {
+        let Some(function) = self.extern_function(args) else {
+            return Ok(());
+        };
+        for (arg, &typ) in args[1..].iter().zip(&function.params) {
+            self.expect(arg, typ, span)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that `expr` has the `expected` type. `fallback_span` is reported
+    /// for expressions that don't have a span of their own, like literals.
+    fn expect(
+        &self,
+        expr: &Expr,
+        expected: Type,
+        fallback_span: Span,
+    ) -> Result<()> {
+        let typ = self.type_of(expr);
+        if typ == Some(expected) {
+            return Ok(());
+        }
+        let span = expr.span().unwrap_or(fallback_span);
+        Err(Box::new(match typ {
+            Some(found) => Error::TypeMismatch {
+                span,
+                expected: expected.to_str(),
+                found: found.to_str(),
+            },
+            None => Error::ImplicitConversion {
+                span,
+                expected: expected.to_str(),
+                conversion: expected.conversion(),
+            },
+        }))
+    }
+
+    /// Like `expect`, but only checked in strict mode.
+    fn expect_strict(
+        &self,
+        expr: &Expr,
+        expected: Type,
+        fallback_span: Span,
+    ) -> Result<()> {
+        if self.strict {
+            self.expect(expr, expected, fallback_span)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_stmt(&self, stmt: &Statement) -> Result<()> {
+        match stmt {
+            Statement::ProcCall {
+                proc_name,
+                proc_span,
+                args,
+            } => {
+                self.check_proc_call(proc_name, *proc_span, args)?;
+                args.iter().try_for_each(|arg| self.check_expr(arg))
+            }
+            Statement::Do(stmts) => {
+                stmts.iter().try_for_each(|stmt| self.check_stmt(stmt))
+            }
+            Statement::IfElse {
+                condition,
+                then,
+                else_,
+                span,
+            } => {
+                self.expect_strict(condition, Type::Bool, *span)?;
+                self.check_expr(condition)?;
+                self.check_stmt(then)?;
+                self.check_stmt(else_)
+            }
+            Statement::Repeat { times, body, span } => {
+                self.expect_strict(times, Type::Num, *span)?;
+                self.check_expr(times)?;
+                self.check_stmt(body)
+            }
+            Statement::For {
+                counter,
+                times,
+                body,
+            } => {
+                if let Some(typ) = self.annotation(&counter.0) {
+                    if typ != Type::Num {
+                        return Err(Box::new(Error::TypeMismatch {
+                            span: counter.1,
+                            expected: typ.to_str(),
+                            found: Type::Num.to_str(),
+                        }));
+                    }
+                }
+                self.expect_strict(times, Type::Num, counter.1)?;
+                self.check_expr(times)?;
+                self.check_stmt(body)
+            }
+            Statement::Forever(body, _) => self.check_stmt(body),
+            Statement::Until {
+                condition,
+                body,
+                span,
+            }
+            | Statement::While {
+                condition,
+                body,
+                span,
+            } => {
+                self.expect_strict(condition, Type::Bool, *span)?;
+                self.check_expr(condition)?;
+                self.check_stmt(body)
+            }
+        }
+    }
+
+    fn check_proc_call(
+        &self,
+        proc_name: &str,
+        proc_span: Span,
+        args: &[Expr],
+    ) -> Result<()> {
+        if let Some([callee]) =
+            self.sprite.procedures.get(proc_name).map(Vec::as_slice)
+        {
+            for ((param, _), arg) in callee.params.iter().zip(args) {
+                if let Expr::Sym(param_name, _) = param {
+                    if let Some(&typ) = callee.annotations.get(&**param_name) {
+                        self.expect(arg, typ, proc_span)?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        match (proc_name, args) {
+            (":=", [Expr::Sym(var_name, _), value]) => {
+                if let Some(typ) = self.annotation(var_name) {
+                    self.expect(value, typ, proc_span)?;
+                }
+            }
+            ("+=", [Expr::Sym(var_name, var_span), amount]) => {
+                if let Some(typ) = self.annotation(var_name) {
+                    if typ != Type::Num {
+                        return Err(Box::new(Error::TypeMismatch {
+                            span: *var_span,
+                            expected: typ.to_str(),
+                            found: Type::Num.to_str(),
+                        }));
+                    }
+                }
+                self.expect_strict(amount, Type::Num, proc_span)?;
+            }
+            (
+                "wait"
+                | "change-x"
+                | "change-y"
+                | "set-x"
+                | "set-y"
+                | "set-size"
+                | "set-pen-size"
+                | "set-pen-color"
+                | "say-for-seconds"
+                | "rest"
+                | "set-instrument"
+                | "set-tempo"
+                | "set-video-transparency",
+                [first, ..],
+            ) => self.expect_strict(first, Type::Num, proc_span)?,
+            ("static-assert", [condition, ..]) => {
+                self.expect_strict(condition, Type::Bool, proc_span)?;
+            }
+            ("print-fixed", [n, digits]) => {
+                self.expect_strict(n, Type::Num, proc_span)?;
+                self.expect_strict(digits, Type::Num, proc_span)?;
+            }
+            ("play-note", [note, beats]) => {
+                self.expect_strict(note, Type::Num, proc_span)?;
+                self.expect_strict(beats, Type::Num, proc_span)?;
+            }
+            ("call-extern", _) => self.check_extern_call(args, proc_span)?,
+            ("set-xy", [x, y]) => {
+                self.expect_strict(x, Type::Num, proc_span)?;
+                self.expect_strict(y, Type::Num, proc_span)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn check_expr(&self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Imm(_) | Expr::Sym(..) => Ok(()),
+            Expr::AddSub(a, b, span) | Expr::MulDiv(a, b, span) => {
+                for term in a.iter().chain(b) {
+                    self.expect_strict(term, Type::Num, *span)?;
+                    self.check_expr(term)?;
+                }
+                Ok(())
+            }
+            Expr::FuncCall(func_name, span, args) => {
+                if *func_name == "call-extern" {
+                    self.check_extern_call(args, *span)?;
+                }
+                if *func_name == "pressing-key" {
+                    if let [Expr::Imm(Value::String(key))] = &args[..] {
+                        check_key(key, *span)?;
+                    }
+                }
+                if *func_name == "str-match" {
+                    if let [_, Expr::Imm(Value::String(pattern))] = &args[..] {
+                        Pattern::parse(pattern).map_err(|reason| {
+                            Error::InvalidPattern {
+                                span: *span,
+                                reason,
+                            }
+                        })?;
+                    }
+                }
+                for (i, arg) in args.iter().enumerate() {
+                    match (*func_name, i) {
+                        ("and" | "or" | "not", _) => {
+                            self.expect_strict(arg, Type::Bool, *span)?;
+                        }
+                        (
+                            "mod" | "abs" | "floor" | "ceil" | "sqrt" | "ln"
+                            | "log" | "e^" | "ten^" | "sin" | "cos" | "tan"
+                            | "asin" | "acos" | "atan" | "random" | "round-to"
+                            | "format-decimal" | "to-hex" | "to-binary"
+                            | "bit-and" | "bit-or" | "bit-xor"
+                            | "bit-shift-left" | "bit-shift-right" | "min"
+                            | "max" | "clamp" | "dist" | "dir-to",
+                            _,
+                        )
+                        | ("char-at" | "table-get", 1) => {
+                            self.expect_strict(arg, Type::Num, *span)?;
+                        }
+                        _ => {}
+                    }
+                    self.check_expr(arg)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The keys with names longer than one character.
+const KEY_NAMES: &[&str] = &[
+    "space",
+    "enter",
+    "any",
+    "up arrow",
+    "down arrow",
+    "left arrow",
+    "right arrow",
+];
+
+/// Checks that a key name is one that Scratch knows, since any other key is
+/// never pressed.
+fn check_key(key: &str, span: Span) -> Result<()> {
+    if key.chars().count() == 1 || KEY_NAMES.contains(&key) {
+        return Ok(());
+    }
+    Err(Box::new(Error::UnknownKey {
+        span,
+        key: key.to_owned(),
+        suggestion: closest(key, KEY_NAMES),
+    }))
+}
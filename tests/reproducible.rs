@@ -0,0 +1,76 @@
+//! Compiling the same program twice has to give the same bytes, so that
+//! builds can be compared and cached.
+
+use std::{env, fs, process::Command};
+
+/// Declares enough sprites, variables, lists and procedures that iterating
+/// over any of them in an unstable order would show up in the output.
+const PROGRAM: &str = r#"
+(sprite "Stage"
+  (variables score lives level timer-left high-score)
+  (lists names scores levels)
+  (proc when-flag-clicked
+    (:= score 0)
+    (:= lives 3)
+    (:= level 1)
+    (append names "alice")
+    (append scores 10)))
+
+(sprite "Player"
+  (variables x-speed y-speed jumping health)
+  (lists inventory visited)
+  (proc when-flag-clicked
+    (:= x-speed 0)
+    (:= health 100)
+    (append inventory "sword")
+    (move-by 5))
+  (proc (move-by steps)
+    (:= x-speed (+ x-speed steps))
+    (append visited x-speed)))
+
+(sprite "Enemy"
+  (variables speed damage)
+  (lists path)
+  (proc when-flag-clicked
+    (:= speed 2)
+    (:= damage (* speed 5))
+    (append path damage)))
+"#;
+
+/// Compiles [`PROGRAM`] with `args` in a directory of its own and returns
+/// the bytes of `artifact`.
+fn compile(name: &str, args: &[&str], artifact: &str) -> Vec<u8> {
+    let dir = env::temp_dir().join(format!(
+        "scratch-compiler-test-{}-{name}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.scratch");
+    fs::write(&source, PROGRAM).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_scratch-compiler"))
+        .arg(&source)
+        .arg("--out-dir")
+        .arg(&dir)
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "compiling with {args:?} failed");
+    let bytes = fs::read(dir.join(artifact)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    bytes
+}
+
+#[test]
+fn sb3_is_reproducible() {
+    let first = compile("sb3-first", &[], "project.sb3");
+    let second = compile("sb3-second", &[], "project.sb3");
+    assert!(first == second, "the sb3 projects differ");
+}
+
+#[test]
+fn native_code_is_reproducible() {
+    let args = ["--target", "x86_64"];
+    let first = compile("native-first", &args, "project.o");
+    let second = compile("native-second", &args, "project.o");
+    assert!(first == second, "the object files differ");
+}